@@ -0,0 +1,172 @@
+//! The lifecycle of a table through the client's high-level API: creating the table, writing rows,
+//! scanning them back with predicates, altering the schema, and deleting the table.
+//!
+//! Runs against the cluster whose masters are listed in `KUDU_MASTERS`:
+//!
+//! ```bash
+//! env KUDU_MASTERS=127.0.0.1:7051 cargo run --example crud
+//! ```
+//!
+//! The `crud` integration test runs the same lifecycle when `KUDU_MASTERS` is set.
+
+extern crate kudu;
+
+use std::env;
+use std::io::{self, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::process;
+use std::sync::mpsc::sync_channel;
+use std::time::{Duration, Instant};
+
+use kudu::{
+    col,
+    AlterTableBuilder,
+    Client,
+    ClientConfig,
+    Column,
+    DataType,
+    Error,
+    FlushStats,
+    Predicates,
+    Result,
+    Row,
+    SchemaBuilder,
+    Table,
+    TableBuilder,
+    Writer,
+    WriterConfig,
+};
+
+/// Returns the master addresses listed in the comma-separated `KUDU_MASTERS` environment
+/// variable, or `None` if it is not set.
+pub fn masters_from_env() -> Option<Result<Vec<SocketAddr>>> {
+    let masters = match env::var("KUDU_MASTERS") {
+        Ok(masters) => masters,
+        Err(_) => return None,
+    };
+    let mut addrs = Vec::new();
+    for master in masters.split(',').map(str::trim).filter(|master| !master.is_empty()) {
+        match master.to_socket_addrs() {
+            Ok(resolved) => addrs.extend(resolved),
+            Err(error) => return Some(Err(Error::InvalidArgument(
+                        format!("invalid master address '{}': {}", master, error)))),
+        }
+    }
+    Some(Ok(addrs))
+}
+
+/// Creates the table, exercises it, and deletes it. Panics if the table's contents are not as
+/// expected after each step.
+pub fn run(masters: Vec<SocketAddr>, table_name: &str) -> Result<()> {
+    let client = Client::new(ClientConfig::new(masters));
+    let deadline = || Instant::now() + Duration::from_secs(30);
+
+    // Create a table hash partitioned on its key, and wait for its tablets to be created.
+    let schema = try!(SchemaBuilder::new()
+        .add_column(Column::builder("key", DataType::Int32).set_not_null())
+        .add_column(Column::builder("name", DataType::String).set_not_null())
+        .set_primary_key(vec!["key"])
+        .build());
+    let mut table_builder = TableBuilder::new(table_name, schema);
+    table_builder.add_hash_partitions(vec!["key"], 4);
+    table_builder.set_num_replicas(1);
+    try!(client.create_table(table_builder, deadline()));
+    try!(client.wait_for_table_creation(table_name, deadline()));
+    let table = try!(client.open_table(table_name, deadline()));
+
+    // Insert 100 rows, then update one and delete another. Operations are buffered by the writer
+    // and sent in batches; flushing waits for every operation applied so far.
+    let writer = table.new_writer(WriterConfig::default());
+    for key in 0..100 {
+        writer.insert(try!(named_row(&table, key, &format!("row {}", key))));
+    }
+    assert_eq!(100, flush(&writer).successful_operations());
+
+    writer.update(try!(named_row(&table, 0, "first")));
+    let mut delete = table.schema().new_row();
+    try!(delete.set_by_name::<i32>("key", 99));
+    writer.delete(delete);
+    assert_eq!(2, flush(&writer).successful_operations());
+    drop(writer);
+
+    // Scan the rows back with predicates, which are evaluated by the tablet servers.
+    let rows = try!(scan_rows(&table, col("key").ge(10i32) & col("key").lt(20i32)));
+    assert_eq!(10, rows.len());
+    let rows = try!(scan_rows(&table, col("key").is_in(vec![0i32, 99])));
+    assert_eq!(1, rows.len());
+    assert_eq!(Some("first"), try!(rows[0].get_by_name::<&str>("name")));
+
+    // Add a nullable column. Tables must be reopened to see the new schema.
+    try!(client.alter_table(table_name,
+                            AlterTableBuilder::new()
+                                .add_column(Column::builder("note", DataType::String).set_nullable()),
+                            deadline()));
+    try!(client.wait_for_table_alteration(table_name, deadline()));
+    let table = try!(client.open_table(table_name, deadline()));
+    assert!(table.schema().column_index("note").is_some());
+
+    // Existing rows read the new column as null; upserts set it.
+    let writer = table.new_writer(WriterConfig::default());
+    let mut upsert = try!(named_row(&table, 1, "second"));
+    try!(upsert.set_by_name::<&str>("note", "upserted"));
+    writer.upsert(upsert);
+    assert_eq!(1, flush(&writer).successful_operations());
+    drop(writer);
+
+    let rows = try!(scan_rows(&table, col("note").is_not_null()));
+    assert_eq!(1, rows.len());
+    assert_eq!(Some(1), try!(rows[0].get_by_name::<i32>("key")));
+    assert_eq!(Some("upserted"), try!(rows[0].get_by_name::<&str>("note")));
+    assert_eq!(99, try!(scan_rows(&table, Predicates::default())).len());
+
+    try!(client.delete_table(table_name, deadline()));
+    assert!(!try!(client.table_exists(table_name, deadline())));
+    Ok(())
+}
+
+/// Returns a row of the table with the key and name set.
+fn named_row(table: &Table, key: i32, name: &str) -> Result<Row> {
+    let mut row = table.schema().new_row();
+    try!(row.set_by_name::<i32>("key", key));
+    try!(row.set_by_name::<&str>("name", name));
+    Ok(row)
+}
+
+/// Flushes the writer, waits for the flush to complete, and checks that no operation failed.
+fn flush(writer: &Writer) -> FlushStats {
+    let (send, recv) = sync_channel(1);
+    writer.flush(move |stats| send.send(stats).unwrap());
+    let stats = recv.recv().unwrap();
+    assert_eq!(0, stats.failed_operations(), "failed operations: {:?}", stats);
+    stats
+}
+
+/// Scans the rows of the table which match the predicates.
+fn scan_rows<P>(table: &Table, predicates: P) -> Result<Vec<Row>> where P: Into<Predicates> {
+    let mut scan = table.scan_builder();
+    scan.add_predicates(predicates);
+    let mut rows = Vec::new();
+    for batch in try!(scan.build()).batches() {
+        rows.extend(try!(batch).into_rows());
+    }
+    Ok(rows)
+}
+
+fn main() {
+    let masters = match masters_from_env() {
+        Some(Ok(masters)) => masters,
+        Some(Err(error)) => {
+            let _ = writeln!(io::stderr(), "error: {}", error);
+            process::exit(1);
+        },
+        None => {
+            let _ = writeln!(io::stderr(), "error: set KUDU_MASTERS to the cluster's master addresses");
+            process::exit(1);
+        },
+    };
+    if let Err(error) = run(masters, "kudu_rs_crud_example") {
+        let _ = writeln!(io::stderr(), "error: {}", error);
+        process::exit(1);
+    }
+    println!("done");
+}
@@ -0,0 +1,174 @@
+//! Build script for `kudu-pb`.
+//!
+//! By default the checked-in generated sources in `src/` are used, so building the crate does not
+//! require `protoc`. When the `regenerate` feature is enabled, the Protobuf definitions are compiled
+//! with `protoc --rust_out` into `OUT_DIR`, and the crate includes the freshly generated modules
+//! instead. This allows building against a newer Kudu release in order to pick up new RPC fields.
+//!
+//! The following environment variables are consulted when regenerating:
+//!
+//! * `KUDU_PROTO_DIR` - the Kudu source directory containing `kudu/**/*.proto`. Defaults to
+//!   `$KUDU_HOME/src`. The checkout's release is read from the `version.txt` file alongside it,
+//!   and must not be older than the pinned release, since the client relies on its fields.
+//! * `PROTOC` - the `protoc` binary. Defaults to `protoc` on the `$PATH`.
+//! * `PROTO_INCLUDE` - an additional include path for the well-known Protobuf types.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The Kudu release the checked-in sources were generated from, and the oldest release which may
+/// be regenerated from. Exposed to the crate as `kudu_pb::KUDU_VERSION`, along with the release of
+/// the checkout when regenerating.
+const KUDU_VERSION: &'static str = "1.1.0";
+
+/// Protobuf definitions compiled into the crate, relative to the Kudu source directory, along with
+/// the name of the generated module when it differs from the `protoc` default.
+const PROTOS: &'static [(&'static str, Option<&'static str>)] = &[
+    ("kudu/client/client.proto", None),
+    ("kudu/common/common.proto", None),
+    ("kudu/common/wire_protocol.proto", None),
+    ("kudu/consensus/metadata.proto", Some("consensus_metadata")),
+    ("kudu/consensus/opid.proto", None),
+    ("kudu/fs/fs.proto", None),
+    ("kudu/master/master.proto", None),
+    ("kudu/rpc/rpc_header.proto", None),
+    ("kudu/tablet/metadata.proto", Some("tablet_metadata")),
+    ("kudu/tablet/tablet.proto", None),
+    ("kudu/tserver/tserver.proto", None),
+    ("kudu/tserver/tserver_service.proto", None),
+];
+
+/// Protobuf definitions compiled only with the `admin` feature, since they are not part of the
+/// Kudu release the checked-in sources track.
+const ADMIN_PROTOS: &'static [(&'static str, Option<&'static str>)] = &[
+    ("kudu/tserver/tserver_admin.proto", None),
+];
+
+/// Module path rewrites which are necessary because both `metadata.proto` files generate a module
+/// named `metadata`.
+const REWRITES: &'static [(&'static str, &'static str, &'static str)] = &[
+    ("wire_protocol", "super::metadata", "super::consensus_metadata"),
+    ("tablet", "super::metadata", "super::tablet_metadata"),
+    ("master", "super::metadata::Tablet", "super::tablet_metadata::Tablet"),
+    ("master", "super::metadata::Raft", "super::consensus_metadata::Raft"),
+    ("master", "super::metadata::Consensus", "super::consensus_metadata::Consensus"),
+    ("tserver_admin", "super::metadata::TabletDataState", "super::tablet_metadata::TabletDataState"),
+    ("tserver_admin", "super::metadata::", "super::consensus_metadata::"),
+];
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=KUDU_PROTO_DIR");
+    println!("cargo:rerun-if-env-changed=KUDU_HOME");
+    println!("cargo:rerun-if-env-changed=PROTOC");
+    println!("cargo:rerun-if-env-changed=PROTO_INCLUDE");
+
+    if env::var_os("CARGO_FEATURE_REGENERATE").is_none() {
+        println!("cargo:rustc-env=KUDU_PB_KUDU_VERSION={}", KUDU_VERSION);
+        return;
+    }
+
+    let proto_dir = match env::var_os("KUDU_PROTO_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => match env::var_os("KUDU_HOME") {
+            Some(home) => PathBuf::from(home).join("src"),
+            None => panic!("the `regenerate` feature requires KUDU_PROTO_DIR or KUDU_HOME to be set \
+                            to a Kudu source checkout (the checked-in sources track Kudu {})",
+                           KUDU_VERSION),
+        },
+    };
+    let version = checkout_version(&proto_dir);
+    println!("cargo:rustc-env=KUDU_PB_KUDU_VERSION={}", version);
+
+    let protoc = env::var_os("PROTOC").unwrap_or("protoc".into());
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+    let admin_protos = if env::var_os("CARGO_FEATURE_ADMIN").is_some() { ADMIN_PROTOS } else { &[] };
+    for &(proto, module) in PROTOS.iter().chain(admin_protos) {
+        let path = proto_dir.join(proto);
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        // Each proto is compiled into a scratch directory, since the two metadata protos produce
+        // identically named output files.
+        let scratch = out_dir.join("scratch");
+        let _ = fs::remove_dir_all(&scratch);
+        fs::create_dir_all(&scratch).unwrap();
+
+        let mut command = Command::new(&protoc);
+        command.arg("--rust_out").arg(&scratch).arg("--proto_path").arg(&proto_dir);
+        if let Some(include) = env::var_os("PROTO_INCLUDE") {
+            command.arg("--proto_path").arg(include);
+        }
+        command.arg(&path);
+
+        let status = command.status().unwrap_or_else(|error| {
+            panic!("failed to execute protoc ({:?}): {}", protoc, error)
+        });
+        if !status.success() {
+            panic!("protoc failed to compile {}: {}", path.display(), status);
+        }
+
+        let default_module = Path::new(proto).file_stem().unwrap().to_str().unwrap();
+        let module = module.unwrap_or(default_module);
+        let generated = scratch.join(format!("{}.rs", default_module));
+        postprocess(&generated, &out_dir.join(format!("{}.rs", module)), module);
+    }
+}
+
+/// Returns the Kudu release of the source checkout containing the proto directory, as recorded in
+/// its `version.txt`. Panics if the release is older than the pinned release.
+fn checkout_version(proto_dir: &Path) -> String {
+    let path = proto_dir.parent().unwrap_or(proto_dir).join("version.txt");
+    println!("cargo:rerun-if-changed={}", path.display());
+    let mut version = String::new();
+    if let Err(error) = File::open(&path).and_then(|mut file| file.read_to_string(&mut version)) {
+        panic!("unable to read the Kudu release of the source checkout from {}: {}",
+               path.display(), error);
+    }
+    let version = version.trim().to_owned();
+    if parse_version(&version) < parse_version(KUDU_VERSION) {
+        panic!("the Kudu source checkout is release {}, but regenerating requires Kudu {} or later",
+               version, KUDU_VERSION);
+    }
+    if parse_version(&version) != parse_version(KUDU_VERSION) {
+        println!("cargo:warning=regenerating Protobuf definitions from Kudu {} (pinned release: {})",
+                 version, KUDU_VERSION);
+    }
+    version
+}
+
+/// Parses the numeric components of a release, ignoring any suffix such as `-SNAPSHOT`.
+fn parse_version(version: &str) -> Vec<u32> {
+    version.split('-')
+           .next()
+           .unwrap()
+           .split('.')
+           .map(|part| part.parse().unwrap_or(0))
+           .collect()
+}
+
+/// Copies a generated module into place, stripping the inner attributes (which are not allowed in
+/// `include!`d files) and applying the module path rewrites.
+fn postprocess(src: &Path, dst: &Path, module: &str) {
+    let mut contents = String::new();
+    File::open(src).unwrap().read_to_string(&mut contents).unwrap();
+
+    let mut output = String::with_capacity(contents.len());
+    for line in contents.lines() {
+        if line.starts_with("#![") {
+            continue;
+        }
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    for &(target, from, to) in REWRITES {
+        if target == module {
+            output = output.replace(from, to);
+        }
+    }
+
+    File::create(dst).unwrap().write_all(output.as_bytes()).unwrap();
+}
@@ -1,14 +1,47 @@
+//! Protobuf message definitions for the Kudu RPC protocol.
+//!
+//! The modules are generated from the Kudu `.proto` files by `rust-protobuf`. The checked-in
+//! sources track the Kudu release pinned in `build.rs`; enable the `regenerate` feature to compile
+//! the definitions from a Kudu source checkout at build time instead. The `admin` feature
+//! additionally compiles the `tserver_admin` module, which requires a Kudu 1.12 or later checkout.
+
 extern crate protobuf;
 
-pub mod client;
-pub mod common;
-pub mod consensus_metadata;
-pub mod fs;
-pub mod master;
-pub mod opid;
-pub mod rpc_header;
-pub mod tablet;
-pub mod tablet_metadata;
-pub mod tserver;
-pub mod tserver_service;
-pub mod wire_protocol;
+/// The Kudu release the Protobuf definitions were generated from: the pinned release of the
+/// checked-in sources, or the release of the source checkout when the `regenerate` feature is
+/// enabled.
+pub const KUDU_VERSION: &'static str = env!("KUDU_PB_KUDU_VERSION");
+
+#[cfg(not(feature = "regenerate"))] pub mod client;
+#[cfg(not(feature = "regenerate"))] pub mod common;
+#[cfg(not(feature = "regenerate"))] pub mod consensus_metadata;
+#[cfg(not(feature = "regenerate"))] pub mod fs;
+#[cfg(not(feature = "regenerate"))] pub mod master;
+#[cfg(not(feature = "regenerate"))] pub mod opid;
+#[cfg(not(feature = "regenerate"))] pub mod rpc_header;
+#[cfg(not(feature = "regenerate"))] pub mod tablet;
+#[cfg(not(feature = "regenerate"))] pub mod tablet_metadata;
+#[cfg(not(feature = "regenerate"))] pub mod tserver;
+#[cfg(not(feature = "regenerate"))] pub mod tserver_service;
+#[cfg(not(feature = "regenerate"))] pub mod wire_protocol;
+
+#[cfg(feature = "regenerate")]
+macro_rules! generated {
+    ($($module:ident),*) => {
+        $(
+            #[allow(box_pointers, dead_code, missing_docs, non_camel_case_types, non_snake_case,
+                    non_upper_case_globals, trivial_casts, unsafe_code, unused_imports,
+                    unused_results)]
+            pub mod $module {
+                include!(concat!(env!("OUT_DIR"), "/", stringify!($module), ".rs"));
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "regenerate")]
+generated!(client, common, consensus_metadata, fs, master, opid, rpc_header, tablet,
+           tablet_metadata, tserver, tserver_service, wire_protocol);
+
+#[cfg(feature = "admin")]
+generated!(tserver_admin);
@@ -0,0 +1,143 @@
+//! Cluster administration operations, available with the `admin` feature.
+//!
+//! These operations are intended for operators recovering or maintaining a cluster, and can lose
+//! data when misused.
+
+use std::net::SocketAddr;
+use std::sync::mpsc::sync_channel;
+use std::time::Instant;
+
+use kudu_pb::master::ReplaceTabletRequestPB;
+use kudu_pb::tablet_metadata::TabletDataState;
+use kudu_pb::tserver_admin::{DeleteTabletRequestPB, QuiesceTabletServerRequestPB};
+
+use Client;
+use Error;
+use Result;
+use TabletId;
+use TabletServerError;
+use TabletServerId;
+use dns;
+use rpc::tablet_server_admin::TabletServerAdminService;
+
+/// The quiescing state of a tablet server.
+///
+/// A quiescing tablet server transfers away its tablet leaderships and stops accepting new scans,
+/// so that it can be restarted without interrupting clients once its leaders and scanners drain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuiescingStatus {
+    is_quiescing: bool,
+    num_leaders: u32,
+    num_active_scanners: u32,
+}
+
+impl QuiescingStatus {
+
+    /// Returns `true` if the tablet server is quiescing.
+    pub fn is_quiescing(&self) -> bool {
+        self.is_quiescing
+    }
+
+    /// Returns the number of tablet replicas the tablet server leads.
+    pub fn num_leaders(&self) -> u32 {
+        self.num_leaders
+    }
+
+    /// Returns the number of scanners open on the tablet server.
+    pub fn num_active_scanners(&self) -> u32 {
+        self.num_active_scanners
+    }
+
+    /// Returns `true` if the tablet server is quiescing, and no longer leads any tablets or serves
+    /// any scans.
+    pub fn is_quiesced(&self) -> bool {
+        self.is_quiescing && self.num_leaders == 0 && self.num_active_scanners == 0
+    }
+}
+
+impl Client {
+
+    /// Replaces a tablet with a new, empty tablet covering the same partition, and returns the ID
+    /// of the replacement.
+    ///
+    /// This is a last resort for recovering a table with a tablet which has permanently lost a
+    /// majority of its replicas. All of the tablet's data is lost.
+    pub fn replace_tablet(&self, tablet: &TabletId, deadline: Instant) -> Result<TabletId> {
+        let mut request = ReplaceTabletRequestPB::new();
+        request.set_tablet_id(tablet.to_string().into_bytes());
+
+        let (send, recv) = sync_channel(1);
+        self.master_proxy().replace_tablet(deadline, request, move |resp| send.send(resp).unwrap());
+        let resp = try!(recv.recv().unwrap());
+        TabletId::parse_bytes(resp.get_replacement_tablet_id())
+    }
+
+    /// Deletes a tablet replica from a tablet server, along with its data. The replica should
+    /// already have been removed from the tablet's Raft configuration, for instance because the
+    /// tablet server was evicted while it was down.
+    ///
+    /// The reason is recorded in the tablet server's log.
+    pub fn delete_tablet_replica<S>(&self,
+                                    tablet_server: &TabletServerId,
+                                    tablet: &TabletId,
+                                    reason: S,
+                                    deadline: Instant)
+                                    -> Result<()>
+    where S: Into<String> {
+        let addr = try!(self.tablet_server_addr(tablet_server, deadline));
+        let mut request = DeleteTabletRequestPB::new();
+        request.set_dest_uuid(tablet_server.to_string().into_bytes());
+        request.set_tablet_id(tablet.to_string().into_bytes());
+        request.set_reason(reason.into());
+        request.set_delete_type(TabletDataState::TABLET_DATA_DELETED);
+
+        let (send, recv) = sync_channel(1);
+        self.messenger().delete_tablet(addr, deadline, request, move |resp| send.send(resp).unwrap());
+        let mut resp = try!(recv.recv().unwrap());
+        if resp.has_error() {
+            return Err(Error::TabletServer(TabletServerError::from(resp.take_error())));
+        }
+        Ok(())
+    }
+
+    /// Starts or stops quiescing a tablet server, and returns its quiescing status.
+    ///
+    /// Quiescing is typically started before a rolling restart, and the tablet server restarted
+    /// once `QuiescingStatus::is_quiesced` returns `true`. Quiescing a tablet server which is
+    /// already quiescing only retrieves its status.
+    pub fn set_tablet_server_quiescing(&self,
+                                       tablet_server: &TabletServerId,
+                                       quiescing: bool,
+                                       deadline: Instant)
+                                       -> Result<QuiescingStatus> {
+        let addr = try!(self.tablet_server_addr(tablet_server, deadline));
+        let mut request = QuiesceTabletServerRequestPB::new();
+        request.set_quiesce(quiescing);
+        request.set_return_stats(true);
+
+        let (send, recv) = sync_channel(1);
+        self.messenger().quiesce(addr, deadline, request, move |resp| send.send(resp).unwrap());
+        let mut resp = try!(recv.recv().unwrap());
+        if resp.has_error() {
+            return Err(Error::TabletServer(TabletServerError::from(resp.take_error())));
+        }
+        Ok(QuiescingStatus {
+            is_quiescing: resp.get_is_quiescing(),
+            num_leaders: resp.get_num_leaders() as u32,
+            num_active_scanners: resp.get_num_active_scanners() as u32,
+        })
+    }
+
+    /// Looks up the RPC address of a tablet server.
+    fn tablet_server_addr(&self, id: &TabletServerId, deadline: Instant) -> Result<SocketAddr> {
+        let tablet_servers = try!(self.list_tablet_servers(deadline));
+        let tablet_server = match tablet_servers.iter().find(|tablet_server| tablet_server.id() == id) {
+            Some(tablet_server) => tablet_server,
+            None => return Err(Error::InvalidArgument(format!("unknown tablet server {}", id))),
+        };
+        match dns::resolve_hostports(self.master_proxy().resolver(), tablet_server.rpc_addrs()).first() {
+            Some(&addr) => Ok(addr),
+            None => Err(Error::InvalidArgument(format!("unable to resolve tablet server {}", id))),
+        }
+    }
+}
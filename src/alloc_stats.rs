@@ -0,0 +1,169 @@
+//! Allocation counting for the RPC serialization and deserialization paths, available with the
+//! `alloc-stats` feature.
+//!
+//! Allocations are only counted when the application installs `CountingAllocator` as its global
+//! allocator:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: kudu::CountingAllocator = kudu::CountingAllocator;
+//! ```
+//!
+//! The counts are then available per RPC method from `RpcMetrics::allocation_stats`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    /// The number of allocations and allocated bytes of the current thread.
+    static ALLOCATIONS: Cell<(usize, usize)> = Cell::new((0, 0));
+}
+
+/// A global allocator which delegates to the system allocator, and counts the allocations of each
+/// thread.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+fn record(bytes: usize) {
+    // The counter is unavailable while the thread is being torn down.
+    let _ = ALLOCATIONS.try_with(|allocations| {
+        let (count, total) = allocations.get();
+        allocations.set((count + 1, total + bytes));
+    });
+}
+
+/// The allocations made by the current thread so far.
+fn current() -> (usize, usize) {
+    ALLOCATIONS.try_with(Cell::get).unwrap_or((0, 0))
+}
+
+/// Runs the function, and returns its result along with the number of allocations and allocated
+/// bytes it made on the current thread. Reallocations count as allocations of their new size.
+pub fn measure<F, T>(f: F) -> (T, usize, usize) where F: FnOnce() -> T {
+    let (count, bytes) = current();
+    let result = f();
+    let (end_count, end_bytes) = current();
+    (result, end_count - count, end_bytes - bytes)
+}
+
+/// The allocations made while serializing requests and deserializing responses of an RPC method.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AllocationStats {
+    service_name: &'static str,
+    method_name: &'static str,
+    requests: usize,
+    request_allocations: usize,
+    request_bytes: usize,
+    responses: usize,
+    response_allocations: usize,
+    response_bytes: usize,
+}
+
+impl AllocationStats {
+
+    #[doc(hidden)]
+    pub fn new(service_name: &'static str, method_name: &'static str) -> AllocationStats {
+        AllocationStats {
+            service_name: service_name,
+            method_name: method_name,
+            ..AllocationStats::default()
+        }
+    }
+
+    pub fn service_name(&self) -> &'static str {
+        self.service_name
+    }
+
+    pub fn method_name(&self) -> &'static str {
+        self.method_name
+    }
+
+    /// Returns the number of requests which have been serialized into a connection's send buffer.
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    /// Returns the number of allocations made while serializing requests.
+    pub fn request_allocations(&self) -> usize {
+        self.request_allocations
+    }
+
+    /// Returns the number of bytes allocated while serializing requests.
+    pub fn request_bytes(&self) -> usize {
+        self.request_bytes
+    }
+
+    /// Returns the number of responses which have been deserialized.
+    pub fn responses(&self) -> usize {
+        self.responses
+    }
+
+    /// Returns the number of allocations made while deserializing responses and their sidecars.
+    pub fn response_allocations(&self) -> usize {
+        self.response_allocations
+    }
+
+    /// Returns the number of bytes allocated while deserializing responses and their sidecars.
+    pub fn response_bytes(&self) -> usize {
+        self.response_bytes
+    }
+
+    #[doc(hidden)]
+    pub fn record_request(&mut self, allocations: usize, bytes: usize) {
+        self.requests += 1;
+        self.request_allocations += allocations;
+        self.request_bytes += bytes;
+    }
+
+    #[doc(hidden)]
+    pub fn record_response(&mut self, allocations: usize, bytes: usize) {
+        self.responses += 1;
+        self.response_allocations += allocations;
+        self.response_bytes += bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_allocation_stats() {
+        let mut stats = AllocationStats::new("kudu.master.MasterService", "Ping");
+        stats.record_request(2, 100);
+        stats.record_request(1, 50);
+        stats.record_response(3, 10);
+        assert_eq!(2, stats.requests());
+        assert_eq!(3, stats.request_allocations());
+        assert_eq!(150, stats.request_bytes());
+        assert_eq!(1, stats.responses());
+        assert_eq!((3, 10), (stats.response_allocations(), stats.response_bytes()));
+
+        // Without the counting allocator installed, nothing is counted.
+        let (value, allocations, bytes) = measure(|| vec![0u8; 16]);
+        assert_eq!(16, value.len());
+        assert_eq!((0, 0), (allocations, bytes));
+    }
+}
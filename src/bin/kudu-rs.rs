@@ -0,0 +1,443 @@
+//! A command line tool for basic interaction with a Kudu cluster.
+//!
+//! Built only when the `cli` feature is enabled:
+//!
+//! ```bash
+//! cargo run --features cli --bin kudu-rs -- --masters 127.0.0.1:7051 list-tables
+//! ```
+
+extern crate kudu;
+
+use std::cmp::Ordering;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use kudu::{
+    BulkLoaderConfig,
+    Client,
+    ClientConfig,
+    Column,
+    CsvWriter,
+    DataType,
+    JsonWriter,
+    Row,
+    SchemaBuilder,
+    TableBuilder,
+};
+
+const USAGE: &'static str = "\
+Usage: kudu-rs [--masters <addrs>] [--timeout <secs>] <command> [<args>...]
+
+Commands:
+    list-tables
+    describe-table <table>
+    create-table <table> <ddl> [--hash <columns>:<buckets>]... [--range <columns>] [--replicas <n>]
+    scan <table> [--columns <columns>] [--where <predicate>]... [--format csv|json]
+    insert <table> <csv-file>
+
+Options:
+    --masters <addrs>   Comma-separated list of master addresses [default: 127.0.0.1:7051].
+    --timeout <secs>    Operation timeout in seconds [default: 30].
+
+The create-table DDL is a comma separated list of column definitions followed by the primary key,
+for example: 'key int32 not null, value string, primary key (key)'.
+
+Scan predicates take the form '<column><op><value>', where <op> is one of =, !=, <, <=, >, or >=.
+Predicates are evaluated by the client. Timestamp values are in microseconds since the Unix epoch.
+
+The insert CSV file must begin with a header line naming the columns. Empty fields are null.";
+
+type CliResult<T> = Result<T, String>;
+
+fn main() {
+    if let Err(error) = run(env::args().skip(1).collect()) {
+        let _ = writeln!(io::stderr(), "error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run(args: Vec<String>) -> CliResult<()> {
+    let mut masters = "127.0.0.1:7051".to_owned();
+    let mut timeout = Duration::from_secs(30);
+    let mut command = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--masters" => masters = try!(next_arg(&mut args, "--masters")),
+            "--timeout" => {
+                let secs = try!(next_arg(&mut args, "--timeout"));
+                timeout = Duration::from_secs(try!(parse(&secs)));
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            },
+            _ => {
+                command = Some(arg.clone());
+                break;
+            },
+        }
+    }
+    let command = try!(command.ok_or(format!("missing command\n\n{}", USAGE)));
+    let args = args.collect::<Vec<_>>();
+
+    let client = Client::new(ClientConfig::new(try!(resolve_masters(&masters))));
+    let deadline = Instant::now() + timeout;
+
+    match &command[..] {
+        "list-tables" => list_tables(&client, deadline),
+        "describe-table" => describe_table(&client, &args, deadline),
+        "create-table" => create_table(&client, &args, deadline),
+        "scan" => scan(&client, &args, deadline),
+        "insert" => insert(&client, &args, deadline),
+        _ => Err(format!("unknown command '{}'\n\n{}", command, USAGE)),
+    }
+}
+
+fn list_tables(client: &Client, deadline: Instant) -> CliResult<()> {
+    let mut tables = try!(client.list_tables(deadline).map_err(|e| e.to_string()));
+    tables.sort();
+    for (name, id) in tables {
+        println!("{}\t{}", name, id);
+    }
+    Ok(())
+}
+
+fn describe_table(client: &Client, args: &[String], deadline: Instant) -> CliResult<()> {
+    let name = try!(args.get(0).ok_or("missing table name".to_owned()));
+    let table = try!(client.open_table(&name[..], deadline).map_err(|e| e.to_string()));
+    let schema = table.schema();
+    println!("table: {} ({})", table.name(), table.id());
+    println!("replicas: {}", table.num_replicas());
+    println!("columns:");
+    for (idx, column) in schema.columns().iter().enumerate() {
+        println!("    {} {:?}{}{}",
+                 column.name(),
+                 column.data_type(),
+                 if column.is_nullable() { "" } else { " NOT NULL" },
+                 if idx < schema.num_primary_key_columns() { " PRIMARY KEY" } else { "" });
+    }
+    println!("partitioning: {}", table.partition_schema().display(schema));
+
+    // Each range partition is split into a tablet per combination of hash buckets.
+    let tablets = try!(table.list_tablets(deadline).map_err(|e| e.to_string()));
+    println!("tablets:");
+    for tablet in tablets {
+        println!("    {} {}", tablet.id(), tablet.partition());
+    }
+    Ok(())
+}
+
+fn create_table(client: &Client, args: &[String], deadline: Instant) -> CliResult<()> {
+    if args.len() < 2 {
+        return Err("create-table requires a table name and DDL".to_owned());
+    }
+    let schema = try!(parse_ddl(&args[1]));
+    let mut builder = TableBuilder::new(&args[0][..], schema);
+
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        let value = try!(options.next().ok_or(format!("missing value for {}", option)));
+        match &option[..] {
+            "--hash" => {
+                let mut parts = value.rsplitn(2, ':');
+                let buckets = try!(parse(parts.next().unwrap()));
+                let columns = try!(parts.next().ok_or(format!("invalid hash partitioning: {}", value)));
+                builder.add_hash_partitions(split_list(columns), buckets);
+            },
+            "--range" => { builder.set_range_partition_columns(split_list(value)); },
+            "--replicas" => builder.set_num_replicas(try!(parse(value))),
+            _ => return Err(format!("unknown create-table option '{}'", option)),
+        }
+    }
+
+    let id = try!(client.create_table(builder, deadline).map_err(|e| e.to_string()));
+    try!(client.wait_for_table_creation_by_id(&id, deadline).map_err(|e| e.to_string()));
+    println!("created table {} ({})", args[0], id);
+    Ok(())
+}
+
+fn scan(client: &Client, args: &[String], deadline: Instant) -> CliResult<()> {
+    let name = try!(args.get(0).ok_or("missing table name".to_owned()));
+    let table = try!(client.open_table(&name[..], deadline).map_err(|e| e.to_string()));
+
+    let mut builder = table.scan_builder();
+    let mut predicates = Vec::new();
+    let mut json = false;
+    let mut options = args[1..].iter();
+    while let Some(option) = options.next() {
+        let value = try!(options.next().ok_or(format!("missing value for {}", option)));
+        match &option[..] {
+            "--columns" => { builder.set_projection(split_list(value)); },
+            "--where" => predicates.push(value.clone()),
+            "--format" if value == "csv" => json = false,
+            "--format" if value == "json" => json = true,
+            _ => return Err(format!("invalid scan option '{} {}'", option, value)),
+        }
+    }
+    let mut scanner = try!(builder.build().map_err(|e| e.to_string()));
+    let predicates = try!(predicates.iter()
+                                    .map(|predicate| Predicate::parse(predicate))
+                                    .collect::<CliResult<Vec<_>>>());
+
+    let mut csv = CsvWriter::new(io::stdout());
+    let mut json_writer = JsonWriter::new(io::stdout());
+    while let Some(batch) = try!(scanner.next_batch(deadline).map_err(|e| e.to_string())) {
+        for row in batch.rows() {
+            let mut matches = true;
+            for predicate in &predicates {
+                if !try!(predicate.matches(row)) {
+                    matches = false;
+                    break;
+                }
+            }
+            if !matches { continue; }
+            if json {
+                try!(json_writer.write_row(row).map_err(|e| e.to_string()));
+            } else {
+                try!(csv.write_row(row).map_err(|e| e.to_string()));
+            }
+        }
+    }
+    try!(csv.flush().map_err(|e| e.to_string()));
+    try!(json_writer.flush().map_err(|e| e.to_string()));
+    Ok(())
+}
+
+fn insert(client: &Client, args: &[String], deadline: Instant) -> CliResult<()> {
+    if args.len() < 2 {
+        return Err("insert requires a table name and CSV file".to_owned());
+    }
+    let table = try!(client.open_table(&args[0][..], deadline).map_err(|e| e.to_string()));
+    let file = try!(File::open(&args[1]).map_err(|e| format!("unable to open {}: {}", args[1], e)));
+    let mut lines = BufReader::new(file).lines();
+
+    let header = match lines.next() {
+        Some(line) => try!(parse_csv_line(&try!(line.map_err(|e| e.to_string())))),
+        None => return Err(format!("{} is empty", args[1])),
+    };
+    let mut indices = Vec::with_capacity(header.len());
+    for column in &header {
+        indices.push(try!(table.schema().column_index(column)
+                                        .ok_or(format!("unknown column '{}'", column))));
+    }
+
+    let mut loader = table.new_bulk_loader(BulkLoaderConfig::default());
+    for (line_number, line) in lines.enumerate() {
+        let line = try!(line.map_err(|e| e.to_string()));
+        if line.is_empty() { continue; }
+        let fields = try!(parse_csv_line(&line));
+        if fields.len() != indices.len() {
+            return Err(format!("line {}: expected {} fields, found {}",
+                               line_number + 2, indices.len(), fields.len()));
+        }
+        let mut row = table.schema().new_row();
+        for (&idx, field) in indices.iter().zip(fields.iter()) {
+            try!(set_from_str(&mut row, idx, field)
+                    .map_err(|e| format!("line {}: {}", line_number + 2, e)));
+        }
+        try!(loader.insert(row).map_err(|e| e.to_string()));
+    }
+
+    let stats = try!(loader.finish().map_err(|e| e.to_string()));
+    for &(ref row, ref error) in stats.errors() {
+        let _ = writeln!(io::stderr(), "failed to insert {:?}: {}", row, error);
+    }
+    println!("inserted {} rows ({} failed) in {:?} ({:.0} rows/s)",
+             stats.successful_operations(), stats.failed_operations(),
+             stats.elapsed(), stats.rows_per_second());
+    if stats.failed_operations() > 0 {
+        return Err(format!("{} rows failed to insert", stats.failed_operations()));
+    }
+    Ok(())
+}
+
+/// Parses a DDL string of the form `<column> <type> [not null | null], ..., primary key (<columns>)`.
+fn parse_ddl(ddl: &str) -> CliResult<kudu::Schema> {
+    let mut builder = SchemaBuilder::new();
+    let mut primary_key = None;
+
+    // ASCII lowercasing preserves byte offsets, so positions in `lower` are valid in `ddl`.
+    let lower = ddl.to_ascii_lowercase();
+    let (columns, key) = match lower.find("primary key") {
+        Some(pos) => (&ddl[..pos], Some(&ddl[pos + "primary key".len()..])),
+        None => (ddl, None),
+    };
+    if let Some(key) = key {
+        let key = key.trim().trim_left_matches('(').trim_right_matches(')');
+        primary_key = Some(split_list(key));
+    }
+
+    for definition in columns.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let tokens = definition.split_whitespace().collect::<Vec<_>>();
+        if tokens.len() < 2 {
+            return Err(format!("invalid column definition '{}'", definition));
+        }
+        let mut column = Column::builder(tokens[0], try!(parse_data_type(tokens[1])));
+        match &tokens[2..].join(" ").to_lowercase()[..] {
+            "" | "null" => { column.set_nullable_by_ref(); },
+            "not null" => { column.set_not_null_by_ref(); },
+            other => return Err(format!("invalid column option '{}'", other)),
+        }
+        builder.add_column_by_ref(column);
+    }
+
+    let primary_key = try!(primary_key.ok_or("DDL is missing a primary key".to_owned()));
+    builder.set_primary_key_by_ref(primary_key);
+    builder.build().map_err(|e| e.to_string())
+}
+
+fn parse_data_type(data_type: &str) -> CliResult<DataType> {
+    Ok(match &data_type.to_lowercase()[..] {
+        "bool" => DataType::Bool,
+        "int8" => DataType::Int8,
+        "int16" => DataType::Int16,
+        "int32" => DataType::Int32,
+        "int64" => DataType::Int64,
+        "timestamp" => DataType::Timestamp,
+        "float" => DataType::Float,
+        "double" => DataType::Double,
+        "binary" => DataType::Binary,
+        "string" => DataType::String,
+        _ => return Err(format!("unknown data type '{}'", data_type)),
+    })
+}
+
+/// A client-side scan predicate.
+struct Predicate {
+    column: String,
+    op: &'static str,
+    value: String,
+}
+
+impl Predicate {
+    fn parse(predicate: &str) -> CliResult<Predicate> {
+        for &op in &["!=", "<=", ">=", "=", "<", ">"] {
+            if let Some(pos) = predicate.find(op) {
+                return Ok(Predicate {
+                    column: predicate[..pos].trim().to_owned(),
+                    op: op,
+                    value: predicate[pos + op.len()..].trim().to_owned(),
+                });
+            }
+        }
+        Err(format!("invalid predicate '{}'", predicate))
+    }
+
+    fn matches(&self, row: &Row) -> CliResult<bool> {
+        let idx = try!(row.schema().column_index(&self.column)
+                                   .ok_or(format!("unknown column '{}'", self.column)));
+        if try!(row.is_null(idx).map_err(|e| e.to_string())) {
+            return Ok(false);
+        }
+        let ordering = try!(compare(row, idx, &self.value));
+        Ok(match self.op {
+            "=" => ordering == Ordering::Equal,
+            "!=" => ordering != Ordering::Equal,
+            "<" => ordering == Ordering::Less,
+            "<=" => ordering != Ordering::Greater,
+            ">" => ordering == Ordering::Greater,
+            ">=" => ordering != Ordering::Less,
+            _ => unreachable!(),
+        })
+    }
+}
+
+/// Compares the non-null cell at `idx` to a literal value.
+fn compare(row: &Row, idx: usize, value: &str) -> CliResult<Ordering> {
+    fn cmp<T: PartialOrd>(a: T, b: T) -> Ordering {
+        a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+    }
+    Ok(match row.schema().columns()[idx].data_type() {
+        DataType::Bool => cmp(try!(row.get::<bool>(idx).map_err(e)), try!(parse(value))),
+        DataType::Int8 => cmp(try!(row.get::<i8>(idx).map_err(e)), try!(parse(value))),
+        DataType::Int16 => cmp(try!(row.get::<i16>(idx).map_err(e)), try!(parse(value))),
+        DataType::Int32 => cmp(try!(row.get::<i32>(idx).map_err(e)), try!(parse(value))),
+        DataType::Int64 => cmp(try!(row.get::<i64>(idx).map_err(e)), try!(parse(value))),
+        DataType::Timestamp => cmp(try!(row.get::<SystemTime>(idx).map_err(e)),
+                                   try!(parse_timestamp(value))),
+        DataType::Float => cmp(try!(row.get::<f32>(idx).map_err(e)), try!(parse(value))),
+        DataType::Double => cmp(try!(row.get::<f64>(idx).map_err(e)), try!(parse(value))),
+        DataType::Binary => cmp(try!(row.get::<&[u8]>(idx).map_err(e)), value.as_bytes()),
+        DataType::String => cmp(try!(row.get::<&str>(idx).map_err(e)), value),
+    })
+}
+
+fn e(error: kudu::Error) -> String {
+    error.to_string()
+}
+
+/// Sets the cell at `idx` from its string representation. An empty string sets the cell to null.
+fn set_from_str(row: &mut Row, idx: usize, value: &str) -> CliResult<()> {
+    let result = if value.is_empty() {
+        row.set_null(idx)
+    } else {
+        match row.schema().columns()[idx].data_type() {
+            DataType::Bool => row.set::<bool>(idx, try!(parse(value))),
+            DataType::Int8 => row.set::<i8>(idx, try!(parse(value))),
+            DataType::Int16 => row.set::<i16>(idx, try!(parse(value))),
+            DataType::Int32 => row.set::<i32>(idx, try!(parse(value))),
+            DataType::Int64 => row.set::<i64>(idx, try!(parse(value))),
+            DataType::Timestamp => row.set(idx, try!(parse_timestamp(value))),
+            DataType::Float => row.set::<f32>(idx, try!(parse(value))),
+            DataType::Double => row.set::<f64>(idx, try!(parse(value))),
+            DataType::Binary => row.set(idx, value.as_bytes().to_owned()),
+            DataType::String => row.set(idx, value.to_owned()),
+        }
+    };
+    result.map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn parse_timestamp(value: &str) -> CliResult<SystemTime> {
+    let us: i64 = try!(parse(value));
+    let duration = Duration::new(us.abs() as u64 / 1000_000, (us.abs() as u64 % 1000_000) as u32 * 1000);
+    Ok(if us < 0 { UNIX_EPOCH - duration } else { UNIX_EPOCH + duration })
+}
+
+/// Splits a line of CSV into fields, handling quoted fields.
+fn parse_csv_line(line: &str) -> CliResult<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); field.push('"'); },
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::replace(&mut field, String::new())),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("unterminated quoted field in line '{}'", line));
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+fn resolve_masters(masters: &str) -> CliResult<Vec<SocketAddr>> {
+    let mut addrs = Vec::new();
+    for master in split_list(masters) {
+        let resolved = try!(master.to_socket_addrs()
+                                  .map_err(|e| format!("unable to resolve '{}': {}", master, e)));
+        addrs.extend(resolved);
+    }
+    Ok(addrs)
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',').map(str::trim).filter(|s| !s.is_empty()).map(ToOwned::to_owned).collect()
+}
+
+fn next_arg<I>(args: &mut I, option: &str) -> CliResult<String> where I: Iterator<Item=String> {
+    args.next().ok_or(format!("missing value for {}", option))
+}
+
+fn parse<T>(value: &str) -> CliResult<T> where T: std::str::FromStr {
+    value.parse().map_err(|_| format!("invalid value '{}'", value))
+}
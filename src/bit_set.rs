@@ -25,6 +25,11 @@ impl BitSet {
     pub fn data(&self) -> &[u8] {
         &*self.data
     }
+
+    /// Returns `true` if no bits are set.
+    pub fn is_empty(&self) -> bool {
+        self.data.iter().all(|&byte| byte == 0)
+    }
 }
 
 #[cfg(test)]
@@ -33,7 +38,13 @@ mod tests {
 
     #[test]
     fn test_bitset() {
-        let bitset = BitSet::with_capacity(16);
+        let mut bitset = BitSet::with_capacity(16);
         assert_eq!(bitset.get(0), false);
+        assert!(bitset.is_empty());
+        bitset.insert(9);
+        assert!(bitset.get(9));
+        assert!(!bitset.is_empty());
+        bitset.remove(9);
+        assert!(bitset.is_empty());
     }
 }
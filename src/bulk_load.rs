@@ -0,0 +1,406 @@
+use std::mem;
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, TryRecvError};
+use std::time::{Duration, Instant};
+
+use Error;
+use Event;
+use EventSet;
+use FlushStats;
+use OperationType;
+use Result;
+use Row;
+use Table;
+use Writer;
+use WriterConfig;
+use row::OperationEncoder;
+
+/// Capacity of the writer event channel. Failed operations which do not fit in the channel are
+/// counted, but not recorded.
+const EVENT_CHANNEL_CAPACITY: usize = 10 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct BulkLoaderConfig {
+    /// Maximum number of concurrent in-flight batches per tablet.
+    max_batches_per_tablet: u8,
+
+    /// Amount of buffered row data which triggers a flush.
+    flush_threshold: usize,
+
+    /// Maximum amount of time to wait for a batch to be written.
+    flush_timeout: Duration,
+
+    /// Maximum number of failed operations retained in `BulkLoadStats::errors`.
+    max_recorded_errors: usize,
+}
+
+impl BulkLoaderConfig {
+
+    pub fn max_batches_per_tablet(&self) -> u8 {
+        self.max_batches_per_tablet
+    }
+
+    /// Sets the maximum number of batches which may be in flight to a single tablet at once.
+    /// Batches to different tablets are always written in parallel.
+    ///
+    /// Defaults to 4. Must be at least 1.
+    pub fn set_max_batches_per_tablet(&mut self, max_batches_per_tablet: u8) -> &mut BulkLoaderConfig {
+        assert!(max_batches_per_tablet > 0, "max_batches_per_tablet must be at least 1");
+        self.max_batches_per_tablet = max_batches_per_tablet;
+        self
+    }
+
+    pub fn flush_threshold(&self) -> usize {
+        self.flush_threshold
+    }
+
+    /// Sets the amount of buffered row data which causes the loader to flush and wait for the
+    /// outstanding batches to complete. This bounds the memory held by the loader.
+    ///
+    /// Defaults to 64MiB.
+    pub fn set_flush_threshold(&mut self, flush_threshold: usize) -> &mut BulkLoaderConfig {
+        self.flush_threshold = flush_threshold;
+        self
+    }
+
+    pub fn flush_timeout(&self) -> Duration {
+        self.flush_timeout
+    }
+
+    /// Sets the maximum amount of time to wait for a batch to be written. Operations in batches
+    /// which do not complete in time fail with `Error::TimedOut`.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn set_flush_timeout(&mut self, flush_timeout: Duration) -> &mut BulkLoaderConfig {
+        self.flush_timeout = flush_timeout;
+        self
+    }
+
+    pub fn max_recorded_errors(&self) -> usize {
+        self.max_recorded_errors
+    }
+
+    /// Sets the maximum number of failed operations to retain. Failures beyond the limit are
+    /// counted, but the rows are discarded.
+    ///
+    /// Defaults to 1000.
+    pub fn set_max_recorded_errors(&mut self, max_recorded_errors: usize) -> &mut BulkLoaderConfig {
+        self.max_recorded_errors = max_recorded_errors;
+        self
+    }
+}
+
+impl Default for BulkLoaderConfig {
+    fn default() -> BulkLoaderConfig {
+        BulkLoaderConfig {
+            max_batches_per_tablet: 4,
+            flush_threshold: 64 * 1024 * 1024,
+            flush_timeout: Duration::from_secs(30),
+            max_recorded_errors: 1000,
+        }
+    }
+}
+
+/// Aggregate statistics about a bulk load.
+#[derive(Clone, Debug, Default)]
+pub struct BulkLoadStats {
+    successful_operations: usize,
+    failed_operations: usize,
+    data: usize,
+    elapsed: Duration,
+    errors: Vec<(Row, Error)>,
+}
+
+impl BulkLoadStats {
+
+    /// Returns the number of rows which have been successfully written.
+    pub fn successful_operations(&self) -> usize {
+        self.successful_operations
+    }
+
+    /// Returns the number of rows which failed to be written.
+    pub fn failed_operations(&self) -> usize {
+        self.failed_operations
+    }
+
+    /// Returns the amount of row data written, in bytes.
+    pub fn data(&self) -> usize {
+        self.data
+    }
+
+    /// Returns the time elapsed between creating the loader and the most recent flush.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Returns the write throughput in rows per second.
+    pub fn rows_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs() as f64 + self.elapsed.subsec_nanos() as f64 / 1e9;
+        if secs == 0.0 { 0.0 } else { self.successful_operations as f64 / secs }
+    }
+
+    /// Returns the failed rows along with the cause of the failure.
+    ///
+    /// At most `BulkLoaderConfig::max_recorded_errors` failures are retained.
+    pub fn errors(&self) -> &[(Row, Error)] {
+        &self.errors
+    }
+
+    fn record_flush(&mut self, flush: &FlushStats) {
+        self.successful_operations += flush.successful_operations();
+        self.failed_operations += flush.failed_operations();
+        self.data += flush.data();
+    }
+}
+
+/// Loads a high volume of rows into a table.
+///
+/// Rows are partitioned by tablet and written in batches, with up to
+/// `BulkLoaderConfig::max_batches_per_tablet` batches in flight to each tablet. The loader
+/// periodically flushes to bound the amount of buffered data, and retains the rows which fail to be
+/// written so that they can be inspected or retried.
+pub struct BulkLoader {
+    writer: Writer,
+    events: Receiver<Event>,
+    config: BulkLoaderConfig,
+    buffered_data: usize,
+    start: Instant,
+    stats: BulkLoadStats,
+    /// The failed operations of the in-progress `apply_all` call, if any.
+    call_errors: Option<Vec<(Row, Error)>>,
+}
+
+impl BulkLoader {
+
+    #[doc(hidden)]
+    pub fn new(table: Table, config: BulkLoaderConfig) -> BulkLoader {
+        let mut writer_config = WriterConfig::default();
+        let events = writer_config.event_channel_with_capacity(EVENT_CHANNEL_CAPACITY);
+        writer_config.set_event_set(EventSet::FailedOperations)
+                     .set_max_batches_per_tablet(config.max_batches_per_tablet)
+                     .set_flush_timeout(config.flush_timeout);
+        BulkLoader {
+            writer: Writer::new(table, writer_config),
+            events: events,
+            config: config,
+            buffered_data: 0,
+            start: Instant::now(),
+            stats: BulkLoadStats::default(),
+            call_errors: None,
+        }
+    }
+
+    pub fn insert(&mut self, row: Row) -> Result<()> {
+        self.apply(row, OperationType::Insert)
+    }
+
+    pub fn upsert(&mut self, row: Row) -> Result<()> {
+        self.apply(row, OperationType::Upsert)
+    }
+
+    /// Inserts every row yielded by the iterator, flushes, and returns the statistics of just these
+    /// rows. See `BulkLoader::apply_all`.
+    pub fn insert_all<I>(&mut self, rows: I) -> Result<BulkLoadStats> where I: IntoIterator<Item=Row> {
+        self.apply_all(rows, OperationType::Insert)
+    }
+
+    /// Upserts every row yielded by the iterator, flushes, and returns the statistics of just these
+    /// rows. See `BulkLoader::apply_all`.
+    pub fn upsert_all<I>(&mut self, rows: I) -> Result<BulkLoadStats> where I: IntoIterator<Item=Row> {
+        self.apply_all(rows, OperationType::Upsert)
+    }
+
+    /// Applies an operation for every row yielded by the iterator, flushing whenever the flush
+    /// threshold is exceeded, and then flushes the remaining rows. Returns the statistics of just
+    /// these rows, including the failed rows and their errors. The loader's cumulative statistics
+    /// are updated as well.
+    ///
+    /// Any operations buffered before the call are flushed along with the rows, and are counted in
+    /// the returned statistics.
+    pub fn apply_all<I>(&mut self, rows: I, op_type: OperationType) -> Result<BulkLoadStats>
+    where I: IntoIterator<Item=Row> {
+        let BulkLoadStats { successful_operations, failed_operations, data, .. } = self.stats;
+        let start = Instant::now();
+        self.call_errors = Some(Vec::new());
+        let result = self.apply_rows(rows, op_type);
+        let errors = self.call_errors.take().unwrap_or_default();
+        try!(result);
+        Ok(BulkLoadStats {
+            successful_operations: self.stats.successful_operations - successful_operations,
+            failed_operations: self.stats.failed_operations - failed_operations,
+            data: self.stats.data - data,
+            elapsed: start.elapsed(),
+            errors: errors,
+        })
+    }
+
+    fn apply_rows<I>(&mut self, rows: I, op_type: OperationType) -> Result<()>
+    where I: IntoIterator<Item=Row> {
+        for row in rows {
+            try!(self.apply(row, op_type));
+        }
+        self.flush()
+    }
+
+    /// Applies an operation to the loader. If the amount of buffered data exceeds the flush
+    /// threshold, the loader is flushed before the operation is applied.
+    pub fn apply(&mut self, row: Row, op_type: OperationType) -> Result<()> {
+        let (direct_len, indirect_len) = OperationEncoder::encoded_len(&row);
+        let len = direct_len + indirect_len;
+        if self.buffered_data > 0 && self.buffered_data + len > self.config.flush_threshold {
+            try!(self.flush());
+        }
+        self.buffered_data += len;
+        self.writer.apply(row, op_type);
+        self.drain_events();
+        Ok(())
+    }
+
+    /// Flushes buffered operations, and waits for them to complete.
+    pub fn flush(&mut self) -> Result<()> {
+        let (send, recv) = sync_channel(1);
+        self.writer.flush(move |stats| { let _ = send.send(stats); });
+        loop {
+            match recv.recv_timeout(Duration::from_millis(10)) {
+                Ok(flush) => {
+                    self.stats.record_flush(&flush);
+                    break;
+                },
+                Err(RecvTimeoutError::Timeout) => self.drain_events(),
+                Err(RecvTimeoutError::Disconnected) => return Err(Error::Cancelled),
+            }
+        }
+        self.drain_events();
+        self.buffered_data = 0;
+        self.stats.elapsed = self.start.elapsed();
+        Ok(())
+    }
+
+    /// Returns the statistics as of the most recent flush.
+    pub fn stats(&self) -> &BulkLoadStats {
+        &self.stats
+    }
+
+    /// Flushes all buffered operations, and returns the final statistics.
+    pub fn finish(mut self) -> Result<BulkLoadStats> {
+        try!(self.flush());
+        Ok(mem::replace(&mut self.stats, BulkLoadStats::default()))
+    }
+
+    fn drain_events(&mut self) {
+        loop {
+            match self.events.try_recv() {
+                Ok(Event::FailedOperation(row, _, error)) => {
+                    let max_recorded_errors = self.config.max_recorded_errors;
+                    if let Some(ref mut call_errors) = self.call_errors {
+                        if call_errors.len() < max_recorded_errors {
+                            call_errors.push((row.clone(), error.clone()));
+                        }
+                    }
+                    if self.stats.errors.len() < max_recorded_errors {
+                        self.stats.errors.push((row, error));
+                    }
+                },
+                Ok(_) => (),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use Client;
+    use ClientConfig;
+    use Column;
+    use DataType;
+    use SchemaBuilder;
+    use TableBuilder;
+    use mini_cluster::{MiniCluster, MiniClusterConfig};
+    use super::*;
+
+    use env_logger;
+
+    fn deadline() -> Instant {
+        Instant::now() + Duration::from_secs(5)
+    }
+
+    #[test]
+    fn bulk_load() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(&MiniClusterConfig::default());
+
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("val", DataType::Int32))
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut table_builder = TableBuilder::new("bulk_load", schema.clone());
+        table_builder.add_hash_partitions(vec!["key"], 4);
+        table_builder.set_num_replicas(1);
+
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+
+        let mut config = BulkLoaderConfig::default();
+        // Force multiple flushes.
+        config.set_flush_threshold(1024);
+        let mut loader = table.new_bulk_loader(config);
+
+        for i in 0..1000 {
+            let mut insert = table.schema().new_row();
+            insert.set_by_name::<i32>("key", i).unwrap();
+            insert.set_by_name::<i32>("val", i).unwrap();
+            loader.insert(insert).unwrap();
+        }
+
+        // Insert a duplicate row.
+        let mut insert = table.schema().new_row();
+        insert.set_by_name::<i32>("key", 0).unwrap();
+        insert.set_by_name::<i32>("val", 0).unwrap();
+        loader.insert(insert).unwrap();
+
+        let stats = loader.finish().unwrap();
+        assert_eq!(1000, stats.successful_operations());
+        assert_eq!(1, stats.failed_operations());
+        assert_eq!(1, stats.errors().len());
+        assert_eq!(Some(0), stats.errors()[0].0.get_by_name::<i32>("key").unwrap());
+
+        // Upsert over the loaded rows, and insert a row which collides with one of them.
+        let mut loader = table.new_bulk_loader(BulkLoaderConfig::default());
+        let schema = table.schema().clone();
+        let row = move |key: i32, val: i32| {
+            let mut row = schema.new_row();
+            row.set_by_name::<i32>("key", key).unwrap();
+            row.set_by_name::<i32>("val", val).unwrap();
+            row
+        };
+        let stats = loader.upsert_all((500..1500).map(|i| row(i, -i))).unwrap();
+        assert_eq!(1000, stats.successful_operations());
+        assert_eq!(0, stats.failed_operations());
+
+        let stats = loader.insert_all(vec![row(1500, 0), row(999, 0)]).unwrap();
+        assert_eq!(1, stats.successful_operations());
+        assert_eq!(1, stats.failed_operations());
+        assert_eq!(Some(999), stats.errors()[0].0.get_by_name::<i32>("key").unwrap());
+        assert_eq!(1001, loader.stats().successful_operations());
+
+        // Once the cumulative statistics retain the maximum number of errors, each call still
+        // returns its own failures.
+        let mut config = BulkLoaderConfig::default();
+        config.set_max_recorded_errors(1);
+        let mut loader = table.new_bulk_loader(config);
+        let stats = loader.insert_all(vec![row(0, 0)]).unwrap();
+        assert_eq!(1, stats.errors().len());
+        let stats = loader.insert_all(vec![row(1, 0)]).unwrap();
+        assert_eq!(1, stats.errors().len());
+        assert_eq!(Some(1), stats.errors()[0].0.get_by_name::<i32>("key").unwrap());
+        assert_eq!(1, loader.stats().errors().len());
+    }
+}
@@ -7,9 +7,10 @@ use std::net::{
 };
 use std::str;
 use std::sync::Arc;
-use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::{channel, sync_channel, Receiver};
 use std::thread;
 use std::time::{Duration, Instant};
+use std::vec;
 
 use kudu_pb::master::{
     DeleteTableRequestPB,
@@ -18,25 +19,43 @@ use kudu_pb::master::{
     IsCreateTableDoneRequestPB,
     ListMastersRequestPB,
     ListTablesRequestPB,
+    ListTablesResponsePB_TableInfo as TableInfoPB,
     ListTabletServersRequestPB,
+    PingRequestPB as MasterPingRequestPB,
     TableIdentifierPB,
 };
+use kudu_pb::tserver::PingRequestPB as TabletServerPingRequestPB;
 use parking_lot::Mutex;
 
+use Column;
 use Error;
+use HealthReport;
+use HostPort;
+use MasterErrorCode;
 use Result;
 use Schema;
 use TableId;
+use ServerFeature;
+use ServerHealth;
+use ServerKind;
+use ServerVersions;
 use TabletServer;
 use backoff::Backoff;
+use dns::{self, Resolver, SystemResolver};
 use master::Master;
 use master::MasterProxy;
+use master_watch::MasterWatch;
+use memory_budget::MemoryBudget;
 use meta_cache::MetaCache;
 use partition::PartitionSchema;
-use rpc::Messenger;
+use retry_budget::RetryBudget;
+use rpc::{CallRecord, ConnectionEvent, ConnectionOptions, Messenger, RequestTracker, RpcMetrics, Runtime, ThreadRuntime};
+use rpc::master::{alter_table_features, MasterService};
+use rpc::tablet_server::TabletServerService;
 use table::AlterTableBuilder;
 use table::Table;
 use table::TableBuilder;
+use validation;
 
 /// A Kudu database client.
 ///
@@ -49,29 +68,75 @@ pub struct Client {
     config: ClientConfig,
     meta_caches: Arc<Mutex<HashMap<TableId, MetaCache>>>,
     latest_observed_timestamp: Arc<Mutex<u64>>, // Replace with AtomicU64 when stable.
+    memory_budget: MemoryBudget,
+    request_tracker: RequestTracker,
+    server_versions: ServerVersions,
 }
 
 impl Client {
 
+    /// Returns a builder for configuring and creating a client.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
     /// Creates a new client with the provided configuration.
     pub fn new(config: ClientConfig) -> Client {
-        let messenger = Messenger::new().unwrap();
-        let master = MasterProxy::new(config.master_addresses(), messenger.clone());
+        Client::with_resolver(config, Arc::new(SystemResolver), &ThreadRuntime)
+    }
+
+    fn with_resolver(config: ClientConfig, resolver: Arc<Resolver>, runtime: &Runtime) -> Client {
+        let mut options = ConnectionOptions::default();
+        options.nodelay = config.nodelay();
+        options.max_buffered_rpcs = config.max_buffered_rpcs();
+        options.max_rpcs_in_flight = config.max_rpcs_in_flight();
+        options.max_total_rpcs_in_flight = config.max_total_rpcs_in_flight();
+        options.max_message_length = config.max_message_length();
+        options.slow_rpc_threshold = config.slow_rpc_threshold();
+        options.decode_threads = config.decode_threads();
+        options.decode_offload_threshold = config.decode_offload_threshold();
+        options.reactor_threads = config.reactor_threads();
+        options.connect_timeout = config.connect_timeout();
+        options.negotiation_timeout = config.negotiation_timeout();
+        options.strict_negotiation = config.strict_negotiation();
+        options.negotiation_transcript = config.negotiation_transcript();
+        options.retry_budget = config.retry_budget();
+        options.retry_budget_percent = config.retry_budget_percent();
+        options.circuit_breaker_threshold = config.circuit_breaker_threshold();
+        options.circuit_breaker_open_duration = config.circuit_breaker_open_duration();
+        options.call_trace_capacity = config.call_trace_capacity();
+        options.metrics_scope = config.metrics_scope().map(str::to_owned);
+        let messenger = Messenger::with_runtime(options, runtime).unwrap();
+        let server_versions = ServerVersions::new();
+        let master = MasterProxy::with_resolver(config.master_addresses(),
+                                                config.master_srv_record().map(str::to_owned),
+                                                resolver,
+                                                server_versions.clone(),
+                                                messenger.clone());
+        let memory_budget = MemoryBudget::new(config.memory_budget());
         Client {
             master: master,
             messenger: messenger,
             config: config,
             meta_caches: Arc::new(Mutex::new(HashMap::new())),
             latest_observed_timestamp: Arc::new(Mutex::new(0)),
+            memory_budget: memory_budget,
+            request_tracker: RequestTracker::new(),
+            server_versions: server_versions,
         }
     }
 
     /// Creates a new Kudu table with the schema and options specified by `builder`. Returns the
     /// new table's ID, or an error on failure.
     pub fn create_table(&self, builder: TableBuilder, deadline: Instant) -> Result<TableId> {
+        let (request, required_feature_flags) = try!(builder.into_pb());
+        try!(validation::validate_create_table(&request, self.config.hms_compatible_names()));
+        if !required_feature_flags.is_empty() {
+            try!(self.server_versions.check(ServerFeature::RangePartitionBounds));
+        }
+
         let (send, recv) = sync_channel(0);
-        self.master.create_table(deadline,
-                                 try!(builder.into_pb()),
+        self.master.create_table(deadline, request, required_feature_flags,
                                  move |resp| send.send(resp).unwrap());
         recv.recv().unwrap().and_then(|resp| {
             str::from_utf8(resp.get_table_id())
@@ -187,6 +252,10 @@ impl Client {
                       -> Result<TableId> {
         let AlterTableBuilder { error, mut pb, .. } = alter;
         try!(error);
+        try!(validation::validate_alter_table(&pb, self.config.hms_compatible_names()));
+        if !alter_table_features(&pb).is_empty() {
+            try!(self.server_versions.check(ServerFeature::AddDropRangePartitions));
+        }
         pb.set_table(table);
 
         let (send, recv) = sync_channel(0);
@@ -280,15 +349,25 @@ impl Client {
         self.do_list_tables(request, deadline)
     }
 
-    fn do_list_tables(&self, request: ListTablesRequestPB, deadline: Instant) -> Result<Vec<(String, TableId)>> {
+    /// Lists the tables with a name matching the filter, or all tables if the filter is `None`.
+    ///
+    /// Unlike `list_tables`, the tables are returned as an iterator which decodes each table as it
+    /// is consumed, so listing a cluster with a large number of tables does not require
+    /// materializing a second copy of the list.
+    pub fn tables(&self, name_filter: Option<&str>, deadline: Instant) -> Result<Tables> {
+        let mut request = ListTablesRequestPB::new();
+        if let Some(name_filter) = name_filter {
+            request.set_name_filter(name_filter.to_owned());
+        }
         let (send, recv) = sync_channel(0);
         self.master.list_tables(deadline, request, move |resp| send.send(resp).unwrap());
         let mut resp = try!(recv.recv().unwrap());
-        let mut tables = Vec::with_capacity(resp.get_tables().len());
-        for mut table in resp.take_tables().into_iter() {
-            tables.push((table.take_name(), try!(TableId::parse_bytes(table.get_id()))))
-        }
-        Ok(tables)
+        Ok(Tables { tables: resp.take_tables().into_vec().into_iter() })
+    }
+
+    fn do_list_tables(&self, request: ListTablesRequestPB, deadline: Instant) -> Result<Vec<(String, TableId)>> {
+        let name_filter = if request.has_name_filter() { Some(request.get_name_filter()) } else { None };
+        self.tables(name_filter, deadline).and_then(|tables| tables.collect())
     }
 
     pub fn list_masters(&self, deadline: Instant) -> Result<Vec<Master>> {
@@ -300,9 +379,21 @@ impl Client {
         for master in resp.take_masters().into_iter() {
             masters.push(try!(Master::from_pb(master)));
         }
+        self.server_versions.record_masters(masters.iter().map(Master::software_version));
         Ok(masters)
     }
 
+    /// Returns a stream of changes to the cluster's masters, for services which log or alert on
+    /// master failovers.
+    ///
+    /// The masters are listed in the background every interval, and as soon as the client's
+    /// connection to a master is reset. Each refresh is compared with the previous one, and
+    /// emits an event for every master which was added, removed, or changed role. The first
+    /// refresh reports every master as added.
+    pub fn watch_masters(&self, interval: Duration) -> MasterWatch {
+        MasterWatch::new(self.clone(), interval)
+    }
+
     pub fn list_tablet_servers(&self, deadline: Instant) -> Result<Vec<TabletServer>> {
         let request = ListTabletServersRequestPB::new();
         let (send, recv) = sync_channel(1);
@@ -312,9 +403,145 @@ impl Client {
         for server in resp.take_servers().into_iter() {
             tablet_servers.push(try!(TabletServer::from_pb(server)));
         }
+        self.server_versions.record_tablet_servers(tablet_servers.iter().map(TabletServer::software_version));
         Ok(tablet_servers)
     }
 
+    /// Records the software versions of the cluster's masters and tablet servers, so that
+    /// operations using features which the oldest servers do not support fail with
+    /// `Error::UnsupportedByServer` instead of being sent. See `ServerVersions`.
+    pub fn detect_server_versions(&self, deadline: Instant) -> Result<()> {
+        try!(self.list_masters(deadline));
+        try!(self.list_tablet_servers(deadline));
+        Ok(())
+    }
+
+    /// Returns `true` if a table with the provided name exists.
+    pub fn table_exists<S>(&self, table: S, deadline: Instant) -> Result<bool>
+    where S: Into<String> {
+        let mut request = GetTableSchemaRequestPB::new();
+        request.mut_table().set_table_name(table.into());
+
+        let (send, recv) = sync_channel(0);
+        self.master.get_table_schema(deadline, request, move |resp| send.send(resp).unwrap());
+        match recv.recv().unwrap() {
+            Ok(_) => Ok(true),
+            Err(Error::Master(ref error)) if error.code() == MasterErrorCode::TableNotFound => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Opens the table described by `builder`, creating it if it does not exist.
+    ///
+    /// If the table already exists, its schema must be compatible with the builder's schema:
+    /// every column in the builder's schema must exist in the table with the same type and
+    /// nullability, and the primary keys must match. Additional columns in the table are
+    /// permitted. If `add_missing_columns` is `true`, nullable columns which are missing from the
+    /// table are added to it; otherwise missing columns cause an `Error::InvalidArgument`. The
+    /// partitioning of an existing table is not checked.
+    pub fn ensure_table(&self,
+                        builder: TableBuilder,
+                        add_missing_columns: bool,
+                        deadline: Instant)
+                        -> Result<Table> {
+        let name = builder.name().to_owned();
+        let schema = builder.schema().clone();
+
+        if !try!(self.table_exists(name.clone(), deadline)) {
+            match self.create_table(builder, deadline) {
+                Ok(table_id) => {
+                    try!(self.wait_for_table_creation_by_id(&table_id, deadline));
+                    return self.open_table_by_id(&table_id, deadline);
+                },
+                // The table was concurrently created; fall through to check its schema.
+                Err(Error::Master(ref error)) if error.code() == MasterErrorCode::TableAlreadyPresent => (),
+                Err(error) => return Err(error),
+            }
+        }
+
+        let table = try!(self.open_table(name.clone(), deadline));
+        let missing_columns = try!(check_schema_compatibility(&schema, table.schema()));
+        if missing_columns.is_empty() {
+            return Ok(table);
+        }
+        if !add_missing_columns {
+            return Err(Error::InvalidArgument(format!(
+                "table {} is missing columns: {}",
+                name, missing_columns.iter().map(Column::name).collect::<Vec<_>>().join(", "))));
+        }
+        let mut alter = AlterTableBuilder::new();
+        for column in missing_columns {
+            if !column.is_nullable() {
+                return Err(Error::InvalidArgument(format!(
+                    "table {} is missing non-nullable column {}", name, column.name())));
+            }
+            alter.add_column_by_ref(column);
+        }
+        try!(self.alter_table_by_id(table.id(), alter, deadline));
+        try!(self.wait_for_table_alteration_by_id(table.id(), deadline));
+        self.open_table_by_id(table.id(), deadline)
+    }
+
+    /// Pings every known master and every registered tablet server concurrently, and reports the
+    /// health of each. Servers which do not respond before the deadline are reported as timed
+    /// out. The check is intended for readiness probes, so it should be given a short deadline.
+    pub fn health_check(&self, deadline: Instant) -> HealthReport {
+        let (send, recv) = channel();
+        let mut num_servers = 0;
+
+        let masters = self.master.masters();
+        for &addr in &masters {
+            let send = send.clone();
+            let start = Instant::now();
+            MasterService::ping(&self.messenger, addr, deadline, MasterPingRequestPB::new(),
+                                move |result| {
+                let result = result.map(|_| start.elapsed());
+                let _ = send.send(ServerHealth::new(ServerKind::Master, Some(addr), result));
+            });
+            num_servers += 1;
+        }
+
+        let mut unresolved = Vec::new();
+        let list_tablet_servers_error = match self.list_tablet_servers(deadline) {
+            Ok(tablet_servers) => {
+                for tablet_server in tablet_servers {
+                    let kind = ServerKind::TabletServer(tablet_server.id().clone());
+                    let addrs = dns::resolve_hostports(self.master.resolver(),
+                                                       tablet_server.rpc_addrs());
+                    let addr = match addrs.first() {
+                        Some(&addr) => addr,
+                        None => {
+                            let error = Error::InvalidArgument(
+                                format!("unable to resolve tablet server {}", tablet_server.id()));
+                            unresolved.push(ServerHealth::new(kind, None, Err(error)));
+                            continue;
+                        },
+                    };
+                    let send = send.clone();
+                    let start = Instant::now();
+                    TabletServerService::ping(&self.messenger, addr, deadline,
+                                              TabletServerPingRequestPB::new(),
+                                              move |result| {
+                        let result = result.map(|_| start.elapsed());
+                        let _ = send.send(ServerHealth::new(kind, Some(addr), result));
+                    });
+                    num_servers += 1;
+                }
+                None
+            },
+            Err(error) => Some(error),
+        };
+
+        let mut servers = recv.iter().take(num_servers).collect::<Vec<_>>();
+        servers.extend(unresolved);
+        // Order masters before tablet servers, otherwise preserving the completion order.
+        servers.sort_by_key(|server| match *server.kind() {
+            ServerKind::Master => 0,
+            ServerKind::TabletServer(_) => 1,
+        });
+        HealthReport::new(servers, list_tablet_servers_error)
+    }
+
     /// Returns an open table.
     pub fn open_table<S>(&self, table: S, deadline: Instant) -> Result<Table>
     where S: Into<String> {
@@ -350,16 +577,30 @@ impl Client {
                              .or_insert_with(|| MetaCache::new(id.clone(),
                                                                schema.primary_key_projection(),
                                                                partition_schema.clone(),
+                                                               self.config.meta_cache_ttl(),
                                                                self.master.clone()))
                              .clone();
 
-        Ok(Table::new(name, id, schema, partition_schema, resp.get_num_replicas() as u32, meta_cache, self.clone()))
+        Table::new(name, id, schema, partition_schema, &resp, meta_cache, self.clone())
+    }
+
+    /// Returns the client's configuration.
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
     }
 
+    /// Returns the latest timestamp observed by the client, or 0 if no timestamp has been observed.
+    ///
+    /// The timestamp is advanced automatically by scan and write responses, and is sent with each
+    /// scan and write request, so that the client observes its own writes. To extend this to
+    /// another client, pass the timestamp to the other client's `timestamp_observed`.
     pub fn latest_observed_timestamp(&self) -> u64 {
         *self.latest_observed_timestamp.lock()
     }
 
+    /// Advances the latest observed timestamp of the client to the provided timestamp, if it is
+    /// later. Subsequent scans and writes of the client are ordered after the operations which
+    /// produced the timestamp.
     pub fn timestamp_observed(&self, timestamp: u64) {
         let mut latest = self.latest_observed_timestamp.lock();
         if timestamp > *latest {
@@ -367,6 +608,62 @@ impl Client {
         }
     }
 
+    /// Returns the memory budget shared by the client's writers and scans.
+    pub fn memory_budget(&self) -> &MemoryBudget {
+        &self.memory_budget
+    }
+
+    #[doc(hidden)]
+    /// Returns the oldest server versions the client has seen.
+    pub fn server_versions(&self) -> &ServerVersions {
+        &self.server_versions
+    }
+
+    #[doc(hidden)]
+    pub fn request_tracker(&self) -> &RequestTracker {
+        &self.request_tracker
+    }
+
+    /// Returns the RPC metrics of the client.
+    pub fn rpc_metrics(&self) -> &RpcMetrics {
+        self.messenger.metrics()
+    }
+
+    /// Shuts down the client. Outstanding scans and writes fail with `Error::Shutdown`, as do
+    /// operations started afterwards, the client's connections are closed, and its RPC I/O event
+    /// loops exit. Clones of the client share its connections, and are shut down with it.
+    ///
+    /// If the event loops do not exit within the timeout, they are left to exit in the background
+    /// and `Error::TimedOut` is returned.
+    pub fn shutdown(&self, timeout: Duration) -> Result<()> {
+        let joined = self.messenger.shutdown(timeout);
+        self.master.shutdown();
+        if joined { Ok(()) } else { Err(Error::TimedOut) }
+    }
+
+    /// Returns `true` if the client has been shut down.
+    pub fn is_shut_down(&self) -> bool {
+        self.messenger.is_shut_down()
+    }
+
+    /// Returns the budget which limits the client's RPC retries.
+    pub fn retry_budget(&self) -> &RetryBudget {
+        self.messenger.retry_budget()
+    }
+
+    /// Returns a channel which receives connection lifecycle events for every server the client
+    /// connects to, such as connection resets. Events are dropped if the channel is full.
+    pub fn connection_events(&self, capacity: usize) -> Receiver<ConnectionEvent> {
+        self.messenger.connection_events(capacity)
+    }
+
+    /// Returns the most recently completed RPCs to the server, oldest first, with their call ID,
+    /// result, and latency. Useful for diagnosing a connection which has stopped making progress,
+    /// without enabling trace logging.
+    pub fn recent_calls(&self, addr: &SocketAddr) -> Vec<CallRecord> {
+        self.messenger.recent_calls(addr)
+    }
+
     #[doc(hidden)]
     pub fn master_proxy(&self) -> &MasterProxy {
         &self.master
@@ -382,6 +679,62 @@ impl Client {
     pub fn messenger(&self) -> &Messenger {
         &self.messenger
     }
+
+}
+
+/// An iterator over the tables returned by `Client::tables`, yielding the name and ID of each.
+pub struct Tables {
+    tables: vec::IntoIter<TableInfoPB>,
+}
+
+impl Iterator for Tables {
+    type Item = Result<(String, TableId)>;
+
+    fn next(&mut self) -> Option<Result<(String, TableId)>> {
+        self.tables.next().map(|mut table| {
+            TableId::parse_bytes(table.get_id()).map(|id| (table.take_name(), id))
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.tables.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Tables {}
+
+impl fmt::Debug for Tables {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tables {{ remaining: {} }}", self.tables.len())
+    }
+}
+
+/// Checks that the `actual` schema of a table is compatible with the `expected` schema, and returns
+/// the expected columns which are missing from the table.
+fn check_schema_compatibility(expected: &Schema, actual: &Schema) -> Result<Vec<Column>> {
+    let expected_key = expected.primary_key().iter().map(Column::name).collect::<Vec<_>>();
+    let actual_key = actual.primary_key().iter().map(Column::name).collect::<Vec<_>>();
+    if expected_key != actual_key {
+        return Err(Error::InvalidArgument(format!(
+            "primary key columns ({}) do not match the existing table ({})",
+            expected_key.join(", "), actual_key.join(", "))));
+    }
+
+    let mut missing_columns = Vec::new();
+    for column in expected.columns() {
+        match actual.column_by_name(column.name()) {
+            Some(existing) => {
+                if existing.data_type() != column.data_type() ||
+                   existing.is_nullable() != column.is_nullable() {
+                    return Err(Error::InvalidArgument(format!(
+                        "column {} is incompatible with the existing column: {:?} vs {:?}",
+                        column.name(), column, existing)));
+                }
+            },
+            None => missing_columns.push(column.clone()),
+        }
+    }
+    Ok(missing_columns)
 }
 
 impl fmt::Debug for Client {
@@ -395,12 +748,88 @@ impl fmt::Debug for Client {
 pub struct ClientConfig {
     /// A seed set of master addresses. Must contain at least one active master in the cluster.
     master_addresses: Vec<SocketAddr>,
+
+    /// RPCs taking longer than this threshold are logged at `WARN` level.
+    slow_rpc_threshold: Option<Duration>,
+
+    /// Default timeout for administrative operations, such as creating and altering tables.
+    admin_operation_timeout: Duration,
+
+    /// Default timeout for reads and writes.
+    operation_timeout: Duration,
+
+    /// Whether to disable Nagle's algorithm on connections.
+    nodelay: bool,
+
+    /// Maximum number of RPCs queued per connection.
+    max_buffered_rpcs: u32,
+
+    /// Maximum number of RPCs awaiting a response per connection.
+    max_rpcs_in_flight: u32,
+
+    /// Maximum number of RPCs in flight across all connections.
+    max_total_rpcs_in_flight: Option<u32>,
+
+    /// Maximum allowable RPC message length.
+    max_message_length: u32,
+
+    /// Amount of time table locations are cached before being refreshed from the master.
+    meta_cache_ttl: Option<Duration>,
+
+    /// Maximum number of bytes of buffered writes and prefetched scan batches.
+    memory_budget: Option<usize>,
+
+    /// Number of threads used to decode large RPC responses.
+    decode_threads: usize,
+
+    /// Minimum length of a response body to be decoded on a worker thread.
+    decode_offload_threshold: u32,
+
+    /// Number of RPC I/O threads.
+    reactor_threads: usize,
+
+    /// Maximum time to establish a TCP connection.
+    connect_timeout: Duration,
+
+    /// Maximum time to negotiate a connection.
+    negotiation_timeout: Duration,
+
+    /// Whether to reject negotiation responses with an unexpected call ID.
+    strict_negotiation: bool,
+
+    /// Whether failed negotiations report their transcript.
+    negotiation_transcript: bool,
+
+    /// Whether DDL is checked against the Hive Metastore's naming rules.
+    hms_compatible_names: bool,
+
+    /// Maximum number of retries held by the retry budget.
+    retry_budget: Option<u32>,
+
+    /// Percentage of a retry earned by each successful RPC.
+    retry_budget_percent: u32,
+
+    /// Consecutive connection failures after which a server's circuit breaker opens.
+    circuit_breaker_threshold: Option<u32>,
+
+    /// Time a circuit breaker stays open before probing the server.
+    circuit_breaker_open_duration: Duration,
+
+    /// Number of recently completed calls kept per server.
+    call_trace_capacity: usize,
+
+    /// DNS SRV record listing the masters.
+    master_srv_record: Option<String>,
+
+    /// Name of the client's metrics scope.
+    metrics_scope: Option<String>,
 }
 
 impl ClientConfig {
     pub fn new(master_addresses: Vec<SocketAddr>) -> ClientConfig {
         ClientConfig {
             master_addresses: master_addresses,
+            .. ClientConfig::default()
         }
     }
 
@@ -412,16 +841,698 @@ impl ClientConfig {
         self.master_addresses = master_addresses;
         self
     }
+
+    pub fn master_srv_record(&self) -> Option<&str> {
+        self.master_srv_record.as_ref().map(String::as_str)
+    }
+
+    /// Sets the DNS SRV record which the master addresses were resolved from, for example
+    /// `_kudu-master._tcp.cluster.local`. If every master becomes unreachable, the record is
+    /// resolved again to discover masters which have moved to new addresses.
+    ///
+    /// Defaults to `None`.
+    pub fn set_master_srv_record(&mut self, srv_record: Option<String>) -> &mut ClientConfig {
+        self.master_srv_record = srv_record;
+        self
+    }
+
+    pub fn slow_rpc_threshold(&self) -> Option<Duration> {
+        self.slow_rpc_threshold
+    }
+
+    /// Sets the duration after which an RPC is considered slow and logged at `WARN` level, with a
+    /// breakdown of its client-side queue time versus network and server time. Slow RPC logging is
+    /// disabled by default.
+    pub fn set_slow_rpc_threshold(&mut self, threshold: Option<Duration>) -> &mut ClientConfig {
+        self.slow_rpc_threshold = threshold;
+        self
+    }
+
+    pub fn admin_operation_timeout(&self) -> Duration {
+        self.admin_operation_timeout
+    }
+
+    /// Sets the timeout for administrative operations which the client performs on its own, such
+    /// as each refresh of the masters by `Client::watch_masters`. Operations which take a
+    /// deadline, such as creating, altering and opening tables, use that deadline instead.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn set_admin_operation_timeout(&mut self, timeout: Duration) -> &mut ClientConfig {
+        self.admin_operation_timeout = timeout;
+        self
+    }
+
+    pub fn operation_timeout(&self) -> Duration {
+        self.operation_timeout
+    }
+
+    /// Sets the default timeout for reads and writes: the timeout for retrieving each batch of a
+    /// scan consumed as an iterator or stream, and the flush timeout of writers. See
+    /// `ScanBuilder::set_timeout` and `WriterConfig::set_flush_timeout`.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn set_operation_timeout(&mut self, timeout: Duration) -> &mut ClientConfig {
+        self.operation_timeout = timeout;
+        self
+    }
+
+    pub fn nodelay(&self) -> bool {
+        self.nodelay
+    }
+
+    /// Sets whether Nagle's algorithm is disabled on connections to Kudu servers.
+    ///
+    /// Defaults to `true`.
+    pub fn set_nodelay(&mut self, nodelay: bool) -> &mut ClientConfig {
+        self.nodelay = nodelay;
+        self
+    }
+
+    pub fn max_buffered_rpcs(&self) -> u32 {
+        self.max_buffered_rpcs
+    }
+
+    /// Sets the maximum number of RPCs queued in each connection, including those in flight.
+    /// When the queue is full, additional RPCs fail immediately.
+    ///
+    /// Defaults to 256.
+    pub fn set_max_buffered_rpcs(&mut self, max_buffered_rpcs: u32) -> &mut ClientConfig {
+        self.max_buffered_rpcs = max_buffered_rpcs;
+        self
+    }
+
+    pub fn max_rpcs_in_flight(&self) -> u32 {
+        self.max_rpcs_in_flight
+    }
+
+    /// Sets the maximum number of RPCs sent on each connection which may be awaiting a response
+    /// at once. Lowering the limit bounds the load a client places on each server, while still
+    /// allowing many RPCs to be buffered.
+    ///
+    /// Defaults to 256.
+    pub fn set_max_rpcs_in_flight(&mut self, max_rpcs_in_flight: u32) -> &mut ClientConfig {
+        self.max_rpcs_in_flight = max_rpcs_in_flight;
+        self
+    }
+
+    pub fn max_total_rpcs_in_flight(&self) -> Option<u32> {
+        self.max_total_rpcs_in_flight
+    }
+
+    /// Sets the maximum number of RPCs in flight across all of the client's connections, or
+    /// removes the limit if `None`. RPCs sent while the limit is reached wait in the client until
+    /// other RPCs complete, which bounds the memory held by queued requests when many scans and
+    /// writes run in parallel.
+    ///
+    /// Defaults to `None`.
+    pub fn set_max_total_rpcs_in_flight(&mut self, max_total_rpcs_in_flight: Option<u32>) -> &mut ClientConfig {
+        self.max_total_rpcs_in_flight = max_total_rpcs_in_flight;
+        self
+    }
+
+    pub fn max_message_length(&self) -> u32 {
+        self.max_message_length
+    }
+
+    /// Sets the maximum length of an RPC message.
+    ///
+    /// Defaults to 5MiB.
+    pub fn set_max_message_length(&mut self, max_message_length: u32) -> &mut ClientConfig {
+        self.max_message_length = max_message_length;
+        self
+    }
+
+    pub fn meta_cache_ttl(&self) -> Option<Duration> {
+        self.meta_cache_ttl
+    }
+
+    /// Sets the amount of time table locations are cached. Expired locations are refreshed from
+    /// the master on the next lookup. Locations are also refreshed when a tablet server reports
+    /// that they have changed, so a TTL is only necessary to proactively pick up new leaders.
+    ///
+    /// Defaults to `None` (locations do not expire).
+    pub fn set_meta_cache_ttl(&mut self, ttl: Option<Duration>) -> &mut ClientConfig {
+        self.meta_cache_ttl = ttl;
+        self
+    }
+
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
+    /// Sets the approximate number of bytes which the client's writers and scans may hold in
+    /// buffered operations, in-flight write requests, and prefetched scan batches, or removes the
+    /// limit if `None`. Once the budget is used up, writers fail new operations with
+    /// `Error::Backoff` and prefetching scans wait for their batches to be consumed.
+    ///
+    /// Defaults to `None`.
+    pub fn set_memory_budget(&mut self, memory_budget: Option<usize>) -> &mut ClientConfig {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    pub fn decode_threads(&self) -> usize {
+        self.decode_threads
+    }
+
+    /// Sets the number of worker threads which decode large RPC responses, such as scan
+    /// batches. Decoding a large response on the I/O thread delays every other in-flight RPC, so
+    /// clients issuing large scans concurrently with latency-sensitive operations should enable
+    /// the pool.
+    ///
+    /// Defaults to 0 (responses are decoded on the I/O thread).
+    pub fn set_decode_threads(&mut self, decode_threads: usize) -> &mut ClientConfig {
+        self.decode_threads = decode_threads;
+        self
+    }
+
+    pub fn decode_offload_threshold(&self) -> u32 {
+        self.decode_offload_threshold
+    }
+
+    /// Sets the minimum length of a response for it to be decoded on a worker thread. Has no
+    /// effect unless decode threads are enabled.
+    ///
+    /// Defaults to 1MiB.
+    pub fn set_decode_offload_threshold(&mut self, threshold: u32) -> &mut ClientConfig {
+        self.decode_offload_threshold = threshold;
+        self
+    }
+
+    pub fn reactor_threads(&self) -> usize {
+        self.reactor_threads
+    }
+
+    /// Sets the number of threads which perform RPC I/O. Connections are spread across the
+    /// threads by server address.
+    ///
+    /// Defaults to 1.
+    pub fn set_reactor_threads(&mut self, reactor_threads: usize) -> &mut ClientConfig {
+        self.reactor_threads = reactor_threads;
+        self
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        self.connect_timeout
+    }
+
+    /// Sets the maximum time to wait for a TCP connection to a server to be established. The
+    /// connection is retried after a backoff period if the timeout expires, so this is
+    /// independent of the deadlines of the RPCs waiting on the connection.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn set_connect_timeout(&mut self, timeout: Duration) -> &mut ClientConfig {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn negotiation_timeout(&self) -> Duration {
+        self.negotiation_timeout
+    }
+
+    /// Sets the maximum time to wait for connection negotiation to complete after the TCP
+    /// connection is established.
+    ///
+    /// Defaults to 10 seconds.
+    pub fn set_negotiation_timeout(&mut self, timeout: Duration) -> &mut ClientConfig {
+        self.negotiation_timeout = timeout;
+        self
+    }
+
+    pub fn strict_negotiation(&self) -> bool {
+        self.strict_negotiation
+    }
+
+    /// Sets whether connection negotiation fails when a server answers with an unexpected call
+    /// ID. Disabling strict negotiation allows connecting to servers which are known to reply to
+    /// SASL messages with the wrong call ID; such replies are logged.
+    ///
+    /// Defaults to true.
+    pub fn set_strict_negotiation(&mut self, strict_negotiation: bool) -> &mut ClientConfig {
+        self.strict_negotiation = strict_negotiation;
+        self
+    }
+
+    pub fn negotiation_transcript(&self) -> bool {
+        self.negotiation_transcript
+    }
+
+    /// Sets whether connection negotiation failures carry a transcript of the negotiation. When
+    /// enabled, RPCs failed by a negotiation failure return `Error::NegotiationFailed` as the
+    /// cause of the connection reset, listing each SASL message exchanged with the server and the
+    /// mechanisms it offered, with tokens redacted. Useful when a server rejects the client's
+    /// authentication for a reason the bare error does not explain.
+    ///
+    /// Defaults to false.
+    pub fn set_negotiation_transcript(&mut self, negotiation_transcript: bool) -> &mut ClientConfig {
+        self.negotiation_transcript = negotiation_transcript;
+        self
+    }
+
+    pub fn hms_compatible_names(&self) -> bool {
+        self.hms_compatible_names
+    }
+
+    /// Sets whether the names of created and altered tables and columns are checked against the
+    /// naming rules of the Hive Metastore, before the request is sent. Enable this when the
+    /// cluster's masters are integrated with the Hive Metastore. See `validate_hms_table_name`
+    /// and `validate_hms_column_name`.
+    ///
+    /// Names are always checked against the master's own naming rules. Defaults to false.
+    pub fn set_hms_compatible_names(&mut self, hms_compatible_names: bool) -> &mut ClientConfig {
+        self.hms_compatible_names = hms_compatible_names;
+        self
+    }
+
+    pub fn retry_budget(&self) -> Option<u32> {
+        self.retry_budget
+    }
+
+    /// Sets the maximum number of RPC retries which the client may make in a burst, or removes the
+    /// limit if `None`. Each successful RPC earns back a fraction of a retry, set with
+    /// `set_retry_budget_percent`. Once the budget is spent, RPCs which fail due to network errors
+    /// or leader changes fail immediately instead of being retried, so that a cluster-wide outage
+    /// results in fast failures rather than a storm of retries. The budget covers RPCs resent
+    /// after a connection reset, write batches, and scans failing over to another replica.
+    ///
+    /// Defaults to `None`.
+    pub fn set_retry_budget(&mut self, retry_budget: Option<u32>) -> &mut ClientConfig {
+        self.retry_budget = retry_budget;
+        self
+    }
+
+    pub fn retry_budget_percent(&self) -> u32 {
+        self.retry_budget_percent
+    }
+
+    /// Sets the percentage of a retry which each successful RPC adds to the retry budget. Has no
+    /// effect unless the retry budget is limited.
+    ///
+    /// Defaults to 10, allowing one retry for every ten successful RPCs.
+    pub fn set_retry_budget_percent(&mut self, percent: u32) -> &mut ClientConfig {
+        self.retry_budget_percent = percent;
+        self
+    }
+
+    pub fn circuit_breaker_threshold(&self) -> Option<u32> {
+        self.circuit_breaker_threshold
+    }
+
+    /// Sets the number of consecutive connection failures to a server after which its circuit
+    /// breaker opens, or disables circuit breakers if `None`. While a server's breaker is open,
+    /// RPCs to it fail immediately with `Error::CircuitOpen`, and replicas on other servers are
+    /// tried instead where possible. Breaker state changes are reported as connection events.
+    ///
+    /// Defaults to `None`.
+    pub fn set_circuit_breaker_threshold(&mut self, threshold: Option<u32>) -> &mut ClientConfig {
+        self.circuit_breaker_threshold = threshold;
+        self
+    }
+
+    pub fn circuit_breaker_open_duration(&self) -> Duration {
+        self.circuit_breaker_open_duration
+    }
+
+    /// Sets how long a circuit breaker stays open before a single probe RPC is let through to the
+    /// server. The breaker closes if the probe succeeds, and stays open otherwise.
+    ///
+    /// Defaults to 5 seconds.
+    pub fn set_circuit_breaker_open_duration(&mut self, duration: Duration) -> &mut ClientConfig {
+        self.circuit_breaker_open_duration = duration;
+        self
+    }
+
+    pub fn call_trace_capacity(&self) -> usize {
+        self.call_trace_capacity
+    }
+
+    /// Sets how many recently completed calls are kept per server for `Client::recent_calls`.
+    /// Zero disables call traces.
+    ///
+    /// Defaults to 16.
+    pub fn set_call_trace_capacity(&mut self, capacity: usize) -> &mut ClientConfig {
+        self.call_trace_capacity = capacity;
+        self
+    }
+
+    pub fn metrics_scope(&self) -> Option<&str> {
+        self.metrics_scope.as_ref().map(String::as_str)
+    }
+
+    /// Sets the name of the client's metrics scope, which is returned by `RpcMetrics::scope` and
+    /// included in slow RPC warnings, so that the metrics and logs of several clients in one
+    /// process can be told apart.
+    ///
+    /// Defaults to `None`.
+    pub fn set_metrics_scope(&mut self, scope: Option<String>) -> &mut ClientConfig {
+        self.metrics_scope = scope;
+        self
+    }
+
+    /// Checks that the configuration is usable.
+    fn validate(&self) -> Result<()> {
+        if self.master_addresses.is_empty() {
+            return Err(Error::InvalidArgument("at least one master address is required".to_owned()));
+        }
+        if self.admin_operation_timeout == Duration::from_secs(0) ||
+           self.operation_timeout == Duration::from_secs(0) {
+            return Err(Error::InvalidArgument("operation timeouts must be positive".to_owned()));
+        }
+        if self.connect_timeout == Duration::from_secs(0) ||
+           self.negotiation_timeout == Duration::from_secs(0) {
+            return Err(Error::InvalidArgument("connection timeouts must be positive".to_owned()));
+        }
+        if self.reactor_threads == 0 {
+            return Err(Error::InvalidArgument("at least one reactor thread is required".to_owned()));
+        }
+        if self.max_buffered_rpcs == 0 || self.max_rpcs_in_flight == 0 ||
+           self.max_total_rpcs_in_flight == Some(0) {
+            return Err(Error::InvalidArgument("RPC queue limits must be positive".to_owned()));
+        }
+        if self.max_message_length == 0 {
+            return Err(Error::InvalidArgument("max message length must be positive".to_owned()));
+        }
+        if self.meta_cache_ttl == Some(Duration::from_secs(0)) {
+            return Err(Error::InvalidArgument("meta cache TTL must be positive".to_owned()));
+        }
+        if self.memory_budget == Some(0) {
+            return Err(Error::InvalidArgument("memory budget must be positive".to_owned()));
+        }
+        if self.circuit_breaker_threshold == Some(0) {
+            return Err(Error::InvalidArgument("circuit breaker threshold must be positive".to_owned()));
+        }
+        if self.metrics_scope.as_ref().map_or(false, String::is_empty) {
+            return Err(Error::InvalidArgument("metrics scope must not be empty".to_owned()));
+        }
+        Ok(())
+    }
 }
 
 impl Default for ClientConfig {
     fn default() -> ClientConfig {
+        let connection_options = ConnectionOptions::default();
         ClientConfig {
             master_addresses: vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 7051)],
+            slow_rpc_threshold: connection_options.slow_rpc_threshold,
+            admin_operation_timeout: Duration::from_secs(30),
+            operation_timeout: Duration::from_secs(30),
+            nodelay: connection_options.nodelay,
+            max_buffered_rpcs: connection_options.max_buffered_rpcs,
+            max_rpcs_in_flight: connection_options.max_rpcs_in_flight,
+            max_total_rpcs_in_flight: connection_options.max_total_rpcs_in_flight,
+            max_message_length: connection_options.max_message_length,
+            meta_cache_ttl: None,
+            memory_budget: None,
+            decode_threads: connection_options.decode_threads,
+            decode_offload_threshold: connection_options.decode_offload_threshold,
+            reactor_threads: connection_options.reactor_threads,
+            connect_timeout: connection_options.connect_timeout,
+            negotiation_timeout: connection_options.negotiation_timeout,
+            strict_negotiation: connection_options.strict_negotiation,
+            negotiation_transcript: connection_options.negotiation_transcript,
+            hms_compatible_names: false,
+            retry_budget: connection_options.retry_budget,
+            retry_budget_percent: connection_options.retry_budget_percent,
+            circuit_breaker_threshold: connection_options.circuit_breaker_threshold,
+            circuit_breaker_open_duration: connection_options.circuit_breaker_open_duration,
+            call_trace_capacity: connection_options.call_trace_capacity,
+            master_srv_record: None,
+            metrics_scope: connection_options.metrics_scope,
         }
     }
 }
 
+/// Builds a `Client`, validating its configuration.
+///
+/// The client does not implement TLS, so connections are unencrypted and there are no TLS
+/// settings. Connections authenticate with SASL `PLAIN`.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// let client = kudu::Client::builder()
+///     .add_master_address("127.0.0.1:7051".parse().unwrap())
+///     .set_admin_operation_timeout(Duration::from_secs(60))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    config: ClientConfig,
+    /// Master hostnames, resolved when the client is built.
+    master_hostports: Vec<HostPort>,
+    resolver: Arc<Resolver>,
+    runtime: Arc<Runtime>,
+}
+
+impl ClientBuilder {
+
+    /// Creates a new builder with no master addresses, and otherwise default configuration.
+    pub fn new() -> ClientBuilder {
+        let mut config = ClientConfig::default();
+        config.master_addresses.clear();
+        ClientBuilder {
+            config: config,
+            master_hostports: Vec::new(),
+            resolver: Arc::new(SystemResolver),
+            runtime: Arc::new(ThreadRuntime),
+        }
+    }
+
+    /// Adds a master address. At least one master address is required.
+    pub fn add_master_address(mut self, addr: SocketAddr) -> ClientBuilder {
+        self.config.master_addresses.push(addr);
+        self
+    }
+
+    /// Adds a master by hostname. The hostname is resolved when the client is built, and every
+    /// address it resolves to is used, so that a multi-homed master is reachable as long as any
+    /// of its addresses is.
+    pub fn add_master_hostport<S>(mut self, host: S, port: u16) -> ClientBuilder
+    where S: Into<String> {
+        self.master_hostports.push(HostPort::new(host, port));
+        self
+    }
+
+    /// Discovers the masters from a DNS SRV record, such as `_kudu-master._tcp.cluster.local`.
+    /// The record is resolved when the client is built, and again whenever every known master is
+    /// unreachable, so that masters may be rescheduled to new hosts without reconfiguring the
+    /// client.
+    pub fn set_master_srv_record<S>(mut self, srv_record: S) -> ClientBuilder where S: Into<String> {
+        self.config.set_master_srv_record(Some(srv_record.into()));
+        self
+    }
+
+    /// Sets the master addresses, replacing any previously added addresses and hostnames.
+    pub fn set_master_addresses(mut self, addrs: Vec<SocketAddr>) -> ClientBuilder {
+        self.config.master_addresses = addrs;
+        self.master_hostports.clear();
+        self
+    }
+
+    /// See `ClientConfig::set_admin_operation_timeout`.
+    pub fn set_admin_operation_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.config.set_admin_operation_timeout(timeout);
+        self
+    }
+
+    /// See `ClientConfig::set_operation_timeout`.
+    pub fn set_operation_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.config.set_operation_timeout(timeout);
+        self
+    }
+
+    /// See `ClientConfig::set_nodelay`.
+    pub fn set_nodelay(mut self, nodelay: bool) -> ClientBuilder {
+        self.config.set_nodelay(nodelay);
+        self
+    }
+
+    /// See `ClientConfig::set_max_buffered_rpcs`.
+    pub fn set_max_buffered_rpcs(mut self, max_buffered_rpcs: u32) -> ClientBuilder {
+        self.config.set_max_buffered_rpcs(max_buffered_rpcs);
+        self
+    }
+
+    /// See `ClientConfig::set_max_rpcs_in_flight`.
+    pub fn set_max_rpcs_in_flight(mut self, max_rpcs_in_flight: u32) -> ClientBuilder {
+        self.config.set_max_rpcs_in_flight(max_rpcs_in_flight);
+        self
+    }
+
+    /// See `ClientConfig::set_max_total_rpcs_in_flight`.
+    pub fn set_max_total_rpcs_in_flight(mut self, max_total_rpcs_in_flight: Option<u32>) -> ClientBuilder {
+        self.config.set_max_total_rpcs_in_flight(max_total_rpcs_in_flight);
+        self
+    }
+
+    /// See `ClientConfig::set_max_message_length`.
+    pub fn set_max_message_length(mut self, max_message_length: u32) -> ClientBuilder {
+        self.config.set_max_message_length(max_message_length);
+        self
+    }
+
+    /// See `ClientConfig::set_slow_rpc_threshold`.
+    pub fn set_slow_rpc_threshold(mut self, threshold: Option<Duration>) -> ClientBuilder {
+        self.config.set_slow_rpc_threshold(threshold);
+        self
+    }
+
+    /// See `ClientConfig::set_retry_budget`.
+    pub fn set_retry_budget(mut self, retry_budget: Option<u32>) -> ClientBuilder {
+        self.config.set_retry_budget(retry_budget);
+        self
+    }
+
+    /// See `ClientConfig::set_retry_budget_percent`.
+    pub fn set_retry_budget_percent(mut self, percent: u32) -> ClientBuilder {
+        self.config.set_retry_budget_percent(percent);
+        self
+    }
+
+    /// See `ClientConfig::set_circuit_breaker_threshold`.
+    pub fn set_circuit_breaker_threshold(mut self, threshold: Option<u32>) -> ClientBuilder {
+        self.config.set_circuit_breaker_threshold(threshold);
+        self
+    }
+
+    /// See `ClientConfig::set_circuit_breaker_open_duration`.
+    pub fn set_circuit_breaker_open_duration(mut self, duration: Duration) -> ClientBuilder {
+        self.config.set_circuit_breaker_open_duration(duration);
+        self
+    }
+
+    /// See `ClientConfig::set_call_trace_capacity`.
+    pub fn set_call_trace_capacity(mut self, capacity: usize) -> ClientBuilder {
+        self.config.set_call_trace_capacity(capacity);
+        self
+    }
+
+    /// See `ClientConfig::set_metrics_scope`.
+    pub fn set_metrics_scope<S>(mut self, scope: S) -> ClientBuilder where S: Into<String> {
+        self.config.set_metrics_scope(Some(scope.into()));
+        self
+    }
+
+    /// See `ClientConfig::set_decode_threads`.
+    pub fn set_decode_threads(mut self, decode_threads: usize) -> ClientBuilder {
+        self.config.set_decode_threads(decode_threads);
+        self
+    }
+
+    /// See `ClientConfig::set_decode_offload_threshold`.
+    pub fn set_decode_offload_threshold(mut self, threshold: u32) -> ClientBuilder {
+        self.config.set_decode_offload_threshold(threshold);
+        self
+    }
+
+    /// See `ClientConfig::set_reactor_threads`.
+    pub fn set_reactor_threads(mut self, reactor_threads: usize) -> ClientBuilder {
+        self.config.set_reactor_threads(reactor_threads);
+        self
+    }
+
+    /// See `ClientConfig::set_connect_timeout`.
+    pub fn set_connect_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.config.set_connect_timeout(timeout);
+        self
+    }
+
+    /// See `ClientConfig::set_negotiation_timeout`.
+    pub fn set_negotiation_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.config.set_negotiation_timeout(timeout);
+        self
+    }
+
+    /// See `ClientConfig::set_strict_negotiation`.
+    pub fn set_strict_negotiation(mut self, strict_negotiation: bool) -> ClientBuilder {
+        self.config.set_strict_negotiation(strict_negotiation);
+        self
+    }
+
+    /// See `ClientConfig::set_negotiation_transcript`.
+    pub fn set_negotiation_transcript(mut self, negotiation_transcript: bool) -> ClientBuilder {
+        self.config.set_negotiation_transcript(negotiation_transcript);
+        self
+    }
+
+    /// See `ClientConfig::set_hms_compatible_names`.
+    pub fn set_hms_compatible_names(mut self, hms_compatible_names: bool) -> ClientBuilder {
+        self.config.set_hms_compatible_names(hms_compatible_names);
+        self
+    }
+
+    /// See `ClientConfig::set_meta_cache_ttl`.
+    pub fn set_meta_cache_ttl(mut self, ttl: Option<Duration>) -> ClientBuilder {
+        self.config.set_meta_cache_ttl(ttl);
+        self
+    }
+
+    /// See `ClientConfig::set_memory_budget`.
+    pub fn set_memory_budget(mut self, memory_budget: Option<usize>) -> ClientBuilder {
+        self.config.set_memory_budget(memory_budget);
+        self
+    }
+
+    /// Sets the resolver used to resolve master and tablet server hostnames, and the master SRV
+    /// record.
+    ///
+    /// Defaults to `SystemResolver`.
+    pub fn set_resolver<R>(mut self, resolver: R) -> ClientBuilder where R: Resolver + 'static {
+        self.resolver = Arc::new(resolver);
+        self
+    }
+
+    /// Sets the runtime which runs the client's RPC I/O event loops, so that they run on threads
+    /// managed by the application. Each event loop blocks the thread running it; see
+    /// `Runtime::spawn`.
+    ///
+    /// Defaults to `ThreadRuntime`, which spawns a thread for each event loop.
+    pub fn set_runtime<R>(mut self, runtime: R) -> ClientBuilder where R: Runtime + 'static {
+        self.runtime = Arc::new(runtime);
+        self
+    }
+
+    /// Returns the configuration, without validating it.
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    /// Resolves the master hostnames and SRV record, validates the configuration, and creates the
+    /// client.
+    pub fn build(mut self) -> Result<Client> {
+        if let Some(srv_record) = self.config.master_srv_record.clone() {
+            let targets = try!(self.resolver.resolve_srv(&srv_record).map_err(|error| {
+                Error::InvalidArgument(format!("unable to resolve master SRV record {}: {}",
+                                               srv_record, error))
+            }));
+            if targets.is_empty() {
+                return Err(Error::InvalidArgument(format!("master SRV record {} has no targets",
+                                                          srv_record)));
+            }
+            self.master_hostports.extend(targets);
+        }
+        for hostport in &self.master_hostports {
+            let addrs = dns::resolve_hostports(&*self.resolver, &[hostport.clone()]);
+            if addrs.is_empty() {
+                return Err(Error::InvalidArgument(format!("unable to resolve master {}", hostport)));
+            }
+            for addr in addrs {
+                if !self.config.master_addresses.contains(&addr) {
+                    self.config.master_addresses.push(addr);
+                }
+            }
+        }
+        try!(self.config.validate());
+        Ok(Client::with_resolver(self.config, self.resolver, &*self.runtime))
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::{Duration, Instant};
@@ -429,8 +1540,13 @@ mod tests {
     use AlterTableBuilder;
     use Column;
     use DataType;
+    use MasterEvent;
+    use RaftRole;
     use RangePartitionBound;
+    use ReactorTask;
     use SchemaBuilder;
+    use ServerFeature;
+    use StaticResolver;
     use TableBuilder;
     use mini_cluster::{MiniCluster, MiniClusterConfig};
     use schema::tests::simple_schema;
@@ -478,9 +1594,234 @@ mod tests {
         assert_eq!(1, tables.len());
         assert_eq!("create_and_delete_table", &tables[0].0);
 
+        let mut tables = client.tables(Some("create_and_delete"), deadline()).unwrap();
+        assert_eq!(1, tables.len());
+        assert_eq!(("create_and_delete_table".to_owned(), table_id.clone()),
+                   tables.next().unwrap().unwrap());
+        assert!(tables.next().is_none());
+        assert_eq!(0, client.tables(Some("no_such_table"), deadline()).unwrap().len());
+
         client.delete_table_by_id(&table_id, deadline()).unwrap();
     }
 
+    #[test]
+    fn builder_validation() {
+        assert!(Client::builder().build().is_err());
+
+        let addr = "127.0.0.1:7051".parse().unwrap();
+        assert!(Client::builder().add_master_address(addr)
+                                 .set_operation_timeout(Duration::from_secs(0))
+                                 .build()
+                                 .is_err());
+        assert!(Client::builder().add_master_address(addr)
+                                 .set_max_rpcs_in_flight(0)
+                                 .build()
+                                 .is_err());
+        assert!(Client::builder().add_master_address(addr)
+                                 .set_max_total_rpcs_in_flight(Some(0))
+                                 .build()
+                                 .is_err());
+        assert!(Client::builder().add_master_address(addr)
+                                 .set_meta_cache_ttl(Some(Duration::from_secs(0)))
+                                 .build()
+                                 .is_err());
+        assert!(Client::builder().add_master_address(addr)
+                                 .set_memory_budget(Some(0))
+                                 .build()
+                                 .is_err());
+
+        assert!(Client::builder().add_master_address(addr)
+                                 .set_metrics_scope("")
+                                 .build()
+                                 .is_err());
+
+        let builder = Client::builder().add_master_address(addr)
+                                       .set_admin_operation_timeout(Duration::from_secs(60))
+                                       .set_meta_cache_ttl(Some(Duration::from_secs(300)))
+                                       .set_metrics_scope("ingest");
+        assert_eq!(&[addr], builder.config().master_addresses());
+        assert_eq!(Duration::from_secs(60), builder.config().admin_operation_timeout());
+        assert_eq!(Duration::from_secs(30), builder.config().operation_timeout());
+        assert_eq!(Some(Duration::from_secs(300)), builder.config().meta_cache_ttl());
+        assert_eq!(Some("ingest"), builder.config().metrics_scope());
+        assert_eq!(Some("ingest"), builder.build().unwrap().rpc_metrics().scope());
+
+        assert!(Client::builder().add_master_hostport("host.invalid", 7051).build().is_err());
+        assert!(Client::builder().set_master_srv_record("_kudu-master._tcp.host.invalid")
+                                 .build()
+                                 .is_err());
+    }
+
+    #[test]
+    fn ddl_name_validation() {
+        // Invalid names are rejected before any RPC is sent, so no cluster is necessary.
+        let client = Client::builder().add_master_address("127.0.0.1:1".parse().unwrap())
+                                      .set_hms_compatible_names(true)
+                                      .build()
+                                      .unwrap();
+        fn is_invalid_argument<T>(result: Result<T>) -> bool {
+            match result {
+                Err(Error::InvalidArgument(_)) => true,
+                _ => false,
+            }
+        }
+        let table_builder = TableBuilder::new("not-qualified", simple_schema());
+        assert!(is_invalid_argument(client.create_table(table_builder, deadline())));
+        let alter = AlterTableBuilder::new().add_column(Column::builder("a:b", DataType::Int32));
+        assert!(is_invalid_argument(client.alter_table("db.t", alter, deadline())));
+        let alter = AlterTableBuilder::new().rename_table("");
+        assert!(is_invalid_argument(client.alter_table("db.t", alter, deadline())));
+    }
+
+    #[test]
+    fn shutdown() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::default();
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+        assert!(client.list_tables(deadline()).unwrap().is_empty());
+
+        client.shutdown(Duration::from_secs(10)).unwrap();
+        assert!(client.is_shut_down());
+        assert_eq!(Err(Error::Shutdown), client.list_tables(deadline()));
+        // Shutting down again has no effect.
+        client.shutdown(Duration::from_secs(10)).unwrap();
+    }
+
+    #[test]
+    fn builder() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::default();
+
+        let port = cluster.master_addrs()[0].port();
+        let client = Client::builder().add_master_hostport("localhost", port)
+                                      .set_max_rpcs_in_flight(16)
+                                      .build()
+                                      .unwrap();
+        assert_eq!(16, client.config().max_rpcs_in_flight());
+        assert!(client.list_tables(deadline()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn static_resolver() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::default();
+
+        let master_addr = cluster.master_addrs()[0];
+        let mut resolver = StaticResolver::new();
+        resolver.add_host("kudu-master", master_addr.ip())
+                .add_srv_target("_kudu-master._tcp.test", "kudu-master", master_addr.port());
+
+        let client = Client::builder().set_master_srv_record("_kudu-master._tcp.test")
+                                      .set_resolver(resolver)
+                                      .build()
+                                      .unwrap();
+        assert_eq!(&[master_addr], client.config().master_addresses());
+        assert_eq!(Some("_kudu-master._tcp.test"), client.config().master_srv_record());
+        assert!(client.list_tables(deadline()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn runtime() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Debug, Default)]
+        struct CountingRuntime(Arc<AtomicUsize>);
+        impl Runtime for CountingRuntime {
+            fn spawn(&self, task: ReactorTask) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                thread::spawn(move || task.run());
+            }
+        }
+
+        let _ = env_logger::init();
+        let cluster = MiniCluster::default();
+        let runtime = CountingRuntime::default();
+        let spawned = runtime.0.clone();
+
+        let client = Client::builder().add_master_address(cluster.master_addrs()[0])
+                                      .set_reactor_threads(2)
+                                      .set_runtime(runtime)
+                                      .build()
+                                      .unwrap();
+        assert_eq!(2, spawned.load(Ordering::SeqCst));
+        assert!(client.list_tables(deadline()).unwrap().is_empty());
+        client.shutdown(Duration::from_secs(10)).unwrap();
+    }
+
+    #[test]
+    fn ensure_table() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::default();
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        assert!(!client.table_exists("ensure_table", deadline()).unwrap());
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("val", DataType::Int32))
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+        let mut table_builder = TableBuilder::new("ensure_table", schema.clone());
+        table_builder.set_num_replicas(1);
+        let table = client.ensure_table(table_builder, false, deadline()).unwrap();
+        assert_eq!(&schema, table.schema());
+        assert!(client.table_exists("ensure_table", deadline()).unwrap());
+
+        // Ensuring the same table again opens the existing table.
+        let mut table_builder = TableBuilder::new("ensure_table", schema.clone());
+        table_builder.set_num_replicas(1);
+        let existing = client.ensure_table(table_builder, false, deadline()).unwrap();
+        assert_eq!(table.id(), existing.id());
+
+        // A new nullable column is only added on request.
+        let extended_schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("val", DataType::Int32))
+            .add_column(Column::builder("extra", DataType::String))
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+        let table_builder = TableBuilder::new("ensure_table", extended_schema.clone());
+        assert!(client.ensure_table(table_builder, false, deadline()).is_err());
+        let table_builder = TableBuilder::new("ensure_table", extended_schema.clone());
+        let altered = client.ensure_table(table_builder, true, deadline()).unwrap();
+        assert_eq!(table.id(), altered.id());
+        assert!(altered.schema().column_by_name("extra").is_some());
+
+        // Incompatible column types are rejected.
+        let incompatible_schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("val", DataType::String))
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+        let table_builder = TableBuilder::new("ensure_table", incompatible_schema);
+        assert!(client.ensure_table(table_builder, true, deadline()).is_err());
+    }
+
+    #[test]
+    fn health_check() {
+        let _ = env_logger::init();
+        let mut cluster = MiniCluster::new(MiniClusterConfig::default()
+                                                         .num_masters(1)
+                                                         .num_tservers(2));
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let report = client.health_check(deadline());
+        assert!(report.is_healthy(), "{:?}", report);
+        assert_eq!(3, report.servers().len());
+        assert_eq!(&ServerKind::Master, report.servers()[0].kind());
+
+        let tserver_addr = cluster.tserver_addrs()[0];
+        cluster.stop_node(tserver_addr);
+        let report = client.health_check(Instant::now() + Duration::from_secs(1));
+        assert!(!report.is_healthy());
+        let unhealthy = report.servers().iter().filter(|server| !server.is_healthy()).collect::<Vec<_>>();
+        assert_eq!(1, unhealthy.len());
+        assert_eq!(Some(tserver_addr), unhealthy[0].addr());
+    }
+
     #[test]
     fn list_tablet_servers() {
         let _ = env_logger::init();
@@ -493,6 +1834,56 @@ mod tests {
         assert_eq!(3, tablet_servers.len());
     }
 
+    #[test]
+    fn detect_server_versions() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default()
+                                                         .num_masters(1)
+                                                         .num_tservers(1));
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+        assert_eq!(None, client.server_versions().tablet_server());
+
+        // Discovering the leader master records the masters' versions.
+        client.list_tables(deadline()).unwrap();
+        assert!(client.server_versions().master().is_some());
+        assert_eq!(None, client.server_versions().tablet_server());
+
+        client.detect_server_versions(deadline()).unwrap();
+        let master = client.server_versions().master().unwrap();
+        let tablet_server = client.server_versions().tablet_server().unwrap();
+        assert!(master >= ServerFeature::Upsert.min_version(), "master version: {}", master);
+        assert!(tablet_server >= ServerFeature::Upsert.min_version(),
+                "tablet server version: {}", tablet_server);
+        assert_eq!(Ok(()), client.server_versions().check(ServerFeature::Upsert));
+    }
+
+    #[test]
+    fn watch_masters() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default()
+                                                         .num_masters(3)
+                                                         .num_tservers(0));
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+        let watch = client.watch_masters(Duration::from_millis(100));
+
+        let mut roles = Vec::new();
+        while roles.len() < 3 {
+            match watch.recv_timeout(Duration::from_secs(10)) {
+                Some(MasterEvent::Added { role, .. }) => roles.push(role),
+                Some(MasterEvent::RefreshFailed(error)) => warn!("failed to list masters: {}", error),
+                event => panic!("unexpected event: {:?}", event),
+            }
+        }
+        assert_eq!(1, roles.iter().filter(|&&role| role == RaftRole::Leader).count());
+
+        // Later refreshes only report changes, so the masters are not added again.
+        while let Some(event) = watch.recv_timeout(Duration::from_millis(500)) {
+            if let MasterEvent::Added { .. } = event {
+                panic!("master added twice: {:?}", event);
+            }
+        }
+    }
+
     #[test]
     fn list_masters() {
         let _ = env_logger::init();
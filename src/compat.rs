@@ -0,0 +1,238 @@
+use std::fmt;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use Error;
+use Result;
+use UnsupportedByServerError;
+
+/// The version of a Kudu server, as reported in its registration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ServerVersion {
+    major: u32,
+    minor: u32,
+    patch: u32,
+}
+
+impl ServerVersion {
+
+    pub fn new(major: u32, minor: u32, patch: u32) -> ServerVersion {
+        ServerVersion {
+            major: major,
+            minor: minor,
+            patch: patch,
+        }
+    }
+
+    /// Parses the version from a server's software version string, for instance
+    /// `kudu 1.4.0 (rev 0f49bb9…)` or `1.5.0-SNAPSHOT`. Returns `None` if the string does not
+    /// contain a version.
+    pub fn parse(software_version: &str) -> Option<ServerVersion> {
+        software_version.split_whitespace()
+                        .filter_map(|word| {
+                            let version = word.split('-').next().unwrap();
+                            let mut parts = version.split('.').map(|part| part.parse::<u32>());
+                            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                                (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => {
+                                    Some(ServerVersion::new(major, minor, patch))
+                                },
+                                _ => None,
+                            }
+                        })
+                        .next()
+    }
+
+    pub fn major(&self) -> u32 {
+        self.major
+    }
+
+    pub fn minor(&self) -> u32 {
+        self.minor
+    }
+
+    pub fn patch(&self) -> u32 {
+        self.patch
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// A feature which servers older than a particular version do not support.
+///
+/// Every feature is supported by Kudu 1.0 and later, so the checks only reject operations against
+/// clusters still running a pre-1.0 release.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ServerFeature {
+    /// Scans with column predicates, served by tablet servers.
+    ColumnPredicates,
+    /// Range partitions with explicit bounds in `CreateTable`, served by masters.
+    RangePartitionBounds,
+    /// Adding and dropping range partitions in `AlterTable`, served by masters.
+    AddDropRangePartitions,
+    /// Upsert write operations, served by tablet servers.
+    Upsert,
+}
+
+impl ServerFeature {
+
+    /// Returns the oldest server version which supports the feature.
+    pub fn min_version(self) -> ServerVersion {
+        match self {
+            ServerFeature::ColumnPredicates => ServerVersion::new(0, 9, 0),
+            ServerFeature::RangePartitionBounds => ServerVersion::new(0, 10, 0),
+            ServerFeature::AddDropRangePartitions => ServerVersion::new(0, 10, 0),
+            ServerFeature::Upsert => ServerVersion::new(1, 0, 0),
+        }
+    }
+
+    /// Returns `true` if the feature is served by masters, or `false` if it is served by tablet
+    /// servers.
+    pub fn is_master_feature(self) -> bool {
+        match self {
+            ServerFeature::RangePartitionBounds | ServerFeature::AddDropRangePartitions => true,
+            ServerFeature::ColumnPredicates | ServerFeature::Upsert => false,
+        }
+    }
+}
+
+impl fmt::Display for ServerFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            ServerFeature::ColumnPredicates => "column predicates",
+            ServerFeature::RangePartitionBounds => "range partition bounds",
+            ServerFeature::AddDropRangePartitions => "adding and dropping range partitions",
+            ServerFeature::Upsert => "upsert operations",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The oldest master and tablet server versions in the most recent listing of each kind of server.
+///
+/// Operations which use a feature that the oldest known server of the kind serving it does not
+/// support fail with `Error::UnsupportedByServer` before being sent, rather than being rejected
+/// by the server with an error which does not name the feature. Until a server's version has been
+/// seen, every feature is assumed to be supported.
+///
+/// Master versions are recorded whenever the client refreshes its view of the masters, and by
+/// `Client::list_masters`. The tablet locations which the client caches do not include software
+/// versions, so tablet server versions are only recorded by `Client::list_tablet_servers` and
+/// `Client::detect_server_versions`. Each listing replaces the previously recorded version, so a
+/// cluster which has been upgraded since is not held to the version of its old servers.
+///
+/// Instances are cheap to clone, and clones share their versions.
+#[derive(Clone, Debug, Default)]
+pub struct ServerVersions {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    master: Option<ServerVersion>,
+    tablet_server: Option<ServerVersion>,
+}
+
+impl ServerVersions {
+
+    pub fn new() -> ServerVersions {
+        ServerVersions::default()
+    }
+
+    /// Returns the oldest master version in the most recent listing of the masters, if any.
+    pub fn master(&self) -> Option<ServerVersion> {
+        self.inner.lock().master
+    }
+
+    /// Returns the oldest tablet server version in the most recent listing of the tablet servers,
+    /// if any.
+    pub fn tablet_server(&self) -> Option<ServerVersion> {
+        self.inner.lock().tablet_server
+    }
+
+    /// Records the software versions of every master in a listing of the masters, replacing the
+    /// previously recorded version. Unparseable versions are ignored, and a listing without any
+    /// parseable version leaves the recorded version unchanged.
+    pub fn record_masters<'a, I>(&self, software_versions: I) where I: IntoIterator<Item=&'a str> {
+        if let Some(version) = oldest(software_versions) {
+            self.inner.lock().master = Some(version);
+        }
+    }
+
+    /// Records the software versions of every tablet server in a listing of the tablet servers,
+    /// replacing the previously recorded version. Unparseable versions are ignored, and a listing
+    /// without any parseable version leaves the recorded version unchanged.
+    pub fn record_tablet_servers<'a, I>(&self, software_versions: I)
+    where I: IntoIterator<Item=&'a str> {
+        if let Some(version) = oldest(software_versions) {
+            self.inner.lock().tablet_server = Some(version);
+        }
+    }
+
+    /// Checks that the oldest known server serving the feature supports it.
+    pub fn check(&self, feature: ServerFeature) -> Result<()> {
+        let version = if feature.is_master_feature() { self.master() } else { self.tablet_server() };
+        match version {
+            Some(version) if version < feature.min_version() => {
+                Err(Error::UnsupportedByServer(UnsupportedByServerError::new(feature, version)))
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns the oldest of the parseable software versions.
+fn oldest<'a, I>(software_versions: I) -> Option<ServerVersion> where I: IntoIterator<Item=&'a str> {
+    software_versions.into_iter().filter_map(ServerVersion::parse).min()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use Error;
+
+    #[test]
+    fn test_parse_server_version() {
+        assert_eq!(Some(ServerVersion::new(1, 4, 0)),
+                   ServerVersion::parse("kudu 1.4.0\nrevision 0f49bb9\nbuild type RELEASE"));
+        assert_eq!(Some(ServerVersion::new(1, 5, 0)), ServerVersion::parse("kudu 1.5.0-SNAPSHOT"));
+        assert_eq!(Some(ServerVersion::new(0, 10, 1)), ServerVersion::parse("0.10.1"));
+        assert_eq!(None, ServerVersion::parse("kudu"));
+        assert_eq!(None, ServerVersion::parse(""));
+        assert!(ServerVersion::new(0, 10, 0) < ServerVersion::new(1, 0, 0));
+    }
+
+    #[test]
+    fn test_server_versions() {
+        let versions = ServerVersions::new();
+        assert_eq!(Ok(()), versions.check(ServerFeature::Upsert));
+
+        versions.record_tablet_servers(vec!["kudu 1.2.0", "kudu 0.9.1", "unknown"]);
+        versions.record_masters(vec!["kudu 1.2.0"]);
+        assert_eq!(Some(ServerVersion::new(0, 9, 1)), versions.tablet_server());
+        assert_eq!(Some(ServerVersion::new(1, 2, 0)), versions.master());
+
+        assert_eq!(Ok(()), versions.check(ServerFeature::ColumnPredicates));
+        assert_eq!(Ok(()), versions.check(ServerFeature::AddDropRangePartitions));
+        match versions.check(ServerFeature::Upsert) {
+            Err(Error::UnsupportedByServer(ref error)) => {
+                assert_eq!(ServerFeature::Upsert, error.feature());
+                assert_eq!(ServerVersion::new(0, 9, 1), error.version());
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // A later listing replaces the recorded version, for instance once the cluster has been
+        // upgraded.
+        versions.record_tablet_servers(vec!["unknown"]);
+        assert_eq!(Some(ServerVersion::new(0, 9, 1)), versions.tablet_server());
+        versions.record_tablet_servers(vec!["kudu 1.2.0", "kudu 1.3.0"]);
+        assert_eq!(Some(ServerVersion::new(1, 2, 0)), versions.tablet_server());
+        assert_eq!(Ok(()), versions.check(ServerFeature::Upsert));
+    }
+}
@@ -0,0 +1,498 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use parking_lot::Mutex;
+
+use BulkLoaderConfig;
+use Client;
+use Error;
+use Result;
+use ScanBuilder;
+use Schema;
+use Table;
+use key;
+
+/// The progress of a single scan of the source table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ScanProgress {
+    NotStarted,
+    /// Rows up to and including the encoded primary key have been copied.
+    Copied(Vec<u8>),
+    Done,
+}
+
+/// The progress of a table copy, from which an interrupted copy can be resumed.
+///
+/// The source table is copied by several scans in parallel, each in primary key order. The
+/// checkpoint records the primary key of the last row copied by each scan. A copy resumed from a
+/// checkpoint must use the same number of scans, and the source table's partitioning must not have
+/// changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CopyCheckpoint {
+    scans: Vec<ScanProgress>,
+}
+
+impl CopyCheckpoint {
+
+    /// Returns `true` if every scan of the copy has completed.
+    pub fn is_done(&self) -> bool {
+        !self.scans.is_empty() && self.scans.iter().all(|scan| *scan == ScanProgress::Done)
+    }
+
+    /// Serializes the checkpoint, so that it can be persisted.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.write_u32::<LittleEndian>(self.scans.len() as u32).unwrap();
+        for scan in &self.scans {
+            match *scan {
+                ScanProgress::NotStarted => buf.push(0),
+                ScanProgress::Copied(ref key) => {
+                    buf.push(1);
+                    buf.write_u32::<LittleEndian>(key.len() as u32).unwrap();
+                    buf.extend_from_slice(key);
+                },
+                ScanProgress::Done => buf.push(2),
+            }
+        }
+        buf
+    }
+
+    /// Deserializes a checkpoint serialized with `CopyCheckpoint::to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<CopyCheckpoint> {
+        let mut cursor = Cursor::new(bytes);
+        let num_scans = try!(cursor.read_u32::<LittleEndian>().map_err(invalid_checkpoint));
+        let mut scans = Vec::new();
+        for _ in 0..num_scans {
+            let progress = match try!(cursor.read_u8().map_err(invalid_checkpoint)) {
+                0 => ScanProgress::NotStarted,
+                1 => {
+                    let len = try!(cursor.read_u32::<LittleEndian>().map_err(invalid_checkpoint)) as usize;
+                    // The length is untrusted, so check it before allocating the key.
+                    if len > bytes.len() - cursor.position() as usize {
+                        return Err(invalid_checkpoint(len));
+                    }
+                    let mut key = vec![0; len];
+                    try!(cursor.read_exact(&mut key).map_err(invalid_checkpoint));
+                    ScanProgress::Copied(key)
+                },
+                2 => ScanProgress::Done,
+                tag => return Err(invalid_checkpoint(tag)),
+            };
+            scans.push(progress);
+        }
+        Ok(CopyCheckpoint { scans: scans })
+    }
+}
+
+fn invalid_checkpoint<E>(_: E) -> Error {
+    Error::Serialization("invalid copy checkpoint".to_string())
+}
+
+/// Options for `copy_table`.
+#[derive(Clone)]
+pub struct CopyTableOptions {
+    num_scans: usize,
+    max_rows_per_second: Option<u64>,
+    timeout: Duration,
+    loader_config: BulkLoaderConfig,
+    checkpoint: Option<CopyCheckpoint>,
+    checkpoint_callback: Option<Arc<Fn(&CopyCheckpoint) + Send + Sync>>,
+}
+
+impl CopyTableOptions {
+
+    pub fn num_scans(&self) -> usize {
+        self.num_scans
+    }
+
+    /// Sets the number of scans of the source table, and the number of threads copying them. Each
+    /// thread copies one scan at a time, with its own loader writing to the destination table.
+    /// The table is split into more scans if it has more tablets, in which case the threads take
+    /// turns copying them. See `ScanBuilder::split`.
+    ///
+    /// Defaults to 4. Must be at least 1.
+    pub fn set_num_scans(&mut self, num_scans: usize) -> &mut CopyTableOptions {
+        assert!(num_scans > 0, "num_scans must be at least 1");
+        self.num_scans = num_scans;
+        self
+    }
+
+    pub fn max_rows_per_second(&self) -> Option<u64> {
+        self.max_rows_per_second
+    }
+
+    /// Sets the maximum rate at which rows are copied, across all scans.
+    ///
+    /// Defaults to no limit.
+    pub fn set_max_rows_per_second(&mut self, max_rows_per_second: u64) -> &mut CopyTableOptions {
+        self.max_rows_per_second = Some(max_rows_per_second);
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Sets the timeout for opening the tables and retrieving each batch of rows.
+    ///
+    /// Defaults to 30 seconds.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut CopyTableOptions {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn loader_config(&self) -> &BulkLoaderConfig {
+        &self.loader_config
+    }
+
+    /// Sets the configuration of the loaders writing to the destination table.
+    pub fn set_loader_config(&mut self, loader_config: BulkLoaderConfig) -> &mut CopyTableOptions {
+        self.loader_config = loader_config;
+        self
+    }
+
+    /// Resumes the copy from a checkpoint of a previous copy of the same table. Rows which were
+    /// copied before the checkpoint are not copied again.
+    pub fn set_checkpoint(&mut self, checkpoint: CopyCheckpoint) -> &mut CopyTableOptions {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Sets a callback which is called with the updated checkpoint after each batch of rows has
+    /// been written to the destination table. The callback is typically used to persist the
+    /// checkpoint, so that the copy can be resumed if it fails.
+    pub fn set_checkpoint_callback<F>(&mut self, callback: F) -> &mut CopyTableOptions
+    where F: Fn(&CopyCheckpoint) + Send + Sync + 'static {
+        self.checkpoint_callback = Some(Arc::new(callback));
+        self
+    }
+}
+
+impl Default for CopyTableOptions {
+    fn default() -> CopyTableOptions {
+        CopyTableOptions {
+            num_scans: 4,
+            max_rows_per_second: None,
+            timeout: Duration::from_secs(30),
+            loader_config: BulkLoaderConfig::default(),
+            checkpoint: None,
+            checkpoint_callback: None,
+        }
+    }
+}
+
+impl fmt::Debug for CopyTableOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CopyTableOptions {{ num_scans: {}, max_rows_per_second: {:?}, timeout: {:?}, \
+                   loader_config: {:?}, checkpoint: {:?} }}",
+               self.num_scans, self.max_rows_per_second, self.timeout, self.loader_config,
+               self.checkpoint)
+    }
+}
+
+/// Statistics about a completed table copy.
+#[derive(Clone, Debug, Default)]
+pub struct CopyStats {
+    rows: u64,
+    elapsed: Duration,
+}
+
+impl CopyStats {
+
+    /// Returns the number of rows copied. Rows copied before the checkpoint the copy was resumed
+    /// from are not included.
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Returns the duration of the copy.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Returns the copy throughput in rows per second.
+    pub fn rows_per_second(&self) -> f64 {
+        let secs = self.elapsed.as_secs() as f64 + self.elapsed.subsec_nanos() as f64 / 1e9;
+        if secs == 0.0 { 0.0 } else { self.rows as f64 / secs }
+    }
+}
+
+/// Copies the rows of a table from one cluster to a table with the same name in another cluster,
+/// for instance to back up or restore the table.
+///
+/// The destination table must already exist, and must have a column with the same name and type
+/// for each column of the source table. Rows are upserted, so that a copy resumed from a
+/// checkpoint, or repeated after a failure, does not fail on rows which were already copied.
+///
+/// The source table is divided into at least `CopyTableOptions::num_scans` scans, which are
+/// copied in parallel by `num_scans` threads. Each scan is ordered by primary key, and the progress of every scan is recorded in a
+/// `CopyCheckpoint` after each batch of rows is written.
+pub fn copy_table(src: &Client, dst: &Client, table: &str, options: CopyTableOptions) -> Result<CopyStats> {
+    let start = Instant::now();
+    let deadline = start + options.timeout;
+    let src_table = try!(src.open_table(table, deadline));
+    let dst_table = try!(dst.open_table(table, deadline));
+    try!(check_schemas(src_table.schema(), dst_table.schema()));
+
+    let scans = try!(src_table.scan_builder().split(options.num_scans, deadline));
+    let checkpoint = match options.checkpoint {
+        Some(ref checkpoint) if checkpoint.scans.len() != scans.len() => {
+            return Err(Error::InvalidArgument(format!(
+                        "checkpoint has {} scans, but the copy has {} scans",
+                        checkpoint.scans.len(), scans.len())));
+        },
+        Some(ref checkpoint) => checkpoint.clone(),
+        None => CopyCheckpoint { scans: vec![ScanProgress::NotStarted; scans.len()] },
+    };
+
+    let num_threads = cmp::min(options.num_scans, scans.len());
+    let copy = Arc::new(CopyTask {
+        dst_table: dst_table,
+        options: options,
+        start: start,
+        state: Mutex::new(CopyState {
+            checkpoint: checkpoint,
+            pending: scans.into_iter().enumerate().collect(),
+            rows: 0,
+            error: None,
+        }),
+    });
+
+    let mut threads = Vec::with_capacity(num_threads);
+    for thread_idx in 0..num_threads {
+        let copy = copy.clone();
+        threads.push(thread::Builder::new()
+                                     .name(format!("kudu-copy-{}", thread_idx))
+                                     .spawn(move || copy.copy_scans())
+                                     .unwrap());
+    }
+    for thread in threads {
+        if thread.join().is_err() {
+            return Err(Error::Cancelled);
+        }
+    }
+
+    let mut state = copy.state.lock();
+    if let Some(error) = state.error.take() {
+        return Err(error);
+    }
+    Ok(CopyStats {
+        rows: state.rows,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Checks that rows of the source table can be written to the destination table.
+fn check_schemas(src: &Schema, dst: &Schema) -> Result<()> {
+    for column in src.columns() {
+        match dst.column_by_name(column.name()) {
+            Some(dst_column) if dst_column.data_type() == column.data_type() => (),
+            _ => return Err(Error::InvalidArgument(format!(
+                        "destination table has no column matching source column {:?}", column))),
+        }
+    }
+    Ok(())
+}
+
+/// State shared by the threads of a copy.
+struct CopyTask {
+    dst_table: Table,
+    options: CopyTableOptions,
+    start: Instant,
+    state: Mutex<CopyState>,
+}
+
+struct CopyState {
+    checkpoint: CopyCheckpoint,
+    /// The scans which no thread has started copying, with their index in the checkpoint.
+    pending: VecDeque<(usize, ScanBuilder)>,
+    rows: u64,
+    /// The first error encountered by any scan. Once set, the other scans stop.
+    error: Option<Error>,
+}
+
+impl CopyTask {
+
+    /// Copies pending scans until none are left, or a scan fails.
+    fn copy_scans(&self) {
+        loop {
+            let (idx, scan) = {
+                let mut state = self.state.lock();
+                if state.error.is_some() {
+                    return;
+                }
+                match state.pending.pop_front() {
+                    Some(scan) => scan,
+                    None => return,
+                }
+            };
+            if let Err(error) = self.copy_scan(idx, scan) {
+                let mut state = self.state.lock();
+                if state.error.is_none() {
+                    state.error = Some(error);
+                }
+                return;
+            }
+        }
+    }
+
+    fn copy_scan(&self, idx: usize, mut scan: ScanBuilder) -> Result<()> {
+        match self.state.lock().checkpoint.scans[idx] {
+            ScanProgress::NotStarted => (),
+            ScanProgress::Copied(ref last_key) => {
+                // The smallest encoded key greater than the last copied key.
+                let mut lower_bound = last_key.clone();
+                lower_bound.push(0);
+                scan.set_lower_bound_encoded_primary_key(lower_bound);
+            },
+            ScanProgress::Done => return Ok(()),
+        }
+        scan.set_ordered(true);
+        let mut scanner = try!(scan.build());
+        let mut loader = self.dst_table.new_bulk_loader(self.options.loader_config.clone());
+
+        while let Some(batch) = try!(scanner.next_batch(Instant::now() + self.options.timeout)) {
+            if self.state.lock().error.is_some() {
+                return Ok(());
+            }
+            let last_key = match batch.rows().last() {
+                Some(row) => try!(key::encode_primary_key(row)),
+                None => continue,
+            };
+            let num_rows = batch.len() as u64;
+            for row in batch.into_rows() {
+                try!(loader.upsert(try!(row.project(self.dst_table.schema()))));
+            }
+            try!(loader.flush());
+            if let Some(&(_, ref error)) = loader.stats().errors().first() {
+                return Err(error.clone());
+            }
+
+            self.throttle(num_rows);
+            self.record_progress(idx, ScanProgress::Copied(last_key), num_rows);
+        }
+        self.record_progress(idx, ScanProgress::Done, 0);
+        Ok(())
+    }
+
+    fn record_progress(&self, idx: usize, progress: ScanProgress, num_rows: u64) {
+        let mut state = self.state.lock();
+        state.checkpoint.scans[idx] = progress;
+        state.rows += num_rows;
+        if let Some(ref callback) = self.options.checkpoint_callback {
+            callback(&state.checkpoint);
+        }
+    }
+
+    /// Sleeps until copying `num_rows` more rows keeps the copy within the maximum rate.
+    fn throttle(&self, num_rows: u64) {
+        let max_rows_per_second = match self.options.max_rows_per_second {
+            Some(max_rows_per_second) if max_rows_per_second > 0 => max_rows_per_second,
+            _ => return,
+        };
+        let rows = self.state.lock().rows + num_rows;
+        let us = rows.saturating_mul(1000_000) / max_rows_per_second;
+        let target = Duration::new(us / 1000_000, (us % 1000_000) as u32 * 1000);
+        let elapsed = self.start.elapsed();
+        if target > elapsed {
+            thread::sleep(target - elapsed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use parking_lot::Mutex;
+
+    use Client;
+    use ClientConfig;
+    use Column;
+    use DataType;
+    use SchemaBuilder;
+    use TableBuilder;
+    use mini_cluster::{MiniCluster, MiniClusterConfig};
+    use super::*;
+
+    use env_logger;
+
+    fn deadline() -> Instant {
+        Instant::now() + Duration::from_secs(10)
+    }
+
+    #[test]
+    fn test_checkpoint_serialization() {
+        let checkpoint = CopyCheckpoint {
+            scans: vec![ScanProgress::Done, ScanProgress::Copied(vec![1, 2, 3]), ScanProgress::NotStarted],
+        };
+        assert!(!checkpoint.is_done());
+        assert_eq!(checkpoint, CopyCheckpoint::from_bytes(&checkpoint.to_bytes()).unwrap());
+        assert!(CopyCheckpoint::from_bytes(&checkpoint.to_bytes()[..10]).is_err());
+        // A key length beyond the end of the checkpoint is rejected without allocating the key.
+        assert!(CopyCheckpoint::from_bytes(&[1, 0, 0, 0, 1, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(CopyCheckpoint { scans: vec![ScanProgress::Done] }.is_done());
+    }
+
+    #[test]
+    fn copy() {
+        let _ = env_logger::init();
+        let src_cluster = MiniCluster::new(MiniClusterConfig::default().num_masters(1).num_tservers(1));
+        let dst_cluster = MiniCluster::new(MiniClusterConfig::default().num_masters(1).num_tservers(1));
+        let src = Client::new(ClientConfig::new(src_cluster.master_addrs().to_owned()));
+        let dst = Client::new(ClientConfig::new(dst_cluster.master_addrs().to_owned()));
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("val", DataType::String))
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+        for client in &[&src, &dst] {
+            let mut table_builder = TableBuilder::new("t", schema.clone());
+            table_builder.set_num_replicas(1);
+            table_builder.add_hash_partitions(vec!["key"], 2);
+            client.create_table(table_builder, deadline()).unwrap();
+            client.wait_for_table_creation("t", deadline()).unwrap();
+        }
+
+        let src_table = src.open_table("t", deadline()).unwrap();
+        let mut loader = src_table.new_bulk_loader(BulkLoaderConfig::default());
+        for i in 0..100 {
+            let mut row = src_table.schema().new_row();
+            row.set(0, i).unwrap();
+            row.set(1, format!("val-{}", i)).unwrap();
+            loader.insert(row).unwrap();
+        }
+        assert_eq!(100, loader.finish().unwrap().successful_operations());
+
+        let checkpoints = Arc::new(Mutex::new(Vec::new()));
+        let mut options = CopyTableOptions::default();
+        {
+            let checkpoints = checkpoints.clone();
+            // One thread copies the scans of both tablets.
+            options.set_num_scans(1)
+                   .set_checkpoint_callback(move |checkpoint| checkpoints.lock().push(checkpoint.clone()));
+        }
+        let stats = copy_table(&src, &dst, "t", options.clone()).unwrap();
+        assert_eq!(100, stats.rows());
+        assert!(checkpoints.lock().last().unwrap().is_done());
+
+        let dst_table = dst.open_table("t", deadline()).unwrap();
+        let count = dst_table.scan_builder().build().unwrap().batches()
+                             .map(|batch| batch.unwrap().len())
+                             .sum::<usize>();
+        assert_eq!(100, count);
+
+        // Resuming from the final checkpoint copies nothing.
+        options.set_checkpoint(checkpoints.lock().last().unwrap().clone());
+        assert_eq!(0, copy_table(&src, &dst, "t", options).unwrap().rows());
+    }
+}
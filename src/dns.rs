@@ -1,51 +1,240 @@
 use std::cmp::Ordering;
-use std::collections::HashSet;
-use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
 
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+#[cfg(feature = "local-addrs")]
 use ifaces;
-use kudu_pb::common::HostPortPB;
+use parking_lot::RwLock;
+use rand;
+
+use HostPort;
+
+/// Interval after which the local interface addresses are refreshed.
+const LOCAL_ADDRS_REFRESH_INTERVAL: u64 = 60;
+
+/// Detects the addresses of the local host, which are used to prefer replicas on the local host.
+///
+/// Loopback addresses are always considered local, so a detector only needs to return the
+/// addresses of the host's other interfaces.
+pub trait LocalAddrDetector: fmt::Debug + Send + Sync {
+
+    /// Returns the addresses of the local network interfaces.
+    fn local_addrs(&self) -> io::Result<HashSet<IpAddr>>;
+}
+
+/// Detects local addresses by enumerating the network interfaces of the host. Available with the
+/// `local-addrs` feature, which is enabled by default.
+#[cfg(feature = "local-addrs")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InterfaceAddrDetector;
+
+#[cfg(feature = "local-addrs")]
+impl LocalAddrDetector for InterfaceAddrDetector {
+    fn local_addrs(&self) -> io::Result<HashSet<IpAddr>> {
+        let ifaces = try!(ifaces::Interface::get_all());
+        Ok(ifaces.into_iter().filter_map(|iface| iface.addr).map(|addr| addr.ip()).collect())
+    }
+}
+
+/// A detector which finds no local addresses, for platforms where the network interfaces can not
+/// be enumerated. Only loopback addresses are considered local.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoLocalAddrDetector;
+
+impl LocalAddrDetector for NoLocalAddrDetector {
+    fn local_addrs(&self) -> io::Result<HashSet<IpAddr>> {
+        Ok(HashSet::new())
+    }
+}
+
+#[cfg(feature = "local-addrs")]
+fn default_local_addr_detector() -> Box<LocalAddrDetector> {
+    Box::new(InterfaceAddrDetector)
+}
+
+#[cfg(not(feature = "local-addrs"))]
+fn default_local_addr_detector() -> Box<LocalAddrDetector> {
+    Box::new(NoLocalAddrDetector)
+}
+
+/// The addresses of the local network interfaces, and the time they were retrieved.
+struct LocalAddrs {
+    addrs: HashSet<IpAddr>,
+    refreshed: Instant,
+}
 
 lazy_static! {
-    static ref LOCAL_ADDRS: HashSet<IpAddr> = {
-        let mut addrs = HashSet::new();
-
-        match ifaces::Interface::get_all() {
-            Ok(ifaces) => {
-                for iface in ifaces {
-                    if let Some(addr) = iface.addr {
-                        addrs.insert(addr.ip());
-                    }
-                }
-            },
-            Err(error) => {
-                warn!("failed to resolve local interface addresses: {}", error);
-            },
+    static ref LOCAL_ADDR_DETECTOR: RwLock<Box<LocalAddrDetector>> =
+        RwLock::new(default_local_addr_detector());
+
+    static ref LOCAL_ADDRS: RwLock<LocalAddrs> = RwLock::new(LocalAddrs {
+        addrs: local_interface_addrs(),
+        refreshed: Instant::now(),
+    });
+}
+
+fn local_interface_addrs() -> HashSet<IpAddr> {
+    match LOCAL_ADDR_DETECTOR.read().local_addrs() {
+        Ok(addrs) => addrs,
+        Err(error) => {
+            warn!("failed to resolve local interface addresses: {}", error);
+            HashSet::new()
+        },
+    }
+}
+
+/// Replaces the detector used to find the addresses of the local host, and refreshes the local
+/// addresses with it.
+///
+/// Defaults to `InterfaceAddrDetector` when the `local-addrs` feature is enabled, and to
+/// `NoLocalAddrDetector` otherwise.
+pub fn set_local_addr_detector<D>(detector: D) where D: LocalAddrDetector + 'static {
+    *LOCAL_ADDR_DETECTOR.write() = Box::new(detector);
+    refresh_local_addrs();
+}
+
+/// Refreshes the cached set of local network interface addresses, which is used to prefer
+/// replicas on the local host.
+///
+/// The addresses are refreshed automatically every minute. Applications running on hosts whose
+/// interfaces change, for instance when a VPN connects, may call this to pick up the change
+/// immediately.
+pub fn refresh_local_addrs() {
+    let addrs = local_interface_addrs();
+    let mut local_addrs = LOCAL_ADDRS.write();
+    local_addrs.addrs = addrs;
+    local_addrs.refreshed = Instant::now();
+}
+
+/// Resolves hostnames and service discovery records to socket addresses.
+///
+/// The client resolves the master and tablet server addresses it is configured with or
+/// discovers through a `Resolver`, so that applications can substitute their own service
+/// discovery mechanism for the system resolver. `StaticResolver` serves fixed addresses, which
+/// allows tests to run without DNS.
+pub trait Resolver: fmt::Debug + Send + Sync {
+
+    /// Resolves a host and port to a set of socket addresses.
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+
+    /// Resolves an SRV record to its targets, in order of preference.
+    ///
+    /// By default SRV records are not supported.
+    fn resolve_srv(&self, name: &str) -> io::Result<Vec<HostPort>> {
+        Err(io::Error::new(io::ErrorKind::Other,
+                           format!("SRV records are not supported by this resolver: {}", name)))
+    }
+}
+
+/// Resolves hostnames with the operating system's resolver, and SRV records by querying the
+/// nameservers in `/etc/resolv.conf`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        (host, port).to_socket_addrs().map(|addrs| addrs.collect())
+    }
+
+    fn resolve_srv(&self, name: &str) -> io::Result<Vec<HostPort>> {
+        resolve_srv(name).map(|targets| {
+            targets.into_iter().map(|(host, port)| HostPort::new(host, port)).collect()
+        })
+    }
+}
+
+/// Resolves hostnames and SRV records from fixed tables. Unknown names fail to resolve.
+#[derive(Clone, Debug, Default)]
+pub struct StaticResolver {
+    hosts: HashMap<String, Vec<IpAddr>>,
+    srv_records: HashMap<String, Vec<HostPort>>,
+}
+
+impl StaticResolver {
+
+    pub fn new() -> StaticResolver {
+        StaticResolver::default()
+    }
+
+    /// Adds an address for a hostname.
+    pub fn add_host<S>(&mut self, host: S, addr: IpAddr) -> &mut StaticResolver where S: Into<String> {
+        self.hosts.entry(host.into()).or_insert_with(Vec::new).push(addr);
+        self
+    }
+
+    /// Adds a target to an SRV record. Targets are returned in the order they are added.
+    pub fn add_srv_target<S, T>(&mut self, name: S, host: T, port: u16) -> &mut StaticResolver
+    where S: Into<String>, T: Into<String> {
+        self.srv_records.entry(name.into()).or_insert_with(Vec::new).push(HostPort::new(host, port));
+        self
+    }
+}
+
+impl Resolver for StaticResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        if let Ok(addr) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(addr, port)]);
         }
-        addrs
-    };
+        match self.hosts.get(host) {
+            Some(addrs) => Ok(addrs.iter().map(|&addr| SocketAddr::new(addr, port)).collect()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound,
+                                       format!("unknown host: {}", host))),
+        }
+    }
+
+    fn resolve_srv(&self, name: &str) -> io::Result<Vec<HostPort>> {
+        match self.srv_records.get(name) {
+            Some(targets) => Ok(targets.clone()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound,
+                                       format!("unknown SRV record: {}", name))),
+        }
+    }
+}
+
+/// The outcome of resolving a set of hosts, which may partially fail.
+#[derive(Debug, Default)]
+pub struct Resolution {
+    /// The addresses of the hosts which resolved.
+    pub addrs: HashSet<SocketAddr>,
+    /// The hosts which failed to resolve, along with the cause.
+    pub failures: Vec<(HostPort, io::Error)>,
+}
+
+impl Resolution {
+    /// Returns `true` if every host resolved.
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
 }
 
 /// Resolves a sequence of hostnames into a set of socket addresses. If the hostname DNS lookup
-/// fails, it is filtered from the result. The results are passed to the provided callback upon
-/// completion.
-pub fn resolve_hosts(hostports: &[HostPortPB]) -> HashSet<SocketAddr> {
-    let mut addrs = HashSet::new();
+/// fails, it is filtered from the addresses and recorded in the failures of the resolution.
+pub fn resolve_hosts(resolver: &Resolver, hostports: &[HostPort]) -> Resolution {
+    let mut resolution = Resolution::default();
     for hostport in hostports {
-        match (hostport.get_host(), hostport.get_port() as u16).to_socket_addrs() {
-            Ok(resolved_addrs) => addrs.extend(resolved_addrs),
-            Err(error) => warn!("unable to resolve host '{}': {}",
-                                hostport.get_host(), error),
+        match resolver.resolve(hostport.host(), hostport.port()) {
+            Ok(ref addrs) if addrs.is_empty() => {
+                let error = io::Error::new(io::ErrorKind::NotFound, "host has no addresses");
+                resolution.failures.push((hostport.clone(), error));
+            },
+            Ok(addrs) => resolution.addrs.extend(addrs),
+            Err(error) => resolution.failures.push((hostport.clone(), error)),
         }
     }
-    addrs
+    resolution
 }
 
-pub fn resolve_hostports(hostports: &[(String, u16)]) -> Vec<SocketAddr> {
+pub fn resolve_hostports(resolver: &Resolver, hostports: &[HostPort]) -> Vec<SocketAddr> {
     let mut addrs = Vec::new();
-    for &(ref host, port) in hostports {
-        match (host.as_str(), port).to_socket_addrs() {
+    for hostport in hostports {
+        match resolver.resolve(hostport.host(), hostport.port()) {
             Ok(resolved_addrs) => addrs.extend(resolved_addrs),
-            Err(error) => warn!("unable to resolve hostname '{:?}': {}", host, error),
+            Err(error) => warn!("unable to resolve hostname '{:?}': {}", hostport.host(), error),
         }
     }
     addrs.sort_by(cmp_socket_addrs);
@@ -62,9 +251,187 @@ fn cmp_socket_addrs(a: &SocketAddr, b: &SocketAddr) -> Ordering {
     }
 }
 
+/// DNS resource record type of SRV records.
+const SRV_TYPE: u16 = 33;
+
+/// DNS resource record class for Internet records.
+const IN_CLASS: u16 = 1;
+
+/// Amount of time to wait for a response from each nameserver.
+const SRV_QUERY_TIMEOUT_SECS: u64 = 2;
+
+/// Resolves the targets of a DNS SRV record, such as `_kudu-master._tcp.cluster.local`, by
+/// querying the nameservers listed in `/etc/resolv.conf` in turn. The targets are returned as
+/// `(host, port)` pairs, ordered by priority and then by descending weight.
+///
+/// Queries are sent over UDP only; a truncated response is treated as an error.
+fn resolve_srv(name: &str) -> io::Result<Vec<(String, u16)>> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no nameservers configured");
+    for nameserver in nameservers() {
+        match query_srv(nameserver, name) {
+            Ok(targets) => return Ok(targets),
+            Err(error) => {
+                debug!("SRV query for {} to nameserver {} failed: {}", name, nameserver, error);
+                last_error = error;
+            },
+        }
+    }
+    Err(last_error)
+}
+
+/// Returns the nameservers listed in `/etc/resolv.conf`, or the local host if there are none.
+fn nameservers() -> Vec<SocketAddr> {
+    let mut nameservers = Vec::new();
+    if let Ok(file) = File::open("/etc/resolv.conf") {
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            let mut words = line.split_whitespace();
+            if words.next() != Some("nameserver") { continue; }
+            if let Some(addr) = words.next().and_then(|addr| addr.parse::<IpAddr>().ok()) {
+                nameservers.push(SocketAddr::new(addr, 53));
+            }
+        }
+    }
+    if nameservers.is_empty() {
+        nameservers.push(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 53));
+    }
+    nameservers
+}
+
+fn query_srv(nameserver: SocketAddr, name: &str) -> io::Result<Vec<(String, u16)>> {
+    let bind_addr = match nameserver {
+        SocketAddr::V4(_) => "0.0.0.0:0",
+        SocketAddr::V6(_) => "[::]:0",
+    };
+    let socket = try!(UdpSocket::bind(bind_addr));
+    try!(socket.set_read_timeout(Some(Duration::from_secs(SRV_QUERY_TIMEOUT_SECS))));
+
+    let id = rand::random::<u16>();
+    let query = try!(encode_srv_query(id, name));
+    try!(socket.send_to(&query, nameserver));
+
+    let mut buf = [0; 4096];
+    loop {
+        let (len, from) = try!(socket.recv_from(&mut buf));
+        // Ignore stray datagrams which are not responses to the query.
+        if from != nameserver || len < 2 || BigEndian::read_u16(&buf) != id { continue; }
+        let mut records = try!(decode_srv_response(&buf[..len]));
+        records.sort_by(|a, b| (a.0, b.1).cmp(&(b.0, a.1)));
+        return Ok(records.into_iter().map(|(_, _, port, target)| (target, port)).collect());
+    }
+}
+
+/// Encodes a recursive DNS query for the SRV record with the provided name.
+fn encode_srv_query(id: u16, name: &str) -> io::Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(18 + name.len());
+    try!(query.write_u16::<BigEndian>(id));
+    // Flags: standard query, recursion desired.
+    try!(query.write_u16::<BigEndian>(0x0100));
+    // Question, answer, authority, and additional record counts.
+    try!(query.write_u16::<BigEndian>(1));
+    try!(query.write_u16::<BigEndian>(0));
+    try!(query.write_u16::<BigEndian>(0));
+    try!(query.write_u16::<BigEndian>(0));
+    for label in name.trim_right_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("invalid DNS name: {:?}", name)));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    try!(query.write_u16::<BigEndian>(SRV_TYPE));
+    try!(query.write_u16::<BigEndian>(IN_CLASS));
+    Ok(query)
+}
+
+/// Decodes the SRV records in a DNS response as `(priority, weight, port, target)` tuples.
+fn decode_srv_response(msg: &[u8]) -> io::Result<Vec<(u16, u16, u16, String)>> {
+    fn invalid(msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, msg.to_owned())
+    }
+    fn check_len(msg: &[u8], len: usize) -> io::Result<()> {
+        if msg.len() < len { Err(invalid("truncated DNS message")) } else { Ok(()) }
+    }
+
+    try!(check_len(msg, 12));
+    let flags = BigEndian::read_u16(&msg[2..]);
+    if flags & 0x0200 != 0 {
+        return Err(invalid("DNS response truncated"));
+    }
+    match flags & 0x000f {
+        0 => (),
+        3 => return Err(io::Error::new(io::ErrorKind::NotFound, "DNS name does not exist")),
+        rcode => return Err(invalid(&format!("DNS query failed with response code {}", rcode))),
+    }
+    let questions = BigEndian::read_u16(&msg[4..]);
+    let answers = BigEndian::read_u16(&msg[6..]);
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = try!(decode_name(msg, pos)).1 + 4;
+    }
+
+    let mut records = Vec::with_capacity(answers as usize);
+    for _ in 0..answers {
+        pos = try!(decode_name(msg, pos)).1;
+        try!(check_len(msg, pos + 10));
+        let rtype = BigEndian::read_u16(&msg[pos..]);
+        let rdlength = BigEndian::read_u16(&msg[pos + 8..]) as usize;
+        pos += 10;
+        try!(check_len(msg, pos + rdlength));
+        if rtype == SRV_TYPE {
+            try!(check_len(msg, pos + 6));
+            let priority = BigEndian::read_u16(&msg[pos..]);
+            let weight = BigEndian::read_u16(&msg[pos + 2..]);
+            let port = BigEndian::read_u16(&msg[pos + 4..]);
+            let target = try!(decode_name(msg, pos + 6)).0;
+            records.push((priority, weight, port, target));
+        }
+        pos += rdlength;
+    }
+    Ok(records)
+}
+
+/// Decodes a possibly compressed domain name beginning at `pos`. Returns the name, and the
+/// position following the name.
+fn decode_name(msg: &[u8], mut pos: usize) -> io::Result<(String, usize)> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid DNS name");
+    let mut name = String::new();
+    let mut end = None;
+    // Bound the number of compression pointers followed, so that a malicious response can not
+    // cause an infinite loop.
+    let mut jumps = 0;
+    loop {
+        let len = *try!(msg.get(pos).ok_or_else(&invalid)) as usize;
+        if len & 0xc0 == 0xc0 {
+            let offset = (BigEndian::read_u16(try!(msg.get(pos..pos + 2).ok_or_else(&invalid))) & 0x3fff) as usize;
+            if end.is_none() { end = Some(pos + 2); }
+            jumps += 1;
+            if jumps > 16 { return Err(invalid()); }
+            pos = offset;
+        } else if len == 0 {
+            return Ok((name, end.unwrap_or(pos + 1)));
+        } else {
+            let label = try!(msg.get(pos + 1..pos + 1 + len).ok_or_else(&invalid));
+            if !name.is_empty() { name.push('.'); }
+            name.push_str(&String::from_utf8_lossy(label));
+            pos += 1 + len;
+        }
+    }
+}
+
 /// Returns `true` if socket addr is for a local interface.
 pub fn is_local_addr(addr: &IpAddr) -> bool {
-    LOCAL_ADDRS.contains(addr) || match *addr {
+    let stale = LOCAL_ADDRS.read().refreshed.elapsed() >= Duration::from_secs(LOCAL_ADDRS_REFRESH_INTERVAL);
+    if stale {
+        refresh_local_addrs();
+    }
+    LOCAL_ADDRS.read().addrs.contains(addr) || match *addr {
         // TODO: unwrapping will be unnecessary once 1.12 lands in stable.
         IpAddr::V4(ref addr) => addr.is_loopback(),
         IpAddr::V6(ref addr) => addr.is_loopback(),
@@ -84,4 +451,100 @@ mod tests {
         let addr = "127.0.0.1:0".to_socket_addrs().unwrap().next().unwrap().ip();
         assert!(is_local_addr(&addr));
     }
+
+    #[test]
+    fn test_resolve_hosts() {
+        let mut resolver = StaticResolver::new();
+        resolver.add_host("master-1", "10.0.0.1".parse().unwrap());
+
+        let hostports = vec![HostPort::new("master-1", 7051), HostPort::new("master-2", 7051)];
+
+        let resolution = resolve_hosts(&resolver, &hostports[..1]);
+        assert!(resolution.is_complete());
+        assert_eq!(1, resolution.addrs.len());
+
+        let resolution = resolve_hosts(&resolver, &hostports);
+        assert!(!resolution.is_complete());
+        assert_eq!(1, resolution.addrs.len());
+        assert_eq!(1, resolution.failures.len());
+        assert_eq!(HostPort::new("master-2", 7051), resolution.failures[0].0);
+    }
+
+    #[test]
+    fn test_static_resolver() {
+        let mut resolver = StaticResolver::new();
+        resolver.add_host("master-1", "10.0.0.1".parse().unwrap())
+                .add_host("master-1", "10.0.0.2".parse().unwrap())
+                .add_srv_target("_kudu-master._tcp", "master-1", 7051)
+                .add_srv_target("_kudu-master._tcp", "10.0.0.3", 7052);
+
+        let targets = resolver.resolve_srv("_kudu-master._tcp").unwrap();
+        assert_eq!(vec![HostPort::new("master-1", 7051), HostPort::new("10.0.0.3", 7052)], targets);
+
+        let addrs = resolve_hostports(&resolver, &targets);
+        assert_eq!(vec!["10.0.0.1:7051".parse::<SocketAddr>().unwrap(),
+                        "10.0.0.2:7051".parse().unwrap(),
+                        "10.0.0.3:7052".parse().unwrap()],
+                   addrs);
+
+        assert!(resolver.resolve("master-2", 7051).is_err());
+        assert!(resolver.resolve_srv("_kudu-tserver._tcp").is_err());
+        assert!(resolve_hostports(&resolver, &[HostPort::new("master-2", 7051)]).is_empty());
+    }
+
+    #[test]
+    fn test_decode_srv_response() {
+        let mut msg = encode_srv_query(42, "_kudu-master._tcp.cluster.local").unwrap();
+        // Mark the message as a response with two answers.
+        msg[2] = 0x81;
+        msg[3] = 0x80;
+        msg[7] = 2;
+        for &(priority, weight, port, target) in &[(10u16, 5u16, 7051u16, "m1"), (0, 0, 7052, "m2")] {
+            // Name: a pointer to the question name.
+            msg.extend_from_slice(&[0xc0, 12]);
+            msg.write_u16::<BigEndian>(SRV_TYPE).unwrap();
+            msg.write_u16::<BigEndian>(IN_CLASS).unwrap();
+            msg.write_u32::<BigEndian>(60).unwrap();
+            msg.write_u16::<BigEndian>(6 + 1 + target.len() as u16 + 2).unwrap();
+            msg.write_u16::<BigEndian>(priority).unwrap();
+            msg.write_u16::<BigEndian>(weight).unwrap();
+            msg.write_u16::<BigEndian>(port).unwrap();
+            // Target: a label followed by a pointer to the "cluster.local" suffix.
+            msg.push(target.len() as u8);
+            msg.extend_from_slice(target.as_bytes());
+            msg.extend_from_slice(&[0xc0, 12 + 18]);
+        }
+
+        let records = decode_srv_response(&msg).unwrap();
+        assert_eq!(vec![(10, 5, 7051, "m1.cluster.local".to_owned()),
+                        (0, 0, 7052, "m2.cluster.local".to_owned())],
+                   records);
+
+        // Name error.
+        msg[3] = 0x83;
+        assert_eq!(io::ErrorKind::NotFound, decode_srv_response(&msg).unwrap_err().kind());
+
+        // Truncated message.
+        msg[3] = 0x80;
+        assert!(decode_srv_response(&msg[..msg.len() - 4]).is_err());
+
+        assert!(encode_srv_query(0, "invalid..name").is_err());
+    }
+
+    #[test]
+    fn test_no_local_addr_detector() {
+        assert!(NoLocalAddrDetector.local_addrs().unwrap().is_empty());
+        assert!(is_local_addr(&"127.0.0.1".parse().unwrap()));
+        assert!(is_local_addr(&"::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_refresh_local_addrs() {
+        let before = LOCAL_ADDRS.read().refreshed;
+        refresh_local_addrs();
+        assert!(LOCAL_ADDRS.read().refreshed >= before);
+        for addr in LOCAL_ADDRS.read().addrs.clone() {
+            assert!(is_local_addr(&addr));
+        }
+    }
 }
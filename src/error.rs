@@ -2,7 +2,9 @@ use std::error;
 use std::fmt;
 use std::io;
 use std::mem;
+use std::net::SocketAddr;
 use std::result;
+use std::time::Duration;
 use std::str::Utf8Error;
 use std::string::FromUtf8Error;
 
@@ -24,6 +26,11 @@ use kudu_pb::wire_protocol::{
 };
 use protobuf::ProtobufError;
 
+use DataType;
+use EncodingType;
+use ServerFeature;
+use ServerVersion;
+
 pub type Result<T> = result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -32,6 +39,9 @@ pub enum Error {
     /// The operation failed because of an invalid argument.
     InvalidArgument(String),
 
+    /// A schema definition is invalid. Includes every problem found with the schema.
+    InvalidSchema(Vec<SchemaError>),
+
     /// A Kudu RPC error.
     Rpc(RpcError),
 
@@ -53,34 +63,138 @@ pub enum Error {
     /// The send queue is full.
     Backoff,
 
-    /// The operation timed out. Includes zero or more errors which resulted in retries.
+    /// The operation timed out.
     TimedOut,
 
+    /// The RPC timed out before it was sent, while waiting in the client: in a connection's send
+    /// queue, for a reconnect, for a retry backoff, or for the leader master to be discovered.
+    /// Usually caused by an overloaded or unreachable server, or by too many RPCs in flight.
+    QueueTimedOut,
+
+    /// The RPC was sent, but timed out waiting for the server's response. Usually caused by a
+    /// slow server or network, or by a deadline too short for the work requested.
+    WireTimedOut,
+
+    /// The operation's deadline passed after it was attempted more than once. Includes the error
+    /// of each attempt.
+    DeadlineExceeded(DeadlineExceededError),
+
     /// The operation was cancelled.
     Cancelled,
 
     /// The connection encountered an error or hangup.
     ConnectionError,
 
+    /// The TCP connection was not established within the connect timeout.
+    ConnectTimedOut,
+
+    /// The connection did not complete negotiation within the negotiation timeout.
+    NegotiationTimedOut,
+
     NegotiationError(&'static str),
 
+    /// Connection negotiation failed. Includes the error which caused the failure, and the
+    /// negotiation transcript. Only returned when `ConnectionOptions::negotiation_transcript` is
+    /// enabled; otherwise the cause is returned directly.
+    NegotiationFailed(NegotiationFailedError),
+
     /// An operation failed because the range partition did not exist.
     NoRangePartition,
+
+    /// An RPC failed because its connection was reset. Includes the error which caused the reset.
+    ConnectionReset(ConnectionResetError),
+
+    /// The operation failed because the client was shut down.
+    Shutdown,
+
+    /// An RPC was not sent because the circuit breaker of the server is open, following repeated
+    /// connection failures.
+    CircuitOpen(SocketAddr),
+
+    /// The operation uses a feature which a server in the cluster is too old to support.
+    UnsupportedByServer(UnsupportedByServerError),
 }
 
 impl Error {
+    /// Wraps the error of an operation's final attempt in `Error::DeadlineExceeded`, following the
+    /// errors of its earlier attempts. Returns the error unchanged if there were no earlier
+    /// attempts.
+    #[doc(hidden)]
+    pub fn after_attempts(self, mut attempts: Vec<Error>) -> Error {
+        if attempts.is_empty() {
+            return self;
+        }
+        match self {
+            Error::DeadlineExceeded(error) => attempts.extend(error.errors),
+            error => attempts.push(error),
+        }
+        Error::DeadlineExceeded(DeadlineExceededError::new(attempts))
+    }
+
+    /// Returns `true` if the error is a timeout of an RPC or operation: `TimedOut`,
+    /// `QueueTimedOut`, `WireTimedOut`, or `DeadlineExceeded`. Connect and negotiation timeouts
+    /// are network errors instead.
+    pub fn is_timeout(&self) -> bool {
+        match *self {
+            Error::TimedOut |
+            Error::QueueTimedOut |
+            Error::WireTimedOut |
+            Error::DeadlineExceeded(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the error is a tablet server's `Throttled` error, which indicates that
+    /// the server is under memory pressure and the request should be resent after a backoff, at
+    /// a lower rate.
+    pub fn is_throttled(&self) -> bool {
+        match *self {
+            Error::TabletServer(ref error) => error.is_throttled(),
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the error is a failure to reach a server or a loss of the connection to
+    /// it. `CircuitOpen` is not a network error, since resending the RPC to the same server fails
+    /// again until the breaker's open duration has passed.
     pub fn is_network_error(&self) -> bool {
         match *self {
-            Error::Io(_) | Error::ConnectionError => true,
+            Error::Io(_) |
+            Error::ConnectionError |
+            Error::ConnectTimedOut |
+            Error::NegotiationTimedOut => true,
+            Error::ConnectionReset(ref error) => error.cause().is_network_error(),
+            Error::NegotiationFailed(ref error) => error.cause().is_network_error(),
             _ => false,
         }
     }
+
+    /// Returns `true` if the RPC was not sent because the server's circuit breaker is open. The RPC
+    /// may be sent to another replica instead.
+    pub fn is_circuit_open(&self) -> bool {
+        match *self {
+            Error::CircuitOpen(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the underlying cause of the error. For `Error::ConnectionReset` this is the error
+    /// which caused the connection to be reset, and for `Error::NegotiationFailed` the error which
+    /// caused negotiation to fail. Otherwise it is the error itself.
+    pub fn root_cause(&self) -> &Error {
+        match *self {
+            Error::ConnectionReset(ref error) => error.cause().root_cause(),
+            Error::NegotiationFailed(ref error) => error.cause().root_cause(),
+            ref error => error,
+        }
+    }
 }
 
 impl Clone for Error {
     fn clone(&self) -> Error {
         match *self {
             Error::InvalidArgument(ref error) => Error::InvalidArgument(error.clone()),
+            Error::InvalidSchema(ref errors) => Error::InvalidSchema(errors.clone()),
             Error::Rpc(ref error) => Error::Rpc(error.clone()),
             Error::Master(ref error) => Error::Master(error.clone()),
             Error::TabletServer(ref error) => Error::TabletServer(error.clone()),
@@ -93,10 +207,20 @@ impl Clone for Error {
             Error::VersionMismatch(ref error) => Error::VersionMismatch(error.clone()),
             Error::Backoff => Error::Backoff,
             Error::TimedOut => Error::TimedOut,
+            Error::QueueTimedOut => Error::QueueTimedOut,
+            Error::WireTimedOut => Error::WireTimedOut,
+            Error::DeadlineExceeded(ref error) => Error::DeadlineExceeded(error.clone()),
             Error::Cancelled => Error::Cancelled,
             Error::ConnectionError => Error::ConnectionError,
+            Error::ConnectTimedOut => Error::ConnectTimedOut,
+            Error::NegotiationTimedOut => Error::NegotiationTimedOut,
             Error::NegotiationError(error) => Error::NegotiationError(error),
+            Error::NegotiationFailed(ref error) => Error::NegotiationFailed(error.clone()),
             Error::NoRangePartition => Error::NoRangePartition,
+            Error::ConnectionReset(ref error) => Error::ConnectionReset(error.clone()),
+            Error::Shutdown => Error::Shutdown,
+            Error::CircuitOpen(addr) => Error::CircuitOpen(addr),
+            Error::UnsupportedByServer(ref error) => Error::UnsupportedByServer(error.clone()),
         }
     }
 }
@@ -105,6 +229,7 @@ impl PartialEq for Error {
     fn eq(&self, other: &Error) -> bool {
         match (self, other) {
             (&Error::InvalidArgument(ref a), &Error::InvalidArgument(ref b)) => a == b,
+            (&Error::InvalidSchema(ref a), &Error::InvalidSchema(ref b)) => a == b,
             (&Error::Rpc(ref a), &Error::Rpc(ref b)) => a == b,
             (&Error::Master(ref a), &Error::Master(ref b)) => a == b,
             (&Error::TabletServer(ref a), &Error::TabletServer(ref b)) => a == b,
@@ -113,10 +238,20 @@ impl PartialEq for Error {
             (&Error::VersionMismatch(ref a), &Error::VersionMismatch(ref b)) => a == b,
             (&Error::Backoff, &Error::Backoff) => true,
             (&Error::TimedOut, &Error::TimedOut) => true,
+            (&Error::QueueTimedOut, &Error::QueueTimedOut) => true,
+            (&Error::WireTimedOut, &Error::WireTimedOut) => true,
+            (&Error::DeadlineExceeded(ref a), &Error::DeadlineExceeded(ref b)) => a == b,
             (&Error::Cancelled, &Error::Cancelled) => true,
             (&Error::ConnectionError, &Error::ConnectionError) => true,
+            (&Error::ConnectTimedOut, &Error::ConnectTimedOut) => true,
+            (&Error::NegotiationTimedOut, &Error::NegotiationTimedOut) => true,
             (&Error::NegotiationError(ref a), &Error::NegotiationError(ref b)) => a == b,
+            (&Error::NegotiationFailed(ref a), &Error::NegotiationFailed(ref b)) => a == b,
             (&Error::NoRangePartition, &Error::NoRangePartition) => true,
+            (&Error::ConnectionReset(ref a), &Error::ConnectionReset(ref b)) => a == b,
+            (&Error::Shutdown, &Error::Shutdown) => true,
+            (&Error::CircuitOpen(ref a), &Error::CircuitOpen(ref b)) => a == b,
+            (&Error::UnsupportedByServer(ref a), &Error::UnsupportedByServer(ref b)) => a == b,
             _ => false,
         }
     }
@@ -126,6 +261,7 @@ impl error::Error for Error {
     fn description(&self) -> &str {
         match *self {
             Error::InvalidArgument(_) => "illegal argument",
+            Error::InvalidSchema(_) => "invalid schema",
             Error::Rpc(ref error) => error.description(),
             Error::Master(ref error) => error.description(),
             Error::TabletServer(ref error) => error.description(),
@@ -134,16 +270,27 @@ impl error::Error for Error {
             Error::VersionMismatch(ref description) => description,
             Error::Backoff => "backoff",
             Error::TimedOut => "operation timed out",
+            Error::QueueTimedOut => "RPC timed out before being sent",
+            Error::WireTimedOut => "RPC timed out waiting for a response",
+            Error::DeadlineExceeded(_) => "deadline exceeded",
             Error::Cancelled => "operation cancelled",
             Error::ConnectionError => "connection error",
+            Error::ConnectTimedOut => "connect timed out",
+            Error::NegotiationTimedOut => "connection negotiation timed out",
             Error::NegotiationError(error) => error,
+            Error::NegotiationFailed(_) => "connection negotiation failed",
             Error::NoRangePartition => "no range partition",
+            Error::ConnectionReset(_) => "connection reset",
+            Error::Shutdown => "client shut down",
+            Error::CircuitOpen(_) => "circuit breaker open",
+            Error::UnsupportedByServer(_) => "feature not supported by server",
         }
     }
 
     fn cause(&self) -> Option<&error::Error> {
         match *self {
             Error::InvalidArgument(_) => None,
+            Error::InvalidSchema(_) => None,
             Error::Rpc(ref error) => error.cause(),
             Error::Master(ref error) => error.cause(),
             Error::TabletServer(ref error) => error.cause(),
@@ -152,10 +299,20 @@ impl error::Error for Error {
             Error::VersionMismatch(_) => None,
             Error::Backoff => None,
             Error::TimedOut => None,
+            Error::QueueTimedOut => None,
+            Error::WireTimedOut => None,
+            Error::DeadlineExceeded(ref error) => Some(error.last_error()),
             Error::Cancelled => None,
             Error::ConnectionError => None,
+            Error::ConnectTimedOut => None,
+            Error::NegotiationTimedOut => None,
             Error::NegotiationError(_) => None,
+            Error::NegotiationFailed(ref error) => Some(error.cause()),
             Error::NoRangePartition => None,
+            Error::ConnectionReset(ref error) => Some(error.cause()),
+            Error::Shutdown => None,
+            Error::CircuitOpen(_) => None,
+            Error::UnsupportedByServer(_) => None,
         }
     }
 }
@@ -214,6 +371,38 @@ impl fmt::Display for Error {
     }
 }
 
+/// A problem with a schema definition, found by `SchemaBuilder::build`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SchemaError {
+    /// The primary key has no columns.
+    EmptyPrimaryKey,
+    /// More than one column has the name.
+    DuplicateColumn(String),
+    /// The column is listed more than once in the primary key.
+    DuplicatePrimaryKeyColumn(String),
+    /// The primary key column has no corresponding column.
+    MissingPrimaryKeyColumn(String),
+    /// The primary key column is nullable.
+    NullablePrimaryKeyColumn(String),
+    /// The primary key column has a type which can not be part of a primary key.
+    UnsupportedPrimaryKeyType(String, DataType),
+    /// The primary key column is immutable.
+    ImmutablePrimaryKeyColumn(String),
+    /// The column name is reserved for the auto-incrementing column of a non-unique primary key.
+    ReservedColumnName(String),
+    /// The column's block size is larger than the maximum supported block size.
+    InvalidBlockSize(String, u32),
+    /// The column's encoding is not supported for its data type. See
+    /// `DataType::supported_encodings`.
+    UnsupportedEncoding(String, DataType, EncodingType),
+    /// The column is listed more than once in the range partition columns.
+    DuplicateRangePartitionColumn(String),
+    /// The range partition column has no corresponding column.
+    MissingRangePartitionColumn(String),
+    /// The range partition column is not part of the primary key.
+    RangePartitionColumnNotInPrimaryKey(String),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RpcErrorCode {
     // Non-fatal RPC errors. Connection should be left open for future RPC calls.
@@ -249,11 +438,227 @@ pub enum RpcErrorCode {
     FatalUnauthorized,
 }
 
+/// The failure of an RPC due to a connection reset.
+///
+/// RPCs which are not failed fast are retried on the reset connection until they time out, so an
+/// RPC may have been through several resets before it fails. The error records the cause of the
+/// final reset, along with the number of resets the RPC went through and the time since the RPC
+/// was first queued.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionResetError {
+    cause: Box<Error>,
+    attempts: u32,
+    elapsed: Duration,
+}
+
+impl ConnectionResetError {
+    #[doc(hidden)]
+    pub fn new(cause: Error, attempts: u32, elapsed: Duration) -> ConnectionResetError {
+        ConnectionResetError {
+            cause: Box::new(cause),
+            attempts: attempts,
+            elapsed: elapsed,
+        }
+    }
+
+    /// The error which caused the connection to be reset, for instance a socket error, a
+    /// negotiation failure, or a fatal error returned by the server.
+    pub fn cause(&self) -> &Error {
+        &self.cause
+    }
+
+    /// The number of attempts to deliver the RPC which failed due to a connection reset.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// The time between first queuing the RPC and failing it.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+impl fmt::Display for ConnectionResetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "connection reset after {} attempt(s) in {:?}: {}",
+               self.attempts, self.elapsed, self.cause)
+    }
+}
+
+/// An operation which was not sent because it uses a feature that a server in the cluster is too
+/// old to support. See `ServerVersions`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedByServerError {
+    feature: ServerFeature,
+    version: ServerVersion,
+}
+
+impl UnsupportedByServerError {
+    #[doc(hidden)]
+    pub fn new(feature: ServerFeature, version: ServerVersion) -> UnsupportedByServerError {
+        UnsupportedByServerError {
+            feature: feature,
+            version: version,
+        }
+    }
+
+    /// The unsupported feature.
+    pub fn feature(&self) -> ServerFeature {
+        self.feature
+    }
+
+    /// The version of the oldest server serving the feature.
+    pub fn version(&self) -> ServerVersion {
+        self.version
+    }
+}
+
+impl fmt::Display for UnsupportedByServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} require Kudu {} or later, but a server runs Kudu {}",
+               self.feature, self.feature.min_version(), self.version)
+    }
+}
+
+/// A connection negotiation failure, with the transcript of the negotiation up to the failure.
+///
+/// The transcript has an entry for each negotiation message sent or received, naming the SASL
+/// state, the mechanisms and feature flags offered, and whether the message carried a token.
+/// Token contents, which hold credentials, are never recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NegotiationFailedError {
+    cause: Box<Error>,
+    transcript: Vec<String>,
+}
+
+impl NegotiationFailedError {
+    #[doc(hidden)]
+    pub fn new(cause: Error, transcript: Vec<String>) -> NegotiationFailedError {
+        NegotiationFailedError {
+            cause: Box::new(cause),
+            transcript: transcript,
+        }
+    }
+
+    /// The error which caused negotiation to fail.
+    pub fn cause(&self) -> &Error {
+        &self.cause
+    }
+
+    /// The negotiation transcript, in order.
+    pub fn transcript(&self) -> &[String] {
+        &self.transcript
+    }
+}
+
+impl fmt::Display for NegotiationFailedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "negotiation failed: {}; transcript:", self.cause));
+        for entry in &self.transcript {
+            try!(write!(f, "\n  {}", entry));
+        }
+        Ok(())
+    }
+}
+
+/// An operation which was attempted more than once, and failed because its deadline passed.
+///
+/// The error of each attempt is recorded in order. The earlier errors are the failures which
+/// caused the operation to be retried, and the final error is either the timeout of the last
+/// attempt, or its failure if there was no time left to retry it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeadlineExceededError {
+    errors: Vec<Error>,
+}
+
+impl DeadlineExceededError {
+    #[doc(hidden)]
+    pub fn new(errors: Vec<Error>) -> DeadlineExceededError {
+        assert!(!errors.is_empty(), "a deadline exceeded error must include the last attempt");
+        DeadlineExceededError { errors: errors }
+    }
+
+    /// The number of attempts made before the deadline passed.
+    pub fn attempts(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// The error of each attempt, in order.
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    /// The error of the final attempt.
+    pub fn last_error(&self) -> &Error {
+        self.errors.last().unwrap()
+    }
+}
+
+impl fmt::Display for DeadlineExceededError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "deadline exceeded after {} attempt(s): {:?}", self.errors.len(), self.errors)
+    }
+}
+
+/// Client-side context about the RPC call which resulted in an `RpcError`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ErrorContext {
+    service_name: &'static str,
+    method_name: &'static str,
+    call_id: i32,
+    addr: SocketAddr,
+    elapsed: Duration,
+}
+
+impl ErrorContext {
+    #[doc(hidden)]
+    pub fn new(service_name: &'static str,
+               method_name: &'static str,
+               call_id: i32,
+               addr: SocketAddr,
+               elapsed: Duration)
+               -> ErrorContext {
+        ErrorContext {
+            service_name: service_name,
+            method_name: method_name,
+            call_id: call_id,
+            addr: addr,
+            elapsed: elapsed,
+        }
+    }
+
+    /// The name of the service the failed RPC was sent to.
+    pub fn service_name(&self) -> &'static str {
+        self.service_name
+    }
+
+    /// The name of the method the failed RPC invoked.
+    pub fn method_name(&self) -> &'static str {
+        self.method_name
+    }
+
+    /// The connection-local call ID of the failed RPC.
+    pub fn call_id(&self) -> i32 {
+        self.call_id
+    }
+
+    /// The address of the server which returned the error.
+    pub fn addr(&self) -> &SocketAddr {
+        &self.addr
+    }
+
+    /// The time between sending the RPC and receiving the error response.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct RpcError {
     code: RpcErrorCode,
     message: String,
     unsupported_feature_flags: Vec<u32>,
+    context: Option<ErrorContext>,
 }
 
 impl RpcError {
@@ -262,8 +667,34 @@ impl RpcError {
             code: RpcErrorCode::FatalInvalidRpcHeader,
             message: message,
             unsupported_feature_flags: Vec::new(),
+            context: None,
         }
     }
+
+    pub fn code(&self) -> RpcErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn unsupported_feature_flags(&self) -> &[u32] {
+        &self.unsupported_feature_flags
+    }
+
+    /// Returns the context of the RPC call which failed, if the error was returned by a server in
+    /// response to an RPC.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        self.context.as_ref()
+    }
+
+    #[doc(hidden)]
+    pub fn with_context(mut self, context: ErrorContext) -> RpcError {
+        self.context = Some(context);
+        self
+    }
+
     pub fn is_fatal(&self) -> bool {
         match self.code {
             RpcErrorCode::FatalUnknown |
@@ -324,6 +755,7 @@ impl From<RpcErrorPB> for RpcError {
             code: code,
             message: message,
             unsupported_feature_flags: unsupported_feature_flags,
+            context: None,
         }
     }
 }
@@ -505,6 +937,20 @@ pub struct TabletServerError {
     status: Status,
 }
 
+impl TabletServerError {
+    pub fn code(&self) -> TabletServerErrorCode {
+        self.code
+    }
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    /// Returns `true` if the server rejected the request because it is under memory pressure.
+    pub fn is_throttled(&self) -> bool {
+        self.code == TabletServerErrorCode::Throttled
+    }
+}
+
 impl error::Error for TabletServerError {
     fn description(&self) -> &str {
         match self.code {
@@ -657,3 +1103,51 @@ impl From<MasterErrorPB> for MasterError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use std::io;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_is_network_error() {
+        let io_error = Error::Io(io::Error::new(io::ErrorKind::ConnectionRefused, "refused"));
+        assert!(io_error.is_network_error());
+        assert!(!Error::Serialization("bad message".to_string()).is_network_error());
+
+        // A connection reset is a network error only if its cause is.
+        let reset = Error::ConnectionReset(ConnectionResetError::new(io_error, 2, Duration::from_secs(1)));
+        assert!(reset.is_network_error());
+        let reset = Error::ConnectionReset(ConnectionResetError::new(
+                Error::Rpc(RpcError::invalid_rpc_header("bad header".to_string())), 1, Duration::from_secs(1)));
+        assert!(!reset.is_network_error());
+
+        // An open circuit breaker is not retried as a network error.
+        let circuit_open = Error::CircuitOpen("127.0.0.1:7051".parse().unwrap());
+        assert!(!circuit_open.is_network_error());
+        assert!(circuit_open.is_circuit_open());
+    }
+
+    #[test]
+    fn test_after_attempts() {
+        assert_eq!(Error::WireTimedOut, Error::WireTimedOut.after_attempts(Vec::new()));
+
+        let error = Error::WireTimedOut.after_attempts(vec![Error::ConnectTimedOut]);
+        assert_eq!(Error::DeadlineExceeded(DeadlineExceededError::new(
+                vec![Error::ConnectTimedOut, Error::WireTimedOut])), error);
+
+        // Nested deadlines are flattened into a single history.
+        let error = error.after_attempts(vec![Error::ConnectionError]);
+        match error {
+            Error::DeadlineExceeded(ref error) => {
+                assert_eq!(3, error.attempts());
+                assert_eq!(&[Error::ConnectionError, Error::ConnectTimedOut, Error::WireTimedOut][..],
+                           error.errors());
+            },
+            _ => panic!("unexpected error: {:?}", error),
+        }
+    }
+}
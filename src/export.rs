@@ -0,0 +1,278 @@
+//! Writers which export scanned rows to CSV or newline-delimited JSON.
+//!
+//! Values are formatted according to their column type: timestamps are written as RFC 3339 UTC
+//! datetimes with microsecond precision, and binary values are base64 encoded.
+
+use std::fmt;
+use std::io::Write;
+use std::time::Instant;
+
+use DataType;
+use Result;
+use Row;
+use ScanBatch;
+use Scanner;
+use Schema;
+use util;
+
+/// Writes rows as comma-separated values, one row per line.
+///
+/// Null values are written as empty fields. Fields containing a comma, quote, or line break are
+/// quoted.
+pub struct CsvWriter<W> {
+    writer: W,
+    header: bool,
+    wrote_header: bool,
+}
+
+impl <W> CsvWriter<W> where W: Write {
+
+    /// Creates a new CSV writer which writes to the provided writer.
+    pub fn new(writer: W) -> CsvWriter<W> {
+        CsvWriter {
+            writer: writer,
+            header: true,
+            wrote_header: false,
+        }
+    }
+
+    /// Sets whether a header line with the column names is written before the first row.
+    ///
+    /// Defaults to `true`.
+    pub fn set_header(&mut self, header: bool) -> &mut CsvWriter<W> {
+        self.header = header;
+        self
+    }
+
+    fn write_header(&mut self, schema: &Schema) -> Result<()> {
+        if !self.header || self.wrote_header { return Ok(()); }
+        self.wrote_header = true;
+        for (idx, column) in schema.columns().iter().enumerate() {
+            if idx > 0 { try!(self.writer.write_all(b",")); }
+            try!(write_csv_field(&mut self.writer, column.name()));
+        }
+        try!(self.writer.write_all(b"\n"));
+        Ok(())
+    }
+
+    /// Writes a single row.
+    pub fn write_row(&mut self, row: &Row) -> Result<()> {
+        try!(self.write_header(row.schema()));
+        for (idx, column) in row.schema().columns().iter().enumerate() {
+            if idx > 0 { try!(self.writer.write_all(b",")); }
+            if !try!(row.is_set(idx)) || try!(row.is_null(idx)) { continue; }
+            match column.data_type() {
+                DataType::Binary => {
+                    try!(self.writer.write_all(util::base64_encode(try!(row.get::<&[u8]>(idx))).as_bytes()));
+                },
+                DataType::String => try!(write_csv_field(&mut self.writer, try!(row.get::<&str>(idx)))),
+                _ => try!(write!(self.writer, "{}", Cell(row, idx))),
+            }
+        }
+        try!(self.writer.write_all(b"\n"));
+        Ok(())
+    }
+
+    /// Writes every row in a scan batch.
+    pub fn write_batch(&mut self, batch: &ScanBatch) -> Result<()> {
+        for row in batch.rows() {
+            try!(self.write_row(row));
+        }
+        Ok(())
+    }
+
+    /// Writes every remaining row returned by the scanner, returning the number of rows written.
+    ///
+    /// The header is written even if the scan returns no rows.
+    pub fn write_scan(&mut self, scanner: &mut Scanner, deadline: Instant) -> Result<u64> {
+        try!(self.write_header(scanner.projection()));
+        let mut count = 0;
+        while let Some(batch) = try!(scanner.next_batch(deadline)) {
+            try!(self.write_batch(&batch));
+            count += batch.len() as u64;
+        }
+        Ok(count)
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        try!(self.writer.flush());
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Writes rows as newline-delimited JSON objects keyed by column name.
+///
+/// Null values are written as `null`. Non-finite floating point values have no JSON
+/// representation, and are also written as `null`.
+pub struct JsonWriter<W> {
+    writer: W,
+}
+
+impl <W> JsonWriter<W> where W: Write {
+
+    /// Creates a new JSON writer which writes to the provided writer.
+    pub fn new(writer: W) -> JsonWriter<W> {
+        JsonWriter { writer: writer }
+    }
+
+    /// Writes a single row.
+    pub fn write_row(&mut self, row: &Row) -> Result<()> {
+        try!(self.writer.write_all(b"{"));
+        for (idx, column) in row.schema().columns().iter().enumerate() {
+            if idx > 0 { try!(self.writer.write_all(b",")); }
+            try!(write_json_string(&mut self.writer, column.name()));
+            try!(self.writer.write_all(b":"));
+            if !try!(row.is_set(idx)) || try!(row.is_null(idx)) {
+                try!(self.writer.write_all(b"null"));
+                continue;
+            }
+            match column.data_type() {
+                DataType::Float if !try!(row.get::<f32>(idx)).is_finite() => {
+                    try!(self.writer.write_all(b"null"))
+                },
+                DataType::Double if !try!(row.get::<f64>(idx)).is_finite() => {
+                    try!(self.writer.write_all(b"null"))
+                },
+                DataType::Timestamp => try!(write!(self.writer, "\"{}\"", Cell(row, idx))),
+                DataType::Binary => {
+                    try!(write!(self.writer, "\"{}\"", util::base64_encode(try!(row.get::<&[u8]>(idx)))));
+                },
+                DataType::String => try!(write_json_string(&mut self.writer, try!(row.get::<&str>(idx)))),
+                _ => try!(write!(self.writer, "{}", Cell(row, idx))),
+            }
+        }
+        try!(self.writer.write_all(b"}\n"));
+        Ok(())
+    }
+
+    /// Writes every row in a scan batch.
+    pub fn write_batch(&mut self, batch: &ScanBatch) -> Result<()> {
+        for row in batch.rows() {
+            try!(self.write_row(row));
+        }
+        Ok(())
+    }
+
+    /// Writes every remaining row returned by the scanner, returning the number of rows written.
+    pub fn write_scan(&mut self, scanner: &mut Scanner, deadline: Instant) -> Result<u64> {
+        let mut count = 0;
+        while let Some(batch) = try!(scanner.next_batch(deadline)) {
+            try!(self.write_batch(&batch));
+            count += batch.len() as u64;
+        }
+        Ok(count)
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        try!(self.writer.flush());
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Displays a non-null, non-string cell.
+struct Cell<'a>(&'a Row, usize);
+
+impl <'a> fmt::Display for Cell<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        util::fmt_cell(f, self.0, self.1)
+    }
+}
+
+fn write_csv_field<W>(writer: &mut W, field: &str) -> Result<()> where W: Write {
+    if field.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        try!(write!(writer, "\"{}\"", field.replace('"', "\"\"")));
+    } else {
+        try!(writer.write_all(field.as_bytes()));
+    }
+    Ok(())
+}
+
+fn write_json_string<W>(writer: &mut W, s: &str) -> Result<()> where W: Write {
+    try!(writer.write_all(b"\""));
+    for c in s.chars() {
+        match c {
+            '"' => try!(writer.write_all(b"\\\"")),
+            '\\' => try!(writer.write_all(b"\\\\")),
+            '\n' => try!(writer.write_all(b"\\n")),
+            '\r' => try!(writer.write_all(b"\\r")),
+            '\t' => try!(writer.write_all(b"\\t")),
+            c if (c as u32) < 0x20 => try!(write!(writer, "\\u{:04x}", c as u32)),
+            c => try!(write!(writer, "{}", c)),
+        }
+    }
+    try!(writer.write_all(b"\""));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use schema::{Column, SchemaBuilder};
+    use DataType;
+    use super::*;
+
+    fn schema() -> Schema {
+        SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("timestamp", DataType::Timestamp).set_nullable())
+            .add_column(Column::builder("binary", DataType::Binary).set_nullable())
+            .add_column(Column::builder("string", DataType::String).set_nullable())
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap()
+    }
+
+    fn rows() -> Vec<Row> {
+        let schema = schema();
+        let mut a = schema.new_row();
+        a.set_by_name::<i32>("key", 1).unwrap();
+        a.set_by_name("timestamp", UNIX_EPOCH + Duration::from_millis(1234)).unwrap();
+        a.set_by_name::<&[u8]>("binary", b"foo\x00").unwrap();
+        a.set_by_name("string", "a, \"b\"\n").unwrap();
+
+        let mut b = schema.new_row();
+        b.set_by_name::<i32>("key", 2).unwrap();
+        b.set_null_by_name("timestamp").unwrap();
+        b.set_null_by_name("binary").unwrap();
+        b.set_null_by_name("string").unwrap();
+        vec![a, b]
+    }
+
+    #[test]
+    fn test_csv_writer() {
+        let mut writer = CsvWriter::new(Vec::new());
+        for row in &rows() {
+            writer.write_row(row).unwrap();
+        }
+        assert_eq!("key,timestamp,binary,string\n\
+                    1,1970-01-01T00:00:01.234000Z,Zm9vAA==,\"a, \"\"b\"\"\n\"\n\
+                    2,,,\n",
+                   String::from_utf8(writer.into_inner()).unwrap());
+    }
+
+    #[test]
+    fn test_json_writer() {
+        let mut writer = JsonWriter::new(Vec::new());
+        for row in &rows() {
+            writer.write_row(row).unwrap();
+        }
+        assert_eq!("{\"key\":1,\"timestamp\":\"1970-01-01T00:00:01.234000Z\",\
+                    \"binary\":\"Zm9vAA==\",\"string\":\"a, \\\"b\\\"\\n\"}\n\
+                    {\"key\":2,\"timestamp\":null,\"binary\":null,\"string\":null}\n",
+                   String::from_utf8(writer.into_inner()).unwrap());
+    }
+}
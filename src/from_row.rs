@@ -0,0 +1,183 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use Error;
+use Result;
+use Row;
+use ScanBatch;
+use Schema;
+
+/// A type which can be decoded from the columns of a row.
+///
+/// `FromRow` is usually implemented with the `kudu_row!` macro, and used through a `RowDecoder`.
+pub trait FromRow: Sized {
+
+    /// Returns the index in the schema of the column backing each field, in field order. Fails if
+    /// a column is missing, or if its type can not be read into the corresponding field.
+    fn resolve_columns(schema: &Schema) -> Result<Vec<usize>>;
+
+    /// Decodes a value from the row, using the column indices returned by `resolve_columns` for
+    /// the row's schema.
+    fn from_row(row: &Row, columns: &[usize]) -> Result<Self>;
+}
+
+/// Decodes rows into a `FromRow` type.
+///
+/// Columns are resolved by name and type checked once, when the decoder is created. Decoding a row
+/// is then a matter of reading each cell by index. Rows must have the schema the decoder was
+/// created with, which is the case for all rows returned by a scanner with its projection.
+pub struct RowDecoder<T> where T: FromRow {
+    schema: Schema,
+    columns: Vec<usize>,
+    value_type: PhantomData<T>,
+}
+
+impl <T> RowDecoder<T> where T: FromRow {
+
+    /// Creates a decoder for rows with the provided schema, such as `Scanner::projection`.
+    pub fn new(schema: &Schema) -> Result<RowDecoder<T>> {
+        let columns = try!(T::resolve_columns(schema));
+        Ok(RowDecoder {
+            schema: schema.clone(),
+            columns: columns,
+            value_type: PhantomData,
+        })
+    }
+
+    /// Decodes a single row.
+    pub fn decode(&self, row: &Row) -> Result<T> {
+        try!(self.check_schema(row.schema()));
+        T::from_row(row, &self.columns)
+    }
+
+    /// Decodes every row in a scan batch.
+    pub fn decode_batch(&self, batch: &ScanBatch) -> Result<Vec<T>> {
+        let mut values = Vec::with_capacity(batch.len());
+        for row in batch.rows() {
+            try!(self.check_schema(row.schema()));
+            values.push(try!(T::from_row(row, &self.columns)));
+        }
+        Ok(values)
+    }
+
+    fn check_schema(&self, schema: &Schema) -> Result<()> {
+        // Rows from the same scan share their schema, so the pointer comparison is the common case.
+        if schema.ref_eq(&self.schema) || *schema == self.schema {
+            Ok(())
+        } else {
+            Err(Error::InvalidArgument(format!("row schema {:?} does not match decoder schema {:?}",
+                                               schema, self.schema)))
+        }
+    }
+}
+
+impl <T> fmt::Debug for RowDecoder<T> where T: FromRow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RowDecoder {{ schema: {:?}, columns: {:?} }}", self.schema, self.columns)
+    }
+}
+
+/// Defines a struct with a field per column, and implements `FromRow` for it.
+///
+/// Each field is read from the column with the same name, so field names must match the column
+/// names of the scan projection. Nullable columns are read into `Option` fields.
+///
+/// ```ignore
+/// kudu_row! {
+///     pub struct Metric {
+///         host: String,
+///         timestamp: i64,
+///         value: Option<f64>,
+///     }
+/// }
+///
+/// let decoder = try!(RowDecoder::<Metric>::new(scanner.projection()));
+/// while let Some(batch) = try!(scanner.next_batch(deadline)) {
+///     for metric in try!(decoder.decode_batch(&batch)) { ... }
+/// }
+/// ```
+#[macro_export]
+macro_rules! kudu_row {
+    ($(#[$attr:meta])* pub struct $name:ident { $($field:ident: $ty:ty),+ $(,)* }) => {
+        $(#[$attr])*
+        pub struct $name {
+            $(pub $field: $ty),+
+        }
+
+        impl $crate::FromRow for $name {
+            fn resolve_columns(schema: &$crate::Schema) -> $crate::Result<Vec<usize>> {
+                Ok(vec![$(try!(schema.typed_column::<$ty>(stringify!($field))).index()),+])
+            }
+
+            fn from_row(row: &$crate::Row, columns: &[usize]) -> $crate::Result<$name> {
+                let mut columns = columns.iter();
+                Ok($name {
+                    $($field: try!(row.get::<$ty>(*columns.next().unwrap()))),+
+                })
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+
+    use Column;
+    use DataType;
+    use SchemaBuilder;
+    use super::*;
+
+    kudu_row! {
+        #[derive(Debug, PartialEq)]
+        pub struct Metric {
+            value: Option<f64>,
+            host: String,
+            timestamp: i64,
+        }
+    }
+
+    fn schema() -> Schema {
+        SchemaBuilder::new()
+            .add_column(Column::builder("host", DataType::String).set_not_null())
+            .add_column(Column::builder("timestamp", DataType::Timestamp).set_not_null())
+            .add_column(Column::builder("value", DataType::Double).set_nullable())
+            .set_primary_key(vec!["host", "timestamp"])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_decode() {
+        let schema = schema();
+        let decoder = RowDecoder::<Metric>::new(&schema).unwrap();
+
+        let mut row = schema.new_row();
+        row.set(0, "a").unwrap();
+        row.set(1, 42i64).unwrap();
+        row.set(2, 1.5f64).unwrap();
+        assert_eq!(Metric { host: "a".to_owned(), timestamp: 42, value: Some(1.5) },
+                   decoder.decode(&row).unwrap());
+
+        row.set_null(2).unwrap();
+        assert_eq!(None, decoder.decode(&row).unwrap().value);
+
+        // Rows with a different schema are rejected.
+        let other = schema.projection(&[2, 1, 0]);
+        assert!(decoder.decode(&other.new_row()).is_err());
+    }
+
+    #[test]
+    fn test_resolve_columns() {
+        assert_eq!(vec![2, 0, 1], Metric::resolve_columns(&schema()).unwrap());
+
+        let missing_column = schema().projection(&[0, 2]);
+        assert!(RowDecoder::<Metric>::new(&missing_column).is_err());
+
+        kudu_row! {
+            pub struct NotNullValue {
+                value: f64,
+            }
+        }
+        assert!(RowDecoder::<NotNullValue>::new(&schema()).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use Error;
+use Result;
+use TabletServerId;
+
+/// The type of a server checked by `Client::health_check`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerKind {
+    Master,
+    TabletServer(TabletServerId),
+}
+
+/// The health of an individual master or tablet server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerHealth {
+    kind: ServerKind,
+    addr: Option<SocketAddr>,
+    result: Result<Duration>,
+}
+
+impl ServerHealth {
+
+    #[doc(hidden)]
+    pub fn new(kind: ServerKind, addr: Option<SocketAddr>, result: Result<Duration>) -> ServerHealth {
+        ServerHealth {
+            kind: kind,
+            addr: addr,
+            result: result,
+        }
+    }
+
+    /// Returns whether the server is a master or a tablet server.
+    pub fn kind(&self) -> &ServerKind {
+        &self.kind
+    }
+
+    /// Returns the address which was pinged, or `None` if the server's hostname could not be
+    /// resolved.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addr
+    }
+
+    /// Returns the round-trip time of the ping if it succeeded, or the error otherwise.
+    pub fn result(&self) -> &Result<Duration> {
+        &self.result
+    }
+
+    /// Returns `true` if the server responded to the ping.
+    pub fn is_healthy(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// The result of pinging every known master and registered tablet server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthReport {
+    servers: Vec<ServerHealth>,
+    list_tablet_servers_error: Option<Error>,
+}
+
+impl HealthReport {
+
+    #[doc(hidden)]
+    pub fn new(servers: Vec<ServerHealth>, list_tablet_servers_error: Option<Error>) -> HealthReport {
+        HealthReport {
+            servers: servers,
+            list_tablet_servers_error: list_tablet_servers_error,
+        }
+    }
+
+    /// Returns the health of each server, masters first.
+    pub fn servers(&self) -> &[ServerHealth] {
+        &self.servers
+    }
+
+    /// Returns the error which prevented the registered tablet servers from being listed. When
+    /// this is set the report only includes the masters.
+    pub fn list_tablet_servers_error(&self) -> Option<&Error> {
+        self.list_tablet_servers_error.as_ref()
+    }
+
+    /// Returns `true` if every server responded, and the tablet servers could be listed.
+    pub fn is_healthy(&self) -> bool {
+        self.list_tablet_servers_error.is_none() && self.servers.iter().all(ServerHealth::is_healthy)
+    }
+}
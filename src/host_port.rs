@@ -0,0 +1,90 @@
+use std::fmt;
+
+use kudu_pb::common::HostPortPB;
+
+use Error;
+use Result;
+
+/// The address of a server, as a hostname or IP address and a port.
+///
+/// Host ports are reported by the masters for each server in the cluster, and returned by
+/// `Resolver::resolve_srv`. They are resolved to socket addresses with a `Resolver`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HostPort {
+    host: String,
+    port: u16,
+}
+
+impl HostPort {
+
+    pub fn new<S>(host: S, port: u16) -> HostPort where S: Into<String> {
+        HostPort {
+            host: host.into(),
+            port: port,
+        }
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    #[doc(hidden)]
+    pub fn from_pb(pb: &HostPortPB) -> Result<HostPort> {
+        let port = pb.get_port();
+        if port > u16::max_value() as u32 {
+            return Err(Error::Serialization(format!("invalid port {} for host {}",
+                                                    port, pb.get_host())));
+        }
+        Ok(HostPort::new(pb.get_host(), port as u16))
+    }
+
+    #[doc(hidden)]
+    pub fn to_pb(&self) -> HostPortPB {
+        let mut pb = HostPortPB::new();
+        pb.set_host(self.host.clone());
+        pb.set_port(self.port as u32);
+        pb
+    }
+}
+
+impl fmt::Debug for HostPort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for HostPort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // IPv6 addresses are bracketed so that the port is unambiguous.
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use kudu_pb::common::HostPortPB;
+
+    use super::*;
+
+    #[test]
+    fn test_host_port() {
+        let host_port = HostPort::new("master-1", 7051);
+        assert_eq!("master-1:7051", host_port.to_string());
+        assert_eq!("[::1]:7051", HostPort::new("::1", 7051).to_string());
+        assert_eq!(host_port, HostPort::from_pb(&host_port.to_pb()).unwrap());
+
+        let mut pb = HostPortPB::new();
+        pb.set_host("master-1".to_owned());
+        pb.set_port(70510);
+        assert!(HostPort::from_pb(&pb).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! Conversions between rows and JSON objects, available with the `json` feature.
+//!
+//! Rows are represented as JSON objects keyed by column name. Null cells are `null`, and
+//! non-finite floating point values, which have no JSON representation, are also written as
+//! `null`. Binary and timestamp values are encoded according to the `JsonOptions`.
+
+use std::fmt;
+use std::time::SystemTime;
+
+use chrono::{self, Timelike};
+use serde_json::{Map, Number, Value as JsonValue};
+
+use DataType;
+use Error;
+use Result;
+use Row;
+use Schema;
+use util;
+
+/// The encoding of binary values in JSON strings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BinaryEncoding {
+    /// Padded, standard alphabet base64.
+    Base64,
+    /// Lowercase hexadecimal, without a prefix. Uppercase digits are accepted when decoding.
+    Hex,
+}
+
+/// The representation of timestamp values in JSON.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TimestampFormat {
+    /// An RFC 3339 string. Timestamps are written in UTC with microsecond precision, and may be
+    /// read with any offset.
+    Rfc3339,
+    /// An integer count of microseconds since the Unix epoch.
+    UnixMicros,
+}
+
+/// Options for converting rows to and from JSON.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct JsonOptions {
+    binary_encoding: BinaryEncoding,
+    timestamp_format: TimestampFormat,
+}
+
+impl JsonOptions {
+
+    pub fn binary_encoding(&self) -> BinaryEncoding {
+        self.binary_encoding
+    }
+
+    pub fn timestamp_format(&self) -> TimestampFormat {
+        self.timestamp_format
+    }
+
+    /// Sets the encoding of binary values.
+    ///
+    /// Defaults to `BinaryEncoding::Base64`.
+    pub fn set_binary_encoding(&mut self, encoding: BinaryEncoding) -> &mut JsonOptions {
+        self.binary_encoding = encoding;
+        self
+    }
+
+    /// Sets the representation of timestamp values.
+    ///
+    /// Defaults to `TimestampFormat::Rfc3339`.
+    pub fn set_timestamp_format(&mut self, format: TimestampFormat) -> &mut JsonOptions {
+        self.timestamp_format = format;
+        self
+    }
+}
+
+impl Default for JsonOptions {
+    fn default() -> JsonOptions {
+        JsonOptions {
+            binary_encoding: BinaryEncoding::Base64,
+            timestamp_format: TimestampFormat::Rfc3339,
+        }
+    }
+}
+
+impl Row {
+
+    /// Converts the row to a JSON object with the default options. Unset cells are omitted.
+    pub fn to_json(&self) -> Result<JsonValue> {
+        self.to_json_with(&JsonOptions::default())
+    }
+
+    /// Converts the row to a JSON object. Unset cells are omitted.
+    pub fn to_json_with(&self, options: &JsonOptions) -> Result<JsonValue> {
+        let mut object = Map::new();
+        for (idx, column) in self.schema().columns().iter().enumerate() {
+            if !try!(self.is_set(idx)) { continue; }
+            let value = if try!(self.is_null(idx)) {
+                JsonValue::Null
+            } else {
+                match column.data_type() {
+                    DataType::Bool => JsonValue::Bool(try!(self.get(idx))),
+                    DataType::Int8 => JsonValue::from(try!(self.get::<i8>(idx))),
+                    DataType::Int16 => JsonValue::from(try!(self.get::<i16>(idx))),
+                    DataType::Int32 => JsonValue::from(try!(self.get::<i32>(idx))),
+                    DataType::Int64 => JsonValue::from(try!(self.get::<i64>(idx))),
+                    DataType::Timestamp => {
+                        let timestamp = try!(self.get::<SystemTime>(idx));
+                        match options.timestamp_format {
+                            TimestampFormat::Rfc3339 => JsonValue::String(Timestamp(timestamp).to_string()),
+                            TimestampFormat::UnixMicros => JsonValue::from(util::time_to_us(&timestamp)),
+                        }
+                    },
+                    DataType::Float => float(try!(self.get::<f32>(idx)) as f64),
+                    DataType::Double => float(try!(self.get::<f64>(idx))),
+                    DataType::Binary => {
+                        let bytes = try!(self.get::<&[u8]>(idx));
+                        JsonValue::String(match options.binary_encoding {
+                            BinaryEncoding::Base64 => util::base64_encode(bytes),
+                            BinaryEncoding::Hex => util::hex_encode(bytes),
+                        })
+                    },
+                    DataType::String => JsonValue::String(try!(self.get::<&str>(idx)).to_owned()),
+                }
+            };
+            object.insert(column.name().to_owned(), value);
+        }
+        Ok(JsonValue::Object(object))
+    }
+
+    /// Creates a row from a JSON object with the default options.
+    pub fn from_json(schema: &Schema, value: &JsonValue) -> Result<Row> {
+        Row::from_json_with(schema, value, &JsonOptions::default())
+    }
+
+    /// Creates a row from a JSON object keyed by column name. Columns missing from the object are
+    /// left unset, and `null` values set the column to null.
+    ///
+    /// Fails if the object contains a key which is not a column of the schema, or a value which
+    /// does not match its column's type, such as an integer which is out of range.
+    pub fn from_json_with(schema: &Schema, value: &JsonValue, options: &JsonOptions) -> Result<Row> {
+        let object = match *value {
+            JsonValue::Object(ref object) => object,
+            _ => return Err(Error::InvalidArgument(format!("expected a JSON object, found {}",
+                                                           value))),
+        };
+        let mut row = schema.new_row();
+        for (name, value) in object {
+            let idx = match schema.column_index(name) {
+                Some(idx) => idx,
+                None => return Err(Error::InvalidArgument(format!("unknown column {:?}", name))),
+            };
+            if value.is_null() {
+                try!(row.set_null(idx));
+                continue;
+            }
+            let data_type = schema.columns()[idx].data_type();
+            let invalid = || Error::InvalidArgument(format!(
+                    "invalid JSON value for {:?} column {:?}: {}", data_type, name, value));
+            match data_type {
+                DataType::Bool => { try!(row.set(idx, try!(value.as_bool().ok_or_else(&invalid)))); },
+                DataType::Int8 => {
+                    let i = try!(int(value, i8::min_value() as i64, i8::max_value() as i64).ok_or_else(&invalid));
+                    try!(row.set(idx, i as i8));
+                },
+                DataType::Int16 => {
+                    let i = try!(int(value, i16::min_value() as i64, i16::max_value() as i64).ok_or_else(&invalid));
+                    try!(row.set(idx, i as i16));
+                },
+                DataType::Int32 => {
+                    let i = try!(int(value, i32::min_value() as i64, i32::max_value() as i64).ok_or_else(&invalid));
+                    try!(row.set(idx, i as i32));
+                },
+                DataType::Int64 => { try!(row.set(idx, try!(value.as_i64().ok_or_else(&invalid)))); },
+                DataType::Timestamp => {
+                    let us = match options.timestamp_format {
+                        TimestampFormat::Rfc3339 => value.as_str().and_then(parse_rfc3339),
+                        TimestampFormat::UnixMicros => value.as_i64(),
+                    };
+                    try!(row.set(idx, util::us_to_time(try!(us.ok_or_else(&invalid)))));
+                },
+                DataType::Float => { try!(row.set(idx, try!(value.as_f64().ok_or_else(&invalid)) as f32)); },
+                DataType::Double => { try!(row.set(idx, try!(value.as_f64().ok_or_else(&invalid)))); },
+                DataType::Binary => {
+                    let bytes = value.as_str().and_then(|s| match options.binary_encoding {
+                        BinaryEncoding::Base64 => util::base64_decode(s),
+                        BinaryEncoding::Hex => util::hex_decode(s),
+                    });
+                    try!(row.set(idx, try!(bytes.ok_or_else(&invalid))));
+                },
+                DataType::String => { try!(row.set(idx, try!(value.as_str().ok_or_else(&invalid)))); },
+            }
+        }
+        Ok(row)
+    }
+}
+
+/// Returns the value as an integer, if it is one within the range.
+fn int(value: &JsonValue, min: i64, max: i64) -> Option<i64> {
+    value.as_i64().and_then(|i| if i < min || i > max { None } else { Some(i) })
+}
+
+/// Converts a floating point value to a JSON number, or `null` if it is not finite.
+fn float(value: f64) -> JsonValue {
+    Number::from_f64(value).map_or(JsonValue::Null, JsonValue::Number)
+}
+
+/// Parses an RFC 3339 datetime into microseconds since the Unix epoch.
+fn parse_rfc3339(s: &str) -> Option<i64> {
+    chrono::DateTime::parse_from_rfc3339(s).ok().map(|datetime| {
+        datetime.timestamp() * 1000_000 + (datetime.nanosecond() / 1000) as i64
+    })
+}
+
+/// Displays a timestamp as an RFC 3339 UTC datetime.
+struct Timestamp(SystemTime);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        util::fmt_timestamp(f, self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use serde_json;
+
+    use schema::{Column, SchemaBuilder};
+    use super::*;
+
+    fn schema() -> Schema {
+        SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int8).set_not_null())
+            .add_column(Column::builder("timestamp", DataType::Timestamp).set_nullable())
+            .add_column(Column::builder("binary", DataType::Binary).set_nullable())
+            .add_column(Column::builder("double", DataType::Double).set_nullable())
+            .add_column(Column::builder("string", DataType::String).set_nullable())
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_row_to_json() {
+        let schema = schema();
+        let mut row = schema.new_row();
+        row.set_by_name::<i8>("key", -1).unwrap();
+        row.set_by_name("timestamp", UNIX_EPOCH + Duration::from_millis(1234)).unwrap();
+        row.set_by_name::<&[u8]>("binary", b"foo\x00").unwrap();
+        row.set_by_name("double", ::std::f64::NAN).unwrap();
+
+        assert_eq!(serde_json::from_str::<JsonValue>(
+                       r#"{"key":-1,"timestamp":"1970-01-01T00:00:01.234000Z","binary":"Zm9vAA==",
+                           "double":null}"#).unwrap(),
+                   row.to_json().unwrap());
+
+        let mut options = JsonOptions::default();
+        options.set_binary_encoding(BinaryEncoding::Hex)
+               .set_timestamp_format(TimestampFormat::UnixMicros);
+        let json = row.to_json_with(&options).unwrap();
+        assert_eq!(serde_json::from_str::<JsonValue>(
+                       r#"{"key":-1,"timestamp":1234000,"binary":"666f6f00","double":null}"#).unwrap(),
+                   json);
+
+        let decoded = Row::from_json_with(&schema, &json, &options).unwrap();
+        assert_eq!(-1, decoded.get_by_name::<i8>("key").unwrap().unwrap());
+        assert_eq!(UNIX_EPOCH + Duration::from_millis(1234),
+                   decoded.get_by_name::<SystemTime>("timestamp").unwrap().unwrap());
+        assert_eq!(&b"foo\x00"[..], decoded.get_by_name::<&[u8]>("binary").unwrap().unwrap());
+        assert!(decoded.is_null_by_name("double").unwrap());
+        assert!(!decoded.is_set_by_name("string").unwrap());
+    }
+
+    #[test]
+    fn test_row_from_json() {
+        let schema = schema();
+        let parse = |json: &str| Row::from_json(&schema, &serde_json::from_str(json).unwrap());
+
+        let row = parse(r#"{"key":1,"timestamp":"1970-01-01T01:00:01.5+01:00","string":"a"}"#).unwrap();
+        assert_eq!(UNIX_EPOCH + Duration::from_millis(1500),
+                   row.get_by_name::<SystemTime>("timestamp").unwrap().unwrap());
+        assert_eq!("a", row.get_by_name::<&str>("string").unwrap().unwrap());
+
+        assert!(parse(r#"{"key":128}"#).is_err());
+        assert!(parse(r#"{"key":1.5}"#).is_err());
+        assert!(parse(r#"{"key":null}"#).is_err());
+        assert!(parse(r#"{"unknown":1}"#).is_err());
+        assert!(parse(r#"{"binary":"not base64"}"#).is_err());
+        assert!(parse(r#"[1]"#).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 //! Utility functions for working with keys.
 
 use std::{i8, i16, i32, i64, f32, f64};
+use std::fmt;
 
 use byteorder::{BigEndian, ByteOrder, WriteBytesExt, NativeEndian};
 use ieee754::Ieee754;
@@ -13,6 +14,7 @@ use RangePartitionSchema;
 use Result;
 use Row;
 use Schema;
+use util;
 
 /// Murmur2 hash implementation returning 64-bit hashes.
 pub fn murmur2_64(mut data: &[u8], seed: u64) -> u64 {
@@ -49,12 +51,60 @@ pub fn murmur2_64(mut data: &[u8], seed: u64) -> u64 {
     h
 }
 
+/// An encoded primary key.
+///
+/// The encoding preserves the order of the primary key columns, so encoded keys order the same
+/// as their rows do by `Row::cmp_primary_key`, and can be compared without decoding.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EncodedPrimaryKey {
+    key: Vec<u8>,
+}
+
+impl EncodedPrimaryKey {
+
+    /// Encodes the primary key of the row. Fails if a primary key cell is not set.
+    pub fn from_row(row: &Row) -> Result<EncodedPrimaryKey> {
+        encode_primary_key(row).map(EncodedPrimaryKey::from_bytes)
+    }
+
+    /// Wraps an encoded primary key, for instance one returned by a scan or a tablet's bounds.
+    pub fn from_bytes(key: Vec<u8>) -> EncodedPrimaryKey {
+        EncodedPrimaryKey { key: key }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.key
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.key
+    }
+
+    /// Decodes the key into a row of the schema with only the primary key columns set.
+    pub fn decode(&self, schema: &Schema) -> Result<Row> {
+        decode_primary_key(schema, &self.key)
+    }
+}
+
+impl fmt::Debug for EncodedPrimaryKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EncodedPrimaryKey({})", util::hex_encode(&self.key))
+    }
+}
+
 pub fn encode_primary_key(row: &Row) -> Result<Vec<u8>> {
     let mut buf = Vec::new();
     try!(encode_columns(row, 0..row.schema().num_primary_key_columns(), &mut buf));
     Ok(buf)
 }
 
+/// Encodes the cells of the columns, in order, with the primary key encoding.
+pub fn encode_key_columns(row: &Row, idxs: &[usize]) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    try!(encode_columns(row, idxs.iter().cloned(), &mut buf));
+    Ok(buf)
+}
+
 pub fn encode_partition_key(partition_schema: &PartitionSchema, row: &Row) -> Result<Vec<u8>> {
     let mut buf = Vec::new();
     for hash_schema in partition_schema.hash_partition_schemas() {
@@ -74,13 +124,21 @@ pub fn encode_hash_partition_key(hash_schema: &HashPartitionSchema,
                                  row: &Row,
                                  buf: &mut Vec<u8>) -> Result<()> {
     let len = buf.len();
-    try!(encode_columns(row, hash_schema.columns().iter().cloned(), buf));
-    let bucket = murmur2_64(&buf[len..], hash_schema.seed() as u64) % hash_schema.num_buckets() as u64;
+    let bucket = try!(hash_bucket(hash_schema, row, buf));
     buf.truncate(len);
-    buf.write_u32::<BigEndian>(bucket as u32).unwrap();
+    buf.write_u32::<BigEndian>(bucket).unwrap();
     Ok(())
 }
 
+/// Returns the hash bucket of the row. `buf` is used as scratch space for encoding the hashed
+/// columns, which are appended to it.
+pub fn hash_bucket(hash_schema: &HashPartitionSchema, row: &Row, buf: &mut Vec<u8>) -> Result<u32> {
+    let len = buf.len();
+    try!(encode_columns(row, hash_schema.columns().iter().cloned(), buf));
+    let bucket = murmur2_64(&buf[len..], hash_schema.seed() as u64) % hash_schema.num_buckets() as u64;
+    Ok(bucket as u32)
+}
+
 fn encode_columns<I>(row: &Row, idxs: I, buf: &mut Vec<u8>) -> Result<()>
 where I: Iterator<Item=usize> + ExactSizeIterator {
     let columns = idxs.len();
@@ -2,9 +2,7 @@
 
 extern crate byteorder;
 extern crate chrono;
-extern crate futures;
 extern crate ieee754;
-extern crate ifaces;
 extern crate itertools;
 extern crate kudu_pb;
 extern crate mio;
@@ -17,47 +15,114 @@ extern crate slab;
 extern crate uuid;
 extern crate vec_map;
 
+#[cfg(feature="bytes")] extern crate bytes;
+#[cfg(feature="local-addrs")] extern crate ifaces;
+#[cfg(feature="json")] extern crate serde_json;
 #[cfg(test)] extern crate env_logger;
 #[cfg(test)] extern crate tempdir;
 
 #[cfg(any(feature="quickcheck", test))]
 #[macro_use] extern crate quickcheck;
 
+#[macro_use] extern crate futures;
 #[macro_use] extern crate lazy_static;
 #[macro_use] extern crate log;
 
+#[cfg(feature = "admin")]
+mod admin;
+#[cfg(feature = "alloc-stats")]
+mod alloc_stats;
 mod backoff;
 mod bit_set;
+mod bulk_load;
 mod client;
+mod compat;
+mod copy;
 mod dns;
 mod error;
+mod export;
+#[macro_use] mod from_row;
+mod health;
+mod host_port;
+#[cfg(feature = "json")]
+mod json;
 mod key;
 mod master;
+mod master_watch;
+mod memory_budget;
 mod meta_cache;
 mod partition;
+mod predicate;
 mod queue_map;
+mod range_partition_manager;
+mod rate_limiter;
+mod retry_budget;
 mod row;
 mod rpc;
+mod scanner;
 mod schema;
 mod table;
 mod tablet;
 mod tablet_server;
 mod util;
+mod validation;
 mod value;
 mod writer;
 
 #[cfg(test)]
 mod mini_cluster;
 
+#[cfg(feature = "admin")]
+pub use admin::QuiescingStatus;
+#[cfg(feature = "alloc-stats")]
+pub use alloc_stats::{AllocationStats, CountingAllocator};
+pub use bulk_load::*;
 pub use client::*;
+pub use compat::{ServerFeature, ServerVersion, ServerVersions};
+pub use copy::{copy_table, CopyCheckpoint, CopyStats, CopyTableOptions};
+pub use dns::{
+    refresh_local_addrs,
+    set_local_addr_detector,
+    LocalAddrDetector,
+    NoLocalAddrDetector,
+    Resolver,
+    StaticResolver,
+    SystemResolver,
+};
+#[cfg(feature = "local-addrs")]
+pub use dns::InterfaceAddrDetector;
 pub use error::*;
+pub use export::{CsvWriter, JsonWriter};
+pub use from_row::{FromRow, RowDecoder};
+pub use health::{HealthReport, ServerHealth, ServerKind};
+pub use host_port::HostPort;
+#[cfg(feature = "json")]
+pub use json::{BinaryEncoding, JsonOptions, TimestampFormat};
+pub use key::EncodedPrimaryKey;
 pub use master::Master;
+pub use master_watch::{MasterEvent, MasterWatch};
+pub use memory_budget::MemoryBudget;
 pub use partition::*;
+pub use predicate::{col, Col, ColumnPredicate, Predicates};
+pub use range_partition_manager::{RangePartitionChanges, RangePartitionManager, TimeRange};
+pub use rate_limiter::RateLimiter;
+pub use retry_budget::RetryBudget;
 pub use row::Row;
+pub use rpc::{CallRecord, ConnectionEvent, ReactorTask, RpcMetrics, Runtime, ThreadRuntime};
+#[cfg(feature = "blocking")]
+pub use rpc::{BlockingConnection, BlockingMasterClient};
+pub use scanner::*;
 pub use schema::*;
 pub use table::*;
 pub use tablet::*;
 pub use tablet_server::TabletServer;
+pub use validation::{
+    validate_column_name,
+    validate_hms_column_name,
+    validate_hms_table_name,
+    validate_table_name,
+    MAX_HMS_IDENTIFIER_LENGTH,
+};
 pub use value::Value;
 pub use writer::*;
 
@@ -82,6 +147,32 @@ pub enum DataType {
 
 impl DataType {
 
+    /// Returns the column encodings which the tablet servers support for the data type, including
+    /// `EncodingType::Auto`.
+    pub fn supported_encodings(self) -> &'static [EncodingType] {
+        match self {
+            DataType::Bool => &[EncodingType::Auto, EncodingType::Plain, EncodingType::RunLength],
+            DataType::Int8 | DataType::Int16 |
+            DataType::Int32 | DataType::Int64 | DataType::Timestamp => &[
+                EncodingType::Auto,
+                EncodingType::Plain,
+                EncodingType::RunLength,
+                EncodingType::BitShuffle,
+            ],
+            DataType::Float | DataType::Double => &[
+                EncodingType::Auto,
+                EncodingType::Plain,
+                EncodingType::BitShuffle,
+            ],
+            DataType::Binary | DataType::String => &[
+                EncodingType::Auto,
+                EncodingType::Plain,
+                EncodingType::Prefix,
+                EncodingType::Dictionary,
+            ],
+        }
+    }
+
     fn is_var_len(self) -> bool {
         match self {
             DataType::String | DataType::Binary => true,
@@ -199,33 +290,14 @@ impl EncodingType {
         }
     }
 
+    /// Returns `true` if the tablet servers support the encoding for columns of the data type.
+    pub fn is_supported_for(self, data_type: DataType) -> bool {
+        data_type.supported_encodings().contains(&self)
+    }
+
     #[cfg(any(feature="quickcheck", test))]
     pub fn arbitrary<G>(g: &mut G, data_type: DataType) -> EncodingType where G: quickcheck::Gen {
-        match data_type {
-            DataType::Bool => *g.choose(&[
-                EncodingType::Auto,
-                EncodingType::Plain,
-                EncodingType::RunLength
-            ]).unwrap(),
-            DataType::Int8 | DataType::Int16 |
-            DataType::Int32 | DataType::Int64 | DataType::Timestamp => *g.choose(&[
-                EncodingType::Auto,
-                EncodingType::Plain,
-                EncodingType::RunLength,
-                EncodingType::BitShuffle
-            ]).unwrap(),
-            DataType::Float | DataType::Double => *g.choose(&[
-                EncodingType::Auto,
-                EncodingType::Plain,
-                EncodingType::BitShuffle
-            ]).unwrap(),
-            DataType::Binary | DataType::String => *g.choose(&[
-                EncodingType::Auto,
-                EncodingType::Plain,
-                EncodingType::Prefix,
-                EncodingType::Dictionary
-            ]).unwrap(),
-        }
+        *g.choose(data_type.supported_encodings()).unwrap()
     }
 }
 
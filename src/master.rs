@@ -7,7 +7,7 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use dns;
+use dns::{self, Resolver, SystemResolver};
 use backoff::Backoff;
 use itertools::Itertools;
 use protobuf::Message;
@@ -19,12 +19,15 @@ use rpc::{
     master
 };
 use util;
+use DeadlineExceededError;
 use Error;
+use HostPort;
 use MasterError;
 use MasterErrorCode;
 use MasterId;
 use RaftRole;
 use Result;
+use ServerVersions;
 use Status;
 
 use parking_lot::Mutex;
@@ -43,6 +46,8 @@ use kudu_pb::master::{
     ListTabletServersRequestPB, ListTabletServersResponsePB,
     PingRequestPB, PingResponsePB,
 };
+#[cfg(feature = "admin")]
+use kudu_pb::master::{ReplaceTabletRequestPB, ReplaceTabletResponsePB};
 use kudu_pb::wire_protocol::{ServerEntryPB as MasterEntry};
 
 /// Maximum number of RPCs to queue in the master proxy during leader discovery. When the queue is
@@ -60,7 +65,8 @@ macro_rules! impl_master_rpc {
                 // The real leader address will be filled in by `send_to_leader`.
                 let addr = util::dummy_addr();
                 let mut rpc = master::$fn_name(addr, deadline, request);
-                rpc.callback = Some(Box::new(CB(self.clone(), cb, PhantomData::<$response_type>)));
+                rpc.callback = Some(Box::new(CB(self.clone(), cb, PhantomData::<$response_type>,
+                                                Vec::new())));
                 self.send_to_leader(rpc);
             }
     };
@@ -73,12 +79,19 @@ macro_rules! impl_master_rpc {
 pub struct MasterProxy {
     inner: Arc<Mutex<Inner>>,
     messenger: Messenger,
+    resolver: Arc<Resolver>,
+    /// DNS SRV record which is re-resolved when every known master is unreachable.
+    srv_record: Option<Arc<String>>,
+    /// Updated with the masters' versions from each `ListMasters` response.
+    server_versions: ServerVersions,
 }
 
 /// Container for master metadata.
 struct Inner {
     leader: Leader,
     replicas: HashSet<SocketAddr>,
+    /// Replicas which have failed a `ListMasters` RPC during the current leader refresh.
+    failed: HashSet<SocketAddr>,
 }
 
 impl MasterProxy {
@@ -86,14 +99,32 @@ impl MasterProxy {
     /// Creates a new `MasterProxy` with an initial seed of master addresses, and a `Messenger`
     /// instance to handle sending RPCs.
     pub fn new(replicas: &[SocketAddr], messenger: Messenger) -> MasterProxy {
+        MasterProxy::with_resolver(replicas, None, Arc::new(SystemResolver), ServerVersions::new(),
+                                   messenger)
+    }
+
+    /// Creates a new `MasterProxy` with an initial seed of master addresses, the SRV record the
+    /// addresses were resolved from, and the resolver used to resolve master hostnames. If every
+    /// known master fails to respond during leader discovery, the SRV record is resolved again in
+    /// case the masters have moved. The masters' versions are recorded in `server_versions` during
+    /// leader discovery.
+    pub fn with_resolver(replicas: &[SocketAddr],
+                         srv_record: Option<String>,
+                         resolver: Arc<Resolver>,
+                         server_versions: ServerVersions,
+                         messenger: Messenger) -> MasterProxy {
         assert!(replicas.len() > 0);
         let replicas = replicas.iter().cloned().collect();
         let proxy = MasterProxy {
             inner: Arc::new(Mutex::new(Inner {
                 leader: Leader::Unknown(QueueMap::with_capacity(QUEUE_LEN)),
                 replicas: replicas,
+                failed: HashSet::new(),
             })),
             messenger: messenger,
+            resolver: resolver,
+            srv_record: srv_record.map(Arc::new),
+            server_versions: server_versions,
         };
         proxy.refresh_leader_cache();
         proxy
@@ -103,6 +134,11 @@ impl MasterProxy {
         &self.messenger
     }
 
+    /// Returns the resolver used to resolve server hostnames.
+    pub fn resolver(&self) -> &Resolver {
+        &*self.resolver
+    }
+
     /// Returns the masters which this `MasterProxy` has discovered.
     pub fn masters(&self) -> Vec<SocketAddr> {
         let mut masters = Vec::with_capacity(5);
@@ -119,7 +155,22 @@ impl MasterProxy {
     }
 
     impl_master_rpc!(alter_table, AlterTableRequestPB, AlterTableResponsePB);
-    impl_master_rpc!(create_table, CreateTableRequestPB, CreateTableResponsePB);
+
+    /// Creates a table. The required feature flags are those returned alongside the request by
+    /// `TableBuilder::into_pb`, since they can not be recovered from the encoded request.
+    pub fn create_table<F>(&self,
+                           deadline: Instant,
+                           request: CreateTableRequestPB,
+                           required_feature_flags: Vec<u32>,
+                           cb: F)
+    where F: FnOnce(Result<CreateTableResponsePB>) + Send + 'static {
+        let addr = util::dummy_addr();
+        let mut rpc = master::create_table(addr, deadline, request);
+        rpc.required_feature_flags = required_feature_flags;
+        rpc.callback = Some(Box::new(CB(self.clone(), cb, PhantomData::<CreateTableResponsePB>,
+                                        Vec::new())));
+        self.send_to_leader(rpc);
+    }
     impl_master_rpc!(delete_table, DeleteTableRequestPB, DeleteTableResponsePB);
     impl_master_rpc!(get_table_locations, GetTableLocationsRequestPB, GetTableLocationsResponsePB);
     impl_master_rpc!(get_table_schema, GetTableSchemaRequestPB, GetTableSchemaResponsePB);
@@ -130,6 +181,8 @@ impl MasterProxy {
     impl_master_rpc!(list_tables, ListTablesRequestPB, ListTablesResponsePB);
     impl_master_rpc!(list_tablet_servers, ListTabletServersRequestPB, ListTabletServersResponsePB);
     impl_master_rpc!(ping, PingRequestPB, PingResponsePB);
+    #[cfg(feature = "admin")]
+    impl_master_rpc!(replace_tablet, ReplaceTabletRequestPB, ReplaceTabletResponsePB);
 
     /// Sends the RPC if the leader master is known, otherwise queues the RPC to be sent when the
     /// leader is discovered.
@@ -158,13 +211,18 @@ impl MasterProxy {
             None
         }
 
+        if self.messenger.is_shut_down() {
+            rpc.fail(Error::Shutdown);
+            return;
+        }
+
         // This control flow is a bit funky to keep the critical section short and still appease the
         // borrow checker.
 
         let now = Instant::now();
         // Make sure that the duration_since call below doesn't panic.
         if rpc.timed_out(now) {
-            rpc.fail(Error::TimedOut);
+            rpc.fail(Error::QueueTimedOut);
             return;
         }
         let duration = rpc.deadline.duration_since(now);
@@ -177,6 +235,18 @@ impl MasterProxy {
         }
     }
 
+    /// Fails the RPCs queued while the leader is unknown with `Error::Shutdown`. Called when the
+    /// messenger is shut down, since the leader will never be found.
+    pub fn shutdown(&self) {
+        let rpcs = match self.inner.lock().leader {
+            Leader::Unknown(ref mut queue) => queue.drain().map(|(_, rpc)| rpc).collect::<Vec<_>>(),
+            Leader::Known(_) => return,
+        };
+        for rpc in rpcs {
+            rpc.fail(Error::Shutdown);
+        }
+    }
+
     /// Times out the queued RPC with the given index.
     fn timeout_queued_rpc(&self, queue_idx: usize) {
         // Keep the critical section short.
@@ -185,6 +255,11 @@ impl MasterProxy {
             rpc = queue.remove(queue_idx);
         }
         if let Some(rpc) = rpc {
+            // The timer fires early if the messenger shuts down, and the leader will never be
+            // found.
+            if self.messenger.is_shut_down() {
+                return rpc.fail(Error::Shutdown);
+            }
             // Warning: extreme hack. We can get 'false positive' timeout callbacks when we
             // transition from Unknown -> Known -> Unknown, because the queue resets the idx
             // counter back to 0, so we may get the callback from a previous Unkown era. To
@@ -192,7 +267,7 @@ impl MasterProxy {
             // like in Connection, or keep a transition counter or something. Easier than all that
             // is to just check if the RPC is actually timed out.
             if rpc.timed_out(Instant::now()) {
-                rpc.fail(Error::TimedOut);
+                rpc.fail(Error::QueueTimedOut);
             } else {
                 self.send_to_leader(rpc);
             }
@@ -234,7 +309,11 @@ impl MasterProxy {
     fn refresh_leader_cache(&self) {
         debug_assert!(!self.inner.lock().leader.is_known());
         let cancel = Arc::new(AtomicBool::new(false));
-        let replicas = self.inner.lock().replicas.iter().cloned().collect::<Vec<_>>();
+        let replicas = {
+            let mut inner = self.inner.lock();
+            inner.failed.clear();
+            inner.replicas.iter().cloned().collect::<Vec<_>>()
+        };
         debug!("refreshing leader master from known replicas: {:?}", replicas);
 
         let deadline = Instant::now() + Duration::from_secs(LEADER_REFRESH_TIMEOUT_SECS);
@@ -265,8 +344,8 @@ impl MasterProxy {
 
     /// Retries a `ListMasters` RPC after a backoff period.
     fn retry_list_masters(self, mut rpc: Rpc, mut backoff: Backoff) {
-        // Short circuit if the leader has already been found.
-        if rpc.cancelled() { return; }
+        // Short circuit if the leader has already been found, or the messenger has shut down.
+        if rpc.cancelled() || self.messenger.is_shut_down() { return; }
         let delay_ms = backoff.next_backoff_ms();
         let delay = Duration::from_millis(delay_ms);
         let proxy = self.clone();
@@ -293,6 +372,11 @@ impl MasterProxy {
         // Holds the resolved addresses for the leader master.
         let mut leader: HashSet<SocketAddr> = HashSet::new();
 
+        // The number of masters listed by the response, and the number which failed to resolve.
+        let mut listed_masters = 0;
+        let mut unresolved_masters = 0;
+
+        let failed = result.is_err();
         if let Err(error) = result {
             info!("ListMasters RPC to master {} failed: {}", addr, error);
             // Fall through to retry.
@@ -306,26 +390,57 @@ impl MasterProxy {
                 // Fall through to retry.
             } else {
                 debug!("ListMasters RPC to master {} response: {:?}", addr, response);
+                self.server_versions.record_masters(
+                    response.get_masters()
+                            .iter()
+                            .filter(|entry| !entry.has_error())
+                            .map(|entry| entry.get_registration().get_software_version()));
 
                 for server_entry in response.mut_masters().iter_mut() {
                     if server_entry.has_error()  { continue; }
-                    let addrs = dns::resolve_hosts(server_entry.get_registration()
-                                                               .get_rpc_addresses());
-                    replicas.extend(addrs.iter().cloned());
+                    listed_masters += 1;
+                    let hostports = server_entry.get_registration()
+                                                .get_rpc_addresses()
+                                                .iter()
+                                                .filter_map(|pb| HostPort::from_pb(pb).ok())
+                                                .collect::<Vec<_>>();
+                    let resolution = dns::resolve_hosts(self.resolver(), &hostports);
+                    for &(ref hostport, ref error) in &resolution.failures {
+                        warn!("unable to resolve master {}: {}", hostport, error);
+                    }
+                    if resolution.addrs.is_empty() {
+                        unresolved_masters += 1;
+                        continue;
+                    }
+                    replicas.extend(resolution.addrs.iter().cloned());
 
                     if server_entry.get_role() == Role::LEADER {
                         // Check that an individual ListMasters response contains at most a single
                         // leader node.
                         assert!(leader.is_empty());
-                        leader = addrs;
+                        leader = resolution.addrs;
                     }
                 }
             }
         }
 
+        if unresolved_masters > 0 {
+            let resolved_masters = listed_masters - unresolved_masters;
+            if resolved_masters <= listed_masters / 2 {
+                warn!("only {} of {} masters listed by {} could be resolved, which is not a majority",
+                      resolved_masters, listed_masters, addr);
+            } else {
+                info!("{} of {} masters listed by {} could not be resolved",
+                      unresolved_masters, listed_masters, addr);
+            }
+        }
+
         // Short circuit if the master has already been found.
         if rpc.cancelled() { return; }
         let cancel = rpc.cancel.as_ref().unwrap().clone();
+        if failed {
+            self.handle_failed_replica(addr, cancel.clone());
+        }
         if replicas.is_empty() {
             // The ListMasters call either completely failed, or we weren't able to resolve any
             // master addresses.
@@ -335,7 +450,7 @@ impl MasterProxy {
             self.handle_discovered_replicas(replicas, cancel);
             self.retry_list_masters(rpc, backoff);
         } else {
-            self.handle_discovered_leaders(leader, replicas, cancel);
+            self.handle_discovered_leaders(leader, replicas, unresolved_masters == 0, cancel);
         }
     }
 
@@ -362,10 +477,40 @@ impl MasterProxy {
         }
     }
 
+    /// Handler executed when a `ListMasters` RPC to a replica fails. If every known replica has
+    /// failed, the SRV record (if any) is resolved again, and any new replicas are contacted.
+    /// This should *not* be called on the Event Loop thread, since it may block on DNS.
+    fn handle_failed_replica(&self, addr: SocketAddr, cancel: Arc<AtomicBool>) {
+        let srv_record = match self.srv_record {
+            Some(ref srv_record) => srv_record,
+            None => return,
+        };
+        {
+            let mut inner = self.inner.lock();
+            if cancel.load(Ordering::Relaxed) { return; }
+            inner.failed.insert(addr);
+            if !inner.failed.is_superset(&inner.replicas) { return; }
+            inner.failed.clear();
+        }
+
+        info!("all known masters are unreachable, resolving SRV record {}", srv_record);
+        let targets = match self.resolver.resolve_srv(srv_record) {
+            Ok(targets) => targets,
+            Err(error) => {
+                warn!("unable to resolve SRV record {}: {}", srv_record, error);
+                return;
+            },
+        };
+        let addrs = dns::resolve_hostports(self.resolver(), &targets).into_iter().collect::<HashSet<_>>();
+        self.handle_discovered_replicas(addrs, cancel);
+    }
+
     /// Handler executed when a set of master leader addresses is returned from a `ListMasters` RPC.
+    /// `complete` indicates whether every replica listed in the response was resolved.
     fn handle_discovered_leaders(&self,
                                  leader: HashSet<SocketAddr>,
-                                 replicas: HashSet<SocketAddr>,
+                                 mut replicas: HashSet<SocketAddr>,
+                                 complete: bool,
                                  cancel: Arc<AtomicBool>) {
         let addr = match leader.iter().next() {
             Some(&addr) => addr,
@@ -385,8 +530,17 @@ impl MasterProxy {
 
             // We replace the entire replica set instead of adding to it so that we retain only the
             // replicas that the current leader knows about. This serves to filter out old master
-            // replicas after they are no longer around.
-            mem::replace(&mut *inner, Inner { leader: Leader::Known(addr), replicas: replicas })
+            // replicas after they are no longer around. If some of the listed replicas could not be
+            // resolved the previously known replicas are retained as well, since they may include
+            // the unresolved masters.
+            if !complete {
+                replicas.extend(inner.replicas.iter().cloned());
+            }
+            mem::replace(&mut *inner, Inner {
+                leader: Leader::Known(addr),
+                replicas: replicas,
+                failed: HashSet::new(),
+            })
         };
 
         if leader.len() > 1 {
@@ -456,6 +610,8 @@ impl_master_response!(IsCreateTableDoneResponsePB);
 impl_master_response!(ListTablesResponsePB);
 impl_master_response!(ListTabletServersResponsePB);
 impl_master_response!(PingResponsePB, no_error);
+#[cfg(feature = "admin")]
+impl_master_response!(ReplaceTabletResponsePB);
 
 impl MasterResponse for ListMastersResponsePB {
     fn error(&mut self) -> Option<MasterError> {
@@ -465,15 +621,20 @@ impl MasterResponse for ListMastersResponsePB {
     }
 }
 
-struct CB<Resp, F>(MasterProxy, F, PhantomData<Resp>)
+/// The master RPC callback. Retries the RPC on the new leader when the master it was sent to is
+/// not the leader, or could not be reached. The errors of the retried attempts are kept so that
+/// a retried RPC which times out fails with `Error::DeadlineExceeded`.
+struct CB<Resp, F>(MasterProxy, F, PhantomData<Resp>, Vec<Error>)
 where Resp: MasterResponse, F: FnOnce(Result<Resp>) + Send + 'static;
 impl <Resp, F> Callback for CB<Resp, F>
 where Resp: MasterResponse, F: FnOnce(Result<Resp>) + Send + 'static {
-    fn callback(self: Box<Self>, result: Result<()>, mut rpc: Rpc) {
+    fn callback(mut self: Box<Self>, result: Result<()>, mut rpc: Rpc) {
         match result {
             Ok(_) => match rpc.mut_response::<Resp>().error() {
-                Some(ref error) if error.code() == MasterErrorCode::NotTheLeader ||
-                                   error.code() == MasterErrorCode::CatalogManagerNotInitialized => {
+                Some(ref error) if (error.code() == MasterErrorCode::NotTheLeader ||
+                                    error.code() == MasterErrorCode::CatalogManagerNotInitialized) &&
+                                   self.0.messenger.retry_budget().try_retry() => {
+                    self.3.push(Error::from(error.clone()));
                     self.0.reset_leader_cache(rpc.addr);
                     let proxy: MasterProxy = self.0.clone();
                     rpc.callback = Some(self);
@@ -482,13 +643,20 @@ where Resp: MasterResponse, F: FnOnce(Result<Resp>) + Send + 'static {
                 Some(error) => self.1(Err(Error::from(MasterError::from(error)))),
                 None => self.1(Ok(rpc.take_response::<Resp>())),
             },
-            Err(ref error) if error.is_network_error() => {
+            Err(ref error) if error.is_network_error() &&
+                              self.0.messenger.retry_budget().try_retry() => {
                 // On connection error, reset the leader cache and resend.
+                self.3.push(error.clone());
                 self.0.reset_leader_cache(rpc.addr);
                 let proxy: MasterProxy = self.0.clone();
                 rpc.callback = Some(self);
                 proxy.send_to_leader(rpc);
             }
+            Err(ref error) if error.is_timeout() && !self.3.is_empty() => {
+                let CB(_, cb, _, mut errors) = *self;
+                errors.push(error.clone());
+                cb(Err(Error::DeadlineExceeded(DeadlineExceededError::new(errors))))
+            }
             Err(error) => self.1(Err(error)),
         }
     }
@@ -501,8 +669,9 @@ where Resp: MasterResponse, F: FnOnce(Result<Resp>) + Send + 'static {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Master {
     id: MasterId,
-    rpc_addrs: Vec<(String, u16)>,
-    http_addrs: Vec<(String, u16)>,
+    rpc_addrs: Vec<HostPort>,
+    http_addrs: Vec<HostPort>,
+    software_version: String,
     seqno: i64,
     role: RaftRole,
 }
@@ -512,14 +681,18 @@ impl Master {
         &self.id
     }
 
-    pub fn rpc_addrs(&self) -> &[(String, u16)] {
+    pub fn rpc_addrs(&self) -> &[HostPort] {
         &self.rpc_addrs
     }
 
-    pub fn http_addrs(&self) -> &[(String, u16)] {
+    pub fn http_addrs(&self) -> &[HostPort] {
         &self.http_addrs
     }
 
+    pub fn software_version(&self) -> &str {
+        &self.software_version
+    }
+
     pub fn seqno(&self) -> i64 {
         self.seqno
     }
@@ -538,26 +711,25 @@ impl Master {
         let id = try!(MasterId::parse_bytes(master.get_instance_id().get_permanent_uuid()));
         let seqno = master.get_instance_id().get_instance_seqno();
 
-        // TODO: check bounds on port casts.
-        let rpc_addrs = master.mut_registration()
-                                     .take_rpc_addresses()
-                                     .into_iter()
-                                     .map(|mut host_port| (host_port.take_host(),
-                                                           host_port.get_port() as u16))
-                                     .collect::<Vec<_>>();
-        let http_addrs = master.mut_registration()
-                                      .take_http_addresses()
-                                      .into_iter()
-                                      .map(|mut host_port| (host_port.take_host(),
-                                                            host_port.get_port() as u16))
-                                      .collect::<Vec<_>>();
-
+        let rpc_addrs = try!(master.get_registration()
+                                   .get_rpc_addresses()
+                                   .iter()
+                                   .map(HostPort::from_pb)
+                                   .collect::<Result<Vec<_>>>());
+        let http_addrs = try!(master.get_registration()
+                                    .get_http_addresses()
+                                    .iter()
+                                    .map(HostPort::from_pb)
+                                    .collect::<Result<Vec<_>>>());
+
+        let software_version = master.mut_registration().take_software_version();
         let role = RaftRole::from_pb(master.get_role());
 
         Ok(Master {
             id: id,
             rpc_addrs: rpc_addrs,
             http_addrs: http_addrs,
+            software_version: software_version,
             seqno: seqno,
             role: role,
         })
@@ -667,7 +839,7 @@ mod tests {
 
         let elapsed = Instant::now().duration_since(now);
 
-        assert_eq!(Err(Error::TimedOut), result);
+        assert_eq!(Err(Error::QueueTimedOut), result);
 
         // If this gets flaky, figure out how to get tighter times out of mio.
         assert!(elapsed > Duration::from_millis(100), "expected: 100ms, elapsed: {:?}", elapsed);
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use kudu_pb::master::ListMastersRequestPB;
+
+use Client;
+use ConnectionEvent;
+use Error;
+use HostPort;
+use RaftRole;
+use Result;
+
+/// A change in the membership or roles of the cluster's masters, observed by
+/// `Client::watch_masters`.
+///
+/// Masters are identified by their first RPC address, since masters which the leader can not
+/// reach are listed without their instance ID.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MasterEvent {
+    /// A master was listed which the previous refresh did not list. Every master is reported as
+    /// added by the first refresh.
+    Added {
+        addr: HostPort,
+        role: RaftRole,
+    },
+
+    /// A master which the previous refresh listed is no longer listed.
+    Removed(HostPort),
+
+    /// The role of a master changed, for instance when leadership fails over to another master.
+    /// Masters which the leader can not reach have the `Unknown` role.
+    RoleChanged {
+        addr: HostPort,
+        from: RaftRole,
+        to: RaftRole,
+    },
+
+    /// The masters could not be listed. The previous membership is kept, and the next refresh
+    /// happens at the usual interval.
+    RefreshFailed(Error),
+}
+
+/// A stream of changes to the cluster's masters. See `Client::watch_masters`.
+///
+/// Iterating the watch blocks until the next event. The background refresh stops within one
+/// interval of the watch being dropped, or when the client shuts down.
+pub struct MasterWatch {
+    events: Receiver<MasterEvent>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MasterWatch {
+
+    #[doc(hidden)]
+    pub fn new(client: Client, interval: Duration) -> MasterWatch {
+        let (send, events) = channel();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let connection_events = client.connection_events(64);
+        {
+            let stopped = stopped.clone();
+            thread::spawn(move || watch(client, interval, connection_events, send, stopped));
+        }
+        MasterWatch {
+            events: events,
+            stopped: stopped,
+        }
+    }
+
+    /// Waits up to the timeout for the next event.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<MasterEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Returns the next event if one is ready, without blocking.
+    pub fn try_recv(&self) -> Option<MasterEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Iterator for MasterWatch {
+    type Item = MasterEvent;
+
+    fn next(&mut self) -> Option<MasterEvent> {
+        self.events.recv().ok()
+    }
+}
+
+impl Drop for MasterWatch {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Refreshes the masters until the watch is dropped or the client shuts down. A refresh happens
+/// every interval, and as soon as a connection to a master is reset.
+fn watch(client: Client,
+         interval: Duration,
+         connection_events: Receiver<ConnectionEvent>,
+         events: Sender<MasterEvent>,
+         stopped: Arc<AtomicBool>) {
+    let mut masters = BTreeMap::new();
+    while !stopped.load(Ordering::SeqCst) && !client.is_shut_down() {
+        let deadline = Instant::now() + client.config().admin_operation_timeout();
+        let changes = match list_masters(&client, deadline) {
+            Ok(current) => {
+                let changes = diff(&masters, &current);
+                masters = current;
+                changes
+            },
+            Err(error) => vec![MasterEvent::RefreshFailed(error)],
+        };
+        for change in changes {
+            if events.send(change).is_err() { return; }
+        }
+
+        let next_refresh = Instant::now() + interval;
+        loop {
+            let now = Instant::now();
+            if now >= next_refresh || stopped.load(Ordering::SeqCst) { break; }
+            match connection_events.recv_timeout(next_refresh - now) {
+                Ok(ConnectionEvent::Reset { addr, .. }) => {
+                    if client.master_proxy().masters().contains(&addr) { break; }
+                },
+                Ok(_) => (),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    thread::sleep(next_refresh - now);
+                    break;
+                },
+            }
+        }
+    }
+}
+
+/// Returns the role of each master listed by the leader, by RPC address.
+fn list_masters(client: &Client, deadline: Instant) -> Result<BTreeMap<HostPort, RaftRole>> {
+    let (send, recv) = sync_channel(1);
+    client.master_proxy().list_masters(deadline, ListMastersRequestPB::new(),
+                                       move |resp| send.send(resp).unwrap());
+    let mut resp = try!(recv.recv().unwrap());
+
+    let mut masters = BTreeMap::new();
+    for entry in resp.take_masters().into_iter() {
+        let addr = match entry.get_registration().get_rpc_addresses().first() {
+            Some(addr) => try!(HostPort::from_pb(addr)),
+            None => continue,
+        };
+        let role = if entry.has_error() { RaftRole::Unknown } else { RaftRole::from_pb(entry.get_role()) };
+        masters.insert(addr, role);
+    }
+    Ok(masters)
+}
+
+/// Returns the events which lead from the previous masters to the current masters.
+fn diff(previous: &BTreeMap<HostPort, RaftRole>,
+        current: &BTreeMap<HostPort, RaftRole>)
+        -> Vec<MasterEvent> {
+    let mut events = Vec::new();
+    for addr in previous.keys().filter(|addr| !current.contains_key(addr)) {
+        events.push(MasterEvent::Removed(addr.clone()));
+    }
+    for (addr, &role) in current {
+        match previous.get(addr) {
+            None => events.push(MasterEvent::Added { addr: addr.clone(), role: role }),
+            Some(&previous_role) if previous_role != role => {
+                events.push(MasterEvent::RoleChanged { addr: addr.clone(), from: previous_role, to: role });
+            },
+            Some(_) => (),
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::BTreeMap;
+
+    use HostPort;
+    use RaftRole;
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let a = HostPort::new("master-a", 7051);
+        let b = HostPort::new("master-b", 7051);
+        let c = HostPort::new("master-c", 7051);
+
+        let mut previous = BTreeMap::new();
+        assert!(diff(&previous, &previous).is_empty());
+
+        let mut current = BTreeMap::new();
+        current.insert(a.clone(), RaftRole::Leader);
+        current.insert(b.clone(), RaftRole::Follower);
+        assert_eq!(vec![MasterEvent::Added { addr: a.clone(), role: RaftRole::Leader },
+                        MasterEvent::Added { addr: b.clone(), role: RaftRole::Follower }],
+                   diff(&previous, &current));
+
+        // Leadership fails over from a to b, a becomes unreachable, and c replaces it.
+        previous = current;
+        let mut current = BTreeMap::new();
+        current.insert(b.clone(), RaftRole::Leader);
+        current.insert(c.clone(), RaftRole::Unknown);
+        assert_eq!(vec![MasterEvent::Removed(a.clone()),
+                        MasterEvent::RoleChanged { addr: b.clone(), from: RaftRole::Follower, to: RaftRole::Leader },
+                        MasterEvent::Added { addr: c.clone(), role: RaftRole::Unknown }],
+                   diff(&previous, &current));
+        assert!(diff(&current, &current).is_empty());
+    }
+}
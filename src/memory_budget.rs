@@ -0,0 +1,150 @@
+use std::fmt;
+use std::sync::Arc;
+
+use parking_lot::{Condvar, Mutex};
+
+use Error;
+use Result;
+
+/// Accounts for the approximate memory held by a client's buffered data: row operations buffered
+/// or in flight in writers, and scan batches prefetched ahead of their consumer.
+///
+/// When the budget is exhausted, writers reject new operations with `Error::Backoff`, and
+/// prefetching scans pause until their consumers catch up. A single reservation larger than the
+/// whole budget is admitted when nothing else is reserved, so that it can not stall indefinitely.
+///
+/// Budgets are cheap to clone, and clones share their accounting.
+#[derive(Clone)]
+pub struct MemoryBudget {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// The budget in bytes, or `None` if unlimited.
+    limit: Option<usize>,
+    used: Mutex<usize>,
+    released: Condvar,
+}
+
+impl MemoryBudget {
+
+    /// Creates a budget of `limit` bytes, or an unlimited budget which only tracks usage if
+    /// `None`.
+    pub fn new(limit: Option<usize>) -> MemoryBudget {
+        MemoryBudget {
+            inner: Arc::new(Inner {
+                limit: limit,
+                used: Mutex::new(0),
+                released: Condvar::new(),
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.inner.limit
+    }
+
+    /// Returns the number of bytes currently reserved.
+    pub fn used(&self) -> usize {
+        *self.inner.used.lock()
+    }
+
+    /// Reserves bytes from the budget, failing with `Error::Backoff` if the budget is exhausted.
+    pub fn try_reserve(&self, bytes: usize) -> Result<()> {
+        let mut used = self.inner.used.lock();
+        if !self.fits(*used, bytes) {
+            return Err(Error::Backoff);
+        }
+        *used += bytes;
+        Ok(())
+    }
+
+    /// Reserves bytes from the budget, blocking until enough bytes have been released.
+    pub fn reserve(&self, bytes: usize) {
+        let mut used = self.inner.used.lock();
+        while !self.fits(*used, bytes) {
+            self.inner.released.wait(&mut used);
+        }
+        *used += bytes;
+    }
+
+    /// Reserves bytes from the budget regardless of the limit. Used to account for memory which
+    /// is already held, or which must be admitted for its holder to make progress.
+    pub fn force_reserve(&self, bytes: usize) {
+        *self.inner.used.lock() += bytes;
+    }
+
+    /// Returns bytes to the budget.
+    pub fn release(&self, bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+        let mut used = self.inner.used.lock();
+        debug_assert!(*used >= bytes, "released more memory than reserved");
+        *used = used.saturating_sub(bytes);
+        self.inner.released.notify_all();
+    }
+
+    fn fits(&self, used: usize, bytes: usize) -> bool {
+        match self.inner.limit {
+            Some(limit) => used == 0 || used.saturating_add(bytes) <= limit,
+            None => true,
+        }
+    }
+}
+
+impl fmt::Debug for MemoryBudget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MemoryBudget {{ limit: {:?}, used: {} }}", self.limit(), self.used())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::thread;
+    use std::time::Duration;
+
+    use Error;
+    use super::*;
+
+    #[test]
+    fn test_try_reserve() {
+        let budget = MemoryBudget::new(Some(100));
+        budget.try_reserve(60).unwrap();
+        assert_eq!(Err(Error::Backoff), budget.try_reserve(50));
+        budget.try_reserve(40).unwrap();
+        assert_eq!(100, budget.used());
+        budget.release(100);
+
+        // Oversized reservations are admitted when the budget is otherwise unused.
+        budget.try_reserve(200).unwrap();
+        assert_eq!(Err(Error::Backoff), budget.try_reserve(1));
+        budget.release(200);
+
+        let unlimited = MemoryBudget::new(None);
+        unlimited.try_reserve(usize::max_value()).unwrap();
+        assert_eq!(usize::max_value(), unlimited.used());
+    }
+
+    #[test]
+    fn test_reserve() {
+        let budget = MemoryBudget::new(Some(100));
+        budget.reserve(100);
+
+        let releaser = {
+            let budget = budget.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(100));
+                budget.release(50);
+            })
+        };
+        budget.reserve(50);
+        assert_eq!(100, budget.used());
+        releaser.join().unwrap();
+
+        budget.force_reserve(50);
+        assert_eq!(150, budget.used());
+        assert_eq!(Err(Error::Backoff), budget.try_reserve(1));
+    }
+}
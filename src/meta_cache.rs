@@ -1,7 +1,9 @@
-use std::cmp::Ordering;
-use std::collections::VecDeque;
+use std::cmp::{self, Ordering};
+use std::collections::{HashMap, VecDeque};
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::mpsc::sync_channel;
 use std::thread;
 use std::time::Duration;
 use std::time::Instant;
@@ -12,17 +14,25 @@ use parking_lot::Mutex;
 use Error;
 use MasterErrorCode;
 use PartitionSchema;
-use RaftRole;
 use Result;
 use Schema;
 use TableId;
 use TabletId;
+use TabletServerId;
 use backoff::Backoff;
 use master::MasterProxy;
 use tablet::Tablet;
 
 const MAX_RETURNED_TABLE_LOCATIONS: u32 = 10;
 
+/// Duration a tablet server is blacklisted for after its first failure. The duration doubles with
+/// each consecutive failure, up to `MAX_BLACKLIST_DURATION`.
+const BLACKLIST_DURATION_MS: u64 = 500;
+
+/// Maximum duration a tablet server is blacklisted for. A blacklisted tablet server which does not
+/// fail again within this duration after its blacklisting ends is forgiven its past failures.
+const MAX_BLACKLIST_DURATION_MS: u64 = 30_000;
+
 /// Backoff used for retrying after a `TABLET_NOT_RUNNING` error.
 fn backoff() -> Backoff {
     Backoff::with_duration_range(100, 60_000_000)
@@ -129,6 +139,63 @@ pub struct Inner {
     primary_key_schema: Schema,
     partition_schema: PartitionSchema,
     entries: Mutex<Vec<Entry>>,
+    /// Expiration times of entries, keyed by lower bound partition key. Entries which have been
+    /// marked stale expire immediately. Must be locked after `entries`.
+    expirations: Mutex<HashMap<Vec<u8>, Instant>>,
+    /// Amount of time entries are cached before expiring.
+    ttl: Option<Duration>,
+    /// In-flight `GetTableLocations` lookups.
+    lookups: Mutex<Vec<Lookup>>,
+    /// The number of `GetTableLocations` RPCs sent, including retries.
+    lookup_rpcs: AtomicUsize,
+    /// Tablet servers which recently failed requests, which are avoided in favor of other replicas.
+    blacklist: Mutex<HashMap<TabletServerId, Blacklisted>>,
+}
+
+/// The blacklisting of a tablet server which failed requests.
+struct Blacklisted {
+    /// The number of consecutive failures.
+    failures: u32,
+    /// The time at which the tablet server is no longer blacklisted.
+    until: Instant,
+}
+
+/// An in-flight `GetTableLocations` lookup, and the callers waiting on its result.
+///
+/// Cache misses are coalesced: a miss joins the in-flight lookup with the greatest start key at
+/// or before the missed partition key, since the lookup returns the locations of the tablets
+/// following its start key. When the lookup completes, each waiter checks the cache again, and
+/// starts a new lookup if its partition key is still not covered.
+struct Lookup {
+    partition_key: Vec<u8>,
+    waiters: Vec<Box<Waiter>>,
+}
+
+trait Waiter: Send {
+    fn complete(self: Box<Self>, meta_cache: &MetaCache, result: Result<()>);
+}
+
+struct ExtractWaiter<Extractor, F> {
+    partition_key: Vec<u8>,
+    deadline: Instant,
+    extractor: Extractor,
+    cb: F,
+}
+
+impl <Extractor, T, F> Waiter for ExtractWaiter<Extractor, F>
+where Extractor: FnOnce(&Entry) -> T + Send + 'static,
+      F: FnOnce(Result<T>) + Send + 'static {
+    fn complete(self: Box<Self>, meta_cache: &MetaCache, result: Result<()>) {
+        let ExtractWaiter { partition_key, deadline, extractor, cb } = *self;
+        match result {
+            Ok(()) => meta_cache.extract(partition_key, deadline, extractor, cb),
+            // The lookup was started with an earlier deadline than this waiter's.
+            Err(ref error) if error.is_timeout() && Instant::now() < deadline => {
+                meta_cache.extract(partition_key, deadline, extractor, cb)
+            },
+            Err(error) => cb(Err(error)),
+        }
+    }
 }
 
 impl MetaCache {
@@ -136,6 +203,7 @@ impl MetaCache {
     pub fn new(table: TableId,
                primary_key_schema: Schema,
                partition_schema: PartitionSchema,
+               ttl: Option<Duration>,
                master: MasterProxy)
                -> MetaCache {
         MetaCache {
@@ -145,6 +213,11 @@ impl MetaCache {
                 primary_key_schema: primary_key_schema,
                 partition_schema: partition_schema,
                 entries: Mutex::new(Vec::new()),
+                expirations: Mutex::new(HashMap::new()),
+                ttl: ttl,
+                lookups: Mutex::new(Vec::new()),
+                lookup_rpcs: AtomicUsize::new(0),
+                blacklist: Mutex::new(HashMap::new()),
             })
         }
     }
@@ -154,7 +227,7 @@ impl MetaCache {
                     deadline: Instant,
                     cb: F)
     where F: FnOnce(Result<Entry>) + Send + 'static {
-        self.extract(partition_key, deadline, backoff(), |entry| entry.clone(), cb);
+        self.extract(partition_key, deadline, |entry| entry.clone(), cb);
     }
 
     fn cached_entry(&self, partition_key: &[u8]) -> Option<Entry> {
@@ -176,7 +249,7 @@ impl MetaCache {
                 None
             }
         };
-        self.extract(partition_key, deadline, backoff(), extractor, cb);
+        self.extract(partition_key, deadline, extractor, cb);
     }
 
     pub fn tablet_leader<F>(&self,
@@ -187,21 +260,20 @@ impl MetaCache {
         let mut addrs = Vec::with_capacity(1);
         let extractor = move |entry: &Entry| {
             if let Entry::Tablet(ref tablet) = *entry {
-                if tablet.replicas()[0].role() == RaftRole::Leader {
-                    addrs.extend_from_slice(&tablet.replicas()[0].resolved_rpc_addrs());
+                if let Some(leader) = tablet.leader() {
+                    addrs.extend_from_slice(leader.resolved_rpc_addrs());
                 }
                 Some((tablet.id(), addrs))
             } else {
                 None
             }
         };
-        self.extract(partition_key, deadline, backoff(), extractor, cb);
+        self.extract(partition_key, deadline, extractor, cb);
     }
 
     fn extract<Extractor, T, F>(&self,
                                 partition_key: Vec<u8>,
                                 deadline: Instant,
-                                mut backoff: Backoff,
                                 extractor: Extractor,
                                 cb: F)
     where Extractor: FnOnce(&Entry) -> T + Send + 'static,
@@ -212,32 +284,118 @@ impl MetaCache {
             ExtractCachedResult::Value(value) => return cb(Ok(value)),
         };
 
+        let waiter = Box::new(ExtractWaiter {
+            partition_key: partition_key.clone(),
+            deadline: deadline,
+            extractor: extractor,
+            cb: cb,
+        });
+
+        {
+            let mut lookups = self.inner.lookups.lock();
+            let mut joined: Option<usize> = None;
+            for (idx, lookup) in lookups.iter().enumerate() {
+                if lookup.partition_key <= partition_key &&
+                   joined.map_or(true, |j| lookups[j].partition_key < lookup.partition_key) {
+                    joined = Some(idx);
+                }
+            }
+            if let Some(idx) = joined {
+                trace!("coalescing lookup of partition key {:?} with in-flight lookup of {:?}",
+                       partition_key, lookups[idx].partition_key);
+                lookups[idx].waiters.push(waiter);
+                return;
+            }
+            lookups.push(Lookup {
+                partition_key: partition_key.clone(),
+                waiters: vec![waiter],
+            });
+        }
+
+        self.lookup(partition_key, deadline, backoff());
+    }
+
+    /// Sends a `GetTableLocations` RPC starting at the partition key, and completes the in-flight
+    /// lookup when the locations have been added to the cache.
+    fn lookup(&self, partition_key: Vec<u8>, deadline: Instant, mut backoff: Backoff) {
         let mut request = GetTableLocationsRequestPB::new();
         request.mut_table().set_table_id(self.inner.table.to_string().into_bytes());
         request.set_partition_key_start(partition_key.clone());
         request.set_max_returned_locations(MAX_RETURNED_TABLE_LOCATIONS);
 
+        self.inner.lookup_rpcs.fetch_add(1, AtomicOrdering::Relaxed);
         let meta_cache = self.clone();
         self.master.get_table_locations(deadline, request, move |resp| {
             match resp {
                 Ok(mut resp) => {
                     meta_cache.add_tablet_locations(partition_key,
-                                                    resp.take_tablet_locations().into_vec(),
-                                                    extractor,
-                                                    cb);
+                                                    resp.take_tablet_locations().into_vec());
                 },
-                Err(Error::Master(ref error)) if error.code() == MasterErrorCode::TabletNotRunning => {
+                Err(Error::Master(ref error))
+                        if error.code() == MasterErrorCode::TabletNotRunning &&
+                           meta_cache.master.messenger().retry_budget().try_retry() => {
                     let duration = Duration::from_millis(backoff.next_backoff_ms());
                     let messenger = meta_cache.master.messenger().clone();
                     messenger.timer(duration, Box::new(move || {
-                        meta_cache.extract(partition_key, deadline, backoff, extractor, cb);
+                        // The timer fires early if the messenger shuts down.
+                        if meta_cache.master.messenger().is_shut_down() {
+                            return meta_cache.complete_lookup(&partition_key, Err(Error::Shutdown));
+                        }
+                        meta_cache.lookup(partition_key, deadline, backoff);
                     }));
                 }
-                Err(error) => cb(Err(error)),
+                Err(error) => meta_cache.complete_lookup(&partition_key, Err(error)),
             }
         });
     }
 
+    /// Removes the in-flight lookup with the provided start key, and notifies its waiters.
+    fn complete_lookup(&self, partition_key: &[u8], result: Result<()>) {
+        let waiters = {
+            let mut lookups = self.inner.lookups.lock();
+            match lookups.iter().position(|lookup| &lookup.partition_key[..] == partition_key) {
+                Some(idx) => lookups.swap_remove(idx).waiters,
+                None => return,
+            }
+        };
+        for waiter in waiters {
+            waiter.complete(self, result.clone());
+        }
+    }
+
+    /// Synchronously retrieves the current locations of every tablet in the table from the
+    /// master, bypassing the cache. The cache is not updated.
+    pub fn fetch_tablets(&self, deadline: Instant) -> Result<Vec<Tablet>> {
+        let mut tablets = Vec::new();
+        let mut partition_key = Vec::new();
+        loop {
+            let mut request = GetTableLocationsRequestPB::new();
+            request.mut_table().set_table_id(self.inner.table.to_string().into_bytes());
+            request.set_partition_key_start(partition_key);
+            request.set_max_returned_locations(MAX_RETURNED_TABLE_LOCATIONS);
+
+            let (send, recv) = sync_channel(1);
+            self.master.get_table_locations(deadline, request, move |resp| send.send(resp).unwrap());
+            let locations = try!(recv.recv().unwrap()).take_tablet_locations().into_vec();
+            let num_locations = locations.len();
+
+            partition_key = Vec::new();
+            for pb in locations {
+                let tablet = try!(Tablet::from_pb(&self.inner.primary_key_schema,
+                                                  self.inner.partition_schema.clone(),
+                                                  pb,
+                                                  self.master.resolver()));
+                partition_key = tablet.partition().upper_bound_key().to_owned();
+                tablets.push(tablet);
+            }
+
+            // The master returns fewer locations than requested once the final tablet is reached.
+            if partition_key.is_empty() || num_locations < MAX_RETURNED_TABLE_LOCATIONS as usize {
+                return Ok(tablets);
+            }
+        }
+    }
+
     pub fn table(&self) -> TableId {
         self.inner.table
     }
@@ -250,25 +408,12 @@ impl MetaCache {
         &self.inner.partition_schema
     }
 
-    fn add_tablet_locations<F, Extractor, T>(&self,
-                                             partition_key: Vec<u8>,
-                                             tablets: Vec<TabletLocationsPB>,
-                                             extractor: Extractor,
-                                             cb: F)
-    where Extractor: FnOnce(&Entry) -> T + Send + 'static,
-          F: FnOnce(Result<T>) + Send + 'static {
+    fn add_tablet_locations(&self, partition_key: Vec<u8>, tablets: Vec<TabletLocationsPB>) {
         let meta_cache = self.clone();
         thread::spawn(move || {
-            match meta_cache.tablet_locations_to_entries(&partition_key, tablets) {
-                Ok(entries) => {
-                    meta_cache.splice_entries(entries);
-                    match meta_cache.extract_cached(&partition_key, extractor) {
-                        ExtractCachedResult::Extractor(_) => unreachable!(),
-                        ExtractCachedResult::Value(value) => cb(Ok(value)),
-                    }
-                },
-                Err(error) => cb(Err(error)),
-            }
+            let result = meta_cache.tablet_locations_to_entries(&partition_key, tablets)
+                                   .map(|entries| meta_cache.splice_entries(entries));
+            meta_cache.complete_lookup(&partition_key, result);
         });
     }
 
@@ -279,13 +424,25 @@ impl MetaCache {
     where Extractor: FnOnce(&Entry) -> T {
         let entries = self.inner.entries.lock();
         match entries.binary_search_by(|entry| entry.cmp_partition_key(partition_key)) {
-            Ok(index) => ExtractCachedResult::Value(extractor(&entries[index])),
-            Err(_) => ExtractCachedResult::Extractor(extractor),
+            Ok(index) if !self.is_expired(&entries[index]) => {
+                ExtractCachedResult::Value(extractor(&entries[index]))
+            },
+            _ => ExtractCachedResult::Extractor(extractor),
         }
     }
 
     fn splice_entries(&self, mut new_entries: VecDeque<Entry>) {
         let mut entries = self.inner.entries.lock();
+        {
+            let mut expirations = self.inner.expirations.lock();
+            for entry in &new_entries {
+                let lower_bound = entry.partition_lower_bound();
+                match self.inner.ttl {
+                    Some(ttl) => { expirations.insert(lower_bound.to_owned(), Instant::now() + ttl); },
+                    None => { expirations.remove(lower_bound); },
+                }
+            }
+        }
         let splice_point = match entries.binary_search_by(|entry| entry.cmp_entry(&new_entries[0])) {
             Ok(idx) | Err(idx) => idx,
         };
@@ -359,7 +516,8 @@ impl MetaCache {
         for tablet in tablets {
             let tablet = try!(Tablet::from_pb(&self.inner.primary_key_schema,
                                               self.inner.partition_schema.clone(),
-                                              tablet));
+                                              tablet,
+                                              self.master.resolver()));
             if tablet.partition().lower_bound_key() > &last_upper_bound {
                 entries.push_back(Entry::non_covered_range(last_upper_bound,
                                                            tablet.partition().lower_bound_key().to_owned()));
@@ -376,8 +534,89 @@ impl MetaCache {
         Ok(entries)
     }
 
+    /// Marks the entry covering the partition key as stale. The entry is refreshed from the
+    /// master by the next lookup of a partition key which it covers. Returns `false` if the
+    /// partition key is not cached.
+    pub fn mark_stale(&self, partition_key: &[u8]) -> bool {
+        let entries = self.inner.entries.lock();
+        match entries.binary_search_by(|entry| entry.cmp_partition_key(partition_key)) {
+            Ok(index) => {
+                self.inner.expirations.lock().insert(entries[index].partition_lower_bound().to_owned(),
+                                                     Instant::now());
+                true
+            },
+            Err(_) => false,
+        }
+    }
+
+    /// Returns `true` if the cached entry has expired. The entries lock must be held.
+    fn is_expired(&self, entry: &Entry) -> bool {
+        self.inner
+            .expirations
+            .lock()
+            .get(entry.partition_lower_bound())
+            .map_or(false, |&expiration| expiration <= Instant::now())
+    }
+
     pub fn clear(&self) {
-        self.inner.entries.lock().clear()
+        let mut entries = self.inner.entries.lock();
+        entries.clear();
+        self.inner.expirations.lock().clear();
+    }
+
+    /// Records a failed request to the tablet server, blacklisting it for a duration which grows
+    /// with the number of consecutive failures.
+    pub fn blacklist_tablet_server(&self, id: &TabletServerId) {
+        let now = Instant::now();
+        let mut blacklist = self.inner.blacklist.lock();
+        let blacklisted = blacklist.entry(*id).or_insert(Blacklisted { failures: 0, until: now });
+        if blacklisted.until + Duration::from_millis(MAX_BLACKLIST_DURATION_MS) < now {
+            // The failures have decayed.
+            blacklisted.failures = 0;
+        }
+        blacklisted.failures += 1;
+        let shift = cmp::min(blacklisted.failures - 1, 16);
+        let duration = Duration::from_millis(cmp::min(BLACKLIST_DURATION_MS << shift,
+                                                      MAX_BLACKLIST_DURATION_MS));
+        blacklisted.until = now + duration;
+        debug!("blacklisting tablet server {:?} for {:?} after {} consecutive failures",
+               id, duration, blacklisted.failures);
+    }
+
+    /// Records a successful request to the tablet server, clearing its past failures.
+    pub fn tablet_server_succeeded(&self, id: &TabletServerId) {
+        self.inner.blacklist.lock().remove(id);
+    }
+
+    /// Returns `true` if the tablet server is currently blacklisted.
+    pub fn is_blacklisted(&self, id: &TabletServerId) -> bool {
+        self.inner
+            .blacklist
+            .lock()
+            .get(id)
+            .map_or(false, |blacklisted| blacklisted.until > Instant::now())
+    }
+
+    /// Makes the replica belonging to the tablet server the leader of the cached tablet, as if
+    /// the master had reported a leader change. Returns `false` if the tablet is not cached.
+    #[cfg(test)]
+    pub fn set_leader(&self, tablet_id: TabletId, leader: Option<&TabletServerId>) -> bool {
+        let mut entries = self.inner.entries.lock();
+        for entry in entries.iter_mut() {
+            if let Entry::Tablet(ref mut tablet) = *entry {
+                if tablet.id() == tablet_id {
+                    tablet.set_leader(leader);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns the number of `GetTableLocations` RPCs sent by the cache, including retries.
+    #[cfg(test)]
+    pub fn lookup_rpcs(&self) -> usize {
+        self.inner.lookup_rpcs.load(AtomicOrdering::Relaxed)
     }
 }
 
@@ -401,6 +640,7 @@ mod tests {
     use TableBuilder;
     use mini_cluster::MiniCluster;
     use schema::tests::simple_schema;
+    use super::*;
 
     fn deadline() -> Instant {
         Instant::now() + Duration::from_secs(5)
@@ -565,9 +805,112 @@ mod tests {
         recv.recv().unwrap().unwrap();
 
         let entries = cache.inner.entries.lock().clone();
-        // Technically this could be 10 if the first request comes back before the second is
-        // initiated, but in practice this doesn't really happen.
+        // The second lookup is coalesced with the first, and issues its own lookup once the first
+        // returns without covering its partition key.
         assert!(entries.len() == 12);
+        assert!(cache.inner.lookups.lock().is_empty());
+    }
+
+    #[test]
+    fn stale_entries() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::default();
+
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = simple_schema();
+
+        let mut table_builder = TableBuilder::new("stale_entries", schema.clone());
+        table_builder.add_hash_partitions(vec!["key"], 4);
+        table_builder.set_num_replicas(1);
+
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+        let cache = table.meta_cache().clone();
+
+        let (send, recv) = sync_channel(1);
+
+        let s = send.clone();
+        cache.tablet_leader(vec![0, 0, 0, 1], deadline(), move |leader| s.send(leader).unwrap());
+        let (tablet_id, addrs) = recv.recv().unwrap().unwrap().unwrap();
+        assert_eq!(1, addrs.len());
+        assert_eq!(1, cache.lookup_rpcs());
+
+        let leader = match cache.cached_entry(&[0, 0, 0, 1]).unwrap() {
+            Entry::Tablet(tablet) => *tablet.leader().unwrap().id(),
+            Entry::NonCoveredRange { .. } => panic!("expected tablet"),
+        };
+
+        // Cached lookups do not send an RPC.
+        let s = send.clone();
+        cache.tablet_leader(vec![0, 0, 0, 1], deadline(), move |leader| s.send(leader).unwrap());
+        recv.recv().unwrap().unwrap().unwrap();
+        assert_eq!(1, cache.lookup_rpcs());
+
+        // Simulate losing the leader.
+        assert!(cache.set_leader(tablet_id, None));
+        let s = send.clone();
+        cache.tablet_leader(vec![0, 0, 0, 1], deadline(), move |leader| s.send(leader).unwrap());
+        assert_eq!((tablet_id, vec![]), recv.recv().unwrap().unwrap().unwrap());
+        assert_eq!(1, cache.lookup_rpcs());
+
+        // Marking the entry stale causes the next lookup to refresh the leader from the master.
+        assert!(cache.mark_stale(&[0, 0, 0, 1, 5]));
+        assert!(cache.cached_entry(&[0, 0, 0, 1]).is_none());
+        assert!(cache.cached_entry(&[0, 0, 0, 2]).is_some());
+        let s = send.clone();
+        cache.tablet_leader(vec![0, 0, 0, 1], deadline(), move |leader| s.send(leader).unwrap());
+        assert_eq!((tablet_id, addrs), recv.recv().unwrap().unwrap().unwrap());
+        assert_eq!(2, cache.lookup_rpcs());
+        assert_eq!(4, cache.inner.entries.lock().len());
+
+        assert!(cache.set_leader(tablet_id, Some(&leader)));
+        assert!(!cache.mark_stale(&[0, 0, 0, 5]));
+    }
+
+    #[test]
+    fn coalesced_lookups() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::default();
+
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = simple_schema();
+
+        let mut table_builder = TableBuilder::new("coalesced_lookups", schema.clone());
+        table_builder.add_hash_partitions(vec!["key"], 4);
+        table_builder.set_num_replicas(1);
+
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+        let cache = table.meta_cache().clone();
+
+        let (send, recv) = sync_channel(16);
+
+        for i in 0..16 {
+            let s = send.clone();
+            cache.entry(vec![0, 0, 0, i % 4], deadline(), move |entry| {
+                s.send(entry).unwrap();
+            });
+        }
+
+        {
+            // Every miss joins the lookup started by the first.
+            let lookups = cache.inner.lookups.lock();
+            assert!(lookups.len() <= 1);
+        }
+
+        for _ in 0..16 {
+            assert!(recv.recv().unwrap().unwrap().is_tablet());
+        }
+
+        assert_eq!(4, cache.inner.entries.lock().len());
+        assert!(cache.inner.lookups.lock().is_empty());
+        assert_eq!(1, cache.lookup_rpcs());
     }
 
     #[test]
@@ -73,6 +73,7 @@ impl Drop for Node {
 pub struct MiniCluster {
     dir: TempDir,
     master_addrs: Vec<SocketAddr>,
+    tserver_addrs: Vec<SocketAddr>,
     nodes: HashMap<SocketAddr, Node>,
 }
 
@@ -149,7 +150,9 @@ impl MiniCluster {
                 match client.list_tablet_servers(deadline) {
                     Ok(ref tservers) if tservers.len() == conf.num_tservers as usize => break,
                     Ok(_) => (),
-                    Err(Error::TimedOut) => panic!("timed out waiting for tablet servers to start"),
+                    Err(ref error) if error.is_timeout() => {
+                        panic!("timed out waiting for tablet servers to start: {}", error)
+                    },
                     Err(error) => warn!("error while waiting for tservers: {:?}", error),
                 }
                 let backoff_ms = backoff.next_backoff_ms();
@@ -162,6 +165,7 @@ impl MiniCluster {
         MiniCluster {
             dir: dir,
             master_addrs: master_addrs.to_owned(),
+            tserver_addrs: tserver_addrs.to_owned(),
             nodes: nodes,
         }
     }
@@ -170,6 +174,10 @@ impl MiniCluster {
         &self.master_addrs
     }
 
+    pub fn tserver_addrs(&self) -> &[SocketAddr] {
+        &self.tserver_addrs
+    }
+
     pub fn stop_node(&mut self, addr: SocketAddr) {
         self.nodes.get_mut(&addr).expect(&format!("no node with address {}", addr)).stop();
     }
@@ -274,6 +282,10 @@ pub struct MiniClusterConfig {
     /// Whether to enable fsync() of data blocks, metadata, and their parent directories. Disabling
     /// this flag may cause data loss in the event of a system crash. Default: false.
     data_block_fsync: bool,
+
+    /// Number of milliseconds of inactivity after which tablet servers expire scanners. Expired
+    /// scanners are removed at the same interval.
+    scanner_ttl: Option<i32>,
 }
 
 impl MiniClusterConfig {
@@ -340,6 +352,11 @@ impl MiniClusterConfig {
         self
     }
 
+    pub fn scanner_ttl(&mut self, millis: i32) -> &mut MiniClusterConfig {
+        self.scanner_ttl = Some(millis);
+        self
+    }
+
     fn args(&self) -> Vec<(&'static str, String)> {
         let mut args = Vec::new();
 
@@ -384,6 +401,11 @@ impl MiniClusterConfig {
         args.push(("enable_data_block_fsync",
                    self.data_block_fsync.to_string()));
 
+        if let Some(scanner_ttl) = self.scanner_ttl {
+            args.push(("scanner_ttl_ms", scanner_ttl.to_string()));
+            args.push(("scanner_gc_check_interval_us", (scanner_ttl as i64 * 1000).to_string()));
+        }
+
         args
     }
 }
@@ -403,6 +425,7 @@ impl Default for MiniClusterConfig {
             get_table_locations_delay: None,
             leader_failure_detection: None,
             data_block_fsync: false,
+            scanner_ttl: None,
         }
     }
 }
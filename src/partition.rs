@@ -60,6 +60,12 @@ impl HashPartitionSchema {
     pub fn seed(&self) -> u32 {
         self.seed
     }
+
+    /// Returns the bucket of the row in this hash partitioning level, using the same Murmur2
+    /// based hash as Kudu. The hashed columns of the row must be set.
+    pub fn hash_bucket(&self, row: &Row) -> Result<u32> {
+        key::hash_bucket(self, row, &mut Vec::new())
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -83,6 +89,30 @@ impl PartitionSchema {
         &self.inner.hash_partitions
     }
 
+    /// Returns an object which displays the partition schema with the column names of the table's
+    /// schema, for instance `HASH (id) PARTITIONS 16, RANGE (ts)`.
+    pub fn display<'a>(&'a self, schema: &'a Schema) -> PartitionSchemaDisplay<'a> {
+        PartitionSchemaDisplay {
+            partition_schema: self,
+            schema: schema,
+        }
+    }
+
+    /// Returns the hash bucket of the row in each hash partitioning level, in order. The row must
+    /// belong to the table with this partition schema, and the hashed columns must be set.
+    ///
+    /// Applications can use the buckets to pre-partition data for bulk loads, or to verify the
+    /// placement of rows.
+    pub fn hash_buckets(&self, row: &Row) -> Result<Vec<u32>> {
+        let mut buf = Vec::new();
+        let mut buckets = Vec::with_capacity(self.inner.hash_partitions.len());
+        for hash_schema in &self.inner.hash_partitions {
+            buf.clear();
+            buckets.push(try!(key::hash_bucket(hash_schema, row, &mut buf)));
+        }
+        Ok(buckets)
+    }
+
     #[doc(hidden)]
     pub fn from_pb(pb: &PartitionSchemaPB, schema: &SchemaPB) -> PartitionSchema {
         let mut columns_by_name = HashMap::new();
@@ -125,6 +155,34 @@ impl PartitionSchema {
     }
 }
 
+/// Displays a partition schema along with its column names. See `PartitionSchema::display`.
+pub struct PartitionSchemaDisplay<'a> {
+    partition_schema: &'a PartitionSchema,
+    schema: &'a Schema,
+}
+
+impl <'a> fmt::Display for PartitionSchemaDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hash_schemas = self.partition_schema.hash_partition_schemas();
+        let range_columns = self.partition_schema.range_partition_schema().columns();
+        if hash_schemas.is_empty() && range_columns.is_empty() {
+            return write!(f, "UNPARTITIONED");
+        }
+
+        for (level, hash_schema) in hash_schemas.iter().enumerate() {
+            if level > 0 { try!(write!(f, ", ")); }
+            try!(fmt_hash_schema(f, self.schema, hash_schema));
+            try!(write!(f, " PARTITIONS {}", hash_schema.num_buckets()));
+        }
+        if !range_columns.is_empty() {
+            if !hash_schemas.is_empty() { try!(write!(f, ", ")); }
+            try!(write!(f, "RANGE "));
+            try!(fmt_columns(f, self.schema, range_columns));
+        }
+        Ok(())
+    }
+}
+
 /*
 impl fmt::Debug for PartitionKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -181,6 +239,35 @@ impl fmt::Debug for Partition {
     }
 }
 
+/// Displays the hash bucket of each hash partitioning level and the range bounds of the partition,
+/// with the bounds decoded using the table's schema. For instance:
+///
+/// `HASH (id) PARTITION 3, RANGE (ts) PARTITION 2024-01-01T00:00:00.000000Z <= VALUES < 2024-02-01T00:00:00.000000Z`
+impl fmt::Display for Partition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let schema = self.range_lower_bound.schema();
+        let hash_schemas = self.partition_schema.hash_partition_schemas();
+        let range_columns = self.partition_schema.range_partition_schema().columns();
+        if hash_schemas.is_empty() && range_columns.is_empty() {
+            return write!(f, "UNPARTITIONED");
+        }
+
+        for (level, (hash_schema, bucket)) in hash_schemas.iter().zip(&self.hash_partitions).enumerate() {
+            if level > 0 { try!(write!(f, ", ")); }
+            try!(fmt_hash_schema(f, schema, hash_schema));
+            try!(write!(f, " PARTITION {}", bucket));
+        }
+        if !range_columns.is_empty() {
+            if !hash_schemas.is_empty() { try!(write!(f, ", ")); }
+            try!(write!(f, "RANGE "));
+            try!(fmt_columns(f, schema, range_columns));
+            try!(write!(f, " PARTITION "));
+            try!(self.fmt_range_partition(f));
+        }
+        Ok(())
+    }
+}
+
 impl cmp::PartialEq for Partition {
     fn eq(&self, other: &Partition) -> bool {
         &self.lower_bound_key == &other.lower_bound_key &&
@@ -298,6 +385,28 @@ impl Partition {
     }
 }
 
+/// Formats the hash partitioning level as `HASH (a, b)`, followed by its seed if it is not the
+/// default.
+fn fmt_hash_schema(f: &mut fmt::Formatter, schema: &Schema, hash_schema: &HashPartitionSchema) -> fmt::Result {
+    try!(write!(f, "HASH "));
+    try!(fmt_columns(f, schema, hash_schema.columns()));
+    if hash_schema.seed() != 0 {
+        try!(write!(f, " SEED {}", hash_schema.seed()));
+    }
+    Ok(())
+}
+
+fn fmt_columns(f: &mut fmt::Formatter, schema: &Schema, idxs: &[usize]) -> fmt::Result {
+    try!(write!(f, "("));
+    let mut is_first = true;
+    for &idx in idxs {
+        if is_first { is_first = false; }
+        else { try!(write!(f, ", ")) }
+        try!(write!(f, "{}", schema.columns()[idx].name()));
+    }
+    write!(f, ")")
+}
+
 fn fmt_row(f: &mut fmt::Formatter, row: &Row, idxs: &[usize]) -> fmt::Result {
     debug_assert!(!idxs.is_empty());
 
@@ -314,3 +423,104 @@ fn fmt_row(f: &mut fmt::Formatter, row: &Row, idxs: &[usize]) -> fmt::Result {
     }
     write!(f, ")")
 }
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{BigEndian, ByteOrder};
+
+    use Column;
+    use DataType;
+    use SchemaBuilder;
+    use key;
+    use super::*;
+
+    #[test]
+    fn hash_buckets() {
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("a", DataType::Int32).set_not_null())
+            .add_column(Column::builder("b", DataType::String).set_not_null())
+            .set_primary_key(vec!["a", "b"])
+            .build()
+            .unwrap();
+        let partition_schema = PartitionSchema {
+            inner: Arc::new(Inner {
+                range_partition: RangePartitionSchema::new(vec![0, 1]),
+                hash_partitions: vec![HashPartitionSchema::new(vec![0], 4, 0),
+                                      HashPartitionSchema::new(vec![0, 1], 16, 42)],
+            }),
+        };
+
+        for i in 0..100 {
+            let mut row = schema.new_row();
+            row.set(0, i).unwrap();
+            row.set(1, "foo").unwrap();
+
+            let buckets = partition_schema.hash_buckets(&row).unwrap();
+            assert_eq!(2, buckets.len());
+            assert!(buckets[0] < 4);
+            assert!(buckets[1] < 16);
+
+            // The buckets match those encoded in the partition key.
+            let partition_key = key::encode_partition_key(&partition_schema, &row).unwrap();
+            assert_eq!(buckets[0], BigEndian::read_u32(&partition_key[0..]));
+            assert_eq!(buckets[1], BigEndian::read_u32(&partition_key[4..]));
+            assert_eq!(buckets[1], partition_schema.hash_partition_schemas()[1].hash_bucket(&row).unwrap());
+        }
+
+        // The hashed columns must be set.
+        let row = schema.new_row();
+        assert!(partition_schema.hash_buckets(&row).is_err());
+    }
+
+    #[test]
+    fn display() {
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("id", DataType::Int32).set_not_null())
+            .add_column(Column::builder("ts", DataType::Int64).set_not_null())
+            .add_column(Column::builder("val", DataType::String))
+            .set_primary_key(vec!["id", "ts"])
+            .build()
+            .unwrap();
+        let partition_schema = PartitionSchema {
+            inner: Arc::new(Inner {
+                range_partition: RangePartitionSchema::new(vec![1]),
+                hash_partitions: vec![HashPartitionSchema::new(vec![0], 16, 0),
+                                      HashPartitionSchema::new(vec![0, 1], 4, 42)],
+            }),
+        };
+        assert_eq!("HASH (id) PARTITIONS 16, HASH (id, ts) SEED 42 PARTITIONS 4, RANGE (ts)",
+                   partition_schema.display(&schema).to_string());
+
+        let unpartitioned = PartitionSchema {
+            inner: Arc::new(Inner {
+                range_partition: RangePartitionSchema::new(Vec::new()),
+                hash_partitions: Vec::new(),
+            }),
+        };
+        assert_eq!("UNPARTITIONED", unpartitioned.display(&schema).to_string());
+
+        let primary_key_schema = schema.primary_key_projection();
+        let partition = |lower: Option<i64>, upper: Option<i64>| {
+            let mut range_lower_bound = primary_key_schema.new_row();
+            let mut range_upper_bound = primary_key_schema.new_row();
+            if let Some(lower) = lower { range_lower_bound.set(1, lower).unwrap(); }
+            if let Some(upper) = upper { range_upper_bound.set(1, upper).unwrap(); }
+            Partition {
+                partition_schema: partition_schema.clone(),
+                lower_bound_key: Vec::new(),
+                upper_bound_key: Vec::new(),
+                hash_partitions: vec![3, 1],
+                range_lower_bound: range_lower_bound,
+                range_upper_bound: range_upper_bound,
+            }
+        };
+        assert_eq!("HASH (id) PARTITION 3, HASH (id, ts) SEED 42 PARTITION 1, RANGE (ts) PARTITION 10 <= VALUES < 20",
+                   partition(Some(10), Some(20)).to_string());
+        assert_eq!("HASH (id) PARTITION 3, HASH (id, ts) SEED 42 PARTITION 1, RANGE (ts) PARTITION VALUES = 10",
+                   partition(Some(10), Some(11)).to_string());
+        assert_eq!("HASH (id) PARTITION 3, HASH (id, ts) SEED 42 PARTITION 1, RANGE (ts) PARTITION VALUES >= 10",
+                   partition(Some(10), None).to_string());
+        assert_eq!("HASH (id) PARTITION 3, HASH (id, ts) SEED 42 PARTITION 1, RANGE (ts) PARTITION UNBOUNDED",
+                   partition(None, None).to_string());
+    }
+}
@@ -0,0 +1,698 @@
+use std::cmp::Ordering;
+use std::ops::BitAnd;
+use std::vec;
+
+use byteorder::{ByteOrder, LittleEndian};
+use kudu_pb::common::{
+    ColumnPredicatePB,
+    ColumnPredicatePB_Equality as EqualityPB,
+    ColumnPredicatePB_InList as InListPB,
+    ColumnPredicatePB_IsNotNull as IsNotNullPB,
+    ColumnPredicatePB_Range as RangePB,
+};
+use protobuf::RepeatedField;
+
+use DataType;
+use Error;
+use Result;
+use Row;
+use Schema;
+use TypedColumn;
+use Value;
+
+/// A predicate on the values of a single column.
+///
+/// Predicates are added to a scan with `ScanBuilder::add_predicate`, and are evaluated by the
+/// tablet servers so that only matching rows are returned. Null cells never match a predicate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnPredicate {
+    column: String,
+    /// The type of the predicate values, or `None` if the predicate has no values.
+    value_type: Option<DataType>,
+    kind: PredicateKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum PredicateKind {
+    /// Matches values in the range `[lower, upper)`. At least one bound is set.
+    Range {
+        lower: Option<Vec<u8>>,
+        upper: Option<Vec<u8>>,
+    },
+    Equality(Vec<u8>),
+    /// Matches any of two or more sorted, unique values.
+    InList(Vec<Vec<u8>>),
+    IsNotNull,
+    /// Matches no rows.
+    None,
+}
+
+impl ColumnPredicate {
+
+    /// Creates a predicate matching cells equal to `value`.
+    pub fn equal<'a, S, V>(column: S, value: V) -> ColumnPredicate
+    where S: Into<String>, V: Value<'a> {
+        let value_type = V::data_type();
+        let kind = match encode(value) {
+            Some(value) => PredicateKind::Equality(value),
+            None => PredicateKind::None,
+        };
+        ColumnPredicate::new(column.into(), Some(value_type), kind)
+    }
+
+    /// Creates a predicate matching cells in the range `[lower, upper)`. A `None` bound leaves
+    /// the range unbounded on that side.
+    pub fn range<'a, S, V>(column: S, lower: Option<V>, upper: Option<V>) -> ColumnPredicate
+    where S: Into<String>, V: Value<'a> {
+        let value_type = V::data_type();
+        let lower = lower.and_then(encode);
+        let upper = upper.and_then(encode);
+        let kind = match (lower, upper) {
+            (None, None) => PredicateKind::IsNotNull,
+            (Some(lower), Some(upper)) => {
+                if cmp_encoded(value_type, &lower, &upper) != Ordering::Less {
+                    PredicateKind::None
+                } else {
+                    PredicateKind::Range { lower: Some(lower), upper: Some(upper) }
+                }
+            },
+            (lower, upper) => PredicateKind::Range { lower: lower, upper: upper },
+        };
+        ColumnPredicate::new(column.into(), Some(value_type), kind)
+    }
+
+    /// Creates a predicate matching cells greater than or equal to `value`.
+    pub fn greater_or_equal<'a, S, V>(column: S, value: V) -> ColumnPredicate
+    where S: Into<String>, V: Value<'a> {
+        ColumnPredicate::range(column, Some(value), None)
+    }
+
+    /// Creates a predicate matching cells less than `value`.
+    pub fn less<'a, S, V>(column: S, value: V) -> ColumnPredicate
+    where S: Into<String>, V: Value<'a> {
+        ColumnPredicate::range(column, None, Some(value))
+    }
+
+    /// Creates a predicate matching string or binary cells which begin with `prefix`.
+    ///
+    /// This is the equivalent of `column LIKE 'prefix%'`, rewritten as the range
+    /// `[prefix, prefix + 1)` so that it can be evaluated by the tablet servers.
+    pub fn prefix<S>(column: S, prefix: &str) -> ColumnPredicate where S: Into<String> {
+        let lower = prefix.as_bytes().to_owned();
+        let upper = prefix_successor(prefix.as_bytes());
+        ColumnPredicate::new(column.into(), Some(DataType::String), PredicateKind::Range {
+            lower: Some(lower),
+            upper: upper,
+        })
+    }
+
+    /// Creates a predicate matching binary cells which begin with `prefix`.
+    ///
+    /// See `ColumnPredicate::prefix`.
+    pub fn binary_prefix<S>(column: S, prefix: &[u8]) -> ColumnPredicate where S: Into<String> {
+        let upper = prefix_successor(prefix);
+        ColumnPredicate::new(column.into(), Some(DataType::Binary), PredicateKind::Range {
+            lower: Some(prefix.to_owned()),
+            upper: upper,
+        })
+    }
+
+    /// Creates a predicate matching cells equal to any of `values`.
+    ///
+    /// The values are sorted and deduplicated, as required by the tablet servers. A list with a
+    /// single value becomes an equality predicate, and an empty list matches no rows.
+    pub fn in_list<'a, S, V>(column: S, values: Vec<V>) -> ColumnPredicate
+    where S: Into<String>, V: Value<'a> {
+        let value_type = V::data_type();
+        let mut values = values.into_iter().filter_map(encode).collect::<Vec<_>>();
+        values.sort_by(|a, b| cmp_total_order(value_type, a, b));
+        let mut unique: Vec<Vec<u8>> = Vec::with_capacity(values.len());
+        for value in values {
+            if unique.last().map_or(true, |last| cmp_total_order(value_type, last, &value) != Ordering::Equal) {
+                unique.push(value);
+            }
+        }
+        let mut values = unique;
+
+        let kind = match values.len() {
+            0 => PredicateKind::None,
+            1 => PredicateKind::Equality(values.pop().unwrap()),
+            _ => PredicateKind::InList(values),
+        };
+        ColumnPredicate::new(column.into(), Some(value_type), kind)
+    }
+
+    /// Creates a predicate matching non-null cells.
+    pub fn is_not_null<S>(column: S) -> ColumnPredicate where S: Into<String> {
+        ColumnPredicate::new(column.into(), None, PredicateKind::IsNotNull)
+    }
+
+    fn new(column: String, value_type: Option<DataType>, kind: PredicateKind) -> ColumnPredicate {
+        ColumnPredicate {
+            column: column,
+            value_type: value_type,
+            kind: kind,
+        }
+    }
+
+    /// Returns the name of the column the predicate applies to.
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// Returns `true` if the predicate can not match any rows.
+    pub fn is_none(&self) -> bool {
+        self.kind == PredicateKind::None
+    }
+
+    /// Checks that the predicate applies to a column in the schema, and that the predicate values
+    /// can be compared to the column's values.
+    #[doc(hidden)]
+    pub fn check(&self, schema: &Schema) -> Result<()> {
+        let column = match schema.column_by_name(&self.column) {
+            Some(column) => column,
+            None => return Err(Error::InvalidArgument(
+                    format!("predicate on unknown column '{}'", self.column))),
+        };
+        match self.value_type {
+            Some(value_type) if !is_compatible(value_type, column.data_type()) => {
+                Err(Error::InvalidArgument(format!("predicate value type {:?} is invalid for column {:?}",
+                                                   value_type, column)))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Evaluates the predicate against a row on the client, returning `true` if the row matches.
+    ///
+    /// This applies the same semantics as the tablet servers, so predicates can be evaluated
+    /// against rows which have already been retrieved, for instance as residual filters. Fails if
+    /// the predicate's column is not in the row's schema or is not set.
+    ///
+    /// As on the tablet servers, a NaN cell is unordered, so it matches no range, equality, or IN
+    /// list predicate, and only matches `is_not_null`.
+    pub fn evaluate(&self, row: &Row) -> Result<bool> {
+        try!(self.check(row.schema()));
+        let idx = row.schema().column_index(&self.column).unwrap();
+        let data_type = row.schema().columns()[idx].data_type();
+        let cell = match try!(row.cell_data(idx)) {
+            Some(cell) => cell,
+            None => return Ok(false),
+        };
+        if is_nan(data_type, cell) {
+            return Ok(self.kind == PredicateKind::IsNotNull);
+        }
+        Ok(match self.kind {
+            PredicateKind::Range { ref lower, ref upper } => {
+                lower.as_ref().map_or(true, |lower| cmp_encoded(data_type, cell, lower) != Ordering::Less) &&
+                upper.as_ref().map_or(true, |upper| cmp_encoded(data_type, cell, upper) == Ordering::Less)
+            },
+            PredicateKind::Equality(ref value) => cmp_encoded(data_type, cell, value) == Ordering::Equal,
+            PredicateKind::InList(ref values) => {
+                values.binary_search_by(|value| cmp_encoded(data_type, value, cell)).is_ok()
+            },
+            PredicateKind::IsNotNull => true,
+            PredicateKind::None => false,
+        })
+    }
+
+    /// Returns the type of the predicate values, or `None` if the predicate has no values.
+    #[doc(hidden)]
+    pub fn value_type(&self) -> Option<DataType> {
+        self.value_type
+    }
+
+    #[doc(hidden)]
+    pub fn to_pb(&self) -> ColumnPredicatePB {
+        let mut pb = ColumnPredicatePB::new();
+        pb.set_column(self.column.clone());
+        match self.kind {
+            PredicateKind::Range { ref lower, ref upper } => {
+                let mut range = RangePB::new();
+                if let Some(ref lower) = *lower { range.set_lower(lower.clone()); }
+                if let Some(ref upper) = *upper { range.set_upper(upper.clone()); }
+                pb.set_range(range);
+            },
+            PredicateKind::Equality(ref value) => {
+                let mut equality = EqualityPB::new();
+                equality.set_value(value.clone());
+                pb.set_equality(equality);
+            },
+            PredicateKind::InList(ref values) => {
+                let mut in_list = InListPB::new();
+                in_list.set_values(RepeatedField::from_vec(values.clone()));
+                pb.set_in_list(in_list);
+            },
+            PredicateKind::IsNotNull => pb.set_is_not_null(IsNotNullPB::new()),
+            PredicateKind::None => panic!("predicate {:?} can not be serialized", self),
+        }
+        pb
+    }
+}
+
+impl <V> TypedColumn<V> {
+
+    /// Creates a predicate matching cells equal to `value`.
+    pub fn equal<'a>(&self, value: V) -> ColumnPredicate where V: Value<'a> {
+        ColumnPredicate::equal(self.name(), value)
+    }
+
+    /// Creates a predicate matching cells in the range `[lower, upper)`.
+    pub fn range<'a>(&self, lower: Option<V>, upper: Option<V>) -> ColumnPredicate
+    where V: Value<'a> {
+        ColumnPredicate::range(self.name(), lower, upper)
+    }
+
+    /// Creates a predicate matching cells equal to any of `values`.
+    pub fn in_list<'a>(&self, values: Vec<V>) -> ColumnPredicate where V: Value<'a> {
+        ColumnPredicate::in_list(self.name(), values)
+    }
+
+    /// Creates a predicate matching non-null cells.
+    pub fn is_not_null(&self) -> ColumnPredicate {
+        ColumnPredicate::is_not_null(self.name())
+    }
+}
+
+/// Returns a column reference for building predicates, for instance
+/// `col("age").ge(18i32) & col("city").is_in(vec!["Paris", "Tokyo"])`.
+pub fn col<S>(name: S) -> Col where S: Into<String> {
+    Col { name: name.into() }
+}
+
+/// A reference to a column by name, which creates predicates on the column. Created with `col`.
+///
+/// Ranges are half-open: there are `ge` and `lt` predicates, but no `gt` or `le`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Col {
+    name: String,
+}
+
+impl Col {
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Creates a predicate matching cells equal to `value`.
+    pub fn equal<'a, V>(self, value: V) -> ColumnPredicate where V: Value<'a> {
+        ColumnPredicate::equal(self.name, value)
+    }
+
+    /// Creates a predicate matching cells greater than or equal to `value`.
+    pub fn ge<'a, V>(self, value: V) -> ColumnPredicate where V: Value<'a> {
+        ColumnPredicate::greater_or_equal(self.name, value)
+    }
+
+    /// Creates a predicate matching cells less than `value`.
+    pub fn lt<'a, V>(self, value: V) -> ColumnPredicate where V: Value<'a> {
+        ColumnPredicate::less(self.name, value)
+    }
+
+    /// Creates a predicate matching cells in the range `[lower, upper)`.
+    pub fn between<'a, V>(self, lower: V, upper: V) -> ColumnPredicate where V: Value<'a> {
+        ColumnPredicate::range(self.name, Some(lower), Some(upper))
+    }
+
+    /// Creates a predicate matching cells equal to any of `values`.
+    pub fn is_in<'a, V>(self, values: Vec<V>) -> ColumnPredicate where V: Value<'a> {
+        ColumnPredicate::in_list(self.name, values)
+    }
+
+    /// Creates a predicate matching string cells which begin with `prefix`.
+    pub fn starts_with(self, prefix: &str) -> ColumnPredicate {
+        ColumnPredicate::prefix(self.name, prefix)
+    }
+
+    /// Creates a predicate matching non-null cells.
+    pub fn is_not_null(self) -> ColumnPredicate {
+        ColumnPredicate::is_not_null(self.name)
+    }
+}
+
+/// A conjunction of predicates, created by combining predicates with `&`. Added to a scan with
+/// `ScanBuilder::add_predicates`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Predicates {
+    predicates: Vec<ColumnPredicate>,
+}
+
+impl Predicates {
+
+    pub fn new() -> Predicates {
+        Predicates::default()
+    }
+
+    pub fn predicates(&self) -> &[ColumnPredicate] {
+        &self.predicates
+    }
+
+    /// Checks each predicate against the schema, failing if a predicate is on a column which is
+    /// not in the schema, or has values which can not be compared to the column's values.
+    pub fn check(&self, schema: &Schema) -> Result<()> {
+        for predicate in &self.predicates {
+            try!(predicate.check(schema));
+        }
+        Ok(())
+    }
+
+    /// Evaluates the predicates against a row on the client, returning `true` if the row matches
+    /// all of them. See `ColumnPredicate::evaluate`.
+    pub fn evaluate(&self, row: &Row) -> Result<bool> {
+        for predicate in &self.predicates {
+            if !try!(predicate.evaluate(row)) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl From<ColumnPredicate> for Predicates {
+    fn from(predicate: ColumnPredicate) -> Predicates {
+        Predicates { predicates: vec![predicate] }
+    }
+}
+
+impl IntoIterator for Predicates {
+    type Item = ColumnPredicate;
+    type IntoIter = vec::IntoIter<ColumnPredicate>;
+    fn into_iter(self) -> vec::IntoIter<ColumnPredicate> {
+        self.predicates.into_iter()
+    }
+}
+
+impl BitAnd<ColumnPredicate> for ColumnPredicate {
+    type Output = Predicates;
+    fn bitand(self, rhs: ColumnPredicate) -> Predicates {
+        Predicates { predicates: vec![self, rhs] }
+    }
+}
+
+impl BitAnd<Predicates> for ColumnPredicate {
+    type Output = Predicates;
+    fn bitand(self, rhs: Predicates) -> Predicates {
+        Predicates::from(self) & rhs
+    }
+}
+
+impl BitAnd<ColumnPredicate> for Predicates {
+    type Output = Predicates;
+    fn bitand(mut self, rhs: ColumnPredicate) -> Predicates {
+        self.predicates.push(rhs);
+        self
+    }
+}
+
+impl BitAnd<Predicates> for Predicates {
+    type Output = Predicates;
+    fn bitand(mut self, rhs: Predicates) -> Predicates {
+        self.predicates.extend(rhs.predicates);
+        self
+    }
+}
+
+/// Encodes a predicate value, or returns `None` if the value is null.
+fn encode<'a, V>(value: V) -> Option<Vec<u8>> where V: Value<'a> {
+    if value.is_null() {
+        None
+    } else if V::is_var_len() {
+        Some(value.indirect_data())
+    } else {
+        let mut data = vec![0; V::size()];
+        value.copy_data(&mut data);
+        Some(data)
+    }
+}
+
+/// Returns the smallest byte string which is greater than every byte string beginning with
+/// `prefix`, or `None` if there is no such string (the prefix is empty or all `0xff` bytes).
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut successor = prefix.to_owned();
+    while let Some(last) = successor.pop() {
+        if last < 0xff {
+            successor.push(last + 1);
+            return Some(successor);
+        }
+    }
+    None
+}
+
+/// Returns `true` if predicate values of type `value_type` can be compared to cells of type
+/// `column_type`.
+fn is_compatible(value_type: DataType, column_type: DataType) -> bool {
+    match (value_type, column_type) {
+        (DataType::Int64, DataType::Timestamp) |
+        (DataType::Timestamp, DataType::Int64) |
+        (DataType::String, DataType::Binary) => true,
+        (value_type, column_type) => value_type == column_type,
+    }
+}
+
+/// Compares two encoded values of the provided type.
+///
+/// Floating point values are compared as IEEE 754 values, as on the tablet servers, so `-0.0` is
+/// equal to `0.0`. NaNs, which IEEE 754 leaves unordered, fall back to the total order of
+/// `cmp_total_order`.
+#[doc(hidden)]
+pub fn cmp_encoded(data_type: DataType, a: &[u8], b: &[u8]) -> Ordering {
+    match data_type {
+        DataType::Bool => a[0].cmp(&b[0]),
+        DataType::Int8 => (a[0] as i8).cmp(&(b[0] as i8)),
+        DataType::Int16 => LittleEndian::read_i16(a).cmp(&LittleEndian::read_i16(b)),
+        DataType::Int32 => LittleEndian::read_i32(a).cmp(&LittleEndian::read_i32(b)),
+        DataType::Int64 | DataType::Timestamp => LittleEndian::read_i64(a).cmp(&LittleEndian::read_i64(b)),
+        DataType::Float => {
+            LittleEndian::read_f32(a).partial_cmp(&LittleEndian::read_f32(b))
+                                     .unwrap_or_else(|| cmp_total_order(data_type, a, b))
+        },
+        DataType::Double => {
+            LittleEndian::read_f64(a).partial_cmp(&LittleEndian::read_f64(b))
+                                     .unwrap_or_else(|| cmp_total_order(data_type, a, b))
+        },
+        DataType::Binary | DataType::String => a.cmp(b),
+    }
+}
+
+/// Compares two encoded values of the provided type, ordering floating point values by the IEEE
+/// 754 total order. In the total order `-0.0` sorts before `0.0`, NaNs with the sign bit set sort
+/// before every other value, and other NaNs sort after every other value.
+///
+/// Used to sort and deduplicate IN list values, so that NaNs are handled consistently. Values
+/// sorted by the total order are also sorted by `cmp_encoded`.
+fn cmp_total_order(data_type: DataType, a: &[u8], b: &[u8]) -> Ordering {
+    match data_type {
+        DataType::Float => {
+            total_order_f32(LittleEndian::read_u32(a)).cmp(&total_order_f32(LittleEndian::read_u32(b)))
+        },
+        DataType::Double => {
+            total_order_f64(LittleEndian::read_u64(a)).cmp(&total_order_f64(LittleEndian::read_u64(b)))
+        },
+        _ => cmp_encoded(data_type, a, b),
+    }
+}
+
+/// Returns `true` if the encoded value is a floating point NaN.
+fn is_nan(data_type: DataType, value: &[u8]) -> bool {
+    match data_type {
+        DataType::Float => LittleEndian::read_f32(value).is_nan(),
+        DataType::Double => LittleEndian::read_f64(value).is_nan(),
+        _ => false,
+    }
+}
+
+/// Maps the bits of an `f32` to an integer which sorts in the IEEE 754 total order of the float,
+/// by flipping the magnitude bits of negative values.
+fn total_order_f32(bits: u32) -> i32 {
+    let bits = bits as i32;
+    bits ^ (((bits >> 31) as u32) >> 1) as i32
+}
+
+/// Maps the bits of an `f64` to an integer which sorts in the IEEE 754 total order of the float,
+/// by flipping the magnitude bits of negative values.
+fn total_order_f64(bits: u64) -> i64 {
+    let bits = bits as i64;
+    bits ^ (((bits >> 63) as u64) >> 1) as i64
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::cmp::Ordering;
+    use std::{f32, f64};
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use schema;
+    use super::*;
+
+    #[test]
+    fn test_in_list() {
+        let predicate = ColumnPredicate::in_list("i32", vec![3i32, -1, 3, 200, -1]);
+        assert_eq!(ColumnPredicate::in_list("i32", vec![-1i32, 3, 200]), predicate);
+        let pb = predicate.to_pb();
+        assert_eq!(vec![vec![0xff, 0xff, 0xff, 0xff],
+                        vec![3, 0, 0, 0],
+                        vec![200, 0, 0, 0]],
+                   pb.get_in_list().get_values());
+
+        assert_eq!(ColumnPredicate::equal("i32", 7i32),
+                   ColumnPredicate::in_list("i32", vec![7i32, 7]));
+        assert!(ColumnPredicate::in_list::<_, i32>("i32", vec![]).is_none());
+        assert_eq!(ColumnPredicate::equal("string", "a"),
+                   ColumnPredicate::in_list("string", vec![Some("a"), None]));
+
+        // Floats are sorted by their total order, so NaNs are deduplicated and sort last.
+        let predicate = ColumnPredicate::in_list("f64", vec![f64::NAN, 1.5, f64::NAN, -0.0, f64::NEG_INFINITY]);
+        let values = predicate.to_pb().get_in_list().get_values().iter()
+                              .map(|value| LittleEndian::read_f64(value))
+                              .collect::<Vec<_>>();
+        assert_eq!(4, values.len());
+        assert_eq!(&[f64::NEG_INFINITY, -0.0, 1.5], &values[..3]);
+        assert!(values[3].is_nan());
+    }
+
+    #[test]
+    fn test_cmp_encoded_floats() {
+        let encode_f32 = |value: f32| { let mut data = [0; 4]; LittleEndian::write_f32(&mut data, value); data };
+        let ordered = [-f32::NAN, f32::NEG_INFINITY, -1.0, -0.0, 0.0, 1.0, f32::INFINITY, f32::NAN];
+        for (i, &a) in ordered.iter().enumerate() {
+            for (j, &b) in ordered.iter().enumerate() {
+                assert_eq!(i.cmp(&j), cmp_total_order(DataType::Float, &encode_f32(a), &encode_f32(b)),
+                           "{} vs {}", a, b);
+                // Signed zeros are only distinct in the total order.
+                let expected = if a == 0.0 && b == 0.0 { Ordering::Equal } else { i.cmp(&j) };
+                assert_eq!(expected, cmp_encoded(DataType::Float, &encode_f32(a), &encode_f32(b)),
+                           "{} vs {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_range() {
+        assert!(ColumnPredicate::range("i32", Some(5i32), Some(5)).is_none());
+        assert!(ColumnPredicate::range("i32", Some(6i32), Some(5)).is_none());
+        assert!(ColumnPredicate::range::<_, i32>("i32", None, None).to_pb().has_is_not_null());
+    }
+
+    #[test]
+    fn test_prefix() {
+        assert_eq!(Some(b"abd".to_vec()), prefix_successor(b"abc"));
+        assert_eq!(Some(b"b".to_vec()), prefix_successor(b"a\xff\xff"));
+        assert_eq!(None, prefix_successor(b"\xff\xff"));
+        assert_eq!(None, prefix_successor(b""));
+
+        let pb = ColumnPredicate::prefix("string", "foo").to_pb();
+        assert_eq!(b"foo", pb.get_range().get_lower());
+        assert_eq!(b"fop", pb.get_range().get_upper());
+
+        let pb = ColumnPredicate::binary_prefix("binary", b"\x01\xff").to_pb();
+        assert_eq!(b"\x01\xff", pb.get_range().get_lower());
+        assert_eq!(b"\x02", pb.get_range().get_upper());
+
+        let pb = ColumnPredicate::prefix("string", "").to_pb();
+        assert!(pb.get_range().has_lower());
+        assert!(!pb.get_range().has_upper());
+    }
+
+    #[test]
+    fn test_check() {
+        let schema = schema::tests::all_types_schema();
+        ColumnPredicate::equal("i32", 1i32).check(&schema).unwrap();
+        ColumnPredicate::equal("binary", "foo").check(&schema).unwrap();
+        ColumnPredicate::equal("timestamp", 1i64).check(&schema).unwrap();
+        assert!(ColumnPredicate::equal("i32", 1i64).check(&schema).is_err());
+        assert!(ColumnPredicate::equal("missing", 1i32).check(&schema).is_err());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let schema = schema::tests::all_types_schema();
+        let mut row = schema.new_row();
+        row.set_by_name("i32", -5i32).unwrap();
+        row.set_by_name("string", "foo").unwrap();
+        row.set_by_name("timestamp", 100i64).unwrap();
+        row.set_null_by_name("nullable_i32").unwrap();
+
+        assert!(ColumnPredicate::equal("i32", -5i32).evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::equal("i32", 5i32).evaluate(&row).unwrap());
+        assert!(ColumnPredicate::range("i32", Some(-5i32), Some(0)).evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::range("i32", Some(-4i32), None).evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::less("i32", -5i32).evaluate(&row).unwrap());
+        assert!(ColumnPredicate::in_list("i32", vec![7i32, -5, 3]).evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::in_list("i32", vec![7i32, 3]).evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::in_list::<_, i32>("i32", vec![]).evaluate(&row).unwrap());
+        assert!(ColumnPredicate::is_not_null("i32").evaluate(&row).unwrap());
+
+        assert!(ColumnPredicate::prefix("string", "fo").evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::prefix("string", "fop").evaluate(&row).unwrap());
+        assert!(ColumnPredicate::greater_or_equal("timestamp", 100i64).evaluate(&row).unwrap());
+
+        assert!(!ColumnPredicate::is_not_null("nullable_i32").evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::equal("nullable_i32", 1i32).evaluate(&row).unwrap());
+
+        // Unset and unknown columns.
+        assert!(ColumnPredicate::equal("i64", 1i64).evaluate(&row).is_err());
+        assert!(ColumnPredicate::equal("missing", 1i32).evaluate(&row).is_err());
+    }
+
+    #[test]
+    fn test_evaluate_nan() {
+        let schema = schema::tests::all_types_schema();
+        let mut row = schema.new_row();
+        row.set_by_name("f64", f64::NAN).unwrap();
+        row.set_by_name("f32", -f32::NAN).unwrap();
+
+        assert!(!ColumnPredicate::greater_or_equal("f64", 0.0f64).evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::less("f64", f64::INFINITY).evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::less("f32", 0.0f32).evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::equal("f64", f64::NAN).evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::in_list("f64", vec![1.0f64, f64::NAN]).evaluate(&row).unwrap());
+        assert!(ColumnPredicate::is_not_null("f64").evaluate(&row).unwrap());
+
+        row.set_by_name("f64", 1.0f64).unwrap();
+        assert!(ColumnPredicate::in_list("f64", vec![1.0f64, f64::NAN]).evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::equal("f64", f64::NAN).evaluate(&row).unwrap());
+    }
+
+    #[test]
+    fn test_evaluate_negative_zero() {
+        let schema = schema::tests::all_types_schema();
+        let mut row = schema.new_row();
+        row.set_by_name("f64", -0.0f64).unwrap();
+
+        // As on the tablet servers, -0.0 is equal to 0.0.
+        assert!(ColumnPredicate::equal("f64", 0.0f64).evaluate(&row).unwrap());
+        assert!(ColumnPredicate::greater_or_equal("f64", 0.0f64).evaluate(&row).unwrap());
+        assert!(!ColumnPredicate::less("f64", 0.0f64).evaluate(&row).unwrap());
+        assert!(ColumnPredicate::in_list("f64", vec![0.0f64, 1.0]).evaluate(&row).unwrap());
+        assert!(ColumnPredicate::range("f64", Some(-0.0f64), Some(0.0)).is_none());
+
+        row.set_by_name("f64", 0.0f64).unwrap();
+        assert!(ColumnPredicate::equal("f64", -0.0f64).evaluate(&row).unwrap());
+        assert!(ColumnPredicate::in_list("f64", vec![-1.0f64, -0.0]).evaluate(&row).unwrap());
+    }
+
+    #[test]
+    fn test_predicate_dsl() {
+        let schema = schema::tests::all_types_schema();
+        let predicates = col("i32").ge(-10i32) & col("string").is_in(vec!["foo", "bar"]);
+        assert_eq!(&[ColumnPredicate::greater_or_equal("i32", -10i32),
+                     ColumnPredicate::in_list("string", vec!["foo", "bar"])],
+                   predicates.predicates());
+        predicates.check(&schema).unwrap();
+
+        let predicates = predicates & (col("timestamp").lt(100i64) & col("i32").is_not_null());
+        assert_eq!(4, predicates.predicates().len());
+        assert!((predicates.clone() & col("i32").equal(1i64)).check(&schema).is_err());
+        assert!((predicates.clone() & col("missing").equal(1i32)).check(&schema).is_err());
+
+        let mut row = schema.new_row();
+        row.set_by_name("i32", -5i32).unwrap();
+        row.set_by_name("string", "foo").unwrap();
+        row.set_by_name("timestamp", 99i64).unwrap();
+        assert!(predicates.evaluate(&row).unwrap());
+        row.set_by_name("string", "baz").unwrap();
+        assert!(!predicates.evaluate(&row).unwrap());
+    }
+}
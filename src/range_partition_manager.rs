@@ -0,0 +1,286 @@
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
+
+use AlterTableBuilder;
+use DataType;
+use Error;
+use RangePartitionBound;
+use Result;
+use Table;
+use util::{duration_to_us, time_to_us, us_to_time};
+
+/// A range partition of a time-series table, covering the times `[lower_bound, upper_bound)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TimeRange {
+    lower_bound: SystemTime,
+    upper_bound: SystemTime,
+}
+
+impl TimeRange {
+
+    pub fn lower_bound(&self) -> SystemTime {
+        self.lower_bound
+    }
+
+    pub fn upper_bound(&self) -> SystemTime {
+        self.upper_bound
+    }
+}
+
+/// The range partitions added and dropped by `RangePartitionManager::maintain`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RangePartitionChanges {
+    added: Vec<TimeRange>,
+    dropped: Vec<TimeRange>,
+}
+
+impl RangePartitionChanges {
+
+    /// Returns the partitions which were added, in time order.
+    pub fn added(&self) -> &[TimeRange] {
+        &self.added
+    }
+
+    /// Returns the expired partitions which were dropped, in time order.
+    pub fn dropped(&self) -> &[TimeRange] {
+        &self.dropped
+    }
+
+    /// Returns `true` if no partitions were added or dropped.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.dropped.is_empty()
+    }
+}
+
+/// Maintains the range partitions of a table which is range partitioned by time.
+///
+/// Each partition covers `partition_width` of time, aligned to the Unix epoch. Every call to
+/// `maintain` adds the partition containing the current time and the configured number of
+/// partitions after it, and drops partitions which lie entirely before the retention period. The
+/// changes are computed from the table's current partitions, so `maintain` is idempotent and is
+/// typically run periodically from a single process.
+///
+/// The table must be range partitioned by a single `TIMESTAMP` or `INT64` column. `INT64` values
+/// are interpreted as microseconds since the Unix epoch, like `TIMESTAMP` values. Unbounded range
+/// partitions, and partitions which overlap the managed ones, are left untouched.
+#[derive(Clone)]
+pub struct RangePartitionManager {
+    table: Table,
+    column_idx: usize,
+    partition_width: i64,
+    retention: Duration,
+    num_future_partitions: u32,
+}
+
+impl RangePartitionManager {
+
+    /// Creates a manager for the table's range partitions.
+    pub fn new(table: Table,
+               partition_width: Duration,
+               retention: Duration)
+               -> Result<RangePartitionManager> {
+        let column_idx = {
+            let columns = table.partition_schema().range_partition_schema().columns();
+            if columns.len() != 1 {
+                return Err(Error::InvalidArgument(format!(
+                            "table {} must be range partitioned by a single column", table.name())));
+            }
+            columns[0]
+        };
+        match table.schema().columns()[column_idx].data_type() {
+            DataType::Timestamp | DataType::Int64 => (),
+            data_type => return Err(Error::InvalidArgument(format!(
+                        "range partition column of table {} has type {:?}, expected TIMESTAMP or INT64",
+                        table.name(), data_type))),
+        }
+        let partition_width = duration_to_us(&partition_width) as i64;
+        if partition_width <= 0 {
+            return Err(Error::InvalidArgument(
+                    "range partition width must be at least one microsecond".to_string()));
+        }
+        Ok(RangePartitionManager {
+            table: table,
+            column_idx: column_idx,
+            partition_width: partition_width,
+            retention: retention,
+            num_future_partitions: 2,
+        })
+    }
+
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    pub fn num_future_partitions(&self) -> u32 {
+        self.num_future_partitions
+    }
+
+    /// Sets the number of partitions to create ahead of the partition containing the current time,
+    /// so that writes keep succeeding if maintenance is delayed.
+    ///
+    /// Defaults to 2.
+    pub fn set_num_future_partitions(&mut self, num_future_partitions: u32) -> &mut RangePartitionManager {
+        self.num_future_partitions = num_future_partitions;
+        self
+    }
+
+    /// Returns the bounded range partitions of the table, in time order.
+    pub fn partitions(&self, deadline: Instant) -> Result<Vec<TimeRange>> {
+        let mut ranges = Vec::new();
+        for tablet in try!(self.table.tablets(deadline)) {
+            let partition = tablet.partition();
+            let lower = partition.range_lower_bound();
+            let upper = partition.range_upper_bound();
+            if try!(lower.is_set(self.column_idx)) && try!(upper.is_set(self.column_idx)) {
+                // Tablets of hash partitioned tables share their range partition.
+                let range = (try!(lower.get::<i64>(self.column_idx)), try!(upper.get::<i64>(self.column_idx)));
+                if !ranges.contains(&range) {
+                    ranges.push(range);
+                }
+            }
+        }
+        ranges.sort();
+        Ok(ranges.into_iter().map(|(lower, upper)| time_range(lower, upper)).collect())
+    }
+
+    /// Adds the upcoming range partitions and drops the expired range partitions of the table, as
+    /// of the provided time. The changes are applied with a single alter table operation, which has
+    /// completed once this returns.
+    pub fn maintain(&self, now: SystemTime, deadline: Instant) -> Result<RangePartitionChanges> {
+        let existing = try!(self.partitions(deadline));
+
+        let now = time_to_us(&now);
+        let width = self.partition_width;
+        let current = now - (now % width + width) % width;
+        let expiration = now - duration_to_us(&self.retention) as i64;
+
+        let mut changes = RangePartitionChanges::default();
+        for i in 0..(self.num_future_partitions as i64 + 1) {
+            let lower = current + i * width;
+            let upper = lower + width;
+            let overlaps_existing = existing.iter().any(|range| {
+                time_to_us(&range.lower_bound) < upper && lower < time_to_us(&range.upper_bound)
+            });
+            if !overlaps_existing && upper > expiration {
+                changes.added.push(time_range(lower, upper));
+            }
+        }
+        changes.dropped = existing.into_iter()
+                                  .filter(|range| time_to_us(&range.upper_bound) <= expiration)
+                                  .collect();
+
+        if changes.is_empty() {
+            return Ok(changes);
+        }
+
+        let mut alter = AlterTableBuilder::new();
+        for range in &changes.dropped {
+            let (lower, upper) = try!(self.bounds(range));
+            alter.drop_range_partition_by_ref(&lower, &upper);
+        }
+        for range in &changes.added {
+            let (lower, upper) = try!(self.bounds(range));
+            alter.add_range_partition_by_ref(&lower, &upper);
+        }
+        let client = self.table.client();
+        try!(client.alter_table_by_id(self.table.id(), alter, deadline));
+        try!(client.wait_for_table_alteration_by_id(self.table.id(), deadline));
+        Ok(changes)
+    }
+
+    fn bounds(&self, range: &TimeRange) -> Result<(RangePartitionBound, RangePartitionBound)> {
+        let mut lower = self.table.schema().new_row();
+        let mut upper = self.table.schema().new_row();
+        try!(lower.set(self.column_idx, time_to_us(&range.lower_bound)));
+        try!(upper.set(self.column_idx, time_to_us(&range.upper_bound)));
+        Ok((RangePartitionBound::Inclusive(lower), RangePartitionBound::Exclusive(upper)))
+    }
+}
+
+impl fmt::Debug for RangePartitionManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RangePartitionManager {{ table: {}, column: {}, partition_width: {}us, retention: {:?} }}",
+               self.table.name(), self.table.schema().columns()[self.column_idx].name(),
+               self.partition_width, self.retention)
+    }
+}
+
+fn time_range(lower: i64, upper: i64) -> TimeRange {
+    TimeRange {
+        lower_bound: us_to_time(lower),
+        upper_bound: us_to_time(upper),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+
+    use env_logger;
+
+    use Client;
+    use ClientConfig;
+    use Column;
+    use SchemaBuilder;
+    use TableBuilder;
+    use mini_cluster::{MiniCluster, MiniClusterConfig};
+    use super::*;
+
+    fn deadline() -> Instant {
+        Instant::now() + Duration::from_secs(10)
+    }
+
+    #[test]
+    fn maintain() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default()
+                                                         .num_masters(1)
+                                                         .num_tservers(1));
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("time", DataType::Timestamp).set_not_null())
+            .add_column(Column::builder("val", DataType::Int32))
+            .set_primary_key(vec!["time"])
+            .build()
+            .unwrap();
+
+        let hour = Duration::from_secs(60 * 60);
+        let now = UNIX_EPOCH + hour * 100 + Duration::from_secs(60);
+
+        let mut table_builder = TableBuilder::new("t", schema.clone());
+        table_builder.set_num_replicas(1);
+        table_builder.set_range_partition_columns(vec!["time"]);
+        table_builder.add_hash_partitions(vec!["time"], 2);
+        for &(lower, upper) in &[(90, 91), (98, 99), (101, 102)] {
+            let mut lower_bound = schema.new_row();
+            let mut upper_bound = schema.new_row();
+            lower_bound.set(0, UNIX_EPOCH + hour * lower).unwrap();
+            upper_bound.set(0, UNIX_EPOCH + hour * upper).unwrap();
+            table_builder.add_range_partition(RangePartitionBound::Inclusive(lower_bound),
+                                              RangePartitionBound::Exclusive(upper_bound));
+        }
+        client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation("t", deadline()).unwrap();
+        let table = client.open_table("t", deadline()).unwrap();
+
+        let manager = RangePartitionManager::new(table.clone(), hour, hour * 5).unwrap();
+        let changes = manager.maintain(now, deadline()).unwrap();
+        assert_eq!(vec![(100, 101), (102, 103)],
+                   changes.added().iter().map(hours).collect::<Vec<_>>());
+        assert_eq!(vec![(90, 91)], changes.dropped().iter().map(hours).collect::<Vec<_>>());
+        assert_eq!(vec![(98, 99), (100, 101), (101, 102), (102, 103)],
+                   manager.partitions(deadline()).unwrap().iter().map(hours).collect::<Vec<_>>());
+
+        // Maintenance is idempotent.
+        assert!(manager.maintain(now, deadline()).unwrap().is_empty());
+
+        assert!(RangePartitionManager::new(table, Duration::new(0, 0), hour).is_err());
+    }
+
+    fn hours(range: &TimeRange) -> (u64, u64) {
+        (range.lower_bound().duration_since(UNIX_EPOCH).unwrap().as_secs() / 3600,
+         range.upper_bound().duration_since(UNIX_EPOCH).unwrap().as_secs() / 3600)
+    }
+}
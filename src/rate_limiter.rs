@@ -0,0 +1,196 @@
+use std::cmp;
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use util::duration_to_us;
+
+/// Limits the rate of write operations applied to a `Writer`, in rows per second and bytes per
+/// second.
+///
+/// The limits are enforced with token buckets which hold up to one second of capacity, so short
+/// bursts are allowed. When a limit is exceeded, `Writer::apply` blocks until the operation fits
+/// within the limit. This is typically used to throttle bulk backfills so that they do not degrade
+/// the latency of the cluster's other workloads.
+///
+/// Rate limiters are cheap to clone, and clones share their limits, so the limits may be adjusted
+/// while the writer is in use.
+#[derive(Clone)]
+pub struct RateLimiter {
+    inner: Arc<Mutex<Buckets>>,
+}
+
+struct Buckets {
+    rows: Bucket,
+    bytes: Bucket,
+    refilled: Instant,
+}
+
+/// A token bucket. Tokens are counted in millionths, so that refilling at a rate of tokens per
+/// second is exact for elapsed times in microseconds.
+struct Bucket {
+    /// Tokens per second, or 0 if unlimited.
+    rate: u64,
+    /// The current balance in millionths of a token. Negative when operations have been admitted
+    /// ahead of the rate.
+    balance: i64,
+}
+
+impl Bucket {
+
+    fn new(rate: u64) -> Bucket {
+        Bucket {
+            rate: rate,
+            balance: Bucket::capacity(rate),
+        }
+    }
+
+    fn capacity(rate: u64) -> i64 {
+        cmp::min(rate, i64::max_value() as u64 / 1000_000) as i64 * 1000_000
+    }
+
+    fn set_rate(&mut self, rate: u64) {
+        if self.rate == 0 {
+            self.balance = Bucket::capacity(rate);
+        }
+        self.rate = rate;
+        self.balance = cmp::min(self.balance, Bucket::capacity(rate));
+    }
+
+    fn refill(&mut self, elapsed_us: u64) {
+        if self.rate > 0 {
+            let tokens = self.rate.saturating_mul(elapsed_us);
+            let tokens = cmp::min(tokens, i64::max_value() as u64) as i64;
+            self.balance = cmp::min(self.balance.saturating_add(tokens), Bucket::capacity(self.rate));
+        }
+    }
+
+    /// Takes tokens from the bucket, and returns the time after which the balance is no longer
+    /// negative.
+    fn take(&mut self, tokens: u64) -> Duration {
+        if self.rate == 0 {
+            return Duration::new(0, 0);
+        }
+        let tokens = cmp::min(tokens, i64::max_value() as u64 / 1000_000) as i64 * 1000_000;
+        self.balance = self.balance.saturating_sub(tokens);
+        if self.balance >= 0 {
+            Duration::new(0, 0)
+        } else {
+            let us = (-self.balance) as u64 / self.rate;
+            Duration::new(us / 1000_000, (us % 1000_000) as u32 * 1000)
+        }
+    }
+}
+
+impl RateLimiter {
+
+    /// Creates a rate limiter. A limit of `None` does not limit the rate.
+    pub fn new(max_rows_per_second: Option<u64>, max_bytes_per_second: Option<u64>) -> RateLimiter {
+        RateLimiter {
+            inner: Arc::new(Mutex::new(Buckets {
+                rows: Bucket::new(max_rows_per_second.unwrap_or(0)),
+                bytes: Bucket::new(max_bytes_per_second.unwrap_or(0)),
+                refilled: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn max_rows_per_second(&self) -> Option<u64> {
+        limit(self.inner.lock().rows.rate)
+    }
+
+    pub fn max_bytes_per_second(&self) -> Option<u64> {
+        limit(self.inner.lock().bytes.rate)
+    }
+
+    /// Sets the maximum number of rows per second, or removes the limit if `None`. Takes effect
+    /// for subsequently applied operations.
+    pub fn set_max_rows_per_second(&self, max_rows_per_second: Option<u64>) {
+        self.inner.lock().rows.set_rate(max_rows_per_second.unwrap_or(0));
+    }
+
+    /// Sets the maximum number of bytes per second, or removes the limit if `None`. Takes effect
+    /// for subsequently applied operations.
+    pub fn set_max_bytes_per_second(&self, max_bytes_per_second: Option<u64>) {
+        self.inner.lock().bytes.set_rate(max_bytes_per_second.unwrap_or(0));
+    }
+
+    /// Blocks until the rows and bytes fit within the limits.
+    pub fn acquire(&self, rows: u64, bytes: u64) {
+        let wait = self.reserve(Instant::now(), rows, bytes);
+        if wait > Duration::new(0, 0) {
+            thread::sleep(wait);
+        }
+    }
+
+    /// Takes the rows and bytes from the buckets as of `now`, and returns the duration to wait
+    /// before they fit within the limits.
+    fn reserve(&self, now: Instant, rows: u64, bytes: u64) -> Duration {
+        let mut buckets = self.inner.lock();
+        if now > buckets.refilled {
+            let elapsed_us = duration_to_us(&(now - buckets.refilled));
+            buckets.rows.refill(elapsed_us);
+            buckets.bytes.refill(elapsed_us);
+            buckets.refilled = now;
+        }
+        cmp::max(buckets.rows.take(rows), buckets.bytes.take(bytes))
+    }
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RateLimiter {{ max_rows_per_second: {:?}, max_bytes_per_second: {:?} }}",
+               self.max_rows_per_second(), self.max_bytes_per_second())
+    }
+}
+
+fn limit(rate: u64) -> Option<u64> {
+    if rate == 0 { None } else { Some(rate) }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_reserve() {
+        let limiter = RateLimiter::new(Some(100), None);
+        let start = limiter.inner.lock().refilled;
+
+        // A second of capacity is available up front.
+        assert_eq!(Duration::new(0, 0), limiter.reserve(start, 100, 1000));
+        assert_eq!(Duration::from_millis(500), limiter.reserve(start, 50, 0));
+
+        // The bucket refills at the limit rate.
+        assert_eq!(Duration::new(0, 0), limiter.reserve(start + Duration::from_secs(1), 40, 0));
+        assert_eq!(Duration::from_millis(100), limiter.reserve(start + Duration::from_secs(1), 20, 0));
+
+        // Both limits apply.
+        limiter.set_max_bytes_per_second(Some(1000));
+        assert_eq!(Some(1000), limiter.max_bytes_per_second());
+        let later = start + Duration::from_secs(10);
+        assert_eq!(Duration::from_secs(1), limiter.reserve(later, 1, 2000));
+
+        // Removing the limits admits everything.
+        limiter.set_max_rows_per_second(None);
+        limiter.set_max_bytes_per_second(None);
+        assert_eq!(None, limiter.max_rows_per_second());
+        assert_eq!(Duration::new(0, 0), limiter.reserve(later, 1000_000, 1000_000));
+    }
+
+    #[test]
+    fn test_acquire() {
+        let limiter = RateLimiter::new(Some(1000), None);
+        let start = Instant::now();
+        for _ in 0..1500 {
+            limiter.acquire(1, 0);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+}
@@ -0,0 +1,133 @@
+use std::cmp;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use parking_lot::Mutex;
+
+/// Limits the RPC retries made by a client to a fraction of its successful RPCs.
+///
+/// Every successful RPC deposits a fraction of a retry into the budget, and every retry withdraws
+/// a whole retry. When the budget is exhausted, RPCs which would otherwise be retried fail
+/// immediately with their original error. This keeps a cluster-wide failure from being compounded
+/// by every client retrying every RPC in lockstep; once the cluster recovers, successful RPCs
+/// replenish the budget.
+///
+/// The budget starts full, so that a client which has not yet completed any RPCs can still retry.
+///
+/// Budgets are cheap to clone, and clones share their balance.
+#[derive(Clone)]
+pub struct RetryBudget {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// The maximum balance in whole retries, or `None` if retries are unlimited.
+    limit: Option<u32>,
+    /// The percentage of a retry deposited by each successful RPC.
+    percent: u32,
+    /// The balance in hundredths of a retry.
+    balance: Mutex<u64>,
+    /// The number of retries which were refused.
+    refused: AtomicUsize,
+}
+
+impl RetryBudget {
+
+    /// Creates a budget which holds up to `limit` retries, and earns `percent` hundredths of a
+    /// retry for every successful RPC. If `limit` is `None`, retries are unlimited.
+    pub fn new(limit: Option<u32>, percent: u32) -> RetryBudget {
+        RetryBudget {
+            inner: Arc::new(Inner {
+                limit: limit,
+                percent: percent,
+                balance: Mutex::new(limit.map_or(0, |limit| limit as u64 * 100)),
+                refused: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> Option<u32> {
+        self.inner.limit
+    }
+
+    pub fn percent(&self) -> u32 {
+        self.inner.percent
+    }
+
+    /// Returns the number of whole retries which are available, or `None` if retries are
+    /// unlimited.
+    pub fn available(&self) -> Option<u32> {
+        self.inner.limit.map(|_| (*self.inner.balance.lock() / 100) as u32)
+    }
+
+    /// Returns the number of retries which have been refused because the budget was exhausted.
+    pub fn refused(&self) -> usize {
+        self.inner.refused.load(Ordering::Relaxed)
+    }
+
+    /// Withdraws a retry from the budget. Returns `false` if the budget is exhausted, in which
+    /// case the RPC should not be retried.
+    pub fn try_retry(&self) -> bool {
+        if self.inner.limit.is_none() {
+            return true;
+        }
+        let mut balance = self.inner.balance.lock();
+        if *balance < 100 {
+            self.inner.refused.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        *balance -= 100;
+        true
+    }
+
+    /// Deposits the share of a retry earned by a successful RPC.
+    pub fn record_success(&self) {
+        if let Some(limit) = self.inner.limit {
+            let mut balance = self.inner.balance.lock();
+            *balance = cmp::min(*balance + self.inner.percent as u64, limit as u64 * 100);
+        }
+    }
+}
+
+impl fmt::Debug for RetryBudget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RetryBudget {{ limit: {:?}, percent: {}, available: {:?} }}",
+               self.limit(), self.percent(), self.available())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_retry_budget() {
+        let budget = RetryBudget::new(Some(2), 50);
+        assert_eq!(Some(2), budget.available());
+        assert!(budget.try_retry());
+        assert!(budget.try_retry());
+        assert!(!budget.try_retry());
+        assert_eq!(1, budget.refused());
+
+        // Each success earns half a retry.
+        budget.record_success();
+        assert!(!budget.try_retry());
+        budget.record_success();
+        assert!(budget.try_retry());
+
+        // The balance is capped at the limit.
+        for _ in 0..10 {
+            budget.record_success();
+        }
+        assert_eq!(Some(2), budget.available());
+
+        let unlimited = RetryBudget::new(None, 10);
+        for _ in 0..1000 {
+            assert!(unlimited.try_retry());
+        }
+        assert_eq!(None, unlimited.available());
+        assert_eq!(0, unlimited.refused());
+    }
+}
@@ -1,6 +1,5 @@
 use std::cmp;
 use std::fmt;
-use std::time::SystemTime;
 
 use byteorder::{ByteOrder, LittleEndian};
 use kudu_pb::wire_protocol::{RowOperationsPB_Type as OperationType};
@@ -14,6 +13,7 @@ use Error;
 use RangePartitionBound;
 use Result;
 use Schema;
+use TypedColumn;
 use Value;
 use util;
 
@@ -44,8 +44,57 @@ impl Row {
         }
     }
 
+    /// Decodes a row from a rowwise row block returned by a tablet server scan.
+    ///
+    /// `data` must contain the row's cells, followed by the null bitmap if the schema has nullable
+    /// columns. `indirect_data` holds the variable length cell data of the row block.
+    #[doc(hidden)]
+    pub fn decode(schema: Schema, data: &[u8], indirect_data: &[u8]) -> Result<Row> {
+        let num_columns = schema.columns().len();
+        let row_size = schema.row_size();
+        let bitmap_len = if schema.has_nullable_columns() { (num_columns + 7) / 8 } else { 0 };
+        if data.len() != row_size + bitmap_len {
+            return Err(Error::Serialization(format!("invalid row length {} for schema {:?}",
+                                                    data.len(), schema)));
+        }
+
+        let mut row = Row::new(schema);
+        row.data.copy_from_slice(&data[..row_size]);
+        let null_bitmap = &data[row_size..];
+        for (idx, column) in row.schema.columns().iter().enumerate() {
+            row.set_columns.insert(idx);
+            if column.is_nullable() && null_bitmap[idx >> 3] & (1 << (idx & 7)) > 0 {
+                row.null_columns.insert(idx);
+                continue;
+            }
+            if column.data_type().is_var_len() {
+                let offset = row.schema.column_offsets()[idx];
+                let start = LittleEndian::read_u64(&row.data[offset..]) as usize;
+                let len = LittleEndian::read_u64(&row.data[offset+8..]) as usize;
+                if start.checked_add(len).map_or(true, |end| end > indirect_data.len()) {
+                    return Err(Error::Serialization(format!(
+                        "indirect data for column {:?} out of bounds", column)));
+                }
+                row.indirect_data.insert(idx, indirect_data[start..start+len].to_owned());
+                // The in-line cell only holds the pointer into the indirect data, which is
+                // meaningless once decoded. Zero it so that decoded rows compare equal to rows
+                // built with the setters.
+                for b in &mut row.data[offset..offset+16] { *b = 0; }
+            }
+        }
+        Ok(row)
+    }
+
+    /// Sets the value of a cell.
+    ///
+    /// `Option` values may be written to any column of a compatible type: `Some(value)` sets the
+    /// value, and `None` sets the cell to null, which fails if the column is not nullable.
     pub fn set<'a, V>(&mut self, idx: usize, value: V) -> Result<&mut Row> where V: Value<'a> {
         try!(self.check_column_for_write::<V>(idx));
+        if value.is_null() && !self.schema.columns()[idx].is_nullable() {
+            return Err(Error::InvalidArgument(format!("column {:?} is not nullable",
+                                                      self.schema.columns()[idx])));
+        }
         unsafe {
             Ok(self.set_unchecked(idx, value))
         }
@@ -96,6 +145,10 @@ impl Row {
         self
     }
 
+    /// Gets the value of a cell.
+    ///
+    /// Cells of any column can be read as an `Option`, which is `None` if the cell is null. Reading
+    /// a cell which has not been set fails, so unset and null cells can be told apart.
     pub fn get<'a, V>(&'a self, idx: usize) -> Result<V> where V: Value<'a> {
         try!(self.check_column_for_read::<V>(idx));
         if !self.set_columns.get(idx) {
@@ -118,6 +171,24 @@ impl Row {
         }
     }
 
+    /// Returns the encoded data of a cell, or `None` if the cell is null. Fixed-size cells are
+    /// encoded in little-endian order, and variable-length cells are returned as-is.
+    #[doc(hidden)]
+    pub fn cell_data(&self, idx: usize) -> Result<Option<&[u8]>> {
+        if !try!(self.is_set(idx)) {
+            Err(Error::InvalidArgument(format!("column '{}' ({}) is not set",
+                                               self.schema.columns()[idx].name(), idx)))
+        } else if try!(self.is_null(idx)) {
+            Ok(None)
+        } else if self.schema.columns()[idx].data_type().is_var_len() {
+            Ok(Some(&self.indirect_data[idx]))
+        } else {
+            let offset = self.schema.column_offsets()[idx];
+            let len = self.schema.columns()[idx].data_type().size();
+            Ok(Some(&self.data[offset..offset+len]))
+        }
+    }
+
     pub fn get_by_name<'a, V>(&'a self, column: &str) -> Result<Option<V>> where V: Value<'a> {
         if let Some(idx) = self.schema.column_index(column) {
             self.get(idx)
@@ -126,6 +197,33 @@ impl Row {
         }
     }
 
+    /// Sets the value of a typed column.
+    ///
+    /// Fails if the typed column was created from a different schema.
+    pub fn set_typed<'a, V>(&mut self, column: &TypedColumn<V>, value: V) -> Result<&mut Row>
+    where V: Value<'a> {
+        try!(self.check_typed_column(column));
+        self.set(column.index(), value)
+    }
+
+    /// Gets the value of a typed column.
+    ///
+    /// Fails if the typed column was created from a different schema.
+    pub fn get_typed<'a, V>(&'a self, column: &TypedColumn<V>) -> Result<V> where V: Value<'a> {
+        try!(self.check_typed_column(column));
+        self.get(column.index())
+    }
+
+    fn check_typed_column<V>(&self, column: &TypedColumn<V>) -> Result<()> {
+        match self.schema.columns().get(column.index()) {
+            Some(c) if c.name() == column.name() && c.data_type() == column.data_type() => Ok(()),
+            _ => Err(Error::InvalidArgument(format!("{:?} does not belong to schema {:?}",
+                                                    column, self.schema))),
+        }
+    }
+
+    /// Returns `true` if the cell is null. The null bitmap is consulted directly, so the cell's
+    /// value is not decoded.
     pub fn is_null(&self, idx: usize) -> Result<bool> {
         if idx >= self.schema.columns().len() {
             Err(Error::InvalidArgument(format!("index {} is invalid for schema {:?}",
@@ -143,6 +241,7 @@ impl Row {
         }
     }
 
+    /// Returns `true` if the cell has been set, either to a value or to null.
     pub fn is_set(&self, idx: usize) -> Result<bool> {
         if idx >= self.schema.columns().len() {
             Err(Error::InvalidArgument(format!("index {} is invalid for schema {:?}",
@@ -160,10 +259,91 @@ impl Row {
         }
     }
 
+    /// Returns `true` if any cell in the row is null.
+    pub fn has_nulls(&self) -> bool {
+        !self.null_columns.is_empty()
+    }
+
     pub fn schema(&self) -> &Schema {
         &self.schema
     }
 
+    /// Compares the primary key of the row to the primary key of another row, which is the order
+    /// of the rows in a table. Columns are compared in primary key order; integer and timestamp
+    /// cells compare numerically, and string and binary cells compare bytewise. The ordering
+    /// matches the ordering of the rows' encoded primary keys.
+    ///
+    /// Useful for sorting rows before loading them, for instance with
+    /// `rows.sort_by(|a, b| a.cmp_primary_key(b).unwrap())`. The rows' schemas may differ in their
+    /// non-key columns. Fails if the primary key columns differ, or if a primary key cell of
+    /// either row is not set.
+    pub fn cmp_primary_key(&self, other: &Row) -> Result<cmp::Ordering> {
+        if self.schema.primary_key() != other.schema.primary_key() {
+            return Err(Error::InvalidArgument(
+                    "rows with different primary key columns can not be compared".to_owned()));
+        }
+        for (idx, column) in self.schema.primary_key().iter().enumerate() {
+            if !self.set_columns.get(idx) || !other.set_columns.get(idx) {
+                return Err(Error::InvalidArgument(
+                        format!("primary key column '{}' is not set", column.name())));
+            }
+            let ordering = match column.data_type() {
+                DataType::Int8 => try!(self.get::<i8>(idx)).cmp(&try!(other.get::<i8>(idx))),
+                DataType::Int16 => try!(self.get::<i16>(idx)).cmp(&try!(other.get::<i16>(idx))),
+                DataType::Int32 => try!(self.get::<i32>(idx)).cmp(&try!(other.get::<i32>(idx))),
+                DataType::Int64 | DataType::Timestamp => {
+                    try!(self.get::<i64>(idx)).cmp(&try!(other.get::<i64>(idx)))
+                },
+                DataType::Binary | DataType::String => {
+                    try!(self.get::<&[u8]>(idx)).cmp(try!(other.get::<&[u8]>(idx)))
+                },
+                DataType::Bool | DataType::Float | DataType::Double => {
+                    unreachable!("primary key column of type {:?}", column.data_type())
+                },
+            };
+            if ordering != cmp::Ordering::Equal {
+                return Ok(ordering);
+            }
+        }
+        Ok(cmp::Ordering::Equal)
+    }
+
+    /// Remaps the row to a compatible schema.
+    ///
+    /// Columns are matched by name, and must have the same type in both schemas. Columns which are
+    /// not in the target schema are dropped, and target columns which are not in this row's schema
+    /// are left unset. Fails if a null cell would be copied to a non-nullable target column.
+    pub fn project(&self, schema: &Schema) -> Result<Row> {
+        let mut row = Row::new(schema.clone());
+        for (target_idx, column) in schema.columns().iter().enumerate() {
+            let idx = match self.schema.column_index(column.name()) {
+                Some(idx) => idx,
+                None => continue,
+            };
+            let source = &self.schema.columns()[idx];
+            if source.data_type() != column.data_type() {
+                return Err(Error::InvalidArgument(format!(
+                    "column {:?} can not be projected to column {:?}", source, column)));
+            }
+            if !self.set_columns.get(idx) { continue; }
+
+            if self.schema.has_nullable_columns() && self.null_columns.get(idx) {
+                try!(row.set_null(target_idx));
+            } else if column.data_type().is_var_len() {
+                row.set_columns.insert(target_idx);
+                row.indirect_data.insert(target_idx, self.indirect_data[idx].clone());
+            } else {
+                let size = column.data_type().size();
+                let offset = self.schema.column_offsets()[idx];
+                let target_offset = schema.column_offsets()[target_idx];
+                row.set_columns.insert(target_idx);
+                row.data[target_offset..target_offset+size]
+                   .copy_from_slice(&self.data[offset..offset+size]);
+            }
+        }
+        Ok(row)
+    }
+
     /// Checks that the column with the specified index has the expected type.
     fn check_column_for_write<'a, V>(&self, idx: usize) -> Result<()> where V: Value<'a> {
         if idx >= self.schema.columns().len() {
@@ -176,10 +356,6 @@ impl Row {
                                                       V::data_type(),
                                                       column)));
         }
-        if V::is_nullable() && !column.is_nullable() {
-            return Err(Error::InvalidArgument(format!("nullable type is invalid for column {:?}",
-                                                      column)));
-        }
         Ok(())
     }
 
@@ -207,10 +383,6 @@ impl Row {
                                                       V::data_type(),
                                                       column)));
         }
-        if V::is_nullable() && !column.is_nullable() {
-            return Err(Error::InvalidArgument(format!("nullable type is invalid for column {:?}",
-                                                      column)));
-        }
         Ok(())
     }
 
@@ -272,27 +444,12 @@ impl cmp::PartialEq for Row {
 impl cmp::Eq for Row {}
 
 /// `Row`s can be compared based on primary key column values. If the schemas do not match or if
-/// some of the primary key columns are not set, the ordering is not defined.
+/// some of the primary key columns are not set, the ordering is not defined. See
+/// `Row::cmp_primary_key`.
 impl cmp::PartialOrd for Row {
     fn partial_cmp(&self, other: &Row) -> Option<cmp::Ordering> {
         if self.schema != other.schema { return None; }
-
-        for (idx, column) in self.schema.primary_key().iter().enumerate() {
-            if self.set_columns.get(idx) && other.set_columns.get(idx) {
-                let ord = match column.data_type() {
-                    DataType::Int8 => self.get::<i8>(idx).unwrap().cmp(&other.get::<i8>(idx).unwrap()),
-                    DataType::Int16 => self.get::<i16>(idx).unwrap().cmp(&other.get::<i16>(idx).unwrap()),
-                    DataType::Int32 => self.get::<i32>(idx).unwrap().cmp(&other.get::<i32>(idx).unwrap()),
-                    DataType::Int64 => self.get::<i64>(idx).unwrap().cmp(&other.get::<i64>(idx).unwrap()),
-                    DataType::Timestamp => self.get::<SystemTime>(idx).unwrap().cmp(&other.get::<SystemTime>(idx).unwrap()),
-                    DataType::Binary => self.get::<&[u8]>(idx).unwrap().cmp(other.get::<&[u8]>(idx).unwrap()),
-                    DataType::String => self.get::<&str>(idx).unwrap().cmp(other.get::<&str>(idx).unwrap()),
-                    _ => unreachable!("primary key column of type {:?}", column.data_type()),
-                };
-                if ord != cmp::Ordering::Equal { return Some(ord); }
-            } else { return None; }
-        }
-        Some(cmp::Ordering::Equal)
+        self.cmp_primary_key(other).ok()
     }
 }
 
@@ -347,20 +504,28 @@ impl OperationEncoder {
         self.data.extend_from_slice(set_columns.data());
         self.data.extend_from_slice(null_columns.data());
 
+        // A set variable-length cell has indirect data unless it is null, so null variable-length
+        // cells are found without consulting the null bitmap, and a row whose variable-length
+        // cells are all null skips the indirect data entirely.
+        let has_indirect_data = !indirect_data.is_empty();
+
         let mut offset = self.data.len();
         self.data.resize(offset + schema.row_size(), 0);
         for (idx, column) in schema.columns().iter().enumerate() {
             if !set_columns.get(idx) { continue; }
-            if column.is_nullable() && null_columns.get(idx) { continue; }
 
             let data_type = column.data_type();
             let size = data_type.size();
             if data_type.is_var_len() {
-                let data = &indirect_data[idx];
+                let data = match if has_indirect_data { indirect_data.get(idx) } else { None } {
+                    Some(data) => data,
+                    None => continue,
+                };
                 LittleEndian::write_u64(&mut self.data[offset..], self.indirect_data.len() as u64);
                 LittleEndian::write_u64(&mut self.data[offset+8..], data.len() as u64);
                 self.indirect_data.extend_from_slice(data);
             } else {
+                if column.is_nullable() && null_columns.get(idx) { continue; }
                 let column_offset = schema.column_offsets()[idx];
                 self.data[offset..offset+size].copy_from_slice(&data[column_offset..column_offset+size]);
             }
@@ -378,12 +543,20 @@ impl OperationEncoder {
         direct += set_columns.data().len();
         direct += null_columns.data().len();
 
+        let has_indirect_data = !indirect_data.is_empty();
         for (idx, column) in schema.columns().iter().enumerate() {
             if !set_columns.get(idx) { continue; }
-            if column.is_nullable() && null_columns.get(idx) { continue; }
-            direct += column.data_type().size();
+            let data_type = column.data_type();
+            if data_type.is_var_len() {
+                if !has_indirect_data || !indirect_data.contains_key(idx) { continue; }
+            } else if column.is_nullable() && null_columns.get(idx) {
+                continue;
+            }
+            direct += data_type.size();
         }
-        (direct, indirect_data.values().map(|data| data.len()).sum())
+        let indirect = if has_indirect_data { indirect_data.values().map(|data| data.len()).sum() }
+                       else { 0 };
+        (direct, indirect)
     }
 
     pub fn clear(&mut self) {
@@ -403,6 +576,7 @@ mod tests {
     use quickcheck::{quickcheck, TestResult, StdGen};
     use rand;
     use schema;
+    use EncodedPrimaryKey;
     use super::*;
 
     #[test]
@@ -417,6 +591,194 @@ mod tests {
         assert_eq!("foo".to_owned(), row.get::<String>(10).unwrap());
     }
 
+    #[test]
+    fn test_null_var_len_encoding() {
+        let schema = schema::tests::all_types_schema();
+        let mut row = schema.new_row();
+        row.set_by_name::<i32>("key", 1).unwrap();
+        assert!(!row.has_nulls());
+        row.set_null_by_name("nullable_string").unwrap();
+        row.set_null_by_name("nullable_binary").unwrap();
+        assert!(row.has_nulls());
+        assert!(row.is_null_by_name("nullable_string").unwrap());
+        assert!(row.is_set_by_name("nullable_string").unwrap());
+        assert!(!row.is_set_by_name("string").unwrap());
+
+        let (direct_len, indirect_len) = OperationEncoder::encoded_len(&row);
+        assert_eq!(0, indirect_len);
+
+        let mut encoder = OperationEncoder::new();
+        encoder.encode_row(OperationType::INSERT, &row);
+        let (data, indirect_data) = encoder.unwrap();
+        assert_eq!(direct_len, data.len());
+        assert!(indirect_data.is_empty());
+
+        // Rows with an all-null string column are encoded alongside rows which set it, and the
+        // indirect data offsets of the later rows are unaffected.
+        let mut set_row = row.clone();
+        set_row.set_by_name("nullable_string", "foo").unwrap();
+        let (set_direct_len, set_indirect_len) = OperationEncoder::encoded_len(&set_row);
+        assert_eq!(direct_len + 16, set_direct_len);
+        assert_eq!(3, set_indirect_len);
+
+        let mut encoder = OperationEncoder::new();
+        encoder.encode_row(OperationType::INSERT, &row);
+        encoder.encode_row(OperationType::INSERT, &set_row);
+        encoder.encode_row(OperationType::INSERT, &row);
+        let (data, indirect_data) = encoder.unwrap();
+        assert_eq!(2 * direct_len + set_direct_len, data.len());
+        assert_eq!(b"foo", &indirect_data[..]);
+    }
+
+    #[test]
+    fn test_option_values() {
+        let schema = schema::tests::all_types_schema();
+        let mut row = schema.new_row();
+
+        // Unset cells are distinguished from null cells.
+        assert!(row.get::<Option<i32>>(14).is_err());
+        row.set(14, None::<i32>).unwrap();
+        assert_eq!(None, row.get::<Option<i32>>(14).unwrap());
+        assert!(row.get::<i32>(14).is_err());
+        row.set(14, Some(5i32)).unwrap();
+        assert_eq!(Some(5), row.get::<Option<i32>>(14).unwrap());
+        assert_eq!(5, row.get::<i32>(14).unwrap());
+
+        // Non-nullable columns accept `Some`, but not `None`.
+        row.set(4, Some(7i32)).unwrap();
+        assert_eq!(Some(7), row.get::<Option<i32>>(4).unwrap());
+        assert_eq!(Some(7), row.get_by_name::<i32>("i32").unwrap());
+        assert!(row.set(4, None::<i32>).is_err());
+        assert_eq!(7, row.get::<i32>(4).unwrap());
+
+        row.set_by_name("nullable_string", Some("foo")).unwrap();
+        assert_eq!(Some("foo"), row.get_by_name::<&str>("nullable_string").unwrap());
+    }
+
+    #[test]
+    fn test_typed_column() {
+        let schema = schema::tests::all_types_schema();
+        let key = schema.typed_column::<i32>("key").unwrap();
+        let string = schema.typed_column::<&str>("string").unwrap();
+        let nullable = schema.typed_column::<Option<i64>>("nullable_i64").unwrap();
+
+        assert!(schema.typed_column::<i64>("key").is_err());
+        assert!(schema.typed_column::<i32>("missing").is_err());
+
+        let mut row = schema.new_row();
+        row.set_typed(&key, 42).unwrap();
+        row.set_typed(&string, "foo").unwrap();
+        row.set_typed(&nullable, None).unwrap();
+        assert_eq!(42, row.get_typed(&key).unwrap());
+        assert_eq!("foo", row.get_typed(&string).unwrap());
+        assert_eq!(None, row.get_typed(&nullable).unwrap());
+
+        // Nullable types are accepted for columns which are not nullable, as with `Row::set`.
+        let optional_key = schema.typed_column::<Option<i32>>("key").unwrap();
+        assert!(row.set_typed(&optional_key, None).is_err());
+        row.set_typed(&optional_key, Some(7)).unwrap();
+        assert_eq!(Some(7), row.get_typed(&optional_key).unwrap());
+
+        let other = schema::tests::simple_schema();
+        let mut row = other.new_row();
+        assert!(row.set_typed(&string, "foo").is_err());
+    }
+
+    #[test]
+    fn test_project() {
+        let schema = schema::tests::all_types_schema();
+        let mut row = schema.new_row();
+        row.set_by_name::<i32>("key", 1).unwrap();
+        row.set_by_name::<i64>("i64", 2).unwrap();
+        row.set_by_name("string", "foo").unwrap();
+        row.set_null_by_name("nullable_i32").unwrap();
+
+        let target = schema::SchemaBuilder::new()
+            .add_column(schema::Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(schema::Column::builder("string", DataType::String).set_not_null())
+            .add_column(schema::Column::builder("nullable_i32", DataType::Int32).set_nullable())
+            .add_column(schema::Column::builder("i64", DataType::Int64).set_nullable())
+            .add_column(schema::Column::builder("extra", DataType::Int8).set_nullable())
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let projected = row.project(&target).unwrap();
+        assert_eq!(1, projected.get::<i32>(0).unwrap());
+        assert_eq!("foo", projected.get::<&str>(1).unwrap());
+        assert!(projected.is_null(2).unwrap());
+        assert_eq!(Some(2), projected.get::<Option<i64>>(3).unwrap());
+        assert!(!projected.is_set(4).unwrap());
+
+        let mismatched = schema::SchemaBuilder::new()
+            .add_column(schema::Column::builder("key", DataType::Int64).set_not_null())
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+        assert!(row.project(&mismatched).is_err());
+
+        let not_null = schema::SchemaBuilder::new()
+            .add_column(schema::Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(schema::Column::builder("nullable_i32", DataType::Int32).set_not_null())
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+        assert!(row.project(&not_null).is_err());
+    }
+
+    #[test]
+    fn test_cmp_primary_key() {
+        let schema = schema::SchemaBuilder::new()
+            .add_column(schema::Column::builder("a", DataType::Int32).set_not_null())
+            .add_column(schema::Column::builder("b", DataType::String).set_not_null())
+            .add_column(schema::Column::builder("val", DataType::Int32))
+            .set_primary_key(vec!["a", "b"])
+            .build()
+            .unwrap();
+        let row = |a: i32, b: &str| {
+            let mut row = schema.new_row();
+            row.set_by_name("a", a).unwrap();
+            row.set_by_name("b", b).unwrap();
+            row
+        };
+
+        // Integers compare numerically, including negative values, and strings bytewise.
+        let mut rows = vec![row(1, "b"), row(-1, "z"), row(1, "a\0"), row(1, "a")];
+        rows.sort_by(|a, b| a.cmp_primary_key(b).unwrap());
+        assert_eq!(vec![row(-1, "z"), row(1, "a"), row(1, "a\0"), row(1, "b")], rows);
+
+        let keys = rows.iter().map(|row| EncodedPrimaryKey::from_row(row).unwrap()).collect::<Vec<_>>();
+        let mut sorted_keys = keys.clone();
+        sorted_keys.sort();
+        assert_eq!(keys, sorted_keys);
+
+        assert!(row(1, "a").cmp_primary_key(&schema.new_row()).is_err());
+    }
+
+    #[test]
+    fn check_cmp_primary_key() {
+
+        fn cmp_matches_encoding(schema: schema::Schema) -> TestResult {
+            let mut g = StdGen::new(rand::thread_rng(), 100);
+
+            for _ in 0..10 {
+                let a = Row::arbitrary(&mut g, &schema);
+                let b = Row::arbitrary(&mut g, &schema);
+                let ordering = a.cmp_primary_key(&b).unwrap();
+                let encoded = EncodedPrimaryKey::from_row(&a).unwrap()
+                                                    .cmp(&EncodedPrimaryKey::from_row(&b).unwrap());
+                if ordering != encoded {
+                    return TestResult::error(format!("{:?} and {:?} compare {:?}, but encode {:?}",
+                                                     a, b, ordering, encoded));
+                }
+            }
+
+            TestResult::passed()
+        }
+
+        quickcheck(cmp_matches_encoding as fn(schema::Schema) -> TestResult);
+    }
+
     #[test]
     fn check_to_string() {
 
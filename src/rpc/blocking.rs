@@ -0,0 +1,339 @@
+//! A blocking transport which sends RPCs over a `std::net::TcpStream`, available with the
+//! `blocking` feature.
+//!
+//! The transport sends one RPC at a time on the calling thread, without an event loop, I/O threads,
+//! retries, or failover. It is intended for small tools which make a handful of calls to a single
+//! server, where starting a `Client` would be out of proportion to the work.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use kudu_pb::master::{ListTablesRequestPB, PingRequestPB};
+use kudu_pb::rpc_header::{SaslMessagePB_SaslState as SaslState};
+use kudu_pb::rpc_header;
+use protobuf::{parse_length_delimited_from, Clear, CodedInputStream, Message};
+use protobuf::rt::ProtobufVarint;
+
+use Error;
+use MasterError;
+use Result;
+use TableId;
+use error::RpcError;
+use rpc::Rpc;
+use rpc::connection::{
+    decode_response,
+    prepare_request_header,
+    CONNECTION_CONTEXT_CALL_ID,
+    SASL_CALL_ID,
+};
+use rpc::master;
+use util::duration_to_ms;
+
+/// A negotiated connection to a Kudu server, which sends RPCs and waits for their responses on the
+/// calling thread.
+#[derive(Debug)]
+pub struct BlockingConnection {
+    addr: SocketAddr,
+    stream: TcpStream,
+    max_message_length: u32,
+    next_call_id: i32,
+    request_header: rpc_header::RequestHeader,
+    response_header: rpc_header::ResponseHeader,
+}
+
+impl BlockingConnection {
+
+    /// Connects to the server and negotiates the connection. Fails with `Error::ConnectTimedOut`
+    /// or `Error::NegotiationTimedOut` if either step does not complete by the deadline.
+    pub fn connect(addr: SocketAddr, deadline: Instant) -> Result<BlockingConnection> {
+        let stream = try!(TcpStream::connect_timeout(&addr, try!(remaining(deadline)))
+                                    .map_err(|error| match error.kind() {
+                                        ErrorKind::TimedOut => Error::ConnectTimedOut,
+                                        _ => Error::Io(error),
+                                    }));
+        try!(stream.set_nodelay(true));
+        let mut cxn = BlockingConnection {
+            addr: addr,
+            stream: stream,
+            max_message_length: 5 * 1024 * 1024,
+            next_call_id: 0,
+            request_header: rpc_header::RequestHeader::new(),
+            response_header: rpc_header::ResponseHeader::new(),
+        };
+        try!(cxn.negotiate(deadline).map_err(|error| match error {
+            Error::TimedOut => Error::NegotiationTimedOut,
+            error => error,
+        }));
+        Ok(cxn)
+    }
+
+    pub fn addr(&self) -> &SocketAddr {
+        &self.addr
+    }
+
+    /// Sets the maximum allowable RPC response length. Defaults to 5MiB.
+    pub fn set_max_message_length(&mut self, max_message_length: u32) {
+        self.max_message_length = max_message_length;
+    }
+
+    /// Sends the RPC and waits for its response, which is decoded into the RPC. The RPC's callback
+    /// is not fired.
+    ///
+    /// If an error is returned the connection may be left part way through a message, and should
+    /// be dropped.
+    pub fn call(&mut self, rpc: &mut Rpc) -> Result<()> {
+        let timeout = try!(remaining(rpc.deadline));
+        let call_id = self.next_call_id;
+        self.next_call_id = self.next_call_id.wrapping_add(1) & i32::max_value();
+
+        prepare_request_header(&mut self.request_header,
+                               call_id,
+                               rpc.service_name,
+                               rpc.method_name,
+                               duration_to_ms(&timeout) as u32,
+                               &rpc.required_feature_flags);
+        rpc.timings.sent = Some(Instant::now());
+        let buf = try!(match rpc.serialized_request {
+            Some(ref request) => self.encode(request),
+            None => {
+                let mut request = Vec::new();
+                try!(rpc.request.write_length_delimited_to(&mut request));
+                self.encode(&request)
+            },
+        });
+        try!(self.write(&buf, rpc.deadline));
+
+        loop {
+            let body = try!(self.read_message(rpc.deadline));
+            let header_len = try!(self.read_response_header(&body));
+            let body = &body[header_len..];
+            // Responses to earlier calls which timed out may still arrive, and are skipped.
+            if self.response_header.get_call_id() != call_id {
+                continue;
+            }
+            rpc.timings.received = Some(Instant::now());
+            if self.response_header.get_is_error() {
+                return Err(Error::Rpc(RpcError::from(try!(
+                    parse_length_delimited_from::<rpc_header::ErrorStatusPB>(
+                        &mut CodedInputStream::from_bytes(body))))));
+            }
+            return decode_response(body, self.response_header.get_sidecar_offsets(), rpc);
+        }
+    }
+
+    /// Performs the connection header and SASL PLAIN handshake.
+    fn negotiate(&mut self, deadline: Instant) -> Result<()> {
+        try!(self.write(b"hrpc\x09\0\0", deadline));
+
+        let mut msg = rpc_header::SaslMessagePB::new();
+        msg.set_state(SaslState::NEGOTIATE);
+        try!(self.send_sasl(&msg, deadline));
+        let response = try!(self.recv_sasl(deadline));
+        if !response.get_auths().iter().any(|auth| auth.get_mechanism() == "PLAIN") {
+            return Err(Error::NegotiationError("SASL PLAIN authentication not available"));
+        }
+
+        let mut msg = rpc_header::SaslMessagePB::new();
+        msg.set_state(SaslState::INITIATE);
+        msg.mut_token().extend_from_slice(b"\0user\0");
+        let mut auth = rpc_header::SaslMessagePB_SaslAuth::new();
+        auth.mut_mechanism().push_str("PLAIN");
+        msg.mut_auths().push(auth);
+        try!(self.send_sasl(&msg, deadline));
+        let response = try!(self.recv_sasl(deadline));
+        if response.get_state() != SaslState::SUCCESS {
+            return Err(Error::NegotiationError("unexpected SASL message"));
+        }
+
+        self.request_header.clear();
+        self.request_header.set_call_id(CONNECTION_CONTEXT_CALL_ID);
+        let buf = try!(self.encode_message(&rpc_header::ConnectionContextPB::new()));
+        self.write(&buf, deadline)
+    }
+
+    fn send_sasl(&mut self, msg: &rpc_header::SaslMessagePB, deadline: Instant) -> Result<()> {
+        self.request_header.clear();
+        self.request_header.set_call_id(SASL_CALL_ID);
+        let buf = try!(self.encode_message(msg));
+        self.write(&buf, deadline)
+    }
+
+    fn recv_sasl(&mut self, deadline: Instant) -> Result<rpc_header::SaslMessagePB> {
+        let body = try!(self.read_message(deadline));
+        let header_len = try!(self.read_response_header(&body));
+        // All SASL messages are required to have the SASL call ID.
+        let call_id = self.response_header.get_call_id();
+        if call_id != SASL_CALL_ID {
+            return Err(RpcError::invalid_rpc_header(format!(
+                       "SASL negotiation response has call ID {}, expected {}",
+                       call_id, SASL_CALL_ID)).into());
+        }
+        if self.response_header.get_is_error() {
+            return Err(Error::Rpc(RpcError::from(try!(
+                parse_length_delimited_from::<rpc_header::ErrorStatusPB>(
+                    &mut CodedInputStream::from_bytes(&body[header_len..]))))));
+        }
+        parse_length_delimited_from(&mut CodedInputStream::from_bytes(&body[header_len..]))
+            .map_err(From::from)
+    }
+
+    /// Encodes a message which has already been length-delimited, prefixed by the request header.
+    fn encode(&self, request: &[u8]) -> Result<Vec<u8>> {
+        let header_len = self.request_header.compute_size();
+        let len = header_len + header_len.len_varint() + request.len() as u32;
+        let mut buf = Vec::with_capacity(len as usize + 4);
+        try!(buf.write_u32::<BigEndian>(len));
+        try!(self.request_header.write_length_delimited_to(&mut buf));
+        buf.extend_from_slice(request);
+        Ok(buf)
+    }
+
+    fn encode_message(&self, msg: &Message) -> Result<Vec<u8>> {
+        let mut request = Vec::new();
+        try!(msg.write_length_delimited_to(&mut request));
+        self.encode(&request)
+    }
+
+    /// Reads the response header at the start of a message into `self.response_header`, and
+    /// returns its encoded length.
+    fn read_response_header(&mut self, body: &[u8]) -> Result<usize> {
+        self.response_header.clear();
+        let mut coded_stream = CodedInputStream::from_bytes(body);
+        try!(coded_stream.merge_message(&mut self.response_header));
+        Ok(coded_stream.pos() as usize)
+    }
+
+    /// Reads a length-prefixed message, and returns it without the length prefix.
+    fn read_message(&mut self, deadline: Instant) -> Result<Vec<u8>> {
+        let mut len = [0; 4];
+        try!(self.read_exact(&mut len, deadline));
+        let len = BigEndian::read_u32(&len);
+        if len > self.max_message_length {
+            return Err(RpcError::invalid_rpc_header(format!(
+                       "RPC response message is too long; length: {}, max length: {}",
+                       len, self.max_message_length)).into());
+        }
+        let mut body = vec![0; len as usize];
+        try!(self.read_exact(&mut body, deadline));
+        Ok(body)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8], deadline: Instant) -> Result<()> {
+        try!(self.stream.set_read_timeout(Some(try!(remaining(deadline)))));
+        self.stream.read_exact(buf).map_err(io_error)
+    }
+
+    fn write(&mut self, buf: &[u8], deadline: Instant) -> Result<()> {
+        try!(self.stream.set_write_timeout(Some(try!(remaining(deadline)))));
+        self.stream.write_all(buf).map_err(io_error)
+    }
+}
+
+/// Returns the time remaining until the deadline, or `Error::TimedOut` if it has passed.
+fn remaining(deadline: Instant) -> Result<Duration> {
+    let now = Instant::now();
+    if now >= deadline {
+        return Err(Error::TimedOut);
+    }
+    Ok(deadline - now)
+}
+
+/// Socket timeouts surface as `WouldBlock` or `TimedOut` depending on the platform.
+fn io_error(error: io::Error) -> Error {
+    match error.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => Error::TimedOut,
+        _ => Error::Io(error),
+    }
+}
+
+/// A minimal blocking client for a single Kudu master, available with the `blocking` feature.
+///
+/// Unlike `Client`, the master client does not start any threads: each call is made on the calling
+/// thread over a single connection. It does not locate the leader master, so calls which must be
+/// served by the leader fail with a `NotTheLeader` master error when sent to a follower.
+///
+/// ```no_run
+/// # use std::time::{Duration, Instant};
+/// let mut master = kudu::BlockingMasterClient::connect("127.0.0.1:7051".parse().unwrap(),
+///                                                      Instant::now() + Duration::from_secs(5))
+///                                             .unwrap();
+/// for (name, id) in master.list_tables(Instant::now() + Duration::from_secs(5)).unwrap() {
+///     println!("{}: {}", name, id);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct BlockingMasterClient {
+    cxn: BlockingConnection,
+}
+
+impl BlockingMasterClient {
+
+    /// Connects to the master at the address.
+    pub fn connect(addr: SocketAddr, deadline: Instant) -> Result<BlockingMasterClient> {
+        BlockingConnection::connect(addr, deadline).map(|cxn| BlockingMasterClient { cxn: cxn })
+    }
+
+    /// Pings the master.
+    pub fn ping(&mut self, deadline: Instant) -> Result<()> {
+        let mut rpc = master::ping(*self.cxn.addr(), deadline, PingRequestPB::new());
+        self.cxn.call(&mut rpc)
+    }
+
+    /// Lists the tables in the cluster, and their IDs.
+    pub fn list_tables(&mut self, deadline: Instant) -> Result<Vec<(String, TableId)>> {
+        let mut rpc = master::list_tables(*self.cxn.addr(), deadline, ListTablesRequestPB::new());
+        try!(self.cxn.call(&mut rpc));
+        let response = rpc.response_mut();
+        if response.has_error() {
+            return Err(Error::Master(MasterError::from(response.take_error())));
+        }
+        response.take_tables().into_vec().into_iter().map(|mut table| {
+            TableId::parse_bytes(table.get_id()).map(|id| (table.take_name(), id))
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, Instant};
+
+    use mini_cluster::MiniCluster;
+    use rpc::mock_server::MockServer;
+    use super::*;
+
+    use env_logger;
+
+    fn deadline() -> Instant {
+        Instant::now() + Duration::from_secs(5)
+    }
+
+    #[test]
+    fn master_client() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::default();
+        let mut master = BlockingMasterClient::connect(cluster.master_addrs()[0], deadline()).unwrap();
+        master.ping(deadline()).unwrap();
+        assert!(master.list_tables(deadline()).unwrap().is_empty());
+
+        let addr = ::mini_cluster::get_unbound_address();
+        assert!(BlockingMasterClient::connect(addr, deadline()).is_err());
+    }
+
+    #[test]
+    fn negotiation_call_id() {
+        let _ = env_logger::init();
+        let server = MockServer::start();
+        let mut cxn = BlockingConnection::connect(server.addr(), deadline()).unwrap();
+        let mut rpc = master::ping(server.addr(), deadline(), PingRequestPB::new());
+        cxn.call(&mut rpc).unwrap();
+
+        let server = MockServer::with_sasl_call_id(33);
+        match BlockingConnection::connect(server.addr(), deadline()) {
+            Err(Error::Rpc(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use Result;
+use rpc::Rpc;
+
+/// A completed RPC call on a connection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallRecord {
+    service_name: &'static str,
+    method_name: &'static str,
+    call_id: usize,
+    result: Result<()>,
+    latency: Duration,
+}
+
+impl CallRecord {
+
+    pub fn service_name(&self) -> &'static str {
+        self.service_name
+    }
+
+    pub fn method_name(&self) -> &'static str {
+        self.method_name
+    }
+
+    /// Returns the call ID which the connection assigned to the call.
+    pub fn call_id(&self) -> usize {
+        self.call_id
+    }
+
+    /// Returns the result of the call: `Ok` if the server responded successfully, or the error
+    /// response, decode failure, or timeout which failed the call.
+    pub fn result(&self) -> &Result<()> {
+        &self.result
+    }
+
+    /// Returns the time between the call being queued in the connection and its completion.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+}
+
+/// A ring buffer of the most recently completed calls to a server.
+///
+/// The trace is shared between the messenger and the server's connections, and outlives them,
+/// so that the calls preceding a connection reset can be inspected afterwards.
+#[derive(Clone, Debug)]
+pub struct CallTrace {
+    capacity: usize,
+    calls: Arc<Mutex<VecDeque<CallRecord>>>,
+}
+
+impl CallTrace {
+
+    pub fn new(capacity: usize) -> CallTrace {
+        CallTrace {
+            capacity: capacity,
+            calls: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// Records the completion of the call, evicting the oldest call if the trace is full.
+    pub fn record(&self, rpc: &Rpc, call_id: usize, result: Result<()>) {
+        if self.capacity == 0 { return; }
+        let latency = rpc.timings.queued.map_or(Duration::from_secs(0),
+                                                |queued| Instant::now().duration_since(queued));
+        let mut calls = self.calls.lock();
+        if calls.len() == self.capacity {
+            calls.pop_front();
+        }
+        calls.push_back(CallRecord {
+            service_name: rpc.service_name,
+            method_name: rpc.method_name,
+            call_id: call_id,
+            result: result,
+            latency: latency,
+        });
+    }
+
+    /// Returns the recorded calls, oldest first.
+    pub fn recent_calls(&self) -> Vec<CallRecord> {
+        self.calls.lock().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Instant;
+
+    use kudu_pb::master::PingRequestPB;
+
+    use Error;
+    use rpc::master;
+    use super::*;
+    use util;
+
+    #[test]
+    fn test_call_trace() {
+        let trace = CallTrace::new(2);
+        assert!(trace.recent_calls().is_empty());
+
+        let rpc = master::ping(util::dummy_addr(), Instant::now(), PingRequestPB::new());
+        trace.record(&rpc, 0, Ok(()));
+        trace.record(&rpc, 1, Err(Error::WireTimedOut));
+        trace.record(&rpc, 2, Ok(()));
+
+        let calls = trace.recent_calls();
+        assert_eq!(vec![1, 2], calls.iter().map(CallRecord::call_id).collect::<Vec<_>>());
+        assert_eq!(&Err(Error::WireTimedOut), calls[0].result());
+        assert_eq!(("kudu.master.MasterService", "Ping"), (calls[0].service_name(), calls[0].method_name()));
+
+        let disabled = CallTrace::new(0);
+        disabled.record(&rpc, 0, Ok(()));
+        assert!(disabled.recent_calls().is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+use std::time::{Duration, Instant};
+
+/// The state of the circuit breaker of a server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// RPCs are sent to the server.
+    Closed,
+
+    /// The server has failed repeatedly, and RPCs to it fail immediately.
+    Open,
+
+    /// A single probe RPC has been sent to the server, and RPCs fail immediately until it
+    /// completes.
+    HalfOpen,
+}
+
+/// Tracks consecutive connection failures to a server, and decides whether RPCs may be sent to it.
+///
+/// The breaker opens after `threshold` consecutive failures. Once it has been open for
+/// `open_duration`, the next RPC is let through as a probe: if the probe succeeds the breaker
+/// closes, and if it fails the breaker opens again. If the probe neither succeeds nor fails within
+/// `open_duration`, another probe is let through.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    open_duration: Duration,
+    state: CircuitState,
+    consecutive_failures: u32,
+    /// The time at which the breaker opened, or at which the probe was sent.
+    since: Instant,
+}
+
+impl CircuitBreaker {
+
+    pub fn new(threshold: u32, open_duration: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            threshold: threshold,
+            open_duration: open_duration,
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            since: Instant::now(),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    /// Returns `true` if an RPC may be sent to the server. When the breaker has been open long
+    /// enough, the RPC is allowed as a probe, and the breaker becomes half-open.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        match self.state {
+            CircuitState::Closed => true,
+            CircuitState::Open | CircuitState::HalfOpen => {
+                if now.duration_since(self.since) < self.open_duration {
+                    return false;
+                }
+                self.state = CircuitState::HalfOpen;
+                self.since = now;
+                true
+            },
+        }
+    }
+
+    /// Records a connection failure. Returns `true` if the failure opened the breaker.
+    pub fn record_failure(&mut self, now: Instant) -> bool {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        let open = match self.state {
+            CircuitState::Closed => self.consecutive_failures >= self.threshold,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if open {
+            self.state = CircuitState::Open;
+            self.since = now;
+        }
+        open
+    }
+
+    /// Records a successful RPC. Returns `true` if the success closed the breaker.
+    pub fn record_success(&mut self) -> bool {
+        self.consecutive_failures = 0;
+        let closed = self.state != CircuitState::Closed;
+        self.state = CircuitState::Closed;
+        closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let now = Instant::now();
+        let open_duration = Duration::from_secs(5);
+        let mut breaker = CircuitBreaker::new(2, open_duration);
+        assert!(breaker.allow(now));
+
+        assert!(!breaker.record_failure(now));
+        assert!(breaker.record_failure(now));
+        assert_eq!(CircuitState::Open, breaker.state());
+        assert!(!breaker.allow(now));
+
+        // A single probe is let through once the breaker has been open long enough.
+        let later = now + open_duration;
+        assert!(breaker.allow(later));
+        assert_eq!(CircuitState::HalfOpen, breaker.state());
+        assert!(!breaker.allow(later));
+
+        // A failed probe opens the breaker again.
+        assert!(breaker.record_failure(later));
+        assert!(!breaker.allow(later));
+
+        let later = later + open_duration;
+        assert!(breaker.allow(later));
+        assert!(breaker.record_success());
+        assert_eq!(CircuitState::Closed, breaker.state());
+        assert!(breaker.allow(later));
+        assert!(!breaker.record_success());
+
+        // Successes reset the count of consecutive failures.
+        assert!(!breaker.record_failure(later));
+        breaker.record_success();
+        assert!(!breaker.record_failure(later));
+    }
+}
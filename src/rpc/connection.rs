@@ -4,19 +4,29 @@ use std::collections::hash_map::Entry;
 use std::fmt;
 use std::i32;
 use std::io::{self, ErrorKind, Write};
+use std::mem;
 use std::net::SocketAddr;
-use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::mpsc::{SyncSender, TrySendError};
 use std::time::{Duration, Instant};
 
+use ConnectionResetError;
+use DeadlineExceededError;
 use Error;
+use NegotiationFailedError;
 use Result;
 use backoff::Backoff;
-use error::RpcError;
+use error::{ErrorContext, RpcError, RpcErrorCode};
 use kudu_pb::rpc_header::{SaslMessagePB_SaslState as SaslState};
 use kudu_pb::rpc_header;
 use queue_map::QueueMap;
-use rpc::Rpc;
-use rpc::messenger::{Loop, TimeoutKind};
+use retry_budget::RetryBudget;
+use rpc::{Rpc, RpcTimings};
+use rpc::call_trace::CallTrace;
+use rpc::circuit_breaker::{CircuitBreaker, CircuitState};
+use rpc::decode_pool::DecodePool;
+use rpc::messenger::{Command, Loop, PendingTimers, TimeoutKind};
+use rpc::metrics::RpcMetrics;
 use util::duration_to_ms;
 
 use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
@@ -29,9 +39,17 @@ use mio::timer::Timeout;
 use mio::tcp::TcpStream;
 use net2::TcpBuilder;
 use netbuf::Buf;
+use parking_lot::Mutex;
 use protobuf::{parse_length_delimited_from, Clear, CodedInputStream, Message};
 use protobuf::rt::ProtobufVarint;
 
+/// The call ID of SASL negotiation requests and responses.
+pub const SASL_CALL_ID: i32 = -33;
+
+/// The call ID of the connection context, which is sent once negotiation completes, and is not
+/// answered by the server.
+pub const CONNECTION_CONTEXT_CALL_ID: i32 = -3;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ConnectionOptions {
     /// Whether to disable Nagle's algorithm.
@@ -39,12 +57,24 @@ pub struct ConnectionOptions {
     /// Defaults to true.
     pub nodelay: bool,
 
-    /// Maximum number of RPCs to queue in the connection.
+    /// Maximum number of RPCs to queue in the connection, including RPCs which are in flight.
     ///
     /// When the queue is full, additional attempts to send RPCs will immediately fail.
     ///
     /// Defaults to 256.
-    pub rpc_queue_len: u32,
+    pub max_buffered_rpcs: u32,
+
+    /// Maximum number of RPCs which have been sent to the server and are awaiting a response.
+    /// The limit is temporarily lowered while the server is throttling the connection.
+    ///
+    /// Defaults to 256.
+    pub max_rpcs_in_flight: u32,
+
+    /// Maximum number of RPCs in flight across all connections of the messenger, including RPCs
+    /// queued in connections. Additional RPCs wait in the messenger until others complete.
+    ///
+    /// Defaults to `None` (unlimited).
+    pub max_total_rpcs_in_flight: Option<u32>,
 
     /// Initial time in milliseconds to wait after an error before attempting to reconnect to the
     /// server.
@@ -62,16 +92,122 @@ pub struct ConnectionOptions {
     ///
     /// Defaults to 5 MiB.
     pub max_message_length: u32,
+
+    /// RPCs which take longer than this threshold to complete or time out are logged at `WARN`
+    /// level, along with a breakdown of the time spent queued in the client versus in the network
+    /// and server.
+    ///
+    /// Defaults to `None` (disabled).
+    pub slow_rpc_threshold: Option<Duration>,
+
+    /// Number of worker threads used to decode large responses. When zero, all responses are
+    /// decoded on the event loop thread.
+    ///
+    /// Defaults to 0.
+    pub decode_threads: usize,
+
+    /// Responses with a body at least this long are decoded on a worker thread, if
+    /// `decode_threads` is non-zero.
+    ///
+    /// Defaults to 1 MiB.
+    pub decode_offload_threshold: u32,
+
+    /// Number of event loop threads. Connections are assigned to an event loop by remote
+    /// address, so a busy connection only delays the connections sharing its event loop.
+    ///
+    /// Defaults to 1.
+    pub reactor_threads: usize,
+
+    /// Maximum time to wait for the TCP connection to be established. When exceeded, the
+    /// connection is reset with `Error::ConnectTimedOut`.
+    ///
+    /// Defaults to 10 seconds.
+    pub connect_timeout: Duration,
+
+    /// Maximum time to wait for connection negotiation to complete once the TCP connection is
+    /// established. When exceeded, the connection is reset with `Error::NegotiationTimedOut`.
+    ///
+    /// Defaults to 10 seconds.
+    pub negotiation_timeout: Duration,
+
+    /// Whether SASL negotiation responses must carry the SASL call ID. When disabled, responses
+    /// with another call ID are accepted with a warning, for servers which are known to reply
+    /// with the wrong call ID. Responses to RPC calls are always validated.
+    ///
+    /// Defaults to true.
+    pub strict_negotiation: bool,
+
+    /// Whether to record a transcript of each connection negotiation. When negotiation fails, the
+    /// connection is reset with `Error::NegotiationFailed`, which holds the transcript alongside
+    /// the cause, so that failures against servers with unexpected security settings can be
+    /// diagnosed. SASL tokens are redacted.
+    ///
+    /// Defaults to false.
+    pub negotiation_transcript: bool,
+
+    /// Maximum number of retries which the messenger's retry budget holds, or `None` for
+    /// unlimited retries. See `RetryBudget`.
+    ///
+    /// Defaults to `None`.
+    pub retry_budget: Option<u32>,
+
+    /// Percentage of a retry earned by each successful RPC when the retry budget is limited.
+    ///
+    /// Defaults to 10.
+    pub retry_budget_percent: u32,
+
+    /// Number of consecutive connection failures after which the circuit breaker of a server
+    /// opens, or `None` to disable circuit breakers. While a server's breaker is open, RPCs to it
+    /// fail immediately with `Error::CircuitOpen`.
+    ///
+    /// Defaults to `None`.
+    pub circuit_breaker_threshold: Option<u32>,
+
+    /// Time a circuit breaker stays open before a single probe RPC is sent to the server. The
+    /// breaker closes if the probe succeeds.
+    ///
+    /// Defaults to 5 seconds.
+    pub circuit_breaker_open_duration: Duration,
+
+    /// Number of recently completed calls to keep per server, along with their call ID, result
+    /// and latency, or zero to disable call traces. The calls are returned by
+    /// `Messenger::recent_calls`, and included in the alternate `Debug` output of connections.
+    ///
+    /// Defaults to 16.
+    pub call_trace_capacity: usize,
+
+    /// Name of the messenger's metrics scope, returned by `RpcMetrics::scope` and included in slow
+    /// RPC warnings, so that the metrics and logs of several clients in one process can be told
+    /// apart.
+    ///
+    /// Defaults to `None`.
+    pub metrics_scope: Option<String>,
 }
 
 impl Default for ConnectionOptions {
     fn default() -> ConnectionOptions {
         ConnectionOptions {
             nodelay: true,
-            rpc_queue_len: 256,
+            max_buffered_rpcs: 256,
+            max_rpcs_in_flight: 256,
+            max_total_rpcs_in_flight: None,
             backoff_initial: 10,
             backoff_max: 30_000,
             max_message_length: 5 * 1024 * 1024,
+            slow_rpc_threshold: None,
+            decode_threads: 0,
+            decode_offload_threshold: 1024 * 1024,
+            reactor_threads: 1,
+            connect_timeout: Duration::from_secs(10),
+            negotiation_timeout: Duration::from_secs(10),
+            strict_negotiation: true,
+            negotiation_transcript: false,
+            retry_budget: None,
+            retry_budget_percent: 10,
+            circuit_breaker_threshold: None,
+            circuit_breaker_open_duration: Duration::from_secs(5),
+            metrics_scope: None,
+            call_trace_capacity: 16,
         }
     }
 }
@@ -92,6 +228,75 @@ pub enum ConnectionState {
     Reset,
 }
 
+/// A connection lifecycle event.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConnectionEvent {
+    /// The connection is establishing a TCP connection to the server.
+    Connecting(SocketAddr),
+
+    /// The connection completed negotiation, and is ready to send RPCs.
+    Negotiated(SocketAddr),
+
+    /// The connection was reset due to an error, and will reconnect after the backoff period if
+    /// RPCs are queued.
+    Reset {
+        addr: SocketAddr,
+        error: Error,
+        backoff: Duration,
+    },
+
+    /// The connection was closed, either because it was idle after a reset, or because the
+    /// messenger shut down.
+    Shutdown(SocketAddr),
+
+    /// The server's circuit breaker opened after repeated connection failures. RPCs to the server
+    /// fail immediately until a probe RPC succeeds.
+    CircuitOpened(SocketAddr),
+
+    /// The server's circuit breaker closed after a probe RPC succeeded.
+    CircuitClosed(SocketAddr),
+}
+
+impl ConnectionEvent {
+
+    /// Returns the address of the server.
+    pub fn addr(&self) -> &SocketAddr {
+        match *self {
+            ConnectionEvent::Connecting(ref addr) |
+            ConnectionEvent::Negotiated(ref addr) |
+            ConnectionEvent::Shutdown(ref addr) |
+            ConnectionEvent::CircuitOpened(ref addr) |
+            ConnectionEvent::CircuitClosed(ref addr) => addr,
+            ConnectionEvent::Reset { ref addr, .. } => addr,
+        }
+    }
+}
+
+/// The channels to which connection events are sent, shared by every connection of a messenger.
+///
+/// Events are dropped when a channel is full, so that a slow observer can not stall the event
+/// loop. Channels are removed when their receiver is dropped.
+#[derive(Default)]
+pub struct ConnectionObservers {
+    channels: Mutex<Vec<SyncSender<ConnectionEvent>>>,
+}
+
+impl ConnectionObservers {
+
+    pub fn add(&self, channel: SyncSender<ConnectionEvent>) {
+        self.channels.lock().push(channel);
+    }
+
+    pub fn notify(&self, event: ConnectionEvent) {
+        let mut channels = self.channels.lock();
+        if channels.is_empty() { return; }
+        channels.retain(|channel| match channel.try_send(event.clone()) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
 /// Wraps an `Rpc` and a timeout timer.
 ///
 /// If the RPC completes before the timer fires, the timeout should be cleared.
@@ -122,27 +327,62 @@ impl fmt::Debug for QueuedRpc {
 ///
 /// When the connection is reset, all fail-fast RPCs will be failed with the error which caused the
 /// reset. During the reconnection backoff period, new fail-fast RPCs will be failed immediately
-/// instead of being queued.
+/// instead of being queued. Other RPCs are held until the connection is reestablished, unless
+/// their deadline expires before the backoff period ends, in which case they are timed out
+/// immediately rather than waiting out the backoff.
 ///
 /// # Back Pressure & Flow Control
 ///
 /// Internally, the connection holds a queue of pending and in-flight `Rpc`s. The queue size is
-/// limited by the `ConnectionOptions::rpc_queue_len` option. If the queue is full, then subsequent
-/// attempts to send an `Rpc` will fail with `Error::Backoff`.
+/// limited by the `ConnectionOptions::max_buffered_rpcs` option. If the queue is full, then
+/// subsequent attempts to send an `Rpc` will fail with `Error::Backoff`. Of the queued `Rpc`s, at
+/// most `ConnectionOptions::max_rpcs_in_flight` are sent to the server at once.
 ///
 /// The Kudu Tablet Server has a special error type, `Throttled`, to indicate that the server is
 /// under memory pressure and is currently unable to handle RPCs. When an RPC fails due to
 /// throttling, the `Connection` has a mechanism to artificially limit the in-flight queue, thus
 /// reducing load to the server. This backoff mechanism is a cooperative effort between the RPC
 /// sender and the `Connection`, since the error message is not part of the RPC header, and
-/// therefore is not detectable by `Connection`. See `Connection::throttle()` for details.
+/// therefore is not detectable by `Connection`. Senders which detect the error, such as the
+/// writer, throttle the connection through `Messenger::throttle`. See `Connection::throttle()` for
+/// details.
+///
+/// RPCs which are rejected by the server with a `ServerTooBusy` error are handled entirely within
+/// the connection: the connection is throttled, and the RPC is resent after a backoff period, as
+/// long as the backoff does not exceed the RPC's deadline and the messenger's retry budget is not
+/// exhausted.
 pub struct Connection {
     /// The connection options.
-    options: Rc<ConnectionOptions>,
+    options: Arc<ConnectionOptions>,
+    /// The RPC metrics, shared with the messenger.
+    metrics: Arc<RpcMetrics>,
+    /// The retry budget, shared with the messenger. Replenished by every successful RPC.
+    retry_budget: RetryBudget,
+    /// The circuit breaker of the server, shared with the messenger. `None` if circuit breakers
+    /// are disabled.
+    circuit_breaker: Option<Arc<Mutex<CircuitBreaker>>>,
+    /// Workers to which large responses are offloaded for decoding, shared with the messenger.
+    decode_pool: Option<DecodePool>,
+    /// Connection event observers, shared with the messenger.
+    observers: Arc<ConnectionObservers>,
+    /// The recently completed calls to the server, shared with the messenger.
+    call_trace: CallTrace,
+    /// The messenger's pending timers, which hold RPCs waiting to be retried.
+    timers: Arc<PendingTimers>,
     /// The current connection state.
     state: ConnectionState,
     /// The connection's TCP stream. `None` if the connection is in the `Reset` state.
     stream: Option<TcpStream>,
+    /// Whether the TCP connection has been established and negotiation has begun. Only
+    /// meaningful in the `Initiating` state.
+    negotiating: bool,
+    /// The connect or negotiation timer. Only set in the `Initiating` state.
+    initiate_timer: Option<Timeout>,
+    /// The transcript of the current negotiation. Only set in the `Initiating` state, and when
+    /// `ConnectionOptions::negotiation_transcript` is enabled.
+    transcript: Option<Vec<String>>,
+    /// The time at which the connection will attempt to reconnect. Only set in the `Reset` state.
+    reconnect: Option<Instant>,
     /// The address of the remote Kudu server.
     addr: SocketAddr,
 
@@ -151,7 +391,8 @@ pub struct Connection {
     /// RPCs which have been sent and are awaiting response.
     recv_queue: HashMap<usize, QueuedRpc>,
 
-    /// RPC request header, kept internally to reduce memory allocations.
+    /// RPC request header, kept internally to reduce memory allocations. See
+    /// `prepare_request_header`.
     request_header: rpc_header::RequestHeader,
     /// RPC response header, kept internally to reduce memory allocations.
     response_header: rpc_header::ResponseHeader,
@@ -163,18 +404,32 @@ pub struct Connection {
 
     /// Backoff tracker.
     reset_backoff: Backoff,
+    /// Backoff tracker for RPCs rejected with `ServerTooBusy`. Reset on every successful RPC.
+    busy_backoff: Backoff,
 
     /// Maximum size of recv_queue. The throttle is halved every time `Connection::throttle` is
     /// called (which should be in response to a tablet server `Throttled` error), increased by
-    /// one for every successful RPC, and bounded by `ConnectionOptions::rpc_queue_len`.
+    /// one for every successful RPC, and bounded by `ConnectionOptions::max_rpcs_in_flight`.
     throttle: u32,
 }
 
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.observers.notify(ConnectionEvent::Shutdown(self.addr));
+    }
+}
+
 impl fmt::Debug for Connection {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Connection {{ state: {:?}, addr: {}, queue (tx/rx): {}/{}, buf (tx/rx): {}/{} }}",
-               self.state, self.addr, self.send_queue.len(), self.recv_queue.len(),
-               self.send_buf.len(), self.recv_buf.len())
+        try!(write!(f, "Connection {{ state: {:?}, addr: {}, queue (tx/rx): {}/{}, buf (tx/rx): {}/{}",
+                    self.state, self.addr, self.send_queue.len(), self.recv_queue.len(),
+                    self.send_buf.len(), self.recv_buf.len()));
+        // The recent calls are only included in the alternate format, since the connection is
+        // logged on every event.
+        if f.alternate() {
+            try!(write!(f, ", recent calls: {:?}", self.call_trace.recent_calls()));
+        }
+        write!(f, " }}")
     }
 }
 
@@ -186,14 +441,33 @@ impl Connection {
     pub fn new(event_loop: &mut Loop,
                token: Token,
                addr: SocketAddr,
-               options: Rc<ConnectionOptions>)
+               options: Arc<ConnectionOptions>,
+               metrics: Arc<RpcMetrics>,
+               retry_budget: RetryBudget,
+               circuit_breaker: Option<Arc<Mutex<CircuitBreaker>>>,
+               decode_pool: Option<DecodePool>,
+               observers: Arc<ConnectionObservers>,
+               call_trace: CallTrace,
+               timers: Arc<PendingTimers>)
                -> Connection {
         let reset_backoff = Backoff::with_duration_range(options.backoff_initial, options.backoff_max);
-        let throttle = options.rpc_queue_len;
+        let busy_backoff = Backoff::with_duration_range(options.backoff_initial, options.backoff_max);
+        let throttle = options.max_rpcs_in_flight;
         let mut connection = Connection {
             options: options,
+            metrics: metrics,
+            retry_budget: retry_budget,
+            circuit_breaker: circuit_breaker,
+            decode_pool: decode_pool,
+            observers: observers,
+            call_trace: call_trace,
+            timers: timers,
             state: ConnectionState::Initiating,
             stream: None,
+            negotiating: false,
+            initiate_timer: None,
+            transcript: None,
+            reconnect: None,
             addr: addr,
             send_queue: QueueMap::new(),
             recv_queue: HashMap::new(),
@@ -202,6 +476,7 @@ impl Connection {
             recv_buf: Buf::new(),
             send_buf: Buf::new(),
             reset_backoff: reset_backoff,
+            busy_backoff: busy_backoff,
             throttle: throttle,
         };
         connection.connect(event_loop, token);
@@ -214,9 +489,15 @@ impl Connection {
 
     /// Notifies the connection of socket events.
     pub fn ready(&mut self, event_loop: &mut Loop, token: Token, events: Ready) {
-        fn inner(cxn: &mut Connection, event_loop: &mut Loop, events: Ready) -> Result<()> {
+        fn inner(cxn: &mut Connection, event_loop: &mut Loop, token: Token, events: Ready) -> Result<()> {
             match cxn.state {
                 ConnectionState::Initiating => {
+                    if !cxn.negotiating && (events.is_readable() || events.is_writable()) {
+                        // The TCP connection is established; switch to the negotiation timeout.
+                        cxn.negotiating = true;
+                        let timeout = cxn.options.negotiation_timeout;
+                        cxn.set_initiate_timer(event_loop, token, timeout);
+                    }
                     if events.is_readable() {
                         assert!(!events.is_writable());
                         assert!(cxn.send_buf.is_empty());
@@ -243,7 +524,7 @@ impl Connection {
         if events.is_error() || events.is_hup() {
             self.reset(event_loop, token, Error::ConnectionError);
         } else {
-            inner(self, event_loop, events).and_then(|_| self.reregister(event_loop, token))
+            inner(self, event_loop, token, events).and_then(|_| self.reregister(event_loop, token))
                                            .unwrap_or_else(|error| {
                                                info!("{:?} error: {}", self, &error);
                                                self.reset(event_loop, token, error)
@@ -252,7 +533,7 @@ impl Connection {
     }
 
     /// Send an RPC to the Kudu server.
-    pub fn send_rpc(&mut self, event_loop: &mut Loop, token: Token, rpc: Rpc) {
+    pub fn send_rpc(&mut self, event_loop: &mut Loop, token: Token, mut rpc: Rpc) {
 
         let now = Instant::now();
         if rpc.cancelled() {
@@ -260,15 +541,24 @@ impl Connection {
             return rpc.fail(Error::Cancelled);
         } else if rpc.timed_out(now) {
             trace!("{:?}: rpc timed out before queue: {:?}", self, rpc);
-            return rpc.fail(Error::TimedOut);
-        } else if self.queue_len() > self.options.rpc_queue_len as usize ||
-                  self.queue_len() > self.throttle as usize {
+            return rpc.fail(Error::QueueTimedOut);
+        } else if self.queue_len() >= self.options.max_buffered_rpcs as usize {
             trace!("{:?}: rpc failed due to backoff: {:?}", self, rpc);
             return rpc.fail(Error::Backoff);
+        } else if self.reconnect.map_or(false, |reconnect| rpc.deadline <= reconnect) {
+            trace!("{:?}: rpc would time out before reconnect: {:?}", self, rpc);
+            return rpc.fail(Error::QueueTimedOut);
         }
 
         trace!("{:?}: queueing rpc: {:?}", self, rpc);
 
+        rpc.timings = RpcTimings {
+            queued: Some(now),
+            sent: None,
+            received: None,
+            first_queued: rpc.timings.first_queued.or(Some(now)),
+            attempts: rpc.timings.attempts,
+        };
         self.send_queue.push_with(|call_id| {
             let timer = event_loop.timeout(TimeoutKind::Rpc(token, call_id),
                                            rpc.deadline.duration_since(now));
@@ -288,15 +578,52 @@ impl Connection {
         }
     }
 
+    /// Fails every queued and in-flight RPC with `Error::Shutdown`, in preparation for the event
+    /// loop shutting down.
+    pub fn shutdown(&mut self, event_loop: &mut Loop) {
+        self.clear_initiate_timer(event_loop);
+        for (_, QueuedRpc { rpc, timer }) in self.recv_queue.drain().chain(self.send_queue.drain()) {
+            event_loop.clear_timeout(&timer);
+            rpc.fail(Error::Shutdown);
+        }
+    }
+
     pub fn rpc_timeout(&mut self, call_id: usize) {
         trace!("{:?}: rpc_timeout for call_id: {}", self, call_id);
         // No need to cancel the timeout here, since it fired.
-        self.send_queue
-            .remove(call_id)
-            .or_else(|| self.recv_queue.remove(&call_id))
-            .expect("timed out RPC not found in send or recv queue")
-            .rpc
-            .fail(Error::TimedOut);
+        let rpc = self.send_queue
+                      .remove(call_id)
+                      .or_else(|| self.recv_queue.remove(&call_id))
+                      .expect("timed out RPC not found in send or recv queue")
+                      .rpc;
+        self.call_trace.record(&rpc, call_id, Err(rpc.timeout_error()));
+        log_timed_out_rpc(&self.options, &self.metrics, &rpc);
+        rpc.time_out();
+    }
+
+    /// Replaces the connect or negotiation timer.
+    fn set_initiate_timer(&mut self, event_loop: &mut Loop, token: Token, timeout: Duration) {
+        if let Some(timer) = self.initiate_timer.take() {
+            event_loop.clear_timeout(&timer);
+        }
+        self.initiate_timer = event_loop.timeout(TimeoutKind::Initiate(token), timeout).ok();
+    }
+
+    /// Clears the connect or negotiation timer.
+    fn clear_initiate_timer(&mut self, event_loop: &mut Loop) {
+        if let Some(timer) = self.initiate_timer.take() {
+            event_loop.clear_timeout(&timer);
+        }
+    }
+
+    /// Handles the expiration of the connect or negotiation timer by resetting the connection.
+    pub fn initiate_timeout(&mut self, event_loop: &mut Loop, token: Token) {
+        self.initiate_timer = None;
+        if self.state != ConnectionState::Initiating {
+            return;
+        }
+        let error = if self.negotiating { Error::NegotiationTimedOut } else { Error::ConnectTimedOut };
+        self.reset(event_loop, token, error);
     }
 
     pub fn reset_timeout(&mut self, event_loop: &mut Loop, token: Token) -> bool {
@@ -315,15 +642,62 @@ impl Connection {
         false
     }
 
+    /// Halves the number of RPCs which may be in flight on the connection. The limit is raised by
+    /// one for each subsequent successful RPC, until it reaches
+    /// `ConnectionOptions::max_rpcs_in_flight`.
     pub fn throttle(&mut self) {
-        self.throttle = cmp::min(self.throttle, self.options.rpc_queue_len) / 2;
+        debug!("{:?}: throttling", self);
+        // Never throttle all the way to zero, otherwise queued RPCs would never be sent.
+        self.throttle = cmp::max(1, cmp::min(self.throttle, self.options.max_rpcs_in_flight) / 2);
+    }
+
+    /// Raises the throttle, replenishes the retry budget, and closes the circuit breaker after a
+    /// successful RPC.
+    fn unthrottle(&mut self) {
+        self.throttle = cmp::min(self.throttle + 1, self.options.max_rpcs_in_flight);
+        self.retry_budget.record_success();
+        if self.circuit_breaker.as_ref().map_or(false, |breaker| breaker.lock().record_success()) {
+            info!("{:?}: circuit breaker closed", self);
+            self.observers.notify(ConnectionEvent::CircuitClosed(self.addr));
+        }
+    }
+
+    /// Throttles the connection and resends an RPC which the server rejected with
+    /// `ServerTooBusy` after a backoff period. If the backoff would exceed the RPC's deadline, the
+    /// RPC fails with `Error::DeadlineExceeded`, including the rejection of each attempt.
+    fn retry_too_busy(&mut self, event_loop: &mut Loop, mut rpc: Rpc, error: Error) {
+        self.throttle();
+        rpc.failed_attempts.push(error);
+        let backoff = Duration::from_millis(self.busy_backoff.next_backoff_ms());
+        if Instant::now() + backoff >= rpc.deadline {
+            trace!("{:?}: server too busy, timing out {:?}", self, rpc);
+            let attempts = mem::replace(&mut rpc.failed_attempts, Vec::new());
+            return rpc.fail(Error::DeadlineExceeded(DeadlineExceededError::new(attempts)));
+        }
+
+        debug!("{:?}: server too busy, retrying {:?} in {:?}", self, rpc, backoff);
+        self.metrics.record_server_too_busy_retry();
+        let channel = event_loop.channel();
+        let timers = self.timers.clone();
+        let id = self.timers.add(Box::new(move || {
+            // The timer fired early because the messenger is shutting down.
+            if timers.is_shut_down() {
+                return rpc.fail(Error::Shutdown);
+            }
+            if let Err(error) = channel.send(Command::Send(rpc)) {
+                warn!("unable to retry RPC after ServerTooBusy: {:?}", error);
+            }
+        }));
+        event_loop.timeout(TimeoutKind::Timer(id), backoff).unwrap();
     }
 
     /// Connects an inactive connection to the server.
     fn connect(&mut self, event_loop: &mut Loop, token: Token) {
         fn inner(cxn: &mut Connection, event_loop: &mut Loop, token: Token) -> Result<()> {
             assert!(cxn.recv_queue.is_empty());
+            cxn.reconnect = None;
             debug!("{:?}: connecting", cxn);
+            cxn.observers.notify(ConnectionEvent::Connecting(cxn.addr));
 
             // Create the stream via the net2 StreamBuilder API so that set_nodelay can be called
             // before connect as a workaround to mio#446. This is mostly copied from mio.
@@ -353,6 +727,15 @@ impl Connection {
 
             cxn.stream = Some(try!(TcpStream::connect_stream(stream, &cxn.addr)));
             cxn.state = ConnectionState::Initiating;
+            cxn.negotiating = false;
+            cxn.transcript = if cxn.options.negotiation_transcript {
+                // The client does not implement TLS, so it is never offered to the server.
+                Some(vec![format!("connecting to {}; TLS: not supported by client", cxn.addr)])
+            } else {
+                None
+            };
+            let timeout = cxn.options.connect_timeout;
+            cxn.set_initiate_timer(event_loop, token, timeout);
 
             // Write the connection header and SASL negotiation messages to the send buffer.
             try!(cxn.buffer_connection_header());
@@ -390,9 +773,26 @@ impl Connection {
     }
 
     /// Resets the connection following an error.
+    ///
+    /// Queued RPCs which are not failed fast are retried once the connection is reestablished after
+    /// the backoff period, unless their deadline falls within the backoff period, in which case
+    /// they are timed out immediately. RPCs failed by the reset are recorded in the call trace.
     fn reset(&mut self, event_loop: &mut Loop, token: Token, error: Error) {
+        let error = match self.transcript.take() {
+            Some(transcript) if self.state == ConnectionState::Initiating && self.negotiating => {
+                Error::NegotiationFailed(NegotiationFailedError::new(error, transcript))
+            },
+            _ => error,
+        };
         let backoff_ms = self.reset_backoff.next_backoff_ms();
+        let backoff = Duration::from_millis(backoff_ms);
         warn!("{:?}: reset, error: {}, backoff: {}ms", self, error, backoff_ms);
+        self.observers.notify(ConnectionEvent::Reset {
+            addr: self.addr,
+            error: error.clone(),
+            backoff: backoff,
+        });
+        self.clear_initiate_timer(event_loop);
         self.state = ConnectionState::Reset;
         self.stream.take();
         let recv_buf_len = self.recv_buf.len();
@@ -401,18 +801,62 @@ impl Connection {
         self.send_buf.consume(send_buf_len);
 
         let now = Instant::now();
+        let reconnect = now + backoff;
+        self.reconnect = Some(reconnect);
+
+        // Once the circuit breaker is open, queued RPCs are failed with the reset rather than
+        // held for the reconnect. Only network failures count towards opening the breaker, since
+        // a protocol error does not mean that the server is unavailable.
+        let circuit_open = match self.circuit_breaker {
+            Some(ref breaker) => {
+                let mut breaker = breaker.lock();
+                if error.is_network_error() && breaker.record_failure(now) {
+                    warn!("{:?}: circuit breaker opened", self);
+                    self.metrics.record_circuit_breaker_trip();
+                    self.observers.notify(ConnectionEvent::CircuitOpened(self.addr));
+                }
+                breaker.state() != CircuitState::Closed
+            },
+            None => false,
+        };
         let mut retries = Vec::new();
-        for (call_id, QueuedRpc { rpc, timer }) in self.recv_queue.drain().chain(self.send_queue.drain()) {
+        for (call_id, QueuedRpc { mut rpc, timer }) in self.recv_queue.drain().chain(self.send_queue.drain()) {
+            rpc.timings.attempts += 1;
             if rpc.cancelled() {
                 event_loop.clear_timeout(&timer);
                 rpc.fail(Error::Cancelled);
+                continue;
             } else if rpc.timed_out(now) {
                 event_loop.clear_timeout(&timer);
-                rpc.fail(Error::TimedOut);
-            } else if rpc.fail_fast() {
+                self.call_trace.record(&rpc, call_id, Err(rpc.timeout_error()));
+                rpc.time_out();
+                continue;
+            }
+
+            let elapsed = rpc.timings.first_queued.map_or(Duration::from_secs(0),
+                                                          |queued| now.duration_since(queued));
+            let reset = Error::ConnectionReset(ConnectionResetError::new(error.clone(),
+                                                                         rpc.timings.attempts,
+                                                                         elapsed));
+            if rpc.fail_fast() || circuit_open {
+                event_loop.clear_timeout(&timer);
+                self.call_trace.record(&rpc, call_id, Err(reset.clone()));
+                rpc.fail(reset);
+            } else if rpc.deadline <= reconnect {
+                trace!("{:?}: rpc would time out before reconnect: {:?}", self, rpc);
                 event_loop.clear_timeout(&timer);
-                rpc.fail(error.clone());
+                rpc.timings.sent = None;
+                rpc.failed_attempts.push(reset);
+                self.call_trace.record(&rpc, call_id, Err(rpc.timeout_error()));
+                rpc.time_out();
+            } else if !self.retry_budget.try_retry() {
+                trace!("{:?}: retry budget exhausted, failing {:?}", self, rpc);
+                event_loop.clear_timeout(&timer);
+                self.call_trace.record(&rpc, call_id, Err(reset.clone()));
+                rpc.fail(reset);
             } else {
+                rpc.timings.sent = None;
+                rpc.failed_attempts.push(reset);
                 retries.push((call_id, QueuedRpc { rpc: rpc, timer: timer }));
             }
         }
@@ -422,8 +866,22 @@ impl Connection {
         }
         trace!("{:?}: retrying rpcs: {:?}", self, self.send_queue);
 
-        event_loop.timeout(TimeoutKind::ConnectionReset(token),
-                           Duration::from_millis(backoff_ms)).unwrap();
+        event_loop.timeout(TimeoutKind::ConnectionReset(token), backoff).unwrap();
+    }
+
+    /// Writes an RPC request to the send buffer. If the request has been serialized ahead of time
+    /// it is copied as-is.
+    fn buffer_rpc(&mut self, rpc: &Rpc) -> Result<()> {
+        let request = match rpc.serialized_request {
+            Some(ref request) => request,
+            None => return self.buffer_message(&*rpc.request),
+        };
+        let header_len = self.request_header.compute_size();
+        let len = header_len + header_len.len_varint() + request.len() as u32;
+        try!(self.send_buf.write_u32::<BigEndian>(len));
+        try!(self.request_header.write_length_delimited_to(&mut self.send_buf));
+        try!(self.send_buf.write_all(request));
+        Ok(())
     }
 
     /// Writes the message to the send buffer with a request header.
@@ -458,9 +916,10 @@ impl Connection {
     fn buffer_sasl_negotiation(&mut self) -> Result<()> {
         trace!("{:?}: sending SASL NEGOTIATE request to server", self);
         self.request_header.clear();
-        self.request_header.set_call_id(-33);
+        self.request_header.set_call_id(SASL_CALL_ID);
         let mut msg = rpc_header::SaslMessagePB::new();
         msg.set_state(SaslState::NEGOTIATE);
+        self.record_sasl_message("sent", &msg);
         self.buffer_message(&msg)
     }
 
@@ -472,13 +931,14 @@ impl Connection {
     fn buffer_sasl_initiate(&mut self) -> Result<()> {
         trace!("{:?}: sending SASL INITIATE request to server", self);
         self.request_header.clear();
-        self.request_header.set_call_id(-33);
+        self.request_header.set_call_id(SASL_CALL_ID);
         let mut msg = rpc_header::SaslMessagePB::new();
         msg.set_state(SaslState::INITIATE);
         msg.mut_token().extend_from_slice(b"\0user\0");
         let mut auth = rpc_header::SaslMessagePB_SaslAuth::new();
         auth.mut_mechanism().push_str("PLAIN");
         msg.mut_auths().push(auth);
+        self.record_sasl_message("sent", &msg);
         self.buffer_message(&msg)
     }
 
@@ -490,17 +950,25 @@ impl Connection {
     fn buffer_connection_context(&mut self) -> Result<()> {
         trace!("{:?}: sending connection context to server", self);
         self.request_header.clear();
-        self.request_header.set_call_id(-3);
+        self.request_header.set_call_id(CONNECTION_CONTEXT_CALL_ID);
         let msg = rpc_header::ConnectionContextPB::new();
         self.buffer_message(&msg)
     }
 
+    /// Records the SASL message in the negotiation transcript, if one is being kept.
+    fn record_sasl_message(&mut self, direction: &str, msg: &rpc_header::SaslMessagePB) {
+        if let Some(ref mut transcript) = self.transcript {
+            transcript.push(describe_sasl_message(direction, msg));
+        }
+    }
+
     /// Handles a SASL handshake response message.
     fn handle_sasl_message(&mut self,
                            event_loop: &mut Loop,
                            msg: rpc_header::SaslMessagePB)
                            -> Result<()> {
         trace!("{:?}: received SASL {:?} response from server", self, msg.get_state());
+        self.record_sasl_message("received", &msg);
         match msg.get_state() {
             SaslState::NEGOTIATE => {
                 if msg.get_auths().iter().any(|auth| auth.get_mechanism() == "PLAIN") {
@@ -513,7 +981,11 @@ impl Connection {
             },
             SaslState::SUCCESS => {
                 try!(self.buffer_connection_context());
+                self.clear_initiate_timer(event_loop);
                 self.state = ConnectionState::Connected;
+                self.transcript = None;
+                self.metrics.record_negotiation();
+                self.observers.notify(ConnectionEvent::Negotiated(self.addr));
                 self.reset_backoff.reset();
 
                 // Optimistically flush the connection context and send any queued messages. The
@@ -577,17 +1049,30 @@ impl Connection {
 
             match self.state {
                 ConnectionState::Initiating => {
-                    // All SASL messages are required to have call ID -33.
-                    debug_assert_eq!(-33, self.response_header.get_call_id());
+                    // All SASL messages are required to have the SASL call ID.
+                    let call_id = self.response_header.get_call_id();
+                    if call_id != SASL_CALL_ID {
+                        if self.options.strict_negotiation {
+                            return Err(RpcError::invalid_rpc_header(format!(
+                                       "SASL negotiation response has call ID {}, expected {}",
+                                       call_id, SASL_CALL_ID)).into());
+                        }
+                        warn!("{:?}: SASL negotiation response has call ID {}, expected {}",
+                              self, call_id, SASL_CALL_ID);
+                    }
                     // Only one response should be in flight during SASL negotiation.
                     debug_assert_eq!(msg_len - header_len, self.recv_buf.len());
 
                     if self.response_header.get_is_error() {
                         // All errors during SASL negotiation should result in tearing down the
                         // connection.
-                        return Err(Error::Rpc(RpcError::from(try!(
-                                        parse_length_delimited_from::<rpc_header::ErrorStatusPB>(
-                                            &mut CodedInputStream::from_bytes(&self.recv_buf[..]))))));
+                        let error = RpcError::from(try!(
+                                parse_length_delimited_from::<rpc_header::ErrorStatusPB>(
+                                    &mut CodedInputStream::from_bytes(&self.recv_buf[..]))));
+                        if let Some(ref mut transcript) = self.transcript {
+                            transcript.push(format!("received error: {}", error));
+                        }
+                        return Err(Error::Rpc(error));
                     }
 
                     let sasl_msg = try!(parse_length_delimited_from(
@@ -596,21 +1081,72 @@ impl Connection {
                 },
                 ConnectionState::Connected => {
                     trace!("{:?}: received response from server: {:?}", self, self.response_header);
+                    // Negative call IDs are reserved for negotiation, and are never assigned to
+                    // RPC calls.
+                    if self.response_header.get_call_id() < 0 {
+                        return Err(RpcError::invalid_rpc_header(format!(
+                                   "RPC response has reserved call ID {}",
+                                   self.response_header.get_call_id())).into());
+                    }
                     if self.response_header.get_is_error() {
                         let error = RpcError::from(try!(
                                 parse_length_delimited_from::<rpc_header::ErrorStatusPB>(
                                     &mut CodedInputStream::from_bytes(&self.recv_buf[..msg_len - header_len]))));
                         // Remove the RPC from the recv queue, and fail it. The message may not be
                         // in the recv queue if it has already timed out.
-                        if let Some(QueuedRpc { rpc, timer }) = self.recv_queue.remove(&(self.response_header.get_call_id() as usize)) {
+                        let call_id = self.response_header.get_call_id();
+                        if let Some(QueuedRpc { mut rpc, timer }) = self.recv_queue.remove(&(call_id as usize)) {
                             event_loop.clear_timeout(&timer);
-                            rpc.fail(Error::Rpc(error.clone()));
+                            rpc.timings.received = Some(Instant::now());
+                            record_timings(&self.options, &self.metrics, &rpc);
+                            self.call_trace.record(&rpc, call_id as usize, Err(Error::Rpc(error.clone())));
+                            let context = ErrorContext::new(rpc.service_name,
+                                                            rpc.method_name,
+                                                            call_id,
+                                                            self.addr,
+                                                            rpc.timings.processing_time()
+                                                               .unwrap_or(Duration::from_secs(0)));
+                            let rpc_error = Error::Rpc(error.clone().with_context(context));
+                            if error.code() == RpcErrorCode::ServerTooBusy &&
+                               self.retry_budget.try_retry() {
+                                self.retry_too_busy(event_loop, rpc, rpc_error);
+                                self.recv_buf.consume(msg_len - header_len);
+                                continue;
+                            }
+                            rpc.fail(rpc_error);
                         }
                         // If the message is fatal, then return an error in order to have the
                         // connection torn down.
                         if error.is_fatal() {
                             return Err(Error::Rpc(error.clone()))
                         }
+                    } else if self.decode_pool.is_some() &&
+                              msg_len - header_len >= self.options.decode_offload_threshold as usize {
+                        // Large responses are decoded on a worker thread. A decode failure fails
+                        // the RPC rather than resetting the connection.
+                        let call_id = self.response_header.get_call_id() as usize;
+                        if let Some(QueuedRpc { mut rpc, timer }) = self.recv_queue.remove(&call_id) {
+                            event_loop.clear_timeout(&timer);
+                            rpc.timings.received = Some(Instant::now());
+                            record_timings(&self.options, &self.metrics, &rpc);
+                            let body = self.recv_buf[..msg_len - header_len].to_owned();
+                            let offsets = self.response_header.get_sidecar_offsets().to_owned();
+                            let metrics = self.metrics.clone();
+                            let call_trace = self.call_trace.clone();
+                            self.decode_pool.as_ref().unwrap().execute(move || {
+                                let (service_name, method_name) = (rpc.service_name, rpc.method_name);
+                                let result = metrics.measure_response(service_name, method_name, || {
+                                    decode_response(&body, &offsets, &mut rpc)
+                                });
+                                call_trace.record(&rpc, call_id, result.clone());
+                                match result {
+                                    Ok(()) => rpc.complete(),
+                                    Err(error) => rpc.fail(error),
+                                }
+                            });
+                            self.unthrottle();
+                            self.busy_backoff.reset();
+                        }
                     } else if let Entry::Occupied(mut entry) = self.recv_queue.entry(self.response_header.get_call_id() as usize) {
                         // Use the entry API so that the RPC is not removed from the recv queue
                         // if the protobuf decode step fails. Since it isn't removed, it has the
@@ -618,17 +1154,24 @@ impl Connection {
                         // connection is reset.
                         //
                         // The message may not be in the recv queue if it has already timed out.
-                        try!(CodedInputStream::from_bytes(&self.recv_buf[..msg_len - header_len])
-                             .merge_message(&mut *entry.get_mut().rpc.response));
-
-                        if !self.response_header.get_sidecar_offsets().is_empty() {
-                            panic!("sidecar decoding not implemented");
-                        }
-
-                        let QueuedRpc { rpc, timer } = entry.remove();
+                        let (service_name, method_name) = {
+                            let rpc = &entry.get().rpc;
+                            (rpc.service_name, rpc.method_name)
+                        };
+                        let body = &self.recv_buf[..msg_len - header_len];
+                        let offsets = self.response_header.get_sidecar_offsets();
+                        try!(self.metrics.measure_response(service_name, method_name, || {
+                            decode_response(body, offsets, &mut entry.get_mut().rpc)
+                        }));
+
+                        let QueuedRpc { mut rpc, timer } = entry.remove();
                         event_loop.clear_timeout(&timer);
+                        rpc.timings.received = Some(Instant::now());
+                        record_timings(&self.options, &self.metrics, &rpc);
+                        self.call_trace.record(&rpc, self.response_header.get_call_id() as usize, Ok(()));
                         rpc.complete();
-                        self.throttle += 1;
+                        self.unthrottle();
+                        self.busy_backoff.reset();
                     }
                 },
                 _ => unreachable!("{:?}: recv"),
@@ -653,7 +1196,7 @@ impl Connection {
         let now = Instant::now();
         while !self.send_buf.is_empty() || self.can_send() {
             while self.send_buf.len() < 4096 && self.can_send() {
-                let (call_id, QueuedRpc { rpc, timer }) = self.send_queue.pop().unwrap();
+                let (call_id, QueuedRpc { mut rpc, timer }) = self.send_queue.pop().unwrap();
 
                 if rpc.cancelled() {
                     trace!("{:?}: cancelling {:?}", self, rpc);
@@ -663,7 +1206,8 @@ impl Connection {
                 } else if rpc.timed_out(now) {
                     trace!("{:?}: timing out {:?}", self, rpc);
                     event_loop.clear_timeout(&timer);
-                    rpc.fail(Error::TimedOut);
+                    log_timed_out_rpc(&self.options, &self.metrics, &rpc);
+                    rpc.time_out();
                     break;
                 }
 
@@ -672,15 +1216,27 @@ impl Connection {
                     return Err(Error::ConnectionError);
                 }
 
-                self.request_header.clear();
-                self.request_header.set_call_id(call_id as i32);
-                self.request_header.mut_remote_method().mut_service_name().push_str(rpc.service_name);
-                self.request_header.mut_remote_method().mut_method_name().push_str(rpc.method_name);
-                self.request_header.set_timeout_millis(duration_to_ms(&rpc.deadline.duration_since(now)) as u32);
-                self.request_header.mut_required_feature_flags().extend_from_slice(&rpc.required_feature_flags);
+                prepare_request_header(&mut self.request_header,
+                                       call_id as i32,
+                                       rpc.service_name,
+                                       rpc.method_name,
+                                       duration_to_ms(&rpc.deadline.duration_since(now)) as u32,
+                                       &rpc.required_feature_flags);
+                match rpc.request_id {
+                    Some(ref request_id) => {
+                        let mut request_id = request_id.clone();
+                        let attempt_no = request_id.get_attempt_no() + rpc.timings.attempts as i64;
+                        request_id.set_attempt_no(attempt_no);
+                        self.request_header.set_request_id(request_id);
+                    },
+                    None => self.request_header.clear_request_id(),
+                }
 
                 trace!("{:?}: sending rpc to server; call ID: {}, rpc: {:?}", self, call_id, rpc);
-                try!(self.buffer_message(&*rpc.request));
+                let metrics = self.metrics.clone();
+                try!(metrics.measure_request(rpc.service_name, rpc.method_name,
+                                             || self.buffer_rpc(&rpc)));
+                rpc.timings.sent = Some(now);
                 self.recv_queue.insert(call_id, QueuedRpc { rpc: rpc, timer: timer });
             }
 
@@ -745,3 +1301,328 @@ impl Connection {
         event_set
     }
 }
+
+/// Decodes a response body into the RPC's response message and sidecars.
+///
+/// The body is the varint length of the response message, followed by the message and the
+/// sidecars. Sidecars are located by their offsets from the end of the varint.
+pub fn decode_response(body: &[u8], offsets: &[u32], rpc: &mut Rpc) -> Result<()> {
+    let (base, message_len) = {
+        let mut coded_stream = CodedInputStream::from_bytes(body);
+        let message_len = try!(coded_stream.read_raw_varint32()) as usize;
+        (coded_stream.pos() as usize, message_len)
+    };
+    let message_end = offsets.first().map_or(body.len(), |&offset| base + offset as usize);
+    if message_end > body.len() || base + message_len > message_end {
+        return Err(RpcError::invalid_rpc_header(format!(
+                   "RPC sidecar offset out of bounds; offsets: {:?}, message length: {}, body length: {}",
+                   offsets, message_len, body.len())).into());
+    }
+    try!(CodedInputStream::from_bytes(&body[..message_end]).merge_message(&mut *rpc.response));
+
+    rpc.sidecars.clear();
+    for (idx, &offset) in offsets.iter().enumerate() {
+        let start = base + offset as usize;
+        let end = offsets.get(idx + 1).map_or(body.len(), |&offset| base + offset as usize);
+        if start > end || end > body.len() {
+            return Err(RpcError::invalid_rpc_header(format!(
+                       "RPC sidecar offsets out of bounds; offsets: {:?}, body length: {}",
+                       offsets, body.len())).into());
+        }
+        rpc.sidecars.push(body[start..end].to_owned());
+    }
+    Ok(())
+}
+
+/// Fills in the request header of an RPC call, reusing the header's allocations.
+///
+/// Consecutive calls on a connection usually target the same method, so the remote method is only
+/// rewritten when it changes. Otherwise sending an RPC copies the service and method names into
+/// the header and recomputes their encoded lengths.
+pub fn prepare_request_header(header: &mut rpc_header::RequestHeader,
+                              call_id: i32,
+                              service_name: &str,
+                              method_name: &str,
+                              timeout_millis: u32,
+                              required_feature_flags: &[u32]) {
+    header.set_call_id(call_id);
+    if !header.has_remote_method() ||
+       header.get_remote_method().get_service_name() != service_name ||
+       header.get_remote_method().get_method_name() != method_name {
+        let remote_method = header.mut_remote_method();
+        remote_method.mut_service_name().clear();
+        remote_method.mut_service_name().push_str(service_name);
+        remote_method.mut_method_name().clear();
+        remote_method.mut_method_name().push_str(method_name);
+    }
+    header.set_timeout_millis(timeout_millis);
+    header.mut_required_feature_flags().clear();
+    header.mut_required_feature_flags().extend_from_slice(required_feature_flags);
+}
+
+/// Records the timings of a completed RPC in the metrics, and logs a warning if the RPC took
+/// longer than the configured slow RPC threshold to complete.
+fn record_timings(options: &ConnectionOptions, metrics: &RpcMetrics, rpc: &Rpc) {
+    let (queue_time, processing_time) = match (rpc.timings.queue_time(),
+                                               rpc.timings.processing_time()) {
+        (Some(queue_time), Some(processing_time)) => (queue_time, processing_time),
+        _ => return,
+    };
+    metrics.record_rpc_timings(queue_time, processing_time);
+
+    if let Some(threshold) = options.slow_rpc_threshold {
+        let elapsed = queue_time + processing_time;
+        if elapsed >= threshold {
+            let scope = metrics.scope().map_or(String::new(), |scope| format!(" in scope {}", scope));
+            warn!("slow RPC {}::{} to {}{}: elapsed: {:?}, queue time: {:?}, network and server time: {:?}",
+                  rpc.service_name, rpc.method_name, rpc.addr, scope, elapsed, queue_time,
+                  processing_time);
+        }
+    }
+}
+
+/// Logs a warning if an RPC which timed out had been queued for longer than the configured slow
+/// RPC threshold. Its timings are not recorded in the metrics, since no response was received.
+fn log_timed_out_rpc(options: &ConnectionOptions, metrics: &RpcMetrics, rpc: &Rpc) {
+    let (threshold, queued) = match (options.slow_rpc_threshold, rpc.timings.queued) {
+        (Some(threshold), Some(queued)) => (threshold, queued),
+        _ => return,
+    };
+    let now = Instant::now();
+    let elapsed = now.duration_since(queued);
+    if elapsed < threshold {
+        return;
+    }
+    let scope = metrics.scope().map_or(String::new(), |scope| format!(" in scope {}", scope));
+    match rpc.timings.sent {
+        Some(sent) => {
+            warn!("slow RPC {}::{} to {}{} timed out: elapsed: {:?}, queue time: {:?}, \
+                   network and server time: {:?}",
+                  rpc.service_name, rpc.method_name, rpc.addr, scope, elapsed,
+                  sent.duration_since(queued), now.duration_since(sent));
+        },
+        None => {
+            warn!("slow RPC {}::{} to {}{} timed out before being sent: elapsed: {:?}",
+                  rpc.service_name, rpc.method_name, rpc.addr, scope, elapsed);
+        },
+    }
+}
+
+/// Describes a SASL message for the negotiation transcript. The token is redacted, since it may
+/// hold credentials; only its length is recorded.
+fn describe_sasl_message(direction: &str, msg: &rpc_header::SaslMessagePB) -> String {
+    let mechanisms = msg.get_auths().iter().map(|auth| auth.get_mechanism()).collect::<Vec<_>>();
+    let mut description = format!("{} SASL {:?}; mechanisms: {:?}; features: {:?}",
+                                  direction, msg.get_state(), mechanisms, msg.get_supported_features());
+    if msg.has_token() {
+        description.push_str(&format!("; token: <{} bytes redacted>", msg.get_token().len()));
+    }
+    description
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::mpsc::sync_channel;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use env_logger;
+    use kudu_pb;
+    use kudu_pb::rpc_header::RequestHeader;
+    use protobuf::{Clear, Message};
+
+    use Error;
+    use Result;
+    use rpc::{channel_callback, master, ConnectionOptions, Messenger, Rpc};
+    use rpc::mock_server::MockServer;
+    use super::{prepare_request_header, SASL_CALL_ID};
+
+    /// Sends pings to the server, and returns the call IDs of the requests which the server
+    /// received, once every ping has completed.
+    fn send_pings(server: &MockServer, options: ConnectionOptions, count: usize) -> Vec<i32> {
+        let messenger = Messenger::with_options(options).unwrap();
+        let (send, recv) = sync_channel::<(Result<()>, Rpc)>(count);
+        for _ in 0..count {
+            let mut rpc = master::ping(server.addr(),
+                                       Instant::now() + Duration::from_secs(5),
+                                       kudu_pb::master::PingRequestPB::new());
+            rpc.callback = Some(channel_callback(send.clone()));
+            messenger.send(rpc);
+        }
+        for _ in 0..count {
+            let (result, _) = recv.recv().unwrap();
+            assert_eq!(Ok(()), result);
+        }
+        server.requests().iter().map(RequestHeader::get_call_id).collect()
+    }
+
+    #[test]
+    fn send_queued_rpcs() {
+        let _ = env_logger::init();
+        // The RPCs are queued while the connection negotiates, and then sent together. Every
+        // RPC is sent exactly once, in order, including with an odd number of RPCs.
+        for &count in &[1, 2, 7, 255] {
+            let server = MockServer::start();
+            assert_eq!((0..count as i32).collect::<Vec<_>>(),
+                       send_pings(&server, ConnectionOptions::default(), count));
+        }
+    }
+
+    #[test]
+    fn decode_sidecars() {
+        let _ = env_logger::init();
+        let server = MockServer::with_sidecars(&[&b"first"[..], &b""[..], &b"third sidecar"[..]]);
+        let messenger = Messenger::new().unwrap();
+        let rpc = master::ping(server.addr(),
+                               Instant::now() + Duration::from_secs(5),
+                               kudu_pb::master::PingRequestPB::new());
+        let (result, rpc) = rpc.send_sync(&messenger);
+        assert_eq!(Ok(()), result);
+        assert_eq!(vec![b"first".to_vec(), Vec::new(), b"third sidecar".to_vec()], rpc.sidecars);
+    }
+
+    #[test]
+    fn recent_calls() {
+        let _ = env_logger::init();
+        let server = MockServer::start();
+        let mut options = ConnectionOptions::default();
+        options.call_trace_capacity = 4;
+        let messenger = Messenger::with_options(options).unwrap();
+        assert!(messenger.recent_calls(&server.addr()).is_empty());
+
+        for _ in 0..6 {
+            let rpc = master::ping(server.addr(),
+                                   Instant::now() + Duration::from_secs(5),
+                                   kudu_pb::master::PingRequestPB::new());
+            let (result, _) = rpc.send_sync(&messenger);
+            assert_eq!(Ok(()), result);
+        }
+
+        // Only the most recent calls are kept.
+        let calls = messenger.recent_calls(&server.addr());
+        assert_eq!(vec![2, 3, 4, 5], calls.iter().map(|call| call.call_id()).collect::<Vec<_>>());
+        for call in &calls {
+            assert_eq!("Ping", call.method_name());
+            assert_eq!(&Ok(()), call.result());
+        }
+    }
+
+    #[test]
+    fn recent_calls_reset() {
+        let _ = env_logger::init();
+        // Negotiation fails, resetting the connection and failing the fail-fast ping.
+        let server = MockServer::with_sasl_mechanism("GSSAPI");
+        let messenger = Messenger::new().unwrap();
+        let rpc = master::ping(server.addr(),
+                               Instant::now() + Duration::from_secs(5),
+                               kudu_pb::master::PingRequestPB::new());
+        let (result, _) = messenger.send_sync(rpc);
+        assert!(result.is_err());
+
+        let calls = messenger.recent_calls(&server.addr());
+        assert_eq!(1, calls.len());
+        assert_eq!("Ping", calls[0].method_name());
+        assert_eq!(&result, calls[0].result());
+
+        // The connection is closed once it is idle after the reset, discarding the calls.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !messenger.recent_calls(&server.addr()).is_empty() {
+            assert!(Instant::now() < deadline, "calls not discarded after the connection closed");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn send_throttled_rpcs() {
+        let _ = env_logger::init();
+        // The server holds back responses until the connection's in-flight limit is reached, so
+        // the send loop must stop at the limit and resume as out of order responses arrive.
+        let mut options = ConnectionOptions::default();
+        options.max_rpcs_in_flight = 3;
+        let server = MockServer::with_response_batch(3);
+        assert_eq!((0..99).collect::<Vec<_>>(), send_pings(&server, options, 99));
+    }
+
+    #[test]
+    fn negotiation_call_id() {
+        let _ = env_logger::init();
+
+        let server = MockServer::with_sasl_call_id(33);
+        let messenger = Messenger::new().unwrap();
+        let rpc = master::ping(server.addr(),
+                               Instant::now() + Duration::from_millis(500),
+                               kudu_pb::master::PingRequestPB::new());
+        let (result, _) = messenger.send_sync(rpc);
+        assert!(result.is_err());
+        assert!(server.requests().is_empty());
+
+        // Lenient negotiation accepts the server's call ID.
+        let server = MockServer::with_sasl_call_id(33);
+        let mut options = ConnectionOptions::default();
+        options.strict_negotiation = false;
+        assert_eq!(vec![0], send_pings(&server, options, 1));
+    }
+
+    #[test]
+    fn negotiation_transcript() {
+        let _ = env_logger::init();
+
+        // The server only offers a mechanism the client does not support.
+        let server = MockServer::with_sasl_mechanism("GSSAPI");
+        let mut options = ConnectionOptions::default();
+        options.negotiation_transcript = true;
+        let messenger = Messenger::with_options(options).unwrap();
+        let rpc = master::ping(server.addr(),
+                               Instant::now() + Duration::from_millis(500),
+                               kudu_pb::master::PingRequestPB::new());
+        let (result, _) = messenger.send_sync(rpc);
+
+        let error = match result {
+            Err(Error::ConnectionReset(error)) => match *error.cause() {
+                Error::NegotiationFailed(ref error) => error.clone(),
+                ref other => panic!("unexpected reset cause: {}", other),
+            },
+            other => panic!("unexpected result: {:?}", other),
+        };
+        assert_eq!(&Error::NegotiationError("SASL PLAIN authentication not available"),
+                   error.cause());
+        let transcript = error.transcript();
+        assert_eq!(3, transcript.len(), "transcript: {:?}", transcript);
+        assert!(transcript[0].contains("TLS: not supported"));
+        assert!(transcript[1].starts_with("sent SASL NEGOTIATE"));
+        assert!(transcript[2].starts_with("received SASL NEGOTIATE"));
+        assert!(transcript[2].contains("GSSAPI"));
+    }
+
+    fn encode(header: &RequestHeader) -> Vec<u8> {
+        header.write_to_bytes().unwrap()
+    }
+
+    #[test]
+    fn test_prepare_request_header() {
+        let mut reused = RequestHeader::new();
+        for &(call_id, service, method, flags) in &[(0, "kudu.tserver.TabletServerService", "Write", &[][..]),
+                                                    (1, "kudu.tserver.TabletServerService", "Write", &[1][..]),
+                                                    (2, "kudu.tserver.TabletServerService", "Scan", &[][..]),
+                                                    (3, "kudu.master.MasterService", "Ping", &[2, 3][..])] {
+            prepare_request_header(&mut reused, call_id, service, method, 1000, flags);
+
+            let mut fresh = RequestHeader::new();
+            fresh.set_call_id(call_id);
+            fresh.mut_remote_method().set_service_name(service.to_owned());
+            fresh.mut_remote_method().set_method_name(method.to_owned());
+            fresh.set_timeout_millis(1000);
+            fresh.set_required_feature_flags(flags.to_owned());
+
+            assert_eq!(encode(&fresh), encode(&reused));
+        }
+
+        // Negotiation messages clear the header.
+        reused.clear();
+        reused.set_call_id(SASL_CALL_ID);
+        prepare_request_header(&mut reused, 4, "kudu.master.MasterService", "Ping", 1000, &[]);
+        assert_eq!("kudu.master.MasterService", reused.get_remote_method().get_service_name());
+        assert_eq!("Ping", reused.get_remote_method().get_method_name());
+    }
+}
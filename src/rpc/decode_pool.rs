@@ -0,0 +1,94 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use parking_lot::Mutex;
+
+/// A unit of work executed by a `DecodePool` worker. `Job` is used instead of the built-in
+/// function traits so that the `self` parameter can be boxed.
+pub trait Job: Send {
+    fn run(self: Box<Self>);
+}
+
+impl<F> Job for F where F: FnOnce() + Send {
+    fn run(self: Box<F>) {
+        (*self)()
+    }
+}
+
+/// A fixed-size pool of worker threads to which large RPC responses are handed off for
+/// deserialization, so that decoding a multi-megabyte response does not stall the other
+/// connections serviced by the messenger's event loop.
+///
+/// The workers exit once every handle to the pool has been dropped.
+#[derive(Clone)]
+pub struct DecodePool {
+    sender: Sender<Box<Job>>,
+    threads: usize,
+}
+
+impl DecodePool {
+
+    /// Creates a new pool with the provided number of worker threads.
+    pub fn new(threads: usize) -> DecodePool {
+        assert!(threads > 0, "decode pool must have at least one thread");
+        let (sender, receiver) = channel::<Box<Job>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for idx in 0..threads {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("kudu-decode-{}", idx))
+                .spawn(move || worker(receiver))
+                .unwrap();
+        }
+        DecodePool {
+            sender: sender,
+            threads: threads,
+        }
+    }
+
+    /// Executes the job on a worker thread.
+    pub fn execute<J>(&self, job: J) where J: Job + 'static {
+        // The workers only exit after the sender is dropped, so this can not fail.
+        self.sender.send(Box::new(job)).unwrap();
+    }
+}
+
+impl fmt::Debug for DecodePool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DecodePool {{ threads: {} }}", self.threads)
+    }
+}
+
+fn worker(receiver: Arc<Mutex<Receiver<Box<Job>>>>) {
+    loop {
+        // Hold the lock only while waiting for the next job, so that the other workers can
+        // receive while this one runs.
+        let job = match receiver.lock().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        job.run();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        let pool = DecodePool::new(2);
+        let (send, recv) = sync_channel(10);
+        for i in 0..10 {
+            let send = send.clone();
+            pool.execute(move || send.send(i).unwrap());
+        }
+        let mut results = (0..10).map(|_| recv.recv().unwrap()).collect::<Vec<_>>();
+        results.sort();
+        assert_eq!((0..10).collect::<Vec<_>>(), results);
+    }
+}
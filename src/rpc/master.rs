@@ -15,29 +15,49 @@ use kudu_pb::master::{
     ListTablesRequestPB, ListTablesResponsePB,
     ListTabletServersRequestPB, ListTabletServersResponsePB,
     PingRequestPB, PingResponsePB,
+    AlterTableRequestPB_StepType as StepType,
+    MasterFeatures,
 };
-use rpc::Rpc;
+#[cfg(feature = "admin")]
+use kudu_pb::master::{ReplaceTabletRequestPB, ReplaceTabletResponsePB};
+use rpc::{FromRpc, Messenger, Rpc, RpcTimings, TypedRpc};
+use Result;
 
 const SERVICE_NAME: &'static str = "kudu.master.MasterService";
 
 // When macros in type position and concat_idents! land the 3rd and 4th param can be dropped.
 // If/when Rust gets a snake -> camel ident converter the 2nd param can be dropped.
+//
+// RPCs whose requests may depend on application features take a 5th param: a function returning
+// the feature flags required by the request, which the server checks before handling it.
 macro_rules! rpc {
     ($fn_name:ident, $rpc_name:ident, $request_type:ident, $response_type:ident) => {
-        pub fn $fn_name(addr: SocketAddr, deadline: Instant, request: $request_type) -> Rpc {
-            Rpc {
+        rpc!($fn_name, $rpc_name, $request_type, $response_type, |_| Vec::new());
+    };
+    ($fn_name:ident, $rpc_name:ident, $request_type:ident, $response_type:ident, $features:expr) => {
+        pub fn $fn_name(addr: SocketAddr,
+                        deadline: Instant,
+                        request: $request_type)
+                        -> TypedRpc<$request_type, $response_type> {
+            let required_features: fn(&$request_type) -> Vec<u32> = $features;
+            let rpc = Rpc {
                 addr: addr,
                 service_name: SERVICE_NAME,
                 method_name: stringify!($rpc_name),
                 deadline: deadline,
-                required_feature_flags: Vec::new(),
+                required_feature_flags: required_features(&request),
                 request: Box::new(request),
+                serialized_request: None,
                 response: Box::new($response_type::new()),
                 sidecars: Vec::new(),
                 callback: None,
                 cancel: None,
                 fail_fast: true,
-            }
+                timings: RpcTimings::default(),
+                failed_attempts: Vec::new(),
+                request_id: None,
+            };
+            TypedRpc::from_rpc(rpc).unwrap()
         }
     };
 }
@@ -47,7 +67,7 @@ rpc!(get_tablet_locations, GetTabletLocations, GetTabletLocationsRequestPB, GetT
 rpc!(create_table, CreateTable, CreateTableRequestPB, CreateTableResponsePB);
 rpc!(is_create_table_done, IsCreateTableDone, IsCreateTableDoneRequestPB, IsCreateTableDoneResponsePB);
 rpc!(delete_table, DeleteTable, DeleteTableRequestPB, DeleteTableResponsePB);
-rpc!(alter_table, AlterTable, AlterTableRequestPB, AlterTableResponsePB);
+rpc!(alter_table, AlterTable, AlterTableRequestPB, AlterTableResponsePB, alter_table_features);
 rpc!(is_alter_table_done, IsAlterTableDone, IsAlterTableDoneRequestPB, IsAlterTableDoneResponsePB);
 rpc!(list_tables, ListTables, ListTablesRequestPB, ListTablesResponsePB);
 rpc!(get_table_locations, GetTableLocations, GetTableLocationsRequestPB, GetTableLocationsResponsePB);
@@ -55,3 +75,38 @@ rpc!(get_table_schema, GetTableSchema, GetTableSchemaRequestPB, GetTableSchemaRe
 rpc!(list_tablet_servers, ListTabletServers, ListTabletServersRequestPB, ListTabletServersResponsePB);
 rpc!(list_masters, ListMasters, ListMastersRequestPB, ListMastersResponsePB);
 rpc!(get_master_registration, GetMasterRegistration, GetMasterRegistrationRequestPB, GetMasterRegistrationResponsePB);
+#[cfg(feature = "admin")]
+rpc!(replace_tablet, ReplaceTablet, ReplaceTabletRequestPB, ReplaceTabletResponsePB);
+
+pub fn alter_table_features(request: &AlterTableRequestPB) -> Vec<u32> {
+    let alters_range_partitions = request.get_alter_schema_steps().iter().any(|step| {
+        match step.get_field_type() {
+            StepType::ADD_RANGE_PARTITION | StepType::DROP_RANGE_PARTITION => true,
+            _ => false,
+        }
+    });
+    if alters_range_partitions {
+        vec![MasterFeatures::ADD_DROP_RANGE_PARTITIONS as u32]
+    } else {
+        Vec::new()
+    }
+}
+
+service! {
+    /// The Kudu master RPC service.
+    pub trait MasterService {
+        fn ping(PingRequestPB) -> PingResponsePB;
+        fn get_tablet_locations(GetTabletLocationsRequestPB) -> GetTabletLocationsResponsePB;
+        fn create_table(CreateTableRequestPB) -> CreateTableResponsePB;
+        fn is_create_table_done(IsCreateTableDoneRequestPB) -> IsCreateTableDoneResponsePB;
+        fn delete_table(DeleteTableRequestPB) -> DeleteTableResponsePB;
+        fn alter_table(AlterTableRequestPB) -> AlterTableResponsePB;
+        fn is_alter_table_done(IsAlterTableDoneRequestPB) -> IsAlterTableDoneResponsePB;
+        fn list_tables(ListTablesRequestPB) -> ListTablesResponsePB;
+        fn get_table_locations(GetTableLocationsRequestPB) -> GetTableLocationsResponsePB;
+        fn get_table_schema(GetTableSchemaRequestPB) -> GetTableSchemaResponsePB;
+        fn list_tablet_servers(ListTabletServersRequestPB) -> ListTabletServersResponsePB;
+        fn list_masters(ListMastersRequestPB) -> ListMastersResponsePB;
+        fn get_master_registration(GetMasterRegistrationRequestPB) -> GetMasterRegistrationResponsePB;
+    }
+}
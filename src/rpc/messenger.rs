@@ -1,14 +1,25 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::io;
+use std::mem;
 use std::net::SocketAddr;
-use std::rc::Rc;
-use std::sync::mpsc::sync_channel;
-use std::thread;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver};
 use std::time::{Duration, Instant};
 use std::fmt;
 
-use rpc::Rpc;
-use rpc::connection::{Connection, ConnectionOptions};
+use parking_lot::Mutex;
+
+use rpc::{Callback, Rpc};
+use rpc::call_trace::{CallRecord, CallTrace};
+use rpc::circuit_breaker::CircuitBreaker;
+use rpc::connection::{Connection, ConnectionEvent, ConnectionObservers, ConnectionOptions};
+use rpc::decode_pool::DecodePool;
+use rpc::metrics::RpcMetrics;
+use rpc::runtime::{ReactorTask, Runtime, ThreadRuntime};
+use retry_budget::RetryBudget;
 use Result;
 use Error;
 
@@ -29,7 +40,8 @@ pub type Loop = EventLoop<MessengerHandler>;
 pub enum Command {
     Shutdown,
     Send(Rpc),
-    Timer((Duration, Box<TimerCallback>)),
+    Throttle(SocketAddr),
+    Timer((Duration, usize)),
 }
 
 impl fmt::Debug for Command {
@@ -37,6 +49,7 @@ impl fmt::Debug for Command {
         match *self {
             Command::Shutdown => write!(f, "Command::Shutdown"),
             Command::Send(ref rpc) => write!(f, "Command::Send({:?})", rpc),
+            Command::Throttle(ref addr) => write!(f, "Command::Throttle({})", addr),
             Command::Timer((ref duration, _)) => write!(f, "Command::Timer({:?})", duration),
         }
     }
@@ -64,42 +77,416 @@ pub enum TimeoutKind {
     /// This timeout tracks an RPC timeout deadline. When it expires, the RPC should be timed out.
     Rpc(Token, usize),
 
-    /// A general timer timeout.
-    Timer(Box<TimerCallback>),
+    /// Active while a connection is connecting or negotiating. After expiration, the connection
+    /// is reset.
+    Initiate(Token),
+
+    /// A general timer timeout, identified by its ID in the messenger's pending timers.
+    Timer(usize),
 }
 
+/// A handle to a messenger's event loops.
+///
+/// Cloned handles share the same event loops. The event loops are shut down by
+/// `Messenger::shutdown`, or when the last handle is dropped, closing all connections. RPCs which
+/// are queued in a connection at shutdown are failed with `Error::Shutdown`, and pending timers,
+/// such as delayed retries, fire early so that they fail their work.
+///
+/// Each connection belongs to a single event loop, chosen by hashing the remote address.
 #[derive(Clone)]
 pub struct Messenger {
-    channel: Sender<Command>,
+    channels: Arc<Vec<Sender<Command>>>,
+    metrics: Arc<RpcMetrics>,
+    retry_budget: RetryBudget,
+    observers: Arc<ConnectionObservers>,
+    call_traces: Arc<CallTraces>,
+    timers: Arc<PendingTimers>,
+    shutdown: Arc<Shutdown>,
+    in_flight_limit: Option<Arc<InFlightLimit>>,
+}
+
+/// The callbacks of timers which have not yet fired, shared by the messenger and its event loops.
+///
+/// Timer callbacks are kept here rather than in the event loop's timer wheel, so that they can be
+/// run at shutdown instead of being dropped along with the event loop.
+#[derive(Default)]
+pub struct PendingTimers {
+    state: Mutex<PendingTimersState>,
+}
+
+#[derive(Default)]
+struct PendingTimersState {
+    next_id: usize,
+    callbacks: BTreeMap<usize, Box<TimerCallback>>,
+    /// Set once the pending timers have been run at shutdown.
+    shut_down: bool,
+}
+
+impl PendingTimers {
+
+    /// Adds a pending timer callback, returning its ID. The timer fires when the event loop
+    /// times out `TimeoutKind::Timer` with the ID.
+    pub fn add(&self, callback: Box<TimerCallback>) -> usize {
+        let mut state = self.state.lock();
+        let id = state.next_id;
+        state.next_id = state.next_id.wrapping_add(1);
+        state.callbacks.insert(id, callback);
+        id
+    }
+
+    /// Runs the timer's callback, unless it has already been run.
+    fn run(&self, id: usize) {
+        let callback = self.state.lock().callbacks.remove(&id);
+        if let Some(callback) = callback {
+            callback.callback();
+        }
+    }
+
+    /// Runs the callbacks of every pending timer, in the order the timers were added.
+    fn run_all(&self) {
+        let callbacks = {
+            let mut state = self.state.lock();
+            state.shut_down = true;
+            mem::replace(&mut state.callbacks, BTreeMap::new())
+        };
+        for (_, callback) in callbacks {
+            callback.callback();
+        }
+    }
+
+    /// Returns `true` if the pending timers have been run at shutdown.
+    pub fn is_shut_down(&self) -> bool {
+        self.state.lock().shut_down
+    }
+}
+
+/// The call trace of each server, shared by the messenger and its event loops.
+struct CallTraces {
+    capacity: usize,
+    traces: Mutex<HashMap<SocketAddr, CallTrace>>,
+}
+
+impl CallTraces {
+
+    /// Returns the call trace of the server, creating it if necessary.
+    fn get(&self, addr: &SocketAddr) -> CallTrace {
+        if self.capacity == 0 {
+            return CallTrace::new(0);
+        }
+        self.traces.lock().entry(*addr).or_insert_with(|| CallTrace::new(self.capacity)).clone()
+    }
+
+    /// Discards the call trace of the server.
+    fn remove(&self, addr: &SocketAddr) {
+        self.traces.lock().remove(addr);
+    }
+}
+
+/// Limits the number of RPCs in flight across all connections of a messenger.
+///
+/// Each RPC holds a permit from when it is sent until its callback fires. RPCs sent while all
+/// permits are held wait in a queue, and are dispatched in order as permits are released. Queued
+/// RPCs do not time out until they are dispatched.
+struct InFlightLimit {
+    max_rpcs_in_flight: usize,
+    state: Mutex<InFlightState>,
+}
+
+struct InFlightState {
+    in_flight: usize,
+    queue: VecDeque<Rpc>,
+}
+
+impl InFlightLimit {
+
+    /// Takes a permit for the RPC, or queues the RPC if no permit is available.
+    fn acquire(&self, rpc: Rpc) -> Option<Rpc> {
+        let mut state = self.state.lock();
+        if state.in_flight < self.max_rpcs_in_flight {
+            state.in_flight += 1;
+            Some(rpc)
+        } else {
+            trace!("in-flight RPC limit reached, queueing rpc: {:?}", rpc);
+            state.queue.push_back(rpc);
+            None
+        }
+    }
+
+    /// Releases a permit, handing it to the next queued RPC if there is one.
+    fn release(&self) -> Option<Rpc> {
+        let mut state = self.state.lock();
+        let rpc = state.queue.pop_front();
+        if rpc.is_none() {
+            state.in_flight -= 1;
+        }
+        rpc
+    }
+}
+
+/// Wraps the callback of an RPC holding an in-flight permit. The permit is released when the
+/// callback fires, or when the RPC is abandoned.
+struct PermitCallback {
+    callback: Option<Box<Callback>>,
+    channels: Arc<Vec<Sender<Command>>>,
+    limit: Arc<InFlightLimit>,
+}
+
+impl Callback for PermitCallback {
+    fn callback(mut self: Box<Self>, result: Result<()>, rpc: Rpc) {
+        let callback = self.callback.take().unwrap();
+        // Release the permit before firing the callback, since the callback may resend the RPC.
+        drop(self);
+        callback.callback(result, rpc)
+    }
+}
+
+impl Drop for PermitCallback {
+    fn drop(&mut self) {
+        if let Some(rpc) = self.limit.release() {
+            dispatch(&self.channels, Some(&self.limit), rpc);
+        }
+    }
+}
+
+/// Sends the RPC to the event loop which owns connections to its address. If the RPC holds an
+/// in-flight permit, its callback is wrapped so that the permit is released on completion.
+fn dispatch(channels: &Arc<Vec<Sender<Command>>>, limit: Option<&Arc<InFlightLimit>>, mut rpc: Rpc) {
+    if let Some(limit) = limit {
+        rpc.callback = Some(Box::new(PermitCallback {
+            callback: rpc.callback.take(),
+            channels: channels.clone(),
+            limit: limit.clone(),
+        }));
+    }
+    if let Err(error) = channel(channels, &rpc.addr).send(Command::Send(rpc)) {
+        debug!("failed to send RPC to messenger event loop: {:?}", error);
+    }
+}
+
+/// Returns the channel of the event loop which owns connections to the address.
+fn channel<'a>(channels: &'a [Sender<Command>], addr: &SocketAddr) -> &'a Sender<Command> {
+    if channels.len() == 1 {
+        return &channels[0];
+    }
+    let mut hasher = DefaultHasher::new();
+    addr.hash(&mut hasher);
+    &channels[hasher.finish() as usize % channels.len()]
+}
+
+/// Shuts down the event loops, either when requested by `Messenger::shutdown`, or when dropped.
+struct Shutdown {
+    channels: Vec<Sender<Command>>,
+    /// Set once the event loops have been asked to shut down.
+    shut_down: AtomicBool,
+    event_loops: Mutex<EventLoops>,
+}
+
+/// The running event loops of a messenger.
+struct EventLoops {
+    /// The number of event loops which have not yet exited.
+    running: usize,
+    /// Receives a message from each event loop as it exits.
+    exited: Receiver<()>,
+}
+
+impl Shutdown {
+
+    /// Asks the event loops to shut down.
+    fn signal(&self) {
+        for channel in &self.channels {
+            if channel.send(Command::Shutdown).is_err() {
+                debug!("failed to send shutdown command to messenger event loop");
+            }
+        }
+    }
+}
+
+impl Drop for Shutdown {
+    fn drop(&mut self) {
+        if !self.shut_down.swap(true, Ordering::SeqCst) {
+            self.signal();
+        }
+    }
 }
 
 impl Messenger {
     pub fn new() -> io::Result<Messenger> {
-        let mut event_loop_builder = EventLoopBuilder::new();
-        // Timer granularity of 10ms.
-        event_loop_builder.timer_tick(Duration::from_millis(10));
-        let mut event_loop = try!(event_loop_builder.build());
-        let channel = event_loop.channel();
-        thread::spawn(move || {
-            let mut connection_manager = MessengerHandler::new();
-            event_loop.run(&mut connection_manager)
+        Messenger::with_options(ConnectionOptions::default())
+    }
+
+    /// Creates a new messenger which opens connections with the provided options.
+    pub fn with_options(options: ConnectionOptions) -> io::Result<Messenger> {
+        Messenger::with_runtime(options, &ThreadRuntime)
+    }
+
+    /// Creates a new messenger whose event loops are run by the runtime.
+    pub fn with_runtime(options: ConnectionOptions, runtime: &Runtime) -> io::Result<Messenger> {
+        let reactor_threads = ::std::cmp::max(1, options.reactor_threads);
+        let decode_pool = if options.decode_threads > 0 {
+            Some(DecodePool::new(options.decode_threads))
+        } else {
+            None
+        };
+        let in_flight_limit = options.max_total_rpcs_in_flight.map(|max_rpcs_in_flight| {
+            Arc::new(InFlightLimit {
+                max_rpcs_in_flight: max_rpcs_in_flight as usize,
+                state: Mutex::new(InFlightState { in_flight: 0, queue: VecDeque::new() }),
+            })
+        });
+        let retry_budget = RetryBudget::new(options.retry_budget, options.retry_budget_percent);
+        let options = Arc::new(options);
+        let metrics = Arc::new(RpcMetrics::with_scope(options.metrics_scope.clone()));
+        let observers = Arc::new(ConnectionObservers::default());
+        let call_traces = Arc::new(CallTraces {
+            capacity: options.call_trace_capacity,
+            traces: Mutex::new(HashMap::new()),
         });
-        Ok(Messenger { channel: channel })
+        let timers = Arc::new(PendingTimers::default());
+
+        let mut channels = Vec::with_capacity(reactor_threads);
+        let mut tasks = Vec::with_capacity(reactor_threads);
+        let (exited_send, exited_recv) = channel();
+        for _ in 0..reactor_threads {
+            let mut event_loop_builder = EventLoopBuilder::new();
+            // Timer granularity of 10ms.
+            event_loop_builder.timer_tick(Duration::from_millis(10));
+            let event_loop = try!(event_loop_builder.build());
+            channels.push(event_loop.channel());
+            let connection_manager = MessengerHandler::new(options.clone(),
+                                                           metrics.clone(),
+                                                           retry_budget.clone(),
+                                                           decode_pool.clone(),
+                                                           observers.clone(),
+                                                           call_traces.clone(),
+                                                           timers.clone());
+            tasks.push(ReactorTask::new(event_loop, connection_manager, exited_send.clone()));
+        }
+        // Build every event loop before starting any, so that an error does not leave loops
+        // running without a messenger to shut them down.
+        for task in tasks {
+            runtime.spawn(task);
+        }
+        Ok(Messenger {
+            shutdown: Arc::new(Shutdown {
+                channels: channels.clone(),
+                shut_down: AtomicBool::new(false),
+                event_loops: Mutex::new(EventLoops {
+                    running: reactor_threads,
+                    exited: exited_recv,
+                }),
+            }),
+            channels: Arc::new(channels),
+            metrics: metrics,
+            retry_budget: retry_budget,
+            observers: observers,
+            call_traces: call_traces,
+            timers: timers,
+            in_flight_limit: in_flight_limit,
+        })
+    }
+
+    /// Returns the RPC metrics of this messenger.
+    pub fn metrics(&self) -> &RpcMetrics {
+        &self.metrics
+    }
+
+    /// Returns the retry budget of this messenger, which should be consulted before retrying a
+    /// failed RPC.
+    pub fn retry_budget(&self) -> &RetryBudget {
+        &self.retry_budget
+    }
+
+    /// Shuts down the messenger's event loops. RPCs queued in connections or waiting for the
+    /// in-flight RPC limit are failed with `Error::Shutdown`, pending timers fire early,
+    /// connections are closed, and the event loops are waited on until they exit. RPCs sent after
+    /// shutdown fail immediately with `Error::Shutdown`.
+    ///
+    /// Returns `false` if the event loops did not exit within the timeout, in which case the
+    /// timers which they have not yet fired are run on the calling thread, and the event loops
+    /// are left to exit in the background. Must not be called from an RPC callback, since the
+    /// event loop running the callback can not exit until it returns.
+    pub fn shutdown(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        if !self.shutdown.shut_down.swap(true, Ordering::SeqCst) {
+            // New RPCs are now failed by `send`, so the queue can be drained before the event
+            // loops stop.
+            if let Some(ref limit) = self.in_flight_limit {
+                let queued = limit.state.lock().queue.drain(..).collect::<Vec<_>>();
+                for rpc in queued {
+                    rpc.fail(Error::Shutdown);
+                }
+            }
+            self.shutdown.signal();
+        }
+
+        let mut event_loops = self.shutdown.event_loops.lock();
+        while event_loops.running > 0 {
+            let now = Instant::now();
+            if now >= deadline || event_loops.exited.recv_timeout(deadline - now).is_err() {
+                warn!("messenger event loops did not shut down within {:?}", timeout);
+                self.timers.run_all();
+                return false;
+            }
+            event_loops.running -= 1;
+        }
+        true
+    }
+
+    /// Returns `true` if the messenger has been shut down.
+    pub fn is_shut_down(&self) -> bool {
+        self.shutdown.shut_down.load(Ordering::SeqCst)
+    }
+
+    /// Returns a channel which receives the lifecycle events of every connection opened by the
+    /// messenger. Events are dropped if the channel is full.
+    pub fn connection_events(&self, capacity: usize) -> Receiver<ConnectionEvent> {
+        let (send, recv) = sync_channel(capacity);
+        self.observers.add(send);
+        recv
+    }
+
+    /// Returns the most recently completed calls to the server, oldest first, up to
+    /// `ConnectionOptions::call_trace_capacity` of them. Calls are kept across connection resets,
+    /// so that the calls leading up to a stalled or reset connection can be inspected, and are
+    /// discarded once the connection is closed for being idle after a reset.
+    pub fn recent_calls(&self, addr: &SocketAddr) -> Vec<CallRecord> {
+        self.call_traces.traces.lock().get(addr).map_or_else(Vec::new, CallTrace::recent_calls)
     }
 
 
     /// Sends a generic Kudu RPC, and executes the callback when the RPC is complete.
-    pub fn send(&self, mut rpc: Rpc) {
-        // TODO: is there a better way to handle queue failure?
+    ///
+    /// If the messenger limits the total number of RPCs in flight and the limit is reached, the
+    /// RPC is queued until another RPC completes.
+    pub fn send<R>(&self, rpc: R) where R: Into<Rpc> {
+        let mut rpc = rpc.into();
         debug_assert!(rpc.callback.is_some());
+        if self.is_shut_down() {
+            return rpc.fail(Error::Shutdown);
+        }
         rpc.response.clear();
-        self.channel.send(Command::Send(rpc)).unwrap();
+        let rpc = match self.in_flight_limit {
+            Some(ref limit) => match limit.acquire(rpc) {
+                Some(rpc) => rpc,
+                None => return,
+            },
+            None => rpc,
+        };
+        dispatch(&self.channels, self.in_flight_limit.as_ref(), rpc);
+    }
+
+    /// Returns the number of RPCs waiting for the in-flight RPC limit of the messenger.
+    pub fn queued_rpcs(&self) -> usize {
+        self.in_flight_limit.as_ref().map_or(0, |limit| limit.state.lock().queue.len())
     }
 
     pub fn delayed_send(&self, delay: Duration, rpc: Rpc) {
+        if self.is_shut_down() {
+            return rpc.fail(Error::Shutdown);
+        }
         let deadline = rpc.deadline.clone();
         if Instant::now() + delay > deadline {
-            rpc.fail(Error::TimedOut);
+            rpc.fail(Error::QueueTimedOut);
             return;
         }
 
@@ -108,7 +495,8 @@ impl Messenger {
         self.timer(delay, Box::new(move || { messenger.send(rpc) }));
     }
 
-    pub fn send_sync(&self, mut rpc: Rpc) -> (Result<()>, Rpc) {
+    pub fn send_sync<R>(&self, rpc: R) -> (Result<()>, Rpc) where R: Into<Rpc> {
+        let mut rpc = rpc.into();
         let (send, recv) = sync_channel(0);
         assert!(rpc.callback.is_none());
         rpc.callback = Some(Box::new(move |result, rpc| send.send((result, rpc)).unwrap()));
@@ -116,25 +504,79 @@ impl Messenger {
         recv.recv().unwrap()
     }
 
+    /// Lowers the in-flight RPC limit of the connection to the server, in response to the server
+    /// rejecting an RPC with a `Throttled` error. See `Connection::throttle`.
+    pub fn throttle(&self, addr: SocketAddr) {
+        if channel(&self.channels, &addr).send(Command::Throttle(addr)).is_err() {
+            debug!("failed to send throttle to messenger event loop");
+        }
+    }
+
+    /// Runs the callback on an event loop thread after the duration. If the messenger shuts down
+    /// first, the callback runs early during shutdown, and should check
+    /// `Messenger::is_shut_down` to fail its work rather than retrying it.
     pub fn timer(&self, duration: Duration, callback: Box<TimerCallback>) {
-        self.channel.send(Command::Timer((duration, callback))).unwrap();
+        let id = self.timers.add(callback);
+        // The event loops may already have run the pending timers.
+        if self.is_shut_down() {
+            return self.timers.run(id);
+        }
+        if self.channels[0].send(Command::Timer((duration, id))).is_err() {
+            debug!("failed to send timer to messenger event loop");
+            self.timers.run(id);
+        }
     }
 }
 
 pub struct MessengerHandler {
     connection_slab: Slab<Connection, Token>,
     index: HashMap<SocketAddr, Token>,
-    cxn_options: Rc<ConnectionOptions>,
+    cxn_options: Arc<ConnectionOptions>,
+    metrics: Arc<RpcMetrics>,
+    retry_budget: RetryBudget,
+    /// The circuit breaker of each server. Breakers outlive connections, so that a server which
+    /// is down stays marked as down after its idle connection is closed.
+    circuit_breakers: HashMap<SocketAddr, Arc<Mutex<CircuitBreaker>>>,
+    decode_pool: Option<DecodePool>,
+    observers: Arc<ConnectionObservers>,
+    call_traces: Arc<CallTraces>,
+    timers: Arc<PendingTimers>,
 }
 
 impl MessengerHandler {
-    fn new() -> MessengerHandler {
+    fn new(options: Arc<ConnectionOptions>,
+           metrics: Arc<RpcMetrics>,
+           retry_budget: RetryBudget,
+           decode_pool: Option<DecodePool>,
+           observers: Arc<ConnectionObservers>,
+           call_traces: Arc<CallTraces>,
+           timers: Arc<PendingTimers>)
+           -> MessengerHandler {
         MessengerHandler {
             connection_slab: Slab::with_capacity(128),
             index: HashMap::new(),
-            cxn_options: Rc::new(ConnectionOptions::default()),
+            cxn_options: options,
+            metrics: metrics,
+            retry_budget: retry_budget,
+            circuit_breakers: HashMap::new(),
+            decode_pool: decode_pool,
+            observers: observers,
+            call_traces: call_traces,
+            timers: timers,
         }
     }
+
+    /// Returns the circuit breaker of the server, or `None` if circuit breakers are disabled.
+    fn circuit_breaker(&mut self, addr: &SocketAddr) -> Option<Arc<Mutex<CircuitBreaker>>> {
+        let threshold = match self.cxn_options.circuit_breaker_threshold {
+            Some(threshold) => threshold,
+            None => return None,
+        };
+        let open_duration = self.cxn_options.circuit_breaker_open_duration;
+        Some(self.circuit_breakers.entry(*addr).or_insert_with(|| {
+            Arc::new(Mutex::new(CircuitBreaker::new(threshold, open_duration)))
+        }).clone())
+    }
 }
 
 impl fmt::Debug for MessengerHandler {
@@ -154,8 +596,25 @@ impl Handler for MessengerHandler {
 
     fn notify(&mut self, event_loop: &mut Loop, command: Command) {
         match command {
-            Command::Shutdown => event_loop.shutdown(),
+            Command::Shutdown => {
+                for connection in self.connection_slab.iter_mut() {
+                    connection.shutdown(event_loop);
+                }
+                // Timers are not carried across shutdown, so their callbacks run now, observing
+                // that the messenger is shut down.
+                self.timers.run_all();
+                event_loop.shutdown()
+            },
             Command::Send(rpc) => {
+                let circuit_breaker = self.circuit_breaker(&rpc.addr);
+                if let Some(ref breaker) = circuit_breaker {
+                    if !breaker.lock().allow(Instant::now()) {
+                        trace!("circuit breaker open, failing rpc: {:?}", rpc);
+                        self.metrics.record_circuit_breaker_rejection();
+                        let addr = rpc.addr;
+                        return rpc.fail(Error::CircuitOpen(addr));
+                    }
+                }
                 let token = self.index.get(&rpc.addr).cloned().unwrap_or_else(|| {
                     // No open connection for the socket address; create a new one.
                     if !self.connection_slab.has_available() {
@@ -163,10 +622,26 @@ impl Handler for MessengerHandler {
                         self.connection_slab.reserve_exact(len / 2);
                     }
                     let cxn_options = self.cxn_options.clone();
+                    let metrics = self.metrics.clone();
+                    let retry_budget = self.retry_budget.clone();
+                    let decode_pool = self.decode_pool.clone();
+                    let observers = self.observers.clone();
+                    let call_trace = self.call_traces.get(&rpc.addr);
+                    let timers = self.timers.clone();
                     let token = {
                         let entry = self.connection_slab.vacant_entry().unwrap();
                         let token = entry.index();
-                        let connection = Connection::new(event_loop, token, rpc.addr, cxn_options);
+                        let connection = Connection::new(event_loop,
+                                                         token,
+                                                         rpc.addr,
+                                                         cxn_options,
+                                                         metrics,
+                                                         retry_budget,
+                                                         circuit_breaker,
+                                                         decode_pool,
+                                                         observers,
+                                                         call_trace,
+                                                         timers);
                         entry.insert(connection);
                         token
                     };
@@ -175,8 +650,14 @@ impl Handler for MessengerHandler {
                 });
                 self.connection_slab[token].send_rpc(event_loop, token, rpc);
             },
-            Command::Timer((duration, callback)) => {
-                event_loop.timeout(TimeoutKind::Timer(callback), duration).unwrap();
+            Command::Throttle(addr) => {
+                // The connection may have been closed since the server's response.
+                if let Some(&token) = self.index.get(&addr) {
+                    self.connection_slab[token].throttle();
+                }
+            },
+            Command::Timer((duration, id)) => {
+                event_loop.timeout(TimeoutKind::Timer(id), duration).unwrap();
             }
         }
     }
@@ -193,6 +674,8 @@ impl Handler for MessengerHandler {
                     let cxn = self.connection_slab.remove(token).unwrap();
                     let removed_token = self.index.remove(cxn.addr()).unwrap();
                     assert!(removed_token == token);
+                    // The server is no longer in use, so its calls are not kept.
+                    self.call_traces.remove(cxn.addr());
                     debug!("{:?}: closing", cxn);
                 }
             },
@@ -206,7 +689,15 @@ impl Handler for MessengerHandler {
                     .unwrap()
                     .rpc_timeout(call_id);
             },
-            TimeoutKind::Timer(callback) => callback.callback(),
+            TimeoutKind::Initiate(token) => {
+                // The timer is cleared when the connection is reset, so the connection must
+                // still exist.
+                self.connection_slab
+                    .get_mut(token)
+                    .unwrap()
+                    .initiate_timeout(event_loop, token);
+            },
+            TimeoutKind::Timer(id) => self.timers.run(id),
         }
     }
 }
@@ -223,7 +714,8 @@ mod tests {
     use kudu_pb;
 
     use mini_cluster::{self, MiniCluster, MiniClusterConfig};
-    use rpc::{channel_callback, retry_channel_callback, master, Callback, Rpc};
+    use rpc::{channel_callback, retry_channel_callback, master, Callback, Rpc, TypedRpc};
+    use rpc::master::MasterService;
     use super::*;
     use Error;
     use Result;
@@ -248,6 +740,280 @@ mod tests {
         assert_eq!(Ok(()), result);
     }
 
+    #[test]
+    fn max_total_rpcs_in_flight() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default().num_tservers(0));
+
+        let mut options = ConnectionOptions::default();
+        options.max_total_rpcs_in_flight = Some(1);
+        let messenger = Messenger::with_options(options).unwrap();
+
+        let (send, recv) = sync_channel::<(Result<()>, Rpc)>(10);
+        for _ in 0..10 {
+            let mut rpc = master::ping(cluster.master_addrs()[0],
+                                       Instant::now() + Duration::from_secs(5),
+                                       kudu_pb::master::PingRequestPB::new());
+            rpc.callback = Some(channel_callback(send.clone()));
+            messenger.send(rpc);
+        }
+        // Only one RPC is dispatched at a time, so the others wait in the messenger.
+        assert!(messenger.queued_rpcs() > 0);
+
+        for _ in 0..10 {
+            let (result, _) = recv.recv().unwrap();
+            assert_eq!(Ok(()), result);
+        }
+        assert_eq!(0, messenger.queued_rpcs());
+    }
+
+    #[test]
+    fn typed_rpc() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default().num_tservers(0));
+
+        let messenger = Messenger::new().unwrap();
+        let rpc = master::list_masters(cluster.master_addrs()[0],
+                                       Instant::now() + Duration::from_secs(5),
+                                       kudu_pb::master::ListMastersRequestPB::new());
+        let (result, rpc) = rpc.send_sync(&messenger);
+        assert_eq!(Ok(()), result);
+        assert_eq!(1, rpc.response().get_masters().len());
+
+        // The untyped RPC can only be converted back to its own message types.
+        let rpc = rpc.into_rpc();
+        assert!(TypedRpc::<kudu_pb::master::PingRequestPB,
+                           kudu_pb::master::PingResponsePB>::from_rpc(rpc).is_none());
+    }
+
+    #[test]
+    fn rpc_timings() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default().num_tservers(0));
+
+        let messenger = Messenger::new().unwrap();
+        let (send, recv) = sync_channel::<(Result<()>, Rpc)>(0);
+        let mut rpc = master::ping(cluster.master_addrs()[0],
+                                   Instant::now() + Duration::from_secs(5),
+                                   kudu_pb::master::PingRequestPB::new());
+        rpc.callback = Some(retry_channel_callback(messenger.clone(), send));
+
+        messenger.send(rpc);
+        let (result, rpc) = recv.recv().unwrap();
+        assert_eq!(Ok(()), result);
+        assert!(rpc.timings.queue_time().is_some());
+        assert!(rpc.timings.processing_time().is_some());
+        assert_eq!(1, messenger.metrics().completed_rpcs());
+    }
+
+    #[test]
+    fn decode_pool() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default().num_tservers(0));
+
+        // Offload every response.
+        let mut options = ConnectionOptions::default();
+        options.decode_threads = 2;
+        options.decode_offload_threshold = 0;
+        let messenger = Messenger::with_options(options).unwrap();
+
+        let (send, recv) = sync_channel::<(Result<()>, Rpc)>(0);
+        let mut rpc = master::list_masters(cluster.master_addrs()[0],
+                                           Instant::now() + Duration::from_secs(5),
+                                           kudu_pb::master::ListMastersRequestPB::new());
+        rpc.callback = Some(retry_channel_callback(messenger.clone(), send));
+
+        messenger.send(rpc);
+        let (result, rpc) = recv.recv().unwrap();
+        assert_eq!(Ok(()), result);
+        assert_eq!(1, rpc.response::<kudu_pb::master::ListMastersResponsePB>().get_masters().len());
+    }
+
+    #[test]
+    fn core_types_are_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Rpc>();
+        assert_send::<Connection>();
+        assert_send::<MessengerHandler>();
+        assert_send::<Messenger>();
+    }
+
+    #[test]
+    fn multiple_reactors() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default()
+                                                         .num_masters(3)
+                                                         .num_tservers(0));
+
+        let mut options = ConnectionOptions::default();
+        options.reactor_threads = 4;
+        let messenger = Messenger::with_options(options).unwrap();
+
+        let (send, recv) = sync_channel::<(Result<()>, Rpc)>(3);
+        for &addr in cluster.master_addrs() {
+            let mut rpc = master::ping(addr,
+                                       Instant::now() + Duration::from_secs(5),
+                                       kudu_pb::master::PingRequestPB::new());
+            rpc.callback = Some(retry_channel_callback(messenger.clone(), send.clone()));
+            messenger.send(rpc);
+        }
+        for _ in 0..3 {
+            let (result, _) = recv.recv().unwrap();
+            assert_eq!(Ok(()), result);
+        }
+        assert_eq!(3, messenger.metrics().completed_rpcs());
+    }
+
+    #[test]
+    fn connection_events() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default().num_tservers(0));
+        let addr = cluster.master_addrs()[0];
+
+        let messenger = Messenger::new().unwrap();
+        let events = messenger.connection_events(16);
+
+        let rpc = master::ping(addr,
+                               Instant::now() + Duration::from_secs(5),
+                               kudu_pb::master::PingRequestPB::new());
+        let (result, _) = messenger.send_sync(rpc);
+        assert_eq!(Ok(()), result);
+
+        assert_eq!(ConnectionEvent::Connecting(addr), events.recv().unwrap());
+        assert_eq!(ConnectionEvent::Negotiated(addr), events.recv().unwrap());
+        assert_eq!(1, messenger.metrics().negotiations());
+
+        drop(messenger);
+        assert_eq!(ConnectionEvent::Shutdown(addr),
+                   events.recv_timeout(Duration::from_secs(5)).unwrap());
+    }
+
+    #[test]
+    fn negotiation_timeout() {
+        let _ = env_logger::init();
+        // A server which accepts connections, but never responds to negotiation.
+        let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut options = ConnectionOptions::default();
+        options.negotiation_timeout = Duration::from_millis(100);
+        let messenger = Messenger::with_options(options).unwrap();
+
+        let rpc = master::ping(addr,
+                               Instant::now() + Duration::from_secs(5),
+                               kudu_pb::master::PingRequestPB::new());
+        let (result, _) = messenger.send_sync(rpc);
+        assert_eq!(&Error::NegotiationTimedOut, result.unwrap_err().root_cause());
+        drop(listener);
+    }
+
+    /// Tests that an RPC which would time out during the reconnection backoff is failed
+    /// immediately, instead of after waiting out the backoff.
+    #[test]
+    fn reset_backoff_exceeds_deadline() {
+        let _ = env_logger::init();
+        let mut options = ConnectionOptions::default();
+        options.backoff_initial = 30_000;
+        let messenger = Messenger::with_options(options).unwrap();
+
+        let now = Instant::now();
+        let mut rpc = master::ping(mini_cluster::get_unbound_address(),
+                                   now + Duration::from_secs(5),
+                                   kudu_pb::master::PingRequestPB::new());
+        rpc.fail_fast = false;
+        let (result, _) = messenger.send_sync(rpc);
+        match result {
+            Err(Error::DeadlineExceeded(ref error)) => {
+                assert_eq!(2, error.attempts());
+                assert_eq!(&Error::ConnectionError, error.errors()[0].root_cause());
+                assert_eq!(&Error::QueueTimedOut, error.last_error());
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(now.elapsed() < Duration::from_secs(5), "elapsed: {:?}", now.elapsed());
+    }
+
+    /// Tests that an RPC which times out after being retried across several connection resets
+    /// fails with the cause, attempt number, and elapsed time of each reset.
+    #[test]
+    fn reset_history() {
+        let _ = env_logger::init();
+        let mut options = ConnectionOptions::default();
+        options.backoff_initial = 10;
+        options.backoff_max = 50;
+        let messenger = Messenger::with_options(options).unwrap();
+
+        let mut rpc = master::ping(mini_cluster::get_unbound_address(),
+                                   Instant::now() + Duration::from_millis(500),
+                                   kudu_pb::master::PingRequestPB::new());
+        rpc.fail_fast = false;
+        let (result, _) = messenger.send_sync(rpc);
+        let error = match result {
+            Err(Error::DeadlineExceeded(error)) => error,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        assert!(error.attempts() > 3, "attempts: {}", error.attempts());
+        assert_eq!(&Error::QueueTimedOut, error.last_error());
+
+        let resets = &error.errors()[..error.attempts() - 1];
+        let mut last_elapsed = Duration::from_secs(0);
+        for (idx, reset) in resets.iter().enumerate() {
+            match *reset {
+                Error::ConnectionReset(ref reset) => {
+                    assert_eq!(&Error::ConnectionError, reset.cause());
+                    assert_eq!(idx as u32 + 1, reset.attempts());
+                    assert!(reset.elapsed() >= last_elapsed);
+                    last_elapsed = reset.elapsed();
+                },
+                ref other => panic!("unexpected reset: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn max_buffered_rpcs() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default().num_tservers(0));
+
+        let mut options = ConnectionOptions::default();
+        options.max_buffered_rpcs = 2;
+        options.max_rpcs_in_flight = 1;
+        let messenger = Messenger::with_options(options).unwrap();
+
+        // The RPCs are queued while the connection negotiates, so the third is rejected.
+        let (send, recv) = sync_channel::<(Result<()>, Rpc)>(3);
+        for _ in 0..3 {
+            let mut rpc = master::ping(cluster.master_addrs()[0],
+                                       Instant::now() + Duration::from_secs(5),
+                                       kudu_pb::master::PingRequestPB::new());
+            rpc.callback = Some(channel_callback(send.clone()));
+            messenger.send(rpc);
+        }
+        let mut results = (0..3).map(|_| recv.recv().unwrap().0).collect::<Vec<_>>();
+        results.sort_by_key(|result| result.is_err());
+        assert_eq!(vec![Ok(()), Ok(()), Err(Error::Backoff)], results);
+    }
+
+    #[test]
+    fn serialized_request() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default().num_tservers(0));
+
+        let messenger = Messenger::new().unwrap();
+        let (send, recv) = sync_channel::<(Result<()>, Rpc)>(0);
+        let mut rpc = master::list_masters(cluster.master_addrs()[0],
+                                           Instant::now() + Duration::from_secs(5),
+                                           kudu_pb::master::ListMastersRequestPB::new());
+        rpc.serialize_request().unwrap();
+        assert!(rpc.serialized_request.is_some());
+        rpc.callback = Some(retry_channel_callback(messenger.clone(), send));
+
+        messenger.send(rpc);
+        let (result, rpc) = recv.recv().unwrap();
+        assert_eq!(Ok(()), result);
+        assert_eq!(1, rpc.response::<kudu_pb::master::ListMastersResponsePB>().get_masters().len());
+    }
+
     #[test]
     fn send_concurrent() {
         let _ = env_logger::init();
@@ -324,7 +1090,7 @@ mod tests {
 
         match result {
             Ok(()) => panic!("expected failure"),
-            Err(Error::TimedOut) => (),
+            Err(ref error) if error.is_timeout() => (),
             Err(other) => panic!("unexpected error: {}", other),
         }
 
@@ -335,6 +1101,27 @@ mod tests {
         assert!(elapsed < Duration::from_millis(150), "expected: 100ms, elapsed: {:?}", elapsed);
     }
 
+    #[test]
+    fn shutdown_timers() {
+        let _ = env_logger::init();
+        let messenger = Messenger::new().unwrap();
+
+        // Pending timers fire early at shutdown, and observe that the messenger is shut down.
+        let (send, recv) = sync_channel(2);
+        {
+            let messenger = messenger.clone();
+            let send = send.clone();
+            messenger.clone().timer(Duration::from_secs(60),
+                                    Box::new(move || send.send(messenger.is_shut_down()).unwrap()));
+        }
+        assert!(messenger.shutdown(Duration::from_secs(10)));
+        assert_eq!(Ok(true), recv.recv_timeout(Duration::from_secs(1)));
+
+        // Timers added after shutdown fire immediately.
+        messenger.timer(Duration::from_secs(60), Box::new(move || send.send(true).unwrap()));
+        assert_eq!(Ok(true), recv.try_recv());
+    }
+
     #[test]
     fn cancel() {
         let _ = env_logger::init();
@@ -399,7 +1186,90 @@ mod tests {
                                kudu_pb::master::PingRequestPB::new());
 
         let (result, _) = messenger.send_sync(rpc);
-        assert_eq!(Err(Error::ConnectionError), result);
+        match result {
+            Err(Error::ConnectionReset(ref error)) => {
+                assert_eq!(&Error::ConnectionError, error.cause());
+                assert_eq!(1, error.attempts());
+                assert!(error.elapsed() < Duration::from_secs(1));
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    /// Tests that the typed service interface surfaces connection failures.
+    #[test]
+    fn typed_service_connection_error() {
+        let _ = env_logger::init();
+        let messenger = Messenger::new().unwrap();
+
+        let (send, recv) = sync_channel(1);
+        MasterService::ping(&messenger,
+                            mini_cluster::get_unbound_address(),
+                            Instant::now() + Duration::from_millis(100),
+                            kudu_pb::master::PingRequestPB::new(),
+                            move |result| send.send(result).unwrap());
+        assert_eq!(&Error::ConnectionError, recv.recv().unwrap().unwrap_err().root_cause());
+    }
+
+    /// Tests that a server's circuit breaker opens after consecutive connection failures, and
+    /// fails subsequent RPCs without attempting to connect.
+    #[test]
+    fn circuit_breaker() {
+        let _ = env_logger::init();
+        let mut options = ConnectionOptions::default();
+        options.circuit_breaker_threshold = Some(1);
+        options.circuit_breaker_open_duration = Duration::from_secs(60);
+        let messenger = Messenger::with_options(options).unwrap();
+        let events = messenger.connection_events(16);
+        let addr = mini_cluster::get_unbound_address();
+
+        let rpc = master::ping(addr,
+                               Instant::now() + Duration::from_secs(5),
+                               kudu_pb::master::PingRequestPB::new());
+        let (result, _) = messenger.send_sync(rpc);
+        assert_eq!(&Error::ConnectionError, result.unwrap_err().root_cause());
+        assert_eq!(1, messenger.metrics().circuit_breaker_trips());
+        assert!(events.try_iter().any(|event| event == ConnectionEvent::CircuitOpened(addr)));
+
+        let rpc = master::ping(addr,
+                               Instant::now() + Duration::from_secs(5),
+                               kudu_pb::master::PingRequestPB::new());
+        let (result, _) = messenger.send_sync(rpc);
+        assert_eq!(Err(Error::CircuitOpen(addr)), result);
+        assert_eq!(1, messenger.metrics().circuit_breaker_rejections());
+    }
+
+    /// Tests that an RPC which fails with a network error is not retried once the retry budget is
+    /// exhausted.
+    #[test]
+    fn retry_budget_exhausted() {
+        let _ = env_logger::init();
+        let mut options = ConnectionOptions::default();
+        options.retry_budget = Some(0);
+        let messenger = Messenger::with_options(options).unwrap();
+
+        let mut rpc = master::ping(mini_cluster::get_unbound_address(),
+                                   Instant::now() + Duration::from_secs(5),
+                                   kudu_pb::master::PingRequestPB::new());
+        let (send, recv) = sync_channel::<(Result<()>, Rpc)>(1);
+        rpc.callback = Some(retry_channel_callback(messenger.clone(), send));
+        messenger.send(rpc);
+
+        let (result, _) = recv.recv().unwrap();
+        assert_eq!(&Error::ConnectionError, result.unwrap_err().root_cause());
+        assert_eq!(1, messenger.retry_budget().refused());
+
+        // RPCs which the connection would resend after a reset fail instead.
+        let mut rpc = master::ping(mini_cluster::get_unbound_address(),
+                                   Instant::now() + Duration::from_secs(5),
+                                   kudu_pb::master::PingRequestPB::new());
+        rpc.fail_fast = false;
+        let (result, _) = messenger.send_sync(rpc);
+        match result {
+            Err(Error::ConnectionReset(ref error)) => assert_eq!(1, error.attempts()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(2, messenger.retry_budget().refused());
     }
 
     /// Tests that a connection will fail an RPC after a failure to connect.
@@ -431,6 +1301,6 @@ mod tests {
                                kudu_pb::master::PingRequestPB::new());
 
         let (result, _) = messenger.send_sync(rpc);
-        assert_eq!(Err(Error::ConnectionError), result);
+        assert_eq!(&Error::ConnectionError, result.unwrap_err().root_cause());
     }
 }
@@ -0,0 +1,165 @@
+#[cfg(feature = "alloc-stats")]
+use std::collections::HashMap;
+#[cfg(feature = "alloc-stats")]
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+#[cfg(feature = "alloc-stats")]
+use alloc_stats::{self, AllocationStats};
+use util::duration_to_us;
+
+/// Counters tracking the RPC activity of a client.
+///
+/// The counters are updated by the RPC I/O thread, and may be read from any thread.
+#[derive(Debug, Default)]
+pub struct RpcMetrics {
+    scope: Option<String>,
+    server_too_busy_retries: AtomicUsize,
+    circuit_breaker_trips: AtomicUsize,
+    circuit_breaker_rejections: AtomicUsize,
+    completed_rpcs: AtomicUsize,
+    total_queue_time_us: AtomicUsize,
+    total_processing_time_us: AtomicUsize,
+    negotiations: AtomicUsize,
+    /// Allocation counts by service and method name.
+    #[cfg(feature = "alloc-stats")]
+    allocations: Mutex<HashMap<(&'static str, &'static str), AllocationStats>>,
+}
+
+impl RpcMetrics {
+
+    #[doc(hidden)]
+    pub fn with_scope(scope: Option<String>) -> RpcMetrics {
+        RpcMetrics { scope: scope, .. RpcMetrics::default() }
+    }
+
+    /// Returns the name of the metrics scope, if one was configured. See
+    /// `ClientConfig::set_metrics_scope`.
+    pub fn scope(&self) -> Option<&str> {
+        self.scope.as_ref().map(String::as_str)
+    }
+
+    /// Returns the number of RPCs which have been retried after the server rejected them with a
+    /// `ServerTooBusy` error.
+    pub fn server_too_busy_retries(&self) -> usize {
+        self.server_too_busy_retries.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of times a server's circuit breaker has opened.
+    pub fn circuit_breaker_trips(&self) -> usize {
+        self.circuit_breaker_trips.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of RPCs which failed without being sent because the circuit breaker of
+    /// their server was open.
+    pub fn circuit_breaker_rejections(&self) -> usize {
+        self.circuit_breaker_rejections.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of RPCs for which a response has been received.
+    pub fn completed_rpcs(&self) -> usize {
+        self.completed_rpcs.load(Ordering::Relaxed)
+    }
+
+    /// Returns the cumulative time completed RPCs spent queued in the client before being sent.
+    pub fn total_queue_time(&self) -> Duration {
+        us_to_duration(self.total_queue_time_us.load(Ordering::Relaxed))
+    }
+
+    /// Returns the cumulative time between sending completed RPCs and receiving their responses.
+    pub fn total_processing_time(&self) -> Duration {
+        us_to_duration(self.total_processing_time_us.load(Ordering::Relaxed))
+    }
+
+    /// Returns the number of connections which have completed negotiation with their server,
+    /// including reconnections after a connection was reset.
+    ///
+    /// Connections are not encrypted, so each negotiation is a full SASL exchange; there is no TLS
+    /// session to resume when reconnecting.
+    pub fn negotiations(&self) -> usize {
+        self.negotiations.load(Ordering::Relaxed)
+    }
+
+    #[doc(hidden)]
+    pub fn record_rpc_timings(&self, queue_time: Duration, processing_time: Duration) {
+        self.completed_rpcs.fetch_add(1, Ordering::Relaxed);
+        self.total_queue_time_us.fetch_add(duration_to_us(&queue_time) as usize, Ordering::Relaxed);
+        self.total_processing_time_us.fetch_add(duration_to_us(&processing_time) as usize,
+                                                Ordering::Relaxed);
+    }
+
+    #[doc(hidden)]
+    pub fn record_server_too_busy_retry(&self) {
+        self.server_too_busy_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[doc(hidden)]
+    pub fn record_circuit_breaker_trip(&self) {
+        self.circuit_breaker_trips.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[doc(hidden)]
+    pub fn record_circuit_breaker_rejection(&self) {
+        self.circuit_breaker_rejections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[doc(hidden)]
+    pub fn record_negotiation(&self) {
+        self.negotiations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the allocations made while serializing requests and deserializing responses, for
+    /// each RPC method which has been called, ordered by service and method name. Allocations are
+    /// only counted if `CountingAllocator` is the global allocator.
+    #[cfg(feature = "alloc-stats")]
+    pub fn allocation_stats(&self) -> Vec<AllocationStats> {
+        let mut stats = self.allocations.lock().unwrap().values().cloned().collect::<Vec<_>>();
+        stats.sort_by_key(|stats| (stats.service_name(), stats.method_name()));
+        stats
+    }
+
+    /// Serializes a request of the RPC method with `f`, counting its allocations.
+    #[doc(hidden)]
+    #[cfg(feature = "alloc-stats")]
+    pub fn measure_request<F, T>(&self, service_name: &'static str, method_name: &'static str, f: F) -> T
+    where F: FnOnce() -> T {
+        let (result, allocations, bytes) = alloc_stats::measure(f);
+        self.allocations.lock().unwrap()
+            .entry((service_name, method_name))
+            .or_insert_with(|| AllocationStats::new(service_name, method_name))
+            .record_request(allocations, bytes);
+        result
+    }
+
+    #[doc(hidden)]
+    #[cfg(not(feature = "alloc-stats"))]
+    pub fn measure_request<F, T>(&self, _: &'static str, _: &'static str, f: F) -> T
+    where F: FnOnce() -> T {
+        f()
+    }
+
+    /// Deserializes a response of the RPC method with `f`, counting its allocations.
+    #[doc(hidden)]
+    #[cfg(feature = "alloc-stats")]
+    pub fn measure_response<F, T>(&self, service_name: &'static str, method_name: &'static str, f: F) -> T
+    where F: FnOnce() -> T {
+        let (result, allocations, bytes) = alloc_stats::measure(f);
+        self.allocations.lock().unwrap()
+            .entry((service_name, method_name))
+            .or_insert_with(|| AllocationStats::new(service_name, method_name))
+            .record_response(allocations, bytes);
+        result
+    }
+
+    #[doc(hidden)]
+    #[cfg(not(feature = "alloc-stats"))]
+    pub fn measure_response<F, T>(&self, _: &'static str, _: &'static str, f: F) -> T
+    where F: FnOnce() -> T {
+        f()
+    }
+}
+
+fn us_to_duration(us: usize) -> Duration {
+    Duration::new((us / 1000_000) as u64, (us % 1000_000) as u32 * 1000)
+}
@@ -0,0 +1,192 @@
+//! A scripted KRPC server for deterministic connection tests.
+//!
+//! The server accepts a single connection, completes SASL PLAIN negotiation, and responds to every
+//! RPC with an empty message, optionally after holding back responses until a number of requests
+//! have been received. The header of every RPC request is recorded, so that tests can check
+//! exactly which calls the connection sent, and in which order. The server can also misbehave
+//! during negotiation, in the ways some servers are known to.
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use kudu_pb::rpc_header::{
+    RequestHeader,
+    ResponseHeader,
+    SaslMessagePB,
+    SaslMessagePB_SaslAuth,
+    SaslMessagePB_SaslState as SaslState,
+};
+use protobuf::{parse_length_delimited_from, CodedInputStream, Message};
+
+use rpc::connection::{CONNECTION_CONTEXT_CALL_ID, SASL_CALL_ID};
+
+pub struct MockServer {
+    addr: SocketAddr,
+    requests: Receiver<RequestHeader>,
+}
+
+/// The behavior of a mock server.
+#[derive(Clone, Copy)]
+struct Script {
+    /// Number of outstanding RPCs at which the server responds.
+    response_batch: usize,
+    /// The call ID of the server's SASL negotiation responses.
+    sasl_call_id: i32,
+    /// The SASL mechanism offered by the server.
+    sasl_mechanism: &'static str,
+    /// The sidecars attached to each RPC response.
+    sidecars: &'static [&'static [u8]],
+}
+
+impl MockServer {
+
+    /// Starts a server which responds to each RPC as soon as it is received.
+    pub fn start() -> MockServer {
+        MockServer::with_script(Script { response_batch: 1,
+                                         sasl_call_id: SASL_CALL_ID,
+                                         sasl_mechanism: "PLAIN",
+                                         sidecars: &[] })
+    }
+
+    /// Starts a server which withholds responses until `batch` RPCs are outstanding, and then
+    /// responds to all of them at once in reverse order.
+    pub fn with_response_batch(batch: usize) -> MockServer {
+        MockServer::with_script(Script { response_batch: batch,
+                                         sasl_call_id: SASL_CALL_ID,
+                                         sasl_mechanism: "PLAIN",
+                                         sidecars: &[] })
+    }
+
+    /// Starts a server which responds to SASL negotiation messages with the provided call ID.
+    pub fn with_sasl_call_id(call_id: i32) -> MockServer {
+        MockServer::with_script(Script { response_batch: 1,
+                                         sasl_call_id: call_id,
+                                         sasl_mechanism: "PLAIN",
+                                         sidecars: &[] })
+    }
+
+    /// Starts a server which offers only the provided SASL mechanism during negotiation.
+    pub fn with_sasl_mechanism(mechanism: &'static str) -> MockServer {
+        MockServer::with_script(Script { response_batch: 1,
+                                         sasl_call_id: SASL_CALL_ID,
+                                         sasl_mechanism: mechanism,
+                                         sidecars: &[] })
+    }
+
+    /// Starts a server which attaches the sidecars to each RPC response. The response message
+    /// holds an unknown field, so that it is not empty.
+    pub fn with_sidecars(sidecars: &'static [&'static [u8]]) -> MockServer {
+        MockServer::with_script(Script { response_batch: 1,
+                                         sasl_call_id: SASL_CALL_ID,
+                                         sasl_mechanism: "PLAIN",
+                                         sidecars: sidecars })
+    }
+
+    fn with_script(script: Script) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (send, recv) = channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            if let Err(error) = serve(stream, script, send) {
+                debug!("mock server connection closed: {}", error);
+            }
+        });
+        MockServer {
+            addr: addr,
+            requests: recv,
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the headers of the RPC requests received so far, in the order they were received.
+    pub fn requests(&self) -> Vec<RequestHeader> {
+        self.requests.try_iter().collect()
+    }
+}
+
+fn serve(mut stream: TcpStream, script: Script, requests: Sender<RequestHeader>) -> io::Result<()> {
+    let mut connection_header = [0; 7];
+    try!(stream.read_exact(&mut connection_header));
+    assert_eq!(b"hrpc\x09\0\0", &connection_header);
+
+    let mut outstanding = Vec::new();
+    loop {
+        let len = try!(stream.read_u32::<BigEndian>()) as usize;
+        let mut msg = vec![0; len];
+        try!(stream.read_exact(&mut msg));
+
+        let mut input = CodedInputStream::from_bytes(&msg);
+        let header: RequestHeader = parse_length_delimited_from(&mut input).unwrap();
+        match header.get_call_id() {
+            SASL_CALL_ID => {
+                let request: SaslMessagePB = parse_length_delimited_from(&mut input).unwrap();
+                let mut response = SaslMessagePB::new();
+                match request.get_state() {
+                    SaslState::NEGOTIATE => {
+                        response.set_state(SaslState::NEGOTIATE);
+                        let mut auth = SaslMessagePB_SaslAuth::new();
+                        auth.set_mechanism(script.sasl_mechanism.to_owned());
+                        response.mut_auths().push(auth);
+                    },
+                    SaslState::INITIATE => response.set_state(SaslState::SUCCESS),
+                    state => panic!("unexpected SASL message: {:?}", state),
+                }
+                try!(respond(&mut stream, script.sasl_call_id,
+                             &response.write_length_delimited_to_bytes().unwrap(), &[]));
+            },
+            // The connection context does not have a response.
+            CONNECTION_CONTEXT_CALL_ID => (),
+            call_id => {
+                let _ = requests.send(header);
+                outstanding.push(call_id);
+                if outstanding.len() >= script.response_batch {
+                    for call_id in outstanding.drain(..).rev() {
+                        if script.sidecars.is_empty() {
+                            // An empty message, which decodes as any response type without
+                            // required fields.
+                            try!(respond(&mut stream, call_id, &[0], &[]));
+                        } else {
+                            // A message holding only field 1 as a varint, which decodes as an
+                            // unknown field of response types without field 1.
+                            try!(respond(&mut stream, call_id, &[2, 0x08, 0x01], script.sidecars));
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// Writes a response with the length-delimited message and the sidecars to the stream. Sidecar
+/// offsets are relative to the end of the message's length prefix.
+fn respond(stream: &mut TcpStream, call_id: i32, msg: &[u8], sidecars: &[&[u8]]) -> io::Result<()> {
+    let prefix_len = {
+        let mut input = CodedInputStream::from_bytes(msg);
+        input.read_raw_varint32().unwrap();
+        input.pos() as usize
+    };
+    let mut header = ResponseHeader::new();
+    header.set_call_id(call_id);
+    let mut offset = (msg.len() - prefix_len) as u32;
+    for sidecar in sidecars {
+        header.mut_sidecar_offsets().push(offset);
+        offset += sidecar.len() as u32;
+    }
+    let mut body = header.write_length_delimited_to_bytes().unwrap();
+    body.extend_from_slice(msg);
+    for sidecar in sidecars {
+        body.extend_from_slice(sidecar);
+    }
+
+    let mut frame = Vec::with_capacity(body.len() + 4);
+    try!(frame.write_u32::<BigEndian>(body.len() as u32));
+    frame.extend_from_slice(&body);
+    stream.write_all(&frame)
+}
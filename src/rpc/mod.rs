@@ -1,22 +1,78 @@
 use std::any::Any;
 use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{SyncSender, SendError};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
+use kudu_pb::rpc_header::RequestIdPB;
 use protobuf::Message;
 
+pub use rpc::call_trace::CallRecord;
+pub use rpc::connection::{ConnectionEvent, ConnectionOptions};
 pub use rpc::messenger::Messenger;
+pub use rpc::metrics::RpcMetrics;
+pub use rpc::request_tracker::RequestTracker;
+pub use rpc::runtime::{ReactorTask, Runtime, ThreadRuntime};
+#[cfg(feature = "blocking")]
+pub use rpc::blocking::{BlockingConnection, BlockingMasterClient};
 
 use Error;
 use Result;
 
+/// Generates a typed service trait from a list of RPC methods, and implements it for `Messenger`.
+///
+/// Each method takes the destination address, a deadline, and the request message, and fires the
+/// callback with the typed response once the RPC completes. Methods which return sidecars should
+/// declare `WithSidecars<ResponsePB>` as their response type. The module invoking the macro must
+/// also define the corresponding `rpc!` constructor function for each method.
+macro_rules! service {
+    ($(#[$attr:meta])* pub trait $service:ident {
+        $( fn $fn_name:ident($request_type:ty) -> $response_type:ty; )*
+    }) => {
+        $(#[$attr])*
+        pub trait $service {
+            $(
+                fn $fn_name<F>(&self, addr: SocketAddr, deadline: Instant, request: $request_type, cb: F)
+                where F: FnOnce(Result<$response_type>) + Send + 'static;
+            )*
+        }
+
+        impl $service for Messenger {
+            $(
+                fn $fn_name<F>(&self, addr: SocketAddr, deadline: Instant, request: $request_type, cb: F)
+                where F: FnOnce(Result<$response_type>) + Send + 'static {
+                    let mut rpc = $fn_name(addr, deadline, request);
+                    rpc.callback = Some(Box::new(move |result: Result<()>, rpc: Rpc| {
+                        cb(result.map(|()| <$response_type as FromRpc>::from_rpc(rpc)))
+                    }));
+                    self.send(rpc);
+                }
+            )*
+        }
+    };
+}
+
+#[cfg(feature = "blocking")]
+mod blocking;
+mod call_trace;
+mod circuit_breaker;
 mod connection;
+mod decode_pool;
 pub mod master;
 mod messenger;
+mod metrics;
+mod request_tracker;
+mod runtime;
 pub mod tablet_server;
+#[cfg(feature = "admin")]
+pub mod tablet_server_admin;
+
+#[cfg(test)]
+mod mock_server;
 
 /// A callback that will be executed when an RPC is complete. If the RPC succeeds, the result will
 /// be `Ok`, and the RPC will contain the response and sidecars. Othewise, the result will contain
@@ -59,6 +115,10 @@ impl<F> Callback for F where F: FnOnce(Result<()>, Rpc) + Send {
 /// fail-fast, it will be retried to the same destination until it either fails, or it times out.
 /// Fail-fast RPCs are useful for situations where the RPC could be retried against an alternate
 /// remote server.
+///
+/// The `timings` of an `Rpc` record when it was queued in the connection, written to the socket,
+/// and when the response was received, which allows distinguishing client-side queuing delays from
+/// network and server latency.
 pub struct Rpc {
     pub addr: SocketAddr,
     pub service_name: &'static str,
@@ -66,11 +126,57 @@ pub struct Rpc {
     pub deadline: Instant,
     pub required_feature_flags: Vec<u32>,
     pub request: Box<Message>,
+    /// The length-delimited encoding of `request`, if it has been serialized ahead of time.
+    pub serialized_request: Option<Vec<u8>>,
     pub response: Box<Message>,
     pub sidecars: Vec<Vec<u8>>,
     pub callback: Option<Box<Callback>>,
     pub cancel: Option<Arc<AtomicBool>>,
     pub fail_fast: bool,
+    pub timings: RpcTimings,
+    /// The errors which the RPC was retried after, oldest first: `Error::ConnectionReset` errors
+    /// for connection resets, and the server's `ServerTooBusy` rejections.
+    pub failed_attempts: Vec<Error>,
+    /// The request ID of the RPC, if the server should apply it exactly once. The sender sets the
+    /// attempt number whenever it resends the RPC, and the connection adds the number of attempts
+    /// lost to connection resets.
+    pub request_id: Option<RequestIdPB>,
+}
+
+/// Timestamps marking the progress of the most recent attempt of an `Rpc`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RpcTimings {
+    /// The time at which the RPC was queued in the connection.
+    pub queued: Option<Instant>,
+    /// The time at which the RPC was written to the connection's send buffer.
+    pub sent: Option<Instant>,
+    /// The time at which the RPC response was received.
+    pub received: Option<Instant>,
+    /// The time at which the RPC was first queued in a connection. Unlike `queued`, this is not
+    /// reset when the RPC is retried.
+    pub first_queued: Option<Instant>,
+    /// The number of attempts to deliver the RPC which failed due to a connection reset.
+    pub attempts: u32,
+}
+
+impl RpcTimings {
+
+    /// Returns the time the RPC spent queued in the client before being sent.
+    pub fn queue_time(&self) -> Option<Duration> {
+        match (self.queued, self.sent) {
+            (Some(queued), Some(sent)) => Some(sent.duration_since(queued)),
+            _ => None,
+        }
+    }
+
+    /// Returns the time between sending the RPC and receiving the response, which includes
+    /// network transit time and server processing time.
+    pub fn processing_time(&self) -> Option<Duration> {
+        match (self.sent, self.received) {
+            (Some(sent), Some(received)) => Some(received.duration_since(sent)),
+            _ => None,
+        }
+    }
 }
 
 impl Rpc {
@@ -86,6 +192,30 @@ impl Rpc {
         }
     }
 
+    /// Fails the RPC because its deadline passed, with `Error::WireTimedOut` if the current
+    /// attempt was sent, or `Error::QueueTimedOut` otherwise. If the RPC was retried, the timeout
+    /// is wrapped in `Error::DeadlineExceeded`, following the error of each earlier attempt.
+    pub fn time_out(self) {
+        let error = self.timeout_error();
+        self.fail(error)
+    }
+
+    /// Returns the error with which `time_out` fails the RPC.
+    pub fn timeout_error(&self) -> Error {
+        let error = if self.timings.sent.is_some() { Error::WireTimedOut } else { Error::QueueTimedOut };
+        error.after_attempts(self.failed_attempts.clone())
+    }
+
+    /// Serializes the request message, so that the connection only has to copy it into the send
+    /// buffer. Large requests should be serialized before being sent in order to keep the work
+    /// done on the I/O thread to a minimum. The request must not be modified afterwards.
+    pub fn serialize_request(&mut self) -> Result<()> {
+        let mut buf = Vec::with_capacity(self.request.compute_size() as usize + 5);
+        try!(self.request.write_length_delimited_to(&mut buf));
+        self.serialized_request = Some(buf);
+        Ok(())
+    }
+
     pub fn response<T>(&self) -> &T where T: Any {
         self.response.as_any().downcast_ref::<T>().unwrap()
     }
@@ -124,7 +254,127 @@ impl fmt::Debug for Rpc {
     }
 }
 
-/// A callback which retries the RPC on network error.
+/// An `Rpc` whose request and response messages are known to be of types `Req` and `Resp`.
+///
+/// The RPC constructors of each service return typed RPCs, so that the request and response can
+/// be accessed as their concrete Protobuf types. The untyped `Rpc` is available through `Deref`
+/// for setting the callback and other options, and typed RPCs may be sent wherever an `Rpc` is
+/// expected.
+pub struct TypedRpc<Req, Resp> {
+    rpc: Rpc,
+    _marker: PhantomData<fn(Req) -> Resp>,
+}
+
+impl <Req, Resp> TypedRpc<Req, Resp> where Req: Message + Any, Resp: Message + Any {
+
+    /// Returns the RPC as a typed RPC, or `None` if its messages are not of types `Req` and
+    /// `Resp`.
+    pub fn from_rpc(rpc: Rpc) -> Option<TypedRpc<Req, Resp>> {
+        if rpc.request.as_any().is::<Req>() && rpc.response.as_any().is::<Resp>() {
+            Some(TypedRpc { rpc: rpc, _marker: PhantomData })
+        } else {
+            None
+        }
+    }
+
+    pub fn request(&self) -> &Req {
+        self.rpc.request.as_any().downcast_ref::<Req>().unwrap()
+    }
+
+    /// Returns the request message for modification. If the request has been serialized ahead of
+    /// time, the serialized request is discarded.
+    pub fn request_mut(&mut self) -> &mut Req {
+        self.rpc.serialized_request = None;
+        self.rpc.request.as_any_mut().downcast_mut::<Req>().unwrap()
+    }
+
+    /// Returns the response message. The response is only populated once the RPC completes
+    /// successfully.
+    pub fn response(&self) -> &Resp {
+        self.rpc.response::<Resp>()
+    }
+
+    pub fn response_mut(&mut self) -> &mut Resp {
+        self.rpc.response_mut::<Resp>()
+    }
+
+    pub fn take_response(self) -> Resp {
+        self.rpc.take_response::<Resp>()
+    }
+
+    /// Sets the callback which is fired with the typed RPC when it completes.
+    pub fn set_callback<F>(&mut self, cb: F) where F: FnOnce(Result<()>, TypedRpc<Req, Resp>) + Send + 'static {
+        self.rpc.callback = Some(Box::new(move |result: Result<()>, rpc: Rpc| {
+            cb(result, TypedRpc { rpc: rpc, _marker: PhantomData })
+        }));
+    }
+
+    /// Sends the RPC and waits for it to complete.
+    pub fn send_sync(self, messenger: &Messenger) -> (Result<()>, TypedRpc<Req, Resp>) {
+        let (result, rpc) = messenger.send_sync(self.rpc);
+        (result, TypedRpc { rpc: rpc, _marker: PhantomData })
+    }
+
+    pub fn into_rpc(self) -> Rpc {
+        self.rpc
+    }
+}
+
+impl <Req, Resp> Deref for TypedRpc<Req, Resp> {
+    type Target = Rpc;
+    fn deref(&self) -> &Rpc {
+        &self.rpc
+    }
+}
+
+impl <Req, Resp> DerefMut for TypedRpc<Req, Resp> {
+    fn deref_mut(&mut self) -> &mut Rpc {
+        &mut self.rpc
+    }
+}
+
+impl <Req, Resp> From<TypedRpc<Req, Resp>> for Rpc {
+    fn from(rpc: TypedRpc<Req, Resp>) -> Rpc {
+        rpc.rpc
+    }
+}
+
+impl <Req, Resp> fmt::Debug for TypedRpc<Req, Resp> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.rpc.fmt(f)
+    }
+}
+
+/// Extracts a typed response from a completed `Rpc`.
+pub trait FromRpc: Sized {
+    fn from_rpc(rpc: Rpc) -> Self;
+}
+
+impl <T> FromRpc for T where T: Message + Any {
+    fn from_rpc(rpc: Rpc) -> T {
+        rpc.take_response::<T>()
+    }
+}
+
+/// A response message along with the sidecars which accompanied it on the wire.
+#[derive(Debug)]
+pub struct WithSidecars<T> {
+    pub response: T,
+    pub sidecars: Vec<Vec<u8>>,
+}
+
+impl <T> FromRpc for WithSidecars<T> where T: Message + Any {
+    fn from_rpc(mut rpc: Rpc) -> WithSidecars<T> {
+        let sidecars = ::std::mem::replace(&mut rpc.sidecars, Vec::new());
+        WithSidecars {
+            response: rpc.take_response::<T>(),
+            sidecars: sidecars,
+        }
+    }
+}
+
+/// A callback which retries the RPC on network error, as long as the messenger's retry budget is
+/// not exhausted.
 #[derive(Clone)]
 pub struct RetryNetworkErrorCB<F>{
     messenger: Messenger,
@@ -140,7 +390,11 @@ impl <F> RetryNetworkErrorCB<F> where F: FnMut(Result<()>, Rpc) + Send + 'static
 }
 impl <F> Callback for RetryNetworkErrorCB<F> where F: FnMut(Result<()>, Rpc) + Send + 'static {
     fn callback(mut self: Box<Self>, result: Result<()>, mut rpc: Rpc) {
-        if result.as_ref().err().map_or(false, |error| error.is_network_error()) {
+        let retry = match result {
+            Err(ref error) => error.is_network_error(),
+            Ok(_) => false,
+        };
+        if retry && self.messenger.retry_budget().try_retry() {
             trace!("retrying RPC {:?} after network error: {}", rpc, result.unwrap_err());
             let messenger = self.messenger.clone();
             rpc.response.clear();
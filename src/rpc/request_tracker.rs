@@ -0,0 +1,108 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use kudu_pb::rpc_header::RequestIdPB;
+use parking_lot::Mutex;
+use rand;
+
+use util;
+
+/// Assigns request IDs to the client's write RPCs, so that tablet servers apply each write exactly
+/// once, even if it is sent more than once.
+///
+/// A request ID is the client's random ID, a sequence number unique to the request, and the
+/// sequence number of the client's oldest incomplete request. Servers cache the response of each
+/// request and reply to resent requests with the cached response, until the client reports through
+/// the oldest incomplete sequence number that the request has completed.
+///
+/// Trackers are cheap to clone, and clones share their sequence numbers.
+#[derive(Clone, Debug)]
+pub struct RequestTracker {
+    client_id: Arc<String>,
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    next_seq_no: i64,
+    /// The sequence numbers of the requests which have not completed.
+    incomplete: BTreeSet<i64>,
+}
+
+impl RequestTracker {
+
+    pub fn new() -> RequestTracker {
+        RequestTracker::with_client_id(util::hex_encode(&rand::random::<[u8; 16]>()))
+    }
+
+    pub fn with_client_id(client_id: String) -> RequestTracker {
+        RequestTracker {
+            client_id: Arc::new(client_id),
+            inner: Arc::new(Mutex::new(Inner {
+                next_seq_no: 0,
+                incomplete: BTreeSet::new(),
+            })),
+        }
+    }
+
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
+    /// Returns a new request ID. The request must be reported with `complete` once it has
+    /// completed, whether or not it succeeded.
+    pub fn new_request_id(&self) -> RequestIdPB {
+        let mut inner = self.inner.lock();
+        let seq_no = inner.next_seq_no;
+        inner.next_seq_no += 1;
+        inner.incomplete.insert(seq_no);
+
+        let mut request_id = RequestIdPB::new();
+        request_id.set_client_id((*self.client_id).clone());
+        request_id.set_seq_no(seq_no);
+        request_id.set_first_incomplete_seq_no(*inner.incomplete.iter().next().unwrap());
+        request_id.set_attempt_no(1);
+        request_id
+    }
+
+    /// Records that the request with the sequence number has completed.
+    pub fn complete(&self, seq_no: i64) {
+        self.inner.lock().incomplete.remove(&seq_no);
+    }
+
+    /// Returns the sequence number of the oldest incomplete request, or `None` if every request
+    /// has completed.
+    pub fn first_incomplete_seq_no(&self) -> Option<i64> {
+        self.inner.lock().incomplete.iter().next().cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_request_tracker() {
+        let tracker = RequestTracker::with_client_id("client".to_string());
+        assert_eq!(None, tracker.first_incomplete_seq_no());
+
+        let first = tracker.new_request_id();
+        let second = tracker.new_request_id();
+        assert_eq!("client", first.get_client_id());
+        assert_eq!((0, 0), (first.get_seq_no(), first.get_first_incomplete_seq_no()));
+        assert_eq!((1, 0), (second.get_seq_no(), second.get_first_incomplete_seq_no()));
+
+        // Requests may complete out of order.
+        tracker.complete(1);
+        assert_eq!(Some(0), tracker.first_incomplete_seq_no());
+        tracker.complete(0);
+        assert_eq!(None, tracker.first_incomplete_seq_no());
+        assert_eq!((2, 2), {
+            let third = tracker.new_request_id();
+            (third.get_seq_no(), third.get_first_incomplete_seq_no())
+        });
+
+        assert_eq!(32, RequestTracker::new().client_id().len());
+    }
+}
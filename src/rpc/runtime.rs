@@ -0,0 +1,67 @@
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use rpc::messenger::{Loop, MessengerHandler};
+
+/// Runs the client's RPC I/O event loops.
+///
+/// By default each event loop runs on a thread spawned by the client. Applications which manage
+/// their own threads, for instance with a thread pool dedicated to blocking work, can provide a
+/// runtime to `ClientBuilder::set_runtime` so that the event loops run on those threads instead.
+pub trait Runtime: fmt::Debug + Send + Sync {
+
+    /// Runs the event loop task. The task blocks the thread which runs it until the client is shut
+    /// down or dropped, so it must be given a thread of its own; running it on a thread shared with
+    /// other work, such as a reactor thread of an asynchronous runtime, will stall that work.
+    ///
+    /// The client spawns one task for each of its configured reactor threads, when it is created.
+    fn spawn(&self, task: ReactorTask);
+}
+
+/// A runtime which runs each event loop on a new thread.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadRuntime;
+
+impl Runtime for ThreadRuntime {
+    fn spawn(&self, task: ReactorTask) {
+        thread::spawn(move || task.run());
+    }
+}
+
+/// An RPC I/O event loop, which runs until the client is shut down or dropped.
+pub struct ReactorTask {
+    event_loop: Loop,
+    handler: MessengerHandler,
+    /// Signalled once the event loop has exited.
+    exited: Sender<()>,
+}
+
+impl ReactorTask {
+
+    #[doc(hidden)]
+    pub fn new(event_loop: Loop, handler: MessengerHandler, exited: Sender<()>) -> ReactorTask {
+        ReactorTask {
+            event_loop: event_loop,
+            handler: handler,
+            exited: exited,
+        }
+    }
+
+    /// Runs the event loop on the current thread, until the client is shut down or dropped.
+    pub fn run(self) {
+        let ReactorTask { mut event_loop, mut handler, exited } = self;
+        if let Err(error) = event_loop.run(&mut handler) {
+            warn!("messenger event loop failed: {}", error);
+        }
+        // Close the connections before signalling that the loop has exited.
+        drop(handler);
+        let _ = exited.send(());
+    }
+}
+
+impl fmt::Debug for ReactorTask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ReactorTask")
+    }
+}
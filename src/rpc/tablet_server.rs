@@ -7,39 +7,78 @@ use kudu_pb::tserver::{
     ScanRequestPB, ScanResponsePB,
     ScannerKeepAliveRequestPB, ScannerKeepAliveResponsePB,
     ListTabletsRequestPB, ListTabletsResponsePB,
+    TabletServerFeatures,
 };
 use kudu_pb::tserver_service::{
     ChecksumRequestPB, ChecksumResponsePB,
 };
-use rpc::Rpc;
+use rpc::{FromRpc, Messenger, Rpc, RpcTimings, TypedRpc, WithSidecars};
+use Result;
 
 const SERVICE_NAME: &'static str = "kudu.tserver.TabletServerService";
 
 // When macros in type position and concat_idents! land the 3rd and 4th param can be dropped.
 // If/when Rust gets a snake -> camel ident converter the 2nd param can be dropped.
+//
+// RPCs whose requests may depend on application features take a 5th param: a function returning
+// the feature flags required by the request, which the server checks before handling it.
 macro_rules! rpc {
     ($fn_name:ident, $rpc_name:ident, $request_type:ident, $response_type:ident) => {
-        pub fn $fn_name(addr: SocketAddr, deadline: Instant, request: $request_type) -> Rpc {
-            Rpc {
+        rpc!($fn_name, $rpc_name, $request_type, $response_type, |_| Vec::new());
+    };
+    ($fn_name:ident, $rpc_name:ident, $request_type:ident, $response_type:ident, $features:expr) => {
+        pub fn $fn_name(addr: SocketAddr,
+                        deadline: Instant,
+                        request: $request_type)
+                        -> TypedRpc<$request_type, $response_type> {
+            let required_features: fn(&$request_type) -> Vec<u32> = $features;
+            let rpc = Rpc {
                 addr: addr,
                 service_name: SERVICE_NAME,
                 method_name: stringify!($rpc_name),
                 deadline: deadline,
-                required_feature_flags: Vec::new(),
+                required_feature_flags: required_features(&request),
                 request: Box::new(request),
+                serialized_request: None,
                 response: Box::new($response_type::new()),
                 sidecars: Vec::new(),
                 callback: None,
                 cancel: None,
                 fail_fast: true,
-            }
+                timings: RpcTimings::default(),
+                failed_attempts: Vec::new(),
+                request_id: None,
+            };
+            TypedRpc::from_rpc(rpc).unwrap()
         }
     };
 }
 
 rpc!(ping, Ping, PingRequestPB, PingResponsePB);
 rpc!(write, Write, WriteRequestPB, WriteResponsePB);
-rpc!(scan, Scan, ScanRequestPB, ScanResponsePB);
+rpc!(scan, Scan, ScanRequestPB, ScanResponsePB, scan_features);
 rpc!(scanner_keep_alive, ScannerKeepAlive, ScannerKeepAliveRequestPB, ScannerKeepAliveResponsePB);
 rpc!(list_tablets, ListTablets, ListTabletsRequestPB, ListTabletsResponsePB);
 rpc!(checksum, Checksum, ChecksumRequestPB, ChecksumResponsePB);
+
+/// Scans with column predicates require `COLUMN_PREDICATES`, since tablet servers which predate
+/// them would silently return unfiltered rows.
+fn scan_features(request: &ScanRequestPB) -> Vec<u32> {
+    if request.get_new_scan_request().get_column_predicates().is_empty() {
+        Vec::new()
+    } else {
+        vec![TabletServerFeatures::COLUMN_PREDICATES as u32]
+    }
+}
+
+service! {
+    /// The Kudu tablet server RPC service.
+    pub trait TabletServerService {
+        fn ping(PingRequestPB) -> PingResponsePB;
+        fn write(WriteRequestPB) -> WriteResponsePB;
+        fn scan(ScanRequestPB) -> WithSidecars<ScanResponsePB>;
+        fn scanner_keep_alive(ScannerKeepAliveRequestPB) -> ScannerKeepAliveResponsePB;
+        fn list_tablets(ListTabletsRequestPB) -> ListTabletsResponsePB;
+        fn checksum(ChecksumRequestPB) -> ChecksumResponsePB;
+    }
+}
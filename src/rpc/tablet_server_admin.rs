@@ -0,0 +1,54 @@
+//! The tablet server administration service, which is only available with the `admin` feature.
+
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use kudu_pb::tserver_admin::{
+    DeleteTabletRequestPB, DeleteTabletResponsePB,
+    QuiesceTabletServerRequestPB, QuiesceTabletServerResponsePB,
+};
+use rpc::{FromRpc, Messenger, Rpc, RpcTimings, TypedRpc};
+use Result;
+
+const SERVICE_NAME: &'static str = "kudu.tserver.TabletServerAdminService";
+
+// When macros in type position and concat_idents! land the 3rd and 4th param can be dropped.
+// If/when Rust gets a snake -> camel ident converter the 2nd param can be dropped.
+macro_rules! rpc {
+    ($fn_name:ident, $rpc_name:ident, $request_type:ident, $response_type:ident) => {
+        pub fn $fn_name(addr: SocketAddr,
+                        deadline: Instant,
+                        request: $request_type)
+                        -> TypedRpc<$request_type, $response_type> {
+            let rpc = Rpc {
+                addr: addr,
+                service_name: SERVICE_NAME,
+                method_name: stringify!($rpc_name),
+                deadline: deadline,
+                required_feature_flags: Vec::new(),
+                request: Box::new(request),
+                serialized_request: None,
+                response: Box::new($response_type::new()),
+                sidecars: Vec::new(),
+                callback: None,
+                cancel: None,
+                fail_fast: true,
+                timings: RpcTimings::default(),
+                failed_attempts: Vec::new(),
+                request_id: None,
+            };
+            TypedRpc::from_rpc(rpc).unwrap()
+        }
+    };
+}
+
+rpc!(delete_tablet, DeleteTablet, DeleteTabletRequestPB, DeleteTabletResponsePB);
+rpc!(quiesce, Quiesce, QuiesceTabletServerRequestPB, QuiesceTabletServerResponsePB);
+
+service! {
+    /// The Kudu tablet server administration RPC service.
+    pub trait TabletServerAdminService {
+        fn delete_tablet(DeleteTabletRequestPB) -> DeleteTabletResponsePB;
+        fn quiesce(QuiesceTabletServerRequestPB) -> QuiesceTabletServerResponsePB;
+    }
+}
@@ -0,0 +1,2013 @@
+use std::cmp;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::mem;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::vec;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use futures::{Async, Future, Poll, Sink, Stream};
+use futures::sync::mpsc;
+use kudu_pb::common::{OrderMode, ReadMode};
+use kudu_pb::tserver::{NewScanRequestPB, ResourceMetricsPB, ScanRequestPB, ScanResponsePB};
+
+use Column;
+use ColumnPredicate;
+use Predicates;
+use DataType;
+use EncodedPrimaryKey;
+use Error;
+use RaftRole;
+use Result;
+use Row;
+use Schema;
+use ServerFeature;
+use Table;
+use TableId;
+use Tablet;
+use TabletServerError;
+use TabletServerErrorCode;
+use TabletServerId;
+use backoff::Backoff;
+use key;
+use memory_budget::MemoryBudget;
+use meta_cache::Entry;
+use partition::Partition;
+use rpc::{Rpc, TypedRpc, tablet_server};
+use util;
+
+/// Server-side resource usage of a scan.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ResourceMetrics {
+    cfile_cache_miss_bytes: i64,
+    cfile_cache_hit_bytes: i64,
+}
+
+impl ResourceMetrics {
+
+    /// Number of bytes which were read from disk because they were not in the block cache.
+    pub fn cfile_cache_miss_bytes(&self) -> i64 {
+        self.cfile_cache_miss_bytes
+    }
+
+    /// Number of bytes which were served from the block cache.
+    pub fn cfile_cache_hit_bytes(&self) -> i64 {
+        self.cfile_cache_hit_bytes
+    }
+
+    /// Total number of bytes read by the scan.
+    pub fn total_bytes_read(&self) -> i64 {
+        self.cfile_cache_miss_bytes + self.cfile_cache_hit_bytes
+    }
+
+    /// Adds the provided metrics to these metrics.
+    pub fn merge(&mut self, other: &ResourceMetrics) {
+        self.cfile_cache_miss_bytes += other.cfile_cache_miss_bytes;
+        self.cfile_cache_hit_bytes += other.cfile_cache_hit_bytes;
+    }
+
+    #[doc(hidden)]
+    pub fn from_pb(pb: &ResourceMetricsPB) -> ResourceMetrics {
+        ResourceMetrics {
+            cfile_cache_miss_bytes: pb.get_cfile_cache_miss_bytes(),
+            cfile_cache_hit_bytes: pb.get_cfile_cache_hit_bytes(),
+        }
+    }
+}
+
+/// A batch of rows returned by a single scan RPC.
+#[derive(Clone, Debug)]
+pub struct ScanBatch {
+    rows: Vec<Row>,
+    resource_metrics: ResourceMetrics,
+    propagated_timestamp: Option<u64>,
+    /// Approximate size of the batch in memory, which is the length of its row data.
+    memory: usize,
+}
+
+impl ScanBatch {
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    pub fn into_rows(self) -> Vec<Row> {
+        self.rows
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Returns the server-side resource usage of the scan RPC which returned this batch.
+    pub fn resource_metrics(&self) -> &ResourceMetrics {
+        &self.resource_metrics
+    }
+
+    /// Returns the timestamp propagated by the tablet server with this batch, if any. The client's
+    /// latest observed timestamp has already been advanced to it.
+    pub fn propagated_timestamp(&self) -> Option<u64> {
+        self.propagated_timestamp
+    }
+
+    /// Removes the rows of the batch which do not match the predicate, as evaluated on the client
+    /// by `ColumnPredicate::evaluate`. The batch is left unchanged if evaluation fails.
+    pub fn retain_matching(&mut self, predicate: &ColumnPredicate) -> Result<()> {
+        let matches = try!(self.rows.iter()
+                                    .map(|row| predicate.evaluate(row))
+                                    .collect::<Result<Vec<bool>>>());
+        let mut matches = matches.into_iter();
+        self.rows.retain(|_| matches.next().unwrap());
+        Ok(())
+    }
+}
+
+/// The columns projected by a scan.
+#[derive(Clone)]
+enum Projection {
+    All,
+    Names(Vec<String>),
+    Ids(Vec<u32>),
+}
+
+/// Policy for choosing which replica of each tablet a scan reads from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplicaSelection {
+    /// Scan the leader replica. Another replica is only scanned if the leader's address can not be
+    /// resolved.
+    LeaderOnly,
+    /// Scan the closest replica, preferring replicas on the local host, and then the leader.
+    ///
+    /// If a tablet server can not be reached when opening a scan, the next replica is tried, and
+    /// the tablet server is blacklisted for a time which grows with each consecutive failure.
+    /// Blacklisted tablet servers are only scanned when no other replica is available.
+    ClosestReplica,
+}
+
+/// The position of a paused scan, from which the scan can be resumed by another scanner, possibly
+/// in another process. See `Scanner::cursor` and `ScanBuilder::resume_from`.
+///
+/// The cursor records the table, the projection, the partition key and primary key bounds of the
+/// scan, the tablet being scanned, and for ordered scans the last returned primary key and the
+/// snapshot timestamp of the tablet. It does not record the predicates of the scan, which must be
+/// added again to the resumed scan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScanCursor {
+    table_id: TableId,
+    projection: Vec<String>,
+    /// The partition key of the tablet to resume the scan from.
+    partition_key: Vec<u8>,
+    upper_bound_partition_key: Vec<u8>,
+    lower_bound_primary_key: Vec<u8>,
+    /// The encoded primary key of the last row returned from the tablet. Empty if the tablet scan
+    /// has not returned any rows.
+    last_primary_key: Vec<u8>,
+    snapshot_timestamp: Option<u64>,
+    ordered: bool,
+    done: bool,
+}
+
+impl ScanCursor {
+
+    /// Returns the ID of the scanned table.
+    pub fn table_id(&self) -> &TableId {
+        &self.table_id
+    }
+
+    /// Returns `true` if the scan had completed when the cursor was taken.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Serializes the cursor.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.push(self.ordered as u8 | (self.done as u8) << 1);
+        write_bytes(&mut buf, self.table_id.to_string().as_bytes());
+        buf.write_u32::<LittleEndian>(self.projection.len() as u32).unwrap();
+        for column in &self.projection {
+            write_bytes(&mut buf, column.as_bytes());
+        }
+        write_bytes(&mut buf, &self.partition_key);
+        write_bytes(&mut buf, &self.upper_bound_partition_key);
+        write_bytes(&mut buf, &self.lower_bound_primary_key);
+        write_bytes(&mut buf, &self.last_primary_key);
+        match self.snapshot_timestamp {
+            Some(timestamp) => {
+                buf.push(1);
+                buf.write_u64::<LittleEndian>(timestamp).unwrap();
+            },
+            None => buf.push(0),
+        }
+        buf
+    }
+
+    /// Deserializes a cursor serialized with `ScanCursor::to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<ScanCursor> {
+        let mut cursor = Cursor::new(bytes);
+        let flags = try!(cursor.read_u8().map_err(invalid_cursor));
+        if flags > 3 {
+            return Err(invalid_cursor(flags));
+        }
+        let table_id = try!(TableId::parse_bytes(&try!(read_bytes(&mut cursor))).map_err(invalid_cursor));
+        let num_columns = try!(cursor.read_u32::<LittleEndian>().map_err(invalid_cursor));
+        let mut projection = Vec::new();
+        for _ in 0..num_columns {
+            let column = try!(String::from_utf8(try!(read_bytes(&mut cursor))).map_err(invalid_cursor));
+            projection.push(column);
+        }
+        let partition_key = try!(read_bytes(&mut cursor));
+        let upper_bound_partition_key = try!(read_bytes(&mut cursor));
+        let lower_bound_primary_key = try!(read_bytes(&mut cursor));
+        let last_primary_key = try!(read_bytes(&mut cursor));
+        let snapshot_timestamp = match try!(cursor.read_u8().map_err(invalid_cursor)) {
+            0 => None,
+            1 => Some(try!(cursor.read_u64::<LittleEndian>().map_err(invalid_cursor))),
+            tag => return Err(invalid_cursor(tag)),
+        };
+        if cursor.position() != bytes.len() as u64 {
+            return Err(invalid_cursor(()));
+        }
+        Ok(ScanCursor {
+            table_id: table_id,
+            projection: projection,
+            partition_key: partition_key,
+            upper_bound_partition_key: upper_bound_partition_key,
+            lower_bound_primary_key: lower_bound_primary_key,
+            last_primary_key: last_primary_key,
+            snapshot_timestamp: snapshot_timestamp,
+            ordered: flags & 1 != 0,
+            done: flags & 2 != 0,
+        })
+    }
+
+    /// Serializes the cursor as an opaque token of lowercase hexadecimal digits, which may be
+    /// embedded in URLs without escaping.
+    pub fn to_token(&self) -> String {
+        util::hex_encode(&self.to_bytes())
+    }
+
+    /// Deserializes a cursor from a token returned by `ScanCursor::to_token`.
+    pub fn from_token(token: &str) -> Result<ScanCursor> {
+        match util::hex_decode(token) {
+            Some(bytes) => ScanCursor::from_bytes(&bytes),
+            None => Err(invalid_cursor(())),
+        }
+    }
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.write_u32::<LittleEndian>(bytes.len() as u32).unwrap();
+    buf.extend_from_slice(bytes);
+}
+
+fn read_bytes(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>> {
+    let len = try!(cursor.read_u32::<LittleEndian>().map_err(invalid_cursor)) as usize;
+    if len as u64 > cursor.get_ref().len() as u64 - cursor.position() {
+        return Err(invalid_cursor(len));
+    }
+    let mut bytes = vec![0; len];
+    try!(cursor.read_exact(&mut bytes).map_err(invalid_cursor));
+    Ok(bytes)
+}
+
+fn invalid_cursor<E>(_: E) -> Error {
+    Error::Serialization("invalid scan cursor".to_string())
+}
+
+/// Builds a `Scanner` over a table.
+#[derive(Clone)]
+pub struct ScanBuilder {
+    table: Table,
+    projection: Projection,
+    predicates: Vec<ColumnPredicate>,
+    batch_size_bytes: Option<u32>,
+    timeout: Duration,
+    /// The inclusive lower bound partition key of the scan. Empty if unbounded.
+    lower_bound_partition_key: Vec<u8>,
+    /// The exclusive upper bound partition key of the scan. Empty if unbounded.
+    upper_bound_partition_key: Vec<u8>,
+    /// The inclusive lower bound encoded primary key of the scan. Empty if unbounded.
+    lower_bound_primary_key: Vec<u8>,
+    ordered: bool,
+    prefetch_depth: usize,
+    replica_selection: ReplicaSelection,
+    /// The position to resume the scan from.
+    cursor: Option<ScanCursor>,
+}
+
+impl ScanBuilder {
+
+    pub fn new(table: Table) -> ScanBuilder {
+        let timeout = table.client().config().operation_timeout();
+        ScanBuilder {
+            table: table,
+            projection: Projection::All,
+            predicates: Vec::new(),
+            batch_size_bytes: None,
+            timeout: timeout,
+            lower_bound_partition_key: Vec::new(),
+            upper_bound_partition_key: Vec::new(),
+            lower_bound_primary_key: Vec::new(),
+            ordered: false,
+            prefetch_depth: 1,
+            replica_selection: ReplicaSelection::LeaderOnly,
+            cursor: None,
+        }
+    }
+
+    /// Sets the columns to return from the scan, in order. By default all columns are returned.
+    pub fn set_projection<S>(&mut self, columns: Vec<S>) -> &mut ScanBuilder where S: Into<String> {
+        self.projection = Projection::Names(columns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the columns to return from the scan by server-assigned column ID, in order.
+    ///
+    /// Unlike column names, column IDs do not change when a column is renamed, so a projection by
+    /// ID remains valid across concurrent column renames. The IDs are resolved to column names
+    /// when the scan is built, and resolved again only if a tablet server rejects the projection
+    /// because a projected column has since been renamed. See `Schema::column_id`.
+    pub fn set_projection_by_ids(&mut self, column_ids: Vec<u32>) -> &mut ScanBuilder {
+        self.projection = Projection::Ids(column_ids);
+        self
+    }
+
+    /// Adds a predicate to the scan. Only rows matching every predicate are returned.
+    pub fn add_predicate(&mut self, predicate: ColumnPredicate) -> &mut ScanBuilder {
+        self.predicates.push(predicate);
+        self
+    }
+
+    /// Adds each of the predicates to the scan. Only rows matching every predicate are returned.
+    pub fn add_predicates<P>(&mut self, predicates: P) -> &mut ScanBuilder where P: Into<Predicates> {
+        self.predicates.extend(predicates.into());
+        self
+    }
+
+    /// Sets the maximum number of bytes of row data to return per scan RPC. By default the server
+    /// decides the batch size.
+    pub fn set_batch_size_bytes(&mut self, batch_size_bytes: u32) -> &mut ScanBuilder {
+        self.batch_size_bytes = Some(batch_size_bytes);
+        self
+    }
+
+    /// Sets the timeout for retrieving each batch when the scanner is consumed as an iterator or
+    /// stream.
+    ///
+    /// Defaults to the client's operation timeout. See `ClientConfig::set_operation_timeout`.
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut ScanBuilder {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the inclusive lower bound primary key of the scan. Only rows with a primary key greater
+    /// than or equal to the primary key of `row` are returned. Fails if a primary key column of the
+    /// row is not set.
+    pub fn set_lower_bound_primary_key(&mut self, row: &Row) -> Result<&mut ScanBuilder> {
+        let encoded_key = try!(key::encode_primary_key(row));
+        Ok(self.set_lower_bound_encoded_primary_key(encoded_key))
+    }
+
+    #[doc(hidden)]
+    pub fn set_lower_bound_encoded_primary_key(&mut self, key: Vec<u8>) -> &mut ScanBuilder {
+        self.lower_bound_primary_key = key;
+        self
+    }
+
+    /// Sets whether the rows of each tablet are returned in primary key order. Ordered scans read a
+    /// snapshot of each tablet as of when its scan begins. Rows of different tablets are not
+    /// ordered with respect to each other.
+    ///
+    /// If a tablet server expires the scan of a tablet, for instance because the scan paused for
+    /// longer than the server's scanner TTL, an ordered scan is resumed after its last returned
+    /// row, and an unordered scan fails with `TabletServerErrorCode::ScannerExpired`.
+    ///
+    /// Defaults to `false`.
+    pub fn set_ordered(&mut self, ordered: bool) -> &mut ScanBuilder {
+        self.ordered = ordered;
+        self
+    }
+
+    /// Sets the number of batches which are retrieved ahead of the consumer when the scanner is
+    /// consumed with `Scanner::prefetch` or as a stream. While the consumer processes a batch, the
+    /// requests for the following batches are already in flight.
+    ///
+    /// Defaults to 1. Must be at least 1.
+    pub fn set_prefetch_depth(&mut self, prefetch_depth: usize) -> &mut ScanBuilder {
+        assert!(prefetch_depth > 0, "prefetch_depth must be at least 1");
+        self.prefetch_depth = prefetch_depth;
+        self
+    }
+
+    /// Sets the policy for choosing which replica of each tablet to scan.
+    ///
+    /// Defaults to `ReplicaSelection::LeaderOnly`.
+    pub fn set_replica_selection(&mut self, replica_selection: ReplicaSelection) -> &mut ScanBuilder {
+        self.replica_selection = replica_selection;
+        self
+    }
+
+    /// Resumes the scan from the position of a paused scan, as returned by `Scanner::cursor`.
+    ///
+    /// The projection, bounds and ordering of the scan are replaced by those of the paused scan.
+    /// The predicates of the paused scan are not recorded by the cursor, so they must be added
+    /// to this scan as well. Building the scan fails if the cursor is of another table.
+    pub fn resume_from(&mut self, cursor: ScanCursor) -> &mut ScanBuilder {
+        self.projection = Projection::Names(cursor.projection.clone());
+        self.lower_bound_partition_key = cursor.partition_key.clone();
+        self.upper_bound_partition_key = cursor.upper_bound_partition_key.clone();
+        self.lower_bound_primary_key = cursor.lower_bound_primary_key.clone();
+        self.ordered = cursor.ordered;
+        self.cursor = Some(cursor);
+        self
+    }
+
+    /// Splits the scan into up to `num_scans` independent scans which together return the same
+    /// rows as the original scan, so that the rows can be retrieved in parallel.
+    ///
+    /// Each tablet is covered by at least one scan, so more than `num_scans` scans are returned if
+    /// the scan spans more tablets. Tablets which are assigned more than one scan are divided into
+    /// equal-width ranges of the leading primary key column. This is only possible when the leading
+    /// primary key column is an integer, and is the leading range partition column with bounds in
+    /// the tablet's partition; otherwise the tablet is scanned by a single scan.
+    ///
+    /// Fails if the scan resumes from a cursor.
+    pub fn split(self, num_scans: usize, deadline: Instant) -> Result<Vec<ScanBuilder>> {
+        if self.cursor.is_some() {
+            return Err(Error::InvalidArgument("a resumed scan can not be split".to_string()));
+        }
+        let mut tablets = try!(self.table.tablets(deadline));
+        tablets.retain(|tablet| self.overlaps(tablet.partition()));
+        let mut scans = Vec::with_capacity(cmp::max(num_scans, tablets.len()));
+        for (idx, tablet) in tablets.iter().enumerate() {
+            // Distribute the scans as evenly as possible among the tablets.
+            let tablet_scans = num_scans / tablets.len() + if idx < num_scans % tablets.len() { 1 } else { 0 };
+
+            let mut tablet_scan = self.clone();
+            tablet_scan.restrict_to_partition(tablet.partition());
+
+            let (lower, upper) = match try!(self.key_column_bounds(tablet.partition())) {
+                Some(bounds) if tablet_scans > 1 => bounds,
+                _ => {
+                    scans.push(tablet_scan);
+                    continue;
+                },
+            };
+
+            let width = upper.wrapping_sub(lower) as u64;
+            let chunks = cmp::min(tablet_scans as u64, width);
+            let key_column = &self.table.schema().columns()[0];
+            for chunk in 0..chunks {
+                let chunk_lower = if chunk == 0 { None } else { Some(split_point(lower, width, chunks, chunk)) };
+                let chunk_upper = if chunk + 1 == chunks { None } else { Some(split_point(lower, width, chunks, chunk + 1)) };
+                let mut scan = tablet_scan.clone();
+                scan.add_predicate(key_range_predicate(key_column, chunk_lower, chunk_upper));
+                scans.push(scan);
+            }
+        }
+        Ok(scans)
+    }
+
+    /// Returns `true` if the partition overlaps the partition key range of the scan.
+    fn overlaps(&self, partition: &Partition) -> bool {
+        (partition.upper_bound_key().is_empty() ||
+         partition.upper_bound_key() > &self.lower_bound_partition_key[..]) &&
+        (self.upper_bound_partition_key.is_empty() ||
+         partition.lower_bound_key() < &self.upper_bound_partition_key[..])
+    }
+
+    /// Narrows the partition key range of the scan to the partition.
+    #[doc(hidden)]
+    pub fn restrict_to_partition(&mut self, partition: &Partition) {
+        if partition.lower_bound_key() > &self.lower_bound_partition_key[..] {
+            self.lower_bound_partition_key = partition.lower_bound_key().to_owned();
+        }
+        if !partition.upper_bound_key().is_empty() &&
+           (self.upper_bound_partition_key.is_empty() ||
+            partition.upper_bound_key() < &self.upper_bound_partition_key[..]) {
+            self.upper_bound_partition_key = partition.upper_bound_key().to_owned();
+        }
+    }
+
+    /// Returns the range `[lower, upper)` of the leading primary key column values in the
+    /// partition, or `None` if the range is unbounded or the column is not an integer.
+    fn key_column_bounds(&self, partition: &Partition) -> Result<Option<(i64, i64)>> {
+        let data_type = self.table.schema().columns()[0].data_type();
+        match data_type {
+            DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 | DataType::Timestamp => (),
+            _ => return Ok(None),
+        }
+        let range_columns = self.table.partition_schema().range_partition_schema().columns();
+        if range_columns.first() != Some(&0) {
+            return Ok(None);
+        }
+
+        let lower_bound = partition.range_lower_bound();
+        let upper_bound = partition.range_upper_bound();
+        if !try!(lower_bound.is_set(0)) || !try!(upper_bound.is_set(0)) {
+            return Ok(None);
+        }
+        let lower = try!(get_integer(lower_bound, 0, data_type));
+        let mut upper = try!(get_integer(upper_bound, 0, data_type));
+
+        // The upper bound is exclusive of the full range partition key, so rows in the partition
+        // may contain the leading column's upper bound value if a trailing column bound is set.
+        for &idx in &range_columns[1..] {
+            if try!(upper_bound.is_set(idx)) {
+                upper = match upper.checked_add(1) {
+                    Some(upper) => upper,
+                    None => return Ok(None),
+                };
+                break;
+            }
+        }
+
+        if lower < upper { Ok(Some((lower, upper))) } else { Ok(None) }
+    }
+
+    /// Builds a scan which retrieves the rows of each tablet in parallel, and merges them into a
+    /// single sequence of rows in primary key order.
+    ///
+    /// Each tablet is scanned by an ordered scan driven by its own background thread, as with
+    /// `Scanner::prefetch`. The projection must begin with the table's primary key columns, since
+    /// the rows are merged by their encoded primary key.
+    pub fn build_merged(mut self, deadline: Instant) -> Result<MergedScanRows> {
+        self.ordered = true;
+        let num_primary_key_columns = self.table.schema().num_primary_key_columns();
+        let scans = try!(self.split(1, deadline));
+
+        let mut sources = Vec::with_capacity(scans.len());
+        for scan in scans {
+            let scanner = try!(scan.build());
+            if scanner.projection().num_primary_key_columns() != num_primary_key_columns {
+                return Err(Error::InvalidArgument(format!(
+                            "merged scan projection {:?} must begin with the primary key columns",
+                            scanner.projection())));
+            }
+            sources.push(MergeSource {
+                batches: scanner.prefetch(),
+                rows: Vec::new().into_iter(),
+            });
+        }
+
+        let mut merged = MergedScanRows {
+            sources: sources,
+            heap: BinaryHeap::new(),
+            failed: false,
+        };
+        for source in 0..merged.sources.len() {
+            try!(merged.advance(source));
+        }
+        Ok(merged)
+    }
+
+    pub fn build(self) -> Result<Scanner> {
+        let ScanBuilder { table, projection, predicates, batch_size_bytes, timeout,
+                          lower_bound_partition_key, upper_bound_partition_key,
+                          lower_bound_primary_key, ordered, prefetch_depth,
+                          replica_selection, cursor } = self;
+
+        {
+            let mut value_types = HashMap::new();
+            for predicate in &predicates {
+                try!(predicate.check(table.schema()));
+                if let Some(value_type) = predicate.value_type() {
+                    let existing = *value_types.entry(predicate.column()).or_insert(value_type);
+                    if existing != value_type {
+                        return Err(Error::InvalidArgument(format!(
+                            "predicates on column '{}' have mixed value types {:?} and {:?}",
+                            predicate.column(), existing, value_type)));
+                    }
+                }
+            }
+        }
+        if !predicates.is_empty() {
+            try!(table.client().server_versions().check(ServerFeature::ColumnPredicates));
+        }
+
+        let mut projection_ids = Vec::new();
+        let projection = match projection {
+            Projection::All => table.schema().clone(),
+            Projection::Names(columns) => try!(table.schema().project(&columns)),
+            Projection::Ids(column_ids) => {
+                projection_ids = column_ids.clone();
+                let mut indices = Vec::with_capacity(column_ids.len());
+                for &id in &column_ids {
+                    match table.schema().column_index_by_id(id) {
+                        Some(idx) => indices.push(idx),
+                        None => return Err(Error::InvalidArgument(
+                                format!("unknown column ID {}", id))),
+                    }
+                }
+                table.schema().projection(&indices)
+            },
+        };
+
+        let (last_primary_key, snapshot_timestamp, resumed_done) = match cursor {
+            Some(cursor) => {
+                if cursor.table_id != *table.id() {
+                    return Err(Error::InvalidArgument(format!(
+                                "scan cursor of table {} can not resume a scan of table {}",
+                                cursor.table_id, table.id())));
+                }
+                (cursor.last_primary_key, cursor.snapshot_timestamp, cursor.done)
+            },
+            None => (Vec::new(), None, false),
+        };
+
+        Ok(Scanner {
+            table: table,
+            projection: projection,
+            projection_ids: projection_ids,
+            batch_size_bytes: batch_size_bytes,
+            timeout: timeout,
+            done: resumed_done || predicates.iter().any(ColumnPredicate::is_none),
+            predicates: predicates,
+            partition_key: lower_bound_partition_key,
+            upper_bound_partition_key: upper_bound_partition_key,
+            lower_bound_primary_key: lower_bound_primary_key,
+            ordered: ordered,
+            prefetch_depth: prefetch_depth,
+            replica_selection: replica_selection,
+            tablet: None,
+            tablet_server: None,
+            scanner_id: None,
+            last_primary_key: last_primary_key,
+            snapshot_timestamp: snapshot_timestamp,
+            call_seq_id: 0,
+            resource_metrics: ResourceMetrics::default(),
+        })
+    }
+}
+
+/// A scan over the rows of a table.
+///
+/// Tablets are scanned sequentially in partition key order. Batches are retrieved with
+/// `Scanner::next_batch`, or by consuming the scanner as a blocking iterator of rows or batches, or
+/// as a stream of rows or batches.
+pub struct Scanner {
+    table: Table,
+    projection: Schema,
+    /// The column IDs of the projection, if the projection was set by ID. The projected columns
+    /// are renamed to their current names when a tablet server rejects the projection.
+    projection_ids: Vec<u32>,
+    predicates: Vec<ColumnPredicate>,
+    batch_size_bytes: Option<u32>,
+    timeout: Duration,
+
+    /// The partition key of the next tablet to scan.
+    partition_key: Vec<u8>,
+    /// The exclusive upper bound partition key of the scan. Empty if unbounded.
+    upper_bound_partition_key: Vec<u8>,
+    /// The inclusive lower bound encoded primary key of the scan. Empty if unbounded.
+    lower_bound_primary_key: Vec<u8>,
+    ordered: bool,
+    prefetch_depth: usize,
+    replica_selection: ReplicaSelection,
+    /// The tablet currently being scanned.
+    tablet: Option<Tablet>,
+    /// The tablet server hosting the scanner of the tablet currently being scanned, and its
+    /// addresses. Requests continuing the scan are sent to the same tablet server.
+    tablet_server: Option<(TabletServerId, Vec<SocketAddr>)>,
+    /// The server-side scanner ID of the tablet currently being scanned.
+    scanner_id: Option<Vec<u8>>,
+    /// The encoded primary key of the last row returned from the tablet currently being scanned,
+    /// if the scan is ordered. Empty if no rows have been returned.
+    last_primary_key: Vec<u8>,
+    /// The snapshot timestamp of the tablet currently being scanned, if the scan is ordered.
+    snapshot_timestamp: Option<u64>,
+    call_seq_id: u32,
+    done: bool,
+
+    /// Cumulative resource metrics of all batches returned so far.
+    resource_metrics: ResourceMetrics,
+}
+
+impl Scanner {
+
+    /// Returns the projected schema of rows returned by the scanner.
+    pub fn projection(&self) -> &Schema {
+        &self.projection
+    }
+
+    /// Returns the cumulative server-side resource usage of the scan so far.
+    pub fn resource_metrics(&self) -> &ResourceMetrics {
+        &self.resource_metrics
+    }
+
+    /// Returns the position of the scan, from which the remaining rows can be retrieved by a new
+    /// scan with `ScanBuilder::resume_from`. The scanner should be closed or dropped once the
+    /// cursor is taken, so that its server-side resources are released.
+    ///
+    /// Ordered scans can be paused after any batch, and resume after the last returned row at the
+    /// same snapshot. Tablet servers do not report the position of other scans within a tablet, so
+    /// fails if an unordered scan is part way through a tablet.
+    pub fn cursor(&self) -> Result<ScanCursor> {
+        if !self.ordered && self.scanner_id.is_some() {
+            return Err(Error::InvalidArgument(
+                    "an unordered scan can only be paused between tablets".to_string()));
+        }
+        Ok(ScanCursor {
+            table_id: *self.table.id(),
+            projection: self.projection.columns().iter().map(|column| column.name().to_owned()).collect(),
+            partition_key: self.partition_key.clone(),
+            upper_bound_partition_key: self.upper_bound_partition_key.clone(),
+            lower_bound_primary_key: self.lower_bound_primary_key.clone(),
+            last_primary_key: self.last_primary_key.clone(),
+            snapshot_timestamp: self.snapshot_timestamp,
+            ordered: self.ordered,
+            done: self.done,
+        })
+    }
+
+    /// Returns the next batch of rows, or `None` if the scan is complete. Fails with
+    /// `Error::Shutdown` once the client has been shut down.
+    pub fn next_batch(&mut self, deadline: Instant) -> Result<Option<ScanBatch>> {
+        loop {
+            if self.done {
+                return Ok(None);
+            }
+            if self.table.client().is_shut_down() {
+                return Err(Error::Shutdown);
+            }
+
+            let mut request = ScanRequestPB::new();
+            match self.scanner_id.clone() {
+                Some(scanner_id) => {
+                    request.set_scanner_id(scanner_id);
+                    request.set_call_seq_id(self.call_seq_id);
+                },
+                None => match try!(self.lookup_tablet(deadline)) {
+                    Some(tablet) => {
+                        request.set_new_scan_request(self.new_scan_request(&tablet));
+                        self.tablet = Some(tablet);
+                        self.tablet_server = None;
+                        self.call_seq_id = 0;
+                    },
+                    None => continue,
+                },
+            }
+            if let Some(batch_size_bytes) = self.batch_size_bytes {
+                request.set_batch_size_bytes(batch_size_bytes);
+            }
+
+            // Requests continuing a scan must be sent to the tablet server hosting the scanner,
+            // while new scans fail over to the next replica when a tablet server is unreachable.
+            let candidates = match self.tablet_server.clone() {
+                Some(tablet_server) => vec![tablet_server],
+                None => try!(self.replica_candidates()),
+            };
+            let num_candidates = candidates.len();
+            let mut failed_attempts = Vec::new();
+            let mut response_rpc = None;
+            for (idx, (id, addrs)) in candidates.into_iter().enumerate() {
+                let (result, rpc) = self.send(&addrs, deadline, request.clone());
+                match result {
+                    Ok(()) => {
+                        self.table.meta_cache().tablet_server_succeeded(&id);
+                        self.tablet_server = Some((id, addrs));
+                        response_rpc = Some(rpc);
+                        break;
+                    },
+                    Err(error) => {
+                        if error.is_network_error() || error.is_timeout() {
+                            self.table.meta_cache().blacklist_tablet_server(&id);
+                        }
+                        if error.is_timeout() {
+                            return Err(error.after_attempts(failed_attempts));
+                        }
+                        if !(error.is_network_error() || error.is_circuit_open()) ||
+                           idx + 1 == num_candidates ||
+                           !self.table.client().retry_budget().try_retry() {
+                            return Err(error);
+                        }
+                        debug!("scan RPC to tablet server {:?} failed: {}, trying the next replica",
+                               id, error);
+                        failed_attempts.push(error);
+                    },
+                }
+            }
+            let mut rpc = response_rpc.expect("no scan response");
+
+            let sidecars = mem::replace(&mut rpc.sidecars, Vec::new());
+            let mut response = rpc.take_response();
+            if response.has_error() {
+                let error = TabletServerError::from(response.take_error());
+                if (error.code() == TabletServerErrorCode::InvalidSchema ||
+                    error.code() == TabletServerErrorCode::MismatchedSchema) &&
+                   request.has_new_scan_request() && !self.projection_ids.is_empty() &&
+                   try!(self.refresh_projection(deadline)) {
+                    continue;
+                }
+                if error.code() == TabletServerErrorCode::ScannerExpired &&
+                   self.ordered && self.scanner_id.is_some() {
+                    self.reopen_expired_scanner();
+                    continue;
+                }
+                return Err(Error::TabletServer(error));
+            }
+            if self.ordered {
+                if response.has_snap_timestamp() {
+                    self.snapshot_timestamp = Some(response.get_snap_timestamp());
+                }
+                if response.has_last_primary_key() {
+                    self.last_primary_key = response.take_last_primary_key();
+                }
+            }
+            let propagated_timestamp = if response.has_propagated_timestamp() {
+                self.table.client().timestamp_observed(response.get_propagated_timestamp());
+                Some(response.get_propagated_timestamp())
+            } else {
+                None
+            };
+
+            let rows = try!(self.decode_rows(&response, &sidecars));
+            let memory = sidecars.iter().map(Vec::len).sum();
+            let resource_metrics = ResourceMetrics::from_pb(response.get_resource_metrics());
+            self.resource_metrics.merge(&resource_metrics);
+
+            if response.get_has_more_results() {
+                self.scanner_id = Some(response.take_scanner_id());
+                self.call_seq_id += 1;
+            } else {
+                self.scanner_id = None;
+                self.advance_partition_key();
+            }
+
+            return Ok(Some(ScanBatch {
+                rows: rows,
+                resource_metrics: resource_metrics,
+                propagated_timestamp: propagated_timestamp,
+                memory: memory,
+            }));
+        }
+    }
+
+    /// Closes the scanner, releasing its server-side resources. Subsequent calls to `next_batch`
+    /// return `None`.
+    ///
+    /// The close RPC is sent on a best-effort basis without waiting for a response; the server
+    /// eventually expires scanners which fail to close. Dropping a scanner closes it.
+    pub fn close(&mut self) {
+        self.done = true;
+        let scanner_id = match self.scanner_id.take() {
+            Some(scanner_id) => scanner_id,
+            None => return,
+        };
+        let addr = match self.tablet_server {
+            Some((_, ref addrs)) => addrs[0],
+            None => return,
+        };
+
+        let mut request = ScanRequestPB::new();
+        request.set_scanner_id(scanner_id);
+        request.set_call_seq_id(self.call_seq_id);
+        request.set_batch_size_bytes(0);
+        request.set_close_scanner(true);
+
+        let mut rpc = tablet_server::scan(addr, Instant::now() + self.timeout, request);
+        rpc.callback = Some(Box::new(|result: Result<()>, rpc: Rpc| {
+            if let Err(error) = result {
+                debug!("failed to close scanner on {}: {}", rpc.addr, error);
+            }
+        }));
+        self.table.client().messenger().send(rpc);
+    }
+
+    /// Returns a blocking iterator over the remaining batches of the scan.
+    pub fn batches(self) -> ScanBatches {
+        ScanBatches { scanner: self, failed: false }
+    }
+
+    /// Returns a blocking iterator over the remaining batches of the scan, which are retrieved
+    /// ahead of the consumer.
+    ///
+    /// The scan is driven by a background thread, which retrieves up to the prefetch depth
+    /// configured with `ScanBuilder::set_prefetch_depth` batches ahead of the consumer. Dropping the
+    /// iterator cancels the scan.
+    ///
+    /// Prefetched batches are accounted against the client's memory budget from before they are
+    /// requested until they are returned by the iterator. If the budget is exhausted, the
+    /// background thread waits before retrieving further batches, unless every batch it retrieved
+    /// has been returned, so that a consumer waiting on this scan can not be starved by others.
+    pub fn prefetch(self) -> PrefetchedScanBatches {
+        let (send, recv) = sync_channel(self.prefetch_depth - 1);
+        thread::spawn(move || {
+            let mut batches = PrefetchingBatches::new(self);
+            while let Some(batch) = batches.next() {
+                // The iterator has been dropped.
+                if send.send(batch).is_err() { break; }
+            }
+        });
+        PrefetchedScanBatches { batches: recv }
+    }
+
+    /// Returns a stream of the remaining batches of the scan.
+    ///
+    /// The scan is driven by a background thread, which retrieves up to the prefetch depth
+    /// configured with `ScanBuilder::set_prefetch_depth` batches ahead of the stream consumer.
+    /// Dropping the stream cancels the scan. Like `Scanner::prefetch`, prefetched batches are
+    /// accounted against the client's memory budget.
+    pub fn into_batch_stream(self) -> ScanBatchStream {
+        let (send, recv) = mpsc::channel(self.prefetch_depth - 1);
+        thread::spawn(move || {
+            let mut batches = PrefetchingBatches::new(self);
+            let mut send = send;
+            while let Some(batch) = batches.next() {
+                send = match send.send(batch).wait() {
+                    Ok(send) => send,
+                    // The stream has been dropped.
+                    Err(_) => break,
+                };
+            }
+        });
+        ScanBatchStream { batches: recv }
+    }
+
+    /// Returns a stream of the remaining rows of the scan.
+    ///
+    /// See `Scanner::into_batch_stream`.
+    pub fn into_row_stream(self) -> ScanRowStream {
+        ScanRowStream {
+            batches: self.into_batch_stream(),
+            rows: Vec::new().into_iter(),
+        }
+    }
+
+    /// Looks up the tablet containing the current partition key. If the partition key falls in a
+    /// non-covered range, the partition key is advanced and `None` is returned.
+    fn lookup_tablet(&mut self, deadline: Instant) -> Result<Option<Tablet>> {
+        let (send, recv) = sync_channel(1);
+        self.table.meta_cache().entry(self.partition_key.clone(),
+                                      deadline,
+                                      move |entry| send.send(entry).unwrap());
+        match try!(recv.recv().unwrap()) {
+            Entry::Tablet(tablet) => Ok(Some(tablet)),
+            Entry::NonCoveredRange { partition_upper_bound, .. } => {
+                self.set_partition_key(partition_upper_bound);
+                Ok(None)
+            },
+        }
+    }
+
+    /// Discards the server-side scanner of the tablet currently being scanned after it has expired,
+    /// so that the next call to `next_batch` opens a new one which resumes after the last returned
+    /// row, at the same snapshot.
+    ///
+    /// Only ordered scans are resumed. Other scans have no position to resume from, so an expired
+    /// scanner fails the scan with `TabletServerErrorCode::ScannerExpired` rather than returning
+    /// rows of the tablet again.
+    fn reopen_expired_scanner(&mut self) {
+        self.scanner_id = None;
+        let tablet = self.tablet.take().expect("no tablet being scanned");
+        debug!("scanner of tablet {} expired, resuming the scan", tablet.id());
+    }
+
+    /// Moves the scan to the tablet following the current tablet.
+    fn advance_partition_key(&mut self) {
+        self.last_primary_key.clear();
+        self.snapshot_timestamp = None;
+        let tablet = self.tablet.take().expect("no tablet being scanned");
+        self.set_partition_key(tablet.partition().upper_bound_key().to_owned());
+    }
+
+    /// Sets the partition key of the next tablet to scan, completing the scan if the key is past
+    /// the end of the table or the upper bound of the scan.
+    fn set_partition_key(&mut self, partition_key: Vec<u8>) {
+        self.done = partition_key.is_empty() ||
+                    (!self.upper_bound_partition_key.is_empty() &&
+                     partition_key >= self.upper_bound_partition_key);
+        self.partition_key = partition_key;
+    }
+
+    /// Resolves the projected column IDs to the columns' current names, since tablet servers
+    /// identify projected columns by name. Returns whether any projected column was renamed.
+    fn refresh_projection(&mut self, deadline: Instant) -> Result<bool> {
+        let table = try!(self.table.client().open_table_by_id(self.table.id(), deadline));
+        let mut indices = Vec::with_capacity(self.projection_ids.len());
+        for &id in &self.projection_ids {
+            match table.schema().column_index_by_id(id) {
+                Some(idx) => indices.push(idx),
+                None => return Err(Error::InvalidArgument(
+                        format!("projected column ID {} has been dropped", id))),
+            }
+        }
+        let projection = table.schema().projection(&indices);
+        let renamed = projection.columns()
+                                .iter()
+                                .zip(self.projection.columns())
+                                .any(|(a, b)| a.name() != b.name());
+        self.projection = projection;
+        Ok(renamed)
+    }
+
+    fn new_scan_request(&self, tablet: &Tablet) -> NewScanRequestPB {
+        let mut request = NewScanRequestPB::new();
+        request.set_tablet_id(tablet.id().to_string().into_bytes());
+        if self.ordered {
+            // The tablet servers only support ordered scans of snapshots.
+            request.set_read_mode(ReadMode::READ_AT_SNAPSHOT);
+            request.set_order_mode(OrderMode::ORDERED);
+        } else {
+            request.set_read_mode(ReadMode::READ_LATEST);
+        }
+        request.set_propagated_timestamp(self.table.client().latest_observed_timestamp());
+        if let Some(snapshot_timestamp) = self.snapshot_timestamp {
+            request.set_snap_timestamp(snapshot_timestamp);
+        }
+        if !self.lower_bound_primary_key.is_empty() {
+            request.set_start_primary_key(self.lower_bound_primary_key.clone());
+        }
+        if !self.last_primary_key.is_empty() {
+            // Resume an ordered scan after the last returned row; the key is exclusive.
+            request.set_last_primary_key(self.last_primary_key.clone());
+        }
+        for (idx, column) in self.projection.columns().iter().enumerate() {
+            let column_pb = column.to_pb(idx < self.projection.num_primary_key_columns());
+            request.mut_projected_columns().push(column_pb);
+        }
+        for predicate in &self.predicates {
+            request.mut_column_predicates().push(predicate.to_pb());
+        }
+        request
+    }
+
+    /// Returns the tablet servers to open the scan of the current tablet on, in order of
+    /// preference according to the replica selection policy, along with their addresses. Only
+    /// replicas with a resolved address are returned, and at least one is returned.
+    fn replica_candidates(&self) -> Result<Vec<(TabletServerId, Vec<SocketAddr>)>> {
+        let tablet = self.tablet.as_ref().expect("no tablet being scanned");
+        let meta_cache = self.table.meta_cache();
+        let mut replicas = tablet.replicas()
+                                 .iter()
+                                 .filter(|replica| !replica.resolved_rpc_addrs().is_empty())
+                                 .collect::<Vec<_>>();
+        match self.replica_selection {
+            ReplicaSelection::LeaderOnly => {
+                replicas.sort_by_key(|replica| replica.role() != RaftRole::Leader);
+                replicas.truncate(1);
+            },
+            ReplicaSelection::ClosestReplica => {
+                replicas.sort_by_key(|replica| (meta_cache.is_blacklisted(replica.id()),
+                                                !replica.is_local(),
+                                                replica.role() != RaftRole::Leader));
+            },
+        }
+        if replicas.is_empty() {
+            return Err(Error::InvalidArgument(
+                    format!("no replica of tablet {} has a resolvable address", tablet.id())));
+        }
+        Ok(replicas.into_iter()
+                   .map(|replica| (*replica.id(), replica.resolved_rpc_addrs().to_owned()))
+                   .collect())
+    }
+
+    /// Sends a scan request to a tablet server. Multi-homed tablet servers register several
+    /// addresses; the next address is tried when the server can not be reached on one. If the
+    /// deadline passes after an address failed, the RPC fails with `Error::DeadlineExceeded`.
+    fn send(&self,
+            addrs: &[SocketAddr],
+            deadline: Instant,
+            request: ScanRequestPB)
+            -> (Result<()>, TypedRpc<ScanRequestPB, ScanResponsePB>) {
+        let mut idx = 0;
+        let mut rpc = tablet_server::scan(addrs[idx], deadline, request);
+        rpc.fail_fast = addrs.len() > 1;
+        let (mut result, mut rpc) = rpc.send_sync(self.table.client().messenger());
+        let mut failed_attempts = Vec::new();
+        while result.as_ref().err().map_or(false, Error::is_network_error) &&
+              idx + 1 < addrs.len() &&
+              self.table.client().retry_budget().try_retry() {
+            idx += 1;
+            let error = result.unwrap_err();
+            debug!("scan RPC to {} failed: {}, retrying with {}", rpc.addr, error, addrs[idx]);
+            failed_attempts.push(error);
+            rpc.addr = addrs[idx];
+            rpc.fail_fast = idx + 1 < addrs.len();
+            let (next_result, next_rpc) = rpc.send_sync(self.table.client().messenger());
+            result = next_result;
+            rpc = next_rpc;
+        }
+        let result = result.map_err(|error| {
+            if error.is_timeout() { error.after_attempts(failed_attempts) } else { error }
+        });
+        (result, rpc)
+    }
+
+    /// Decodes the rows of a scan response from its sidecars.
+    ///
+    /// Sidecars are always uncompressed: neither the scan request nor the RPC header has a field
+    /// to request compressed row data, and column compression (`ColumnSchemaPB::compression`) only
+    /// applies to the tablet server's on-disk storage. Projecting fewer columns is the only way to
+    /// reduce the row data transferred for wide tables.
+    fn decode_rows(&self, response: &ScanResponsePB, sidecars: &[Vec<u8>]) -> Result<Vec<Row>> {
+        if !response.has_data() || response.get_data().get_num_rows() == 0 {
+            return Ok(Vec::new());
+        }
+        let block = response.get_data();
+        let num_rows = block.get_num_rows() as usize;
+
+        let rows_data = match sidecars.get(block.get_rows_sidecar() as usize) {
+            Some(data) => data,
+            None => return Err(Error::Serialization("scan response is missing row data".to_string())),
+        };
+        let indirect_data: &[u8] = if block.has_indirect_data_sidecar() {
+            match sidecars.get(block.get_indirect_data_sidecar() as usize) {
+                Some(data) => data,
+                None => return Err(Error::Serialization(
+                        "scan response is missing indirect row data".to_string())),
+            }
+        } else {
+            &[]
+        };
+
+        if rows_data.len() % num_rows != 0 {
+            return Err(Error::Serialization(format!("invalid row block; rows: {}, length: {}",
+                                                    num_rows, rows_data.len())));
+        }
+        let row_len = rows_data.len() / num_rows;
+
+        let mut rows = Vec::with_capacity(num_rows);
+        for data in rows_data.chunks(row_len) {
+            rows.push(try!(Row::decode(self.projection.clone(), data, indirect_data)));
+        }
+        Ok(rows)
+    }
+}
+
+/// Returns the `idx`th of `chunks` equally spaced points in the `width` values following `lower`.
+fn split_point(lower: i64, width: u64, chunks: u64, idx: u64) -> i64 {
+    let offset = width / chunks * idx + cmp::min(idx, width % chunks);
+    lower.wrapping_add(offset as i64)
+}
+
+/// Returns an integer cell of the row widened to an `i64`.
+fn get_integer(row: &Row, idx: usize, data_type: DataType) -> Result<i64> {
+    Ok(match data_type {
+        DataType::Int8 => try!(row.get::<i8>(idx)) as i64,
+        DataType::Int16 => try!(row.get::<i16>(idx)) as i64,
+        DataType::Int32 => try!(row.get::<i32>(idx)) as i64,
+        DataType::Int64 | DataType::Timestamp => try!(row.get::<i64>(idx)),
+        _ => unreachable!("non-integer type {:?}", data_type),
+    })
+}
+
+/// Returns a predicate matching values of the integer column in the range `[lower, upper)`.
+///
+/// Timestamp bounds are `SystemTime`s, so that the predicate can be combined with predicates on
+/// the column created from `SystemTime` values.
+fn key_range_predicate(column: &Column, lower: Option<i64>, upper: Option<i64>) -> ColumnPredicate {
+    match column.data_type() {
+        DataType::Int8 => ColumnPredicate::range(column.name(), lower.map(|v| v as i8), upper.map(|v| v as i8)),
+        DataType::Int16 => ColumnPredicate::range(column.name(), lower.map(|v| v as i16), upper.map(|v| v as i16)),
+        DataType::Int32 => ColumnPredicate::range(column.name(), lower.map(|v| v as i32), upper.map(|v| v as i32)),
+        DataType::Int64 => ColumnPredicate::range(column.name(), lower, upper),
+        DataType::Timestamp => ColumnPredicate::range(column.name(),
+                                                      lower.map(util::us_to_time),
+                                                      upper.map(util::us_to_time)),
+        data_type => unreachable!("non-integer type {:?}", data_type),
+    }
+}
+
+impl Drop for Scanner {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl IntoIterator for Scanner {
+    type Item = Result<Row>;
+    type IntoIter = ScanRows;
+
+    /// Returns a blocking iterator over the remaining rows of the scan.
+    fn into_iter(self) -> ScanRows {
+        ScanRows {
+            batches: self.batches(),
+            rows: Vec::new().into_iter(),
+        }
+    }
+}
+
+/// A blocking iterator over the batches of a scan.
+///
+/// Each batch is retrieved with the timeout configured by `ScanBuilder::set_timeout`. If retrieving
+/// a batch fails, the error is returned and the iterator ends.
+#[derive(Debug)]
+pub struct ScanBatches {
+    scanner: Scanner,
+    failed: bool,
+}
+
+impl ScanBatches {
+    /// Returns the underlying scanner.
+    pub fn scanner(&self) -> &Scanner {
+        &self.scanner
+    }
+}
+
+impl Iterator for ScanBatches {
+    type Item = Result<ScanBatch>;
+
+    fn next(&mut self) -> Option<Result<ScanBatch>> {
+        if self.failed {
+            return None;
+        }
+        let deadline = Instant::now() + self.scanner.timeout;
+        match self.scanner.next_batch(deadline) {
+            Ok(batch) => batch.map(Ok),
+            Err(error) => {
+                self.failed = true;
+                Some(Err(error))
+            },
+        }
+    }
+}
+
+/// A blocking iterator over the batches of a scan, which are retrieved ahead of the consumer by a
+/// background thread. See `Scanner::prefetch`.
+///
+/// If retrieving a batch fails, the error is returned and the iterator ends.
+#[derive(Debug)]
+pub struct PrefetchedScanBatches {
+    batches: Receiver<(Result<ScanBatch>, Reservation)>,
+}
+
+impl Iterator for PrefetchedScanBatches {
+    type Item = Result<ScanBatch>;
+
+    fn next(&mut self) -> Option<Result<ScanBatch>> {
+        self.batches.recv().ok().map(|(batch, _)| batch)
+    }
+}
+
+/// The tablet servers' default scan batch size, which is reserved from the memory budget before
+/// a batch of unknown size is requested.
+const DEFAULT_BATCH_SIZE_BYTES: usize = 1024 * 1024;
+
+/// The batches of a scan driven by a background thread, each of which is retrieved along with its
+/// memory budget reservation. See `Scanner::prefetch`.
+struct PrefetchingBatches {
+    batches: ScanBatches,
+    budget: MemoryBudget,
+    /// The expected size of the next batch.
+    batch_size_bytes: usize,
+    /// The number of reservations of the scan which have not been released.
+    outstanding: Arc<AtomicUsize>,
+}
+
+impl PrefetchingBatches {
+    fn new(scanner: Scanner) -> PrefetchingBatches {
+        PrefetchingBatches {
+            budget: scanner.table.client().memory_budget().clone(),
+            batch_size_bytes: scanner.batch_size_bytes.map_or(DEFAULT_BATCH_SIZE_BYTES,
+                                                              |bytes| bytes as usize),
+            outstanding: Arc::new(AtomicUsize::new(0)),
+            batches: scanner.batches(),
+        }
+    }
+}
+
+impl Iterator for PrefetchingBatches {
+    type Item = (Result<ScanBatch>, Reservation);
+
+    fn next(&mut self) -> Option<(Result<ScanBatch>, Reservation)> {
+        if self.batches.failed || self.batches.scanner.done {
+            return None;
+        }
+        let mut reservation = Reservation::new(&self.budget, &self.outstanding, self.batch_size_bytes);
+        let batch = match self.batches.next() {
+            Some(batch) => batch,
+            None => return None,
+        };
+        reservation.resize(batch.as_ref().map_or(0, |batch| batch.memory));
+        Some((batch, reservation))
+    }
+}
+
+/// The memory budget reserved by a prefetched batch, which is taken before the batch is requested
+/// and released when the batch is handed to the consumer, or discarded along with a cancelled
+/// scan.
+#[derive(Debug)]
+struct Reservation {
+    budget: MemoryBudget,
+    outstanding: Arc<AtomicUsize>,
+    bytes: usize,
+}
+
+impl Reservation {
+
+    /// Reserves bytes from the budget, backing off while the budget is exhausted. A scan with no
+    /// outstanding reservations is admitted regardless of the budget, since its consumer may be
+    /// waiting on it while the budget is held by the batches of other scans.
+    fn new(budget: &MemoryBudget, outstanding: &Arc<AtomicUsize>, bytes: usize) -> Reservation {
+        let mut backoff = Backoff::with_duration_range(1, 100);
+        loop {
+            if outstanding.load(Ordering::SeqCst) == 0 {
+                budget.force_reserve(bytes);
+                break;
+            }
+            if budget.try_reserve(bytes).is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(backoff.next_backoff_ms()));
+        }
+        outstanding.fetch_add(1, Ordering::SeqCst);
+        Reservation {
+            budget: budget.clone(),
+            outstanding: outstanding.clone(),
+            bytes: bytes,
+        }
+    }
+
+    /// Adjusts the reservation to the actual size of the retrieved batch, which is already held.
+    fn resize(&mut self, bytes: usize) {
+        if bytes > self.bytes {
+            self.budget.force_reserve(bytes - self.bytes);
+        } else {
+            self.budget.release(self.bytes - bytes);
+        }
+        self.bytes = bytes;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+        self.outstanding.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A blocking iterator over the rows of a scan.
+///
+/// If retrieving a batch fails, the error is returned and the iterator ends.
+#[derive(Debug)]
+pub struct ScanRows {
+    batches: ScanBatches,
+    rows: vec::IntoIter<Row>,
+}
+
+impl Iterator for ScanRows {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Result<Row>> {
+        loop {
+            if let Some(row) = self.rows.next() {
+                return Some(Ok(row));
+            }
+            match self.batches.next() {
+                Some(Ok(batch)) => self.rows = batch.into_rows().into_iter(),
+                Some(Err(error)) => return Some(Err(error)),
+                None => return None,
+            }
+        }
+    }
+}
+
+/// A blocking iterator over the rows of a table in primary key order, merged from parallel scans of
+/// each tablet. See `ScanBuilder::build_merged`.
+///
+/// If retrieving a batch fails, the error is returned and the iterator ends. Dropping the iterator
+/// cancels the remaining tablet scans.
+#[derive(Debug)]
+pub struct MergedScanRows {
+    sources: Vec<MergeSource>,
+    /// The next row of each tablet scan which has rows remaining.
+    heap: BinaryHeap<MergeEntry>,
+    failed: bool,
+}
+
+impl MergedScanRows {
+
+    /// Retrieves the next row of the source tablet scan, and adds it to the heap.
+    fn advance(&mut self, source: usize) -> Result<()> {
+        let row = match self.sources[source].next() {
+            Some(row) => try!(row),
+            None => return Ok(()),
+        };
+        let key = try!(EncodedPrimaryKey::from_row(&row));
+        self.heap.push(MergeEntry { key: key, source: source, row: row });
+        Ok(())
+    }
+}
+
+impl Iterator for MergedScanRows {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Result<Row>> {
+        if self.failed {
+            return None;
+        }
+        let MergeEntry { source, row, .. } = match self.heap.pop() {
+            Some(entry) => entry,
+            None => return None,
+        };
+        if let Err(error) = self.advance(source) {
+            self.failed = true;
+            return Some(Err(error));
+        }
+        Some(Ok(row))
+    }
+}
+
+/// The rows of a single tablet scan of a merged scan.
+#[derive(Debug)]
+struct MergeSource {
+    batches: PrefetchedScanBatches,
+    rows: vec::IntoIter<Row>,
+}
+
+impl Iterator for MergeSource {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Result<Row>> {
+        loop {
+            if let Some(row) = self.rows.next() {
+                return Some(Ok(row));
+            }
+            match self.batches.next() {
+                Some(Ok(batch)) => self.rows = batch.into_rows().into_iter(),
+                Some(Err(error)) => return Some(Err(error)),
+                None => return None,
+            }
+        }
+    }
+}
+
+/// A row of a merged scan, ordered so that the `BinaryHeap` max-heap yields the row with the
+/// smallest primary key first.
+#[derive(Debug)]
+struct MergeEntry {
+    key: EncodedPrimaryKey,
+    source: usize,
+    row: Row,
+}
+
+impl Ord for MergeEntry {
+    fn cmp(&self, other: &MergeEntry) -> cmp::Ordering {
+        match other.key.cmp(&self.key) {
+            cmp::Ordering::Equal => other.source.cmp(&self.source),
+            ordering => ordering,
+        }
+    }
+}
+
+impl PartialOrd for MergeEntry {
+    fn partial_cmp(&self, other: &MergeEntry) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MergeEntry {
+    fn eq(&self, other: &MergeEntry) -> bool {
+        self.key == other.key && self.source == other.source
+    }
+}
+
+impl Eq for MergeEntry {}
+
+/// A stream of the batches of a scan.
+///
+/// If retrieving a batch fails, the stream fails with the error.
+pub struct ScanBatchStream {
+    batches: mpsc::Receiver<(Result<ScanBatch>, Reservation)>,
+}
+
+impl Stream for ScanBatchStream {
+    type Item = ScanBatch;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<ScanBatch>, Error> {
+        match self.batches.poll() {
+            Ok(Async::Ready(Some((Ok(batch), _)))) => Ok(Async::Ready(Some(batch))),
+            Ok(Async::Ready(Some((Err(error), _)))) => Err(error),
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(()) => unreachable!("mpsc receiver can not fail"),
+        }
+    }
+}
+
+/// A stream of the rows of a scan.
+///
+/// If retrieving a batch fails, the stream fails with the error.
+pub struct ScanRowStream {
+    batches: ScanBatchStream,
+    rows: vec::IntoIter<Row>,
+}
+
+impl Stream for ScanRowStream {
+    type Item = Row;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Row>, Error> {
+        loop {
+            if let Some(row) = self.rows.next() {
+                return Ok(Async::Ready(Some(row)));
+            }
+            match try_ready!(self.batches.poll()) {
+                Some(batch) => self.rows = batch.into_rows().into_iter(),
+                None => return Ok(Async::Ready(None)),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Scanner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Scanner {{ table: {}, projection: {:?}, tablet: {:?} }}",
+               self.table.name(), self.projection, self.tablet.as_ref().map(Tablet::id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+    use std::thread;
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+
+    use futures::{Future, Stream};
+
+    use AlterTableBuilder;
+    use Client;
+    use ClientConfig;
+    use Column;
+    use ColumnPredicate;
+    use DataType;
+    use Error;
+    use RangePartitionBound;
+    use ReplicaSelection;
+    use Result;
+    use ScanBatch;
+    use ScanCursor;
+    use SchemaBuilder;
+    use TableBuilder;
+    use TabletServerErrorCode;
+    use WriterConfig;
+    use mini_cluster::{MiniCluster, MiniClusterConfig};
+
+    use env_logger;
+
+    fn deadline() -> Instant {
+        Instant::now() + Duration::from_secs(5)
+    }
+
+    #[test]
+    fn scan() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(&MiniClusterConfig::default());
+
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("val", DataType::String))
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut table_builder = TableBuilder::new("scan", schema.clone());
+        table_builder.add_hash_partitions(vec!["key"], 4);
+        table_builder.set_num_replicas(1);
+
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+
+        let writer = table.new_writer(WriterConfig::default());
+        for i in 0..100 {
+            let mut insert = table.schema().new_row();
+            insert.set_by_name::<i32>("key", i).unwrap();
+            if i % 2 == 0 {
+                insert.set_by_name("val", format!("{}", i)).unwrap();
+            } else {
+                insert.set_null_by_name("val").unwrap();
+            }
+            writer.insert(insert);
+        }
+        let (send, recv) = sync_channel(1);
+        writer.flush(move |stats| send.send(stats).unwrap());
+        assert_eq!(100, recv.recv().unwrap().successful_operations());
+
+        let mut scanner = table.scan_builder().build().unwrap();
+        let mut rows = Vec::new();
+        while let Some(batch) = scanner.next_batch(deadline()).unwrap() {
+            rows.extend(batch.into_rows());
+        }
+        rows.sort_by_key(|row| row.get::<i32>(0).unwrap());
+
+        assert_eq!(100, rows.len());
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(i as i32, row.get::<i32>(0).unwrap());
+            if i % 2 == 0 {
+                assert_eq!(Some(format!("{}", i)), row.get::<Option<String>>(1).unwrap());
+            } else {
+                assert!(row.is_null(1).unwrap());
+            }
+        }
+
+        let mut scanner = {
+            let mut builder = table.scan_builder();
+            builder.set_projection(vec!["val"]);
+            builder.build().unwrap()
+        };
+        let mut count = 0;
+        while let Some(batch) = scanner.next_batch(deadline()).unwrap() {
+            for row in batch.rows() {
+                assert_eq!(1, row.schema().columns().len());
+            }
+            count += batch.len();
+        }
+        assert_eq!(100, count);
+        assert!(scanner.resource_metrics().total_bytes_read() >= 0);
+
+        let val_id = table.schema().column_id(1).unwrap();
+        let mut scanner = {
+            let mut builder = table.scan_builder();
+            builder.set_projection_by_ids(vec![val_id]);
+            builder.build().unwrap()
+        };
+        assert_eq!("val", scanner.projection().columns()[0].name());
+        let mut count = 0;
+        while let Some(batch) = scanner.next_batch(deadline()).unwrap() {
+            count += batch.len();
+        }
+        assert_eq!(100, count);
+
+        // A projection by ID follows the column across a rename.
+        let mut scanner = {
+            let mut builder = table.scan_builder();
+            builder.set_projection_by_ids(vec![val_id]);
+            builder.build().unwrap()
+        };
+        client.alter_table_by_id(&table_id, AlterTableBuilder::new().rename_column("val", "renamed"),
+                                 deadline()).unwrap();
+        let mut count = 0;
+        while let Some(batch) = scanner.next_batch(deadline()).unwrap() {
+            count += batch.len();
+        }
+        assert_eq!(100, count);
+        assert_eq!("renamed", scanner.projection().columns()[0].name());
+        client.alter_table_by_id(&table_id, AlterTableBuilder::new().rename_column("renamed", "val"),
+                                 deadline()).unwrap();
+
+        let mut builder = table.scan_builder();
+        builder.set_projection_by_ids(vec![u32::max_value()]);
+        assert!(builder.build().is_err());
+
+        let rows = table.scan_builder().build().unwrap().into_iter().collect::<Result<Vec<_>>>();
+        assert_eq!(100, rows.unwrap().len());
+
+        let rows = table.scan_builder().build().unwrap().into_row_stream().collect().wait();
+        assert_eq!(100, rows.unwrap().len());
+
+        let mut builder = table.scan_builder();
+        builder.set_batch_size_bytes(64).set_prefetch_depth(4);
+        let batches = builder.build().unwrap().prefetch().collect::<Result<Vec<_>>>().unwrap();
+        assert!(batches.len() > 1);
+        assert_eq!(100, batches.iter().map(ScanBatch::len).sum::<usize>());
+
+        // Merged scans return the rows of all tablets in primary key order.
+        let mut builder = table.scan_builder();
+        builder.set_batch_size_bytes(64);
+        let keys = builder.build_merged(deadline())
+                          .unwrap()
+                          .map(|row| row.unwrap().get::<i32>(0).unwrap())
+                          .collect::<Vec<_>>();
+        assert_eq!((0..100).collect::<Vec<_>>(), keys);
+
+        // Merged scans make progress when the memory budget is held by the batches of the other
+        // tablets.
+        let mut config = ClientConfig::new(cluster.master_addrs().to_owned());
+        config.set_memory_budget(Some(1));
+        let budget_client = Client::new(config);
+        let budget_table = budget_client.open_table_by_id(&table_id, deadline()).unwrap();
+        let mut builder = budget_table.scan_builder();
+        builder.set_batch_size_bytes(64).set_prefetch_depth(4);
+        let keys = builder.build_merged(deadline())
+                          .unwrap()
+                          .map(|row| row.unwrap().get::<i32>(0).unwrap())
+                          .collect::<Vec<_>>();
+        assert_eq!((0..100).collect::<Vec<_>>(), keys);
+        assert_eq!(0, budget_client.memory_budget().used());
+
+        let mut builder = table.scan_builder();
+        builder.set_projection(vec!["val", "key"]);
+        assert!(builder.build_merged(deadline()).is_err());
+
+        let predicate = ColumnPredicate::in_list("key", vec![5i32, 1, 5, 1000]);
+        let mut scanner = {
+            let mut builder = table.scan_builder();
+            builder.add_predicate(predicate.clone());
+            builder.build().unwrap()
+        };
+        let mut keys = Vec::new();
+        while let Some(batch) = scanner.next_batch(deadline()).unwrap() {
+            // The server and client evaluation of the predicate agree.
+            assert!(batch.rows().iter().all(|row| predicate.evaluate(row).unwrap()));
+            keys.extend(batch.rows().iter().map(|row| row.get::<i32>(0).unwrap()));
+        }
+        keys.sort();
+        assert_eq!(vec![1, 5], keys);
+
+        // Residual predicates can be applied to batches on the client.
+        let mut scanner = table.scan_builder().build().unwrap();
+        let mut keys = Vec::new();
+        while let Some(mut batch) = scanner.next_batch(deadline()).unwrap() {
+            batch.retain_matching(&predicate).unwrap();
+            keys.extend(batch.rows().iter().map(|row| row.get::<i32>(0).unwrap()));
+        }
+        keys.sort();
+        assert_eq!(vec![1, 5], keys);
+
+        let mut builder = table.scan_builder();
+        builder.add_predicate(ColumnPredicate::equal("key", 1i32));
+        builder.add_predicate(ColumnPredicate::equal("key", 1i64));
+        assert!(builder.build().is_err());
+
+        // Ordered scans bounded below by a primary key. Rows are only ordered within each tablet.
+        let mut lower_bound = table.schema().new_row();
+        lower_bound.set(0, 90i32).unwrap();
+        let mut scanner = {
+            let mut builder = table.scan_builder();
+            builder.set_ordered(true);
+            builder.set_lower_bound_primary_key(&lower_bound).unwrap();
+            builder.build().unwrap()
+        };
+        let mut keys = Vec::new();
+        while let Some(batch) = scanner.next_batch(deadline()).unwrap() {
+            keys.extend(batch.rows().iter().map(|row| row.get::<i32>(0).unwrap()));
+        }
+        keys.sort();
+        assert_eq!((90..100).collect::<Vec<_>>(), keys);
+        assert!(table.scan_builder().set_lower_bound_primary_key(&table.schema().new_row()).is_err());
+
+        // Close a scan part-way through.
+        let mut scanner = {
+            let mut builder = table.scan_builder();
+            builder.set_batch_size_bytes(1);
+            builder.build().unwrap()
+        };
+        assert!(scanner.next_batch(deadline()).unwrap().is_some());
+        scanner.close();
+        assert!(scanner.next_batch(deadline()).unwrap().is_none());
+    }
+
+    #[test]
+    fn split_scan() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(&MiniClusterConfig::default());
+
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("val", DataType::Int32))
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut lower_bound = schema.new_row();
+        lower_bound.set_by_name::<i32>("key", 0).unwrap();
+        let mut upper_bound = schema.new_row();
+        upper_bound.set_by_name::<i32>("key", 100).unwrap();
+        let mut split = schema.new_row();
+        split.set_by_name::<i32>("key", 50).unwrap();
+
+        let mut table_builder = TableBuilder::new("split_scan", schema.clone());
+        table_builder.set_range_partition_columns(vec!["key"]);
+        table_builder.add_range_partition(RangePartitionBound::Inclusive(lower_bound),
+                                          RangePartitionBound::Exclusive(upper_bound));
+        table_builder.add_range_partition_split(split);
+        table_builder.set_num_replicas(1);
+
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+
+        let writer = table.new_writer(WriterConfig::default());
+        for i in 0..100 {
+            let mut insert = table.schema().new_row();
+            insert.set_by_name::<i32>("key", i).unwrap();
+            insert.set_by_name::<i32>("val", i).unwrap();
+            writer.insert(insert);
+        }
+        let (send, recv) = sync_channel(1);
+        writer.flush(move |stats| send.send(stats).unwrap());
+        assert_eq!(100, recv.recv().unwrap().successful_operations());
+
+        // Fewer scans than tablets.
+        let scans = table.scan_builder().split(1, deadline()).unwrap();
+        assert_eq!(2, scans.len());
+
+        let scans = table.scan_builder().split(5, deadline()).unwrap();
+        assert_eq!(5, scans.len());
+
+        let mut keys = Vec::new();
+        for scan in scans {
+            let rows = scan.build().unwrap().into_iter().collect::<Result<Vec<_>>>().unwrap();
+            assert!(!rows.is_empty());
+            assert!(rows.len() <= 25);
+            keys.extend(rows.iter().map(|row| row.get::<i32>(0).unwrap()));
+        }
+        keys.sort();
+        assert_eq!((0..100).collect::<Vec<_>>(), keys);
+    }
+
+    #[test]
+    fn timestamp_key_range_predicate() {
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("ts", DataType::Timestamp).set_not_null())
+            .set_primary_key(vec!["ts"])
+            .build()
+            .unwrap();
+
+        let predicate = super::key_range_predicate(&schema.columns()[0], Some(1_000), None);
+        assert_eq!(Some(DataType::Timestamp), predicate.value_type());
+        assert_eq!(ColumnPredicate::range("ts", Some(UNIX_EPOCH + Duration::from_millis(1)), None),
+                   predicate);
+    }
+
+    #[test]
+    fn scanner_expired() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default().scanner_ttl(500));
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut table_builder = TableBuilder::new("scanner_expired", schema);
+        table_builder.set_num_replicas(1);
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+
+        let writer = table.new_writer(WriterConfig::default());
+        for i in 0..100 {
+            let mut insert = table.schema().new_row();
+            insert.set(0, i).unwrap();
+            writer.insert(insert);
+        }
+        let (send, recv) = sync_channel(1);
+        writer.flush(move |stats| send.send(stats).unwrap());
+        assert_eq!(100, recv.recv().unwrap().successful_operations());
+
+        // Ordered scans resume after the last returned row.
+        let mut scanner = {
+            let mut builder = table.scan_builder();
+            builder.set_batch_size_bytes(64).set_ordered(true);
+            builder.build().unwrap()
+        };
+        let mut keys = Vec::new();
+        while let Some(batch) = scanner.next_batch(deadline()).unwrap() {
+            keys.extend(batch.rows().iter().map(|row| row.get::<i32>(0).unwrap()));
+            thread::sleep(Duration::from_millis(1500));
+        }
+        assert_eq!((0..100).collect::<Vec<_>>(), keys);
+
+        // Unordered scans can not be resumed, so the scan fails rather than returning rows twice.
+        let mut scanner = {
+            let mut builder = table.scan_builder();
+            builder.set_batch_size_bytes(64);
+            builder.build().unwrap()
+        };
+        let first_batch = scanner.next_batch(deadline()).unwrap().unwrap();
+        assert!(first_batch.len() < 100);
+        thread::sleep(Duration::from_millis(1500));
+        match scanner.next_batch(deadline()) {
+            Err(Error::TabletServer(ref error)) if error.code() == TabletServerErrorCode::ScannerExpired => (),
+            other => panic!("expected a scanner expired error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn scan_cursor() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(&MiniClusterConfig::default());
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("val", DataType::Int32))
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut split = schema.new_row();
+        split.set_by_name::<i32>("key", 50).unwrap();
+
+        let mut table_builder = TableBuilder::new("scan_cursor", schema);
+        table_builder.set_range_partition_columns(vec!["key"]);
+        table_builder.add_range_partition_split(split);
+        table_builder.set_num_replicas(1);
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+
+        let writer = table.new_writer(WriterConfig::default());
+        for i in 0..100 {
+            let mut insert = table.schema().new_row();
+            insert.set_by_name::<i32>("key", i).unwrap();
+            insert.set_by_name::<i32>("val", i).unwrap();
+            writer.insert(insert);
+        }
+        let (send, recv) = sync_channel(1);
+        writer.flush(move |stats| send.send(stats).unwrap());
+        assert_eq!(100, recv.recv().unwrap().successful_operations());
+
+        // Retrieve a single batch per scanner, passing the position between scanners as a token.
+        let mut token: Option<String> = None;
+        let mut keys = Vec::new();
+        let mut pages = 0;
+        loop {
+            let mut builder = table.scan_builder();
+            builder.set_batch_size_bytes(64);
+            match token {
+                Some(ref token) => { builder.resume_from(ScanCursor::from_token(token).unwrap()); },
+                None => { builder.set_ordered(true).set_projection(vec!["key"]); },
+            }
+            let mut scanner = builder.build().unwrap();
+            assert_eq!(1, scanner.projection().columns().len());
+            if let Some(batch) = scanner.next_batch(deadline()).unwrap() {
+                keys.extend(batch.rows().iter().map(|row| row.get::<i32>(0).unwrap()));
+            }
+            let cursor = scanner.cursor().unwrap();
+            assert_eq!(cursor, ScanCursor::from_bytes(&cursor.to_bytes()).unwrap());
+            pages += 1;
+            if cursor.is_done() {
+                break;
+            }
+            token = Some(cursor.to_token());
+        }
+        assert!(pages > 2);
+        assert_eq!((0..100).collect::<Vec<_>>(), keys);
+
+        assert!(ScanCursor::from_token("not a token").is_err());
+        let bytes = table.scan_builder().build().unwrap().cursor().unwrap().to_bytes();
+        assert!(ScanCursor::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // Unordered scans can not be paused part way through a tablet.
+        let mut scanner = {
+            let mut builder = table.scan_builder();
+            builder.set_batch_size_bytes(64);
+            builder.build().unwrap()
+        };
+        scanner.next_batch(deadline()).unwrap();
+        assert!(scanner.cursor().is_err());
+    }
+
+    #[test]
+    fn closest_replica() {
+        let _ = env_logger::init();
+        let mut cluster = MiniCluster::new(MiniClusterConfig::default().num_tservers(3));
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut table_builder = TableBuilder::new("closest_replica", schema);
+        table_builder.set_num_replicas(3);
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+
+        let writer = table.new_writer(WriterConfig::default());
+        for i in 0..100 {
+            let mut insert = table.schema().new_row();
+            insert.set(0, i).unwrap();
+            writer.insert(insert);
+        }
+        let (send, recv) = sync_channel(1);
+        writer.flush(move |stats| send.send(stats).unwrap());
+        assert_eq!(100, recv.recv().unwrap().successful_operations());
+
+        // Stop the tablet server of the leader replica, which is scanned first since all replicas
+        // are local.
+        let tablets = table.tablets(deadline()).unwrap();
+        let leader = tablets[0].leader().unwrap().clone();
+        cluster.stop_node(leader.resolved_rpc_addrs()[0]);
+
+        let mut builder = table.scan_builder();
+        builder.set_replica_selection(ReplicaSelection::ClosestReplica);
+        let rows = builder.build().unwrap().into_iter().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(100, rows.len());
+        assert!(table.meta_cache().is_blacklisted(leader.id()));
+    }
+}
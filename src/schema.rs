@@ -1,9 +1,13 @@
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
 use std::sync::Arc;
 
+use byteorder::{LittleEndian, WriteBytesExt};
 use kudu_pb::common::{ColumnSchemaPB, SchemaPB};
+use protobuf::Message;
 #[cfg(any(feature="quickcheck", test))] use quickcheck;
 
 use CompressionType;
@@ -12,6 +16,21 @@ use EncodingType;
 use Error;
 use Result;
 use Row;
+use SchemaError;
+use Value;
+use key::murmur2_64;
+
+/// `ColumnSchemaPB` field numbers of column attributes introduced after the Kudu release which the
+/// checked-in Protobuf sources are generated from. The attributes are carried as unknown fields.
+const IMMUTABLE_FIELD_NUMBER: u32 = 13;
+const IS_AUTO_INCREMENTING_FIELD_NUMBER: u32 = 14;
+
+/// The largest column block size supported by Kudu, which stores block sizes as a signed 32-bit
+/// integer.
+const MAX_BLOCK_SIZE: u32 = 0x7fff_ffff;
+
+/// The name of the auto-incrementing primary key column of tables with a non-unique primary key.
+pub const AUTO_INCREMENTING_COLUMN_NAME: &'static str = "auto_incrementing_id";
 
 /// `Column` instances hold metadata information about columns in a Kudu table.
 ///
@@ -25,6 +44,8 @@ pub struct Column {
     compression: CompressionType,
     encoding: EncodingType,
     block_size: u32,
+    is_immutable: bool,
+    is_auto_incrementing: bool,
 }
 
 impl Column {
@@ -57,6 +78,19 @@ impl Column {
         }
     }
 
+    /// Returns `true` if the column's values can not be changed once a row is inserted.
+    pub fn is_immutable(&self) -> bool {
+        self.is_immutable
+    }
+
+    /// Returns `true` if the column's values are assigned by the server when rows are inserted.
+    ///
+    /// Auto-incrementing columns are added by the server to tables with a non-unique primary key,
+    /// and can not be created directly.
+    pub fn is_auto_incrementing(&self) -> bool {
+        self.is_auto_incrementing
+    }
+
     /// Returns a new column builder.
     pub fn builder<S>(name: S, data_type: DataType) -> Column where S: Into<String> {
         Column {
@@ -66,6 +100,8 @@ impl Column {
             compression: CompressionType::Default,
             encoding: EncodingType::Auto,
             block_size: 0,
+            is_immutable: false,
+            is_auto_incrementing: false,
         }
     }
 
@@ -89,6 +125,9 @@ impl Column {
         self
     }
 
+    /// Sets the column's encoding. Encodings which are not supported for the column's data type,
+    /// see `DataType::supported_encodings`, are rejected when the column is added to a schema or
+    /// table.
     pub fn set_encoding(mut self, encoding: EncodingType) -> Column {
         self.set_encoding_by_ref(encoding);
         self
@@ -109,6 +148,10 @@ impl Column {
         self
     }
 
+    /// Sets the column's storage block size in bytes. A block size of 0 uses the server's default.
+    ///
+    /// Block sizes larger than 2^31 - 1 bytes are rejected when the column is added to a schema or
+    /// table.
     pub fn set_block_size(mut self, block_size: u32) -> Column {
         self.set_block_size_by_ref(block_size);
         self
@@ -119,6 +162,34 @@ impl Column {
         self
     }
 
+    /// Checks that the column's attributes are within the ranges supported by Kudu.
+    #[doc(hidden)]
+    pub fn check(&self) -> Result<()> {
+        if self.block_size > MAX_BLOCK_SIZE {
+            return Err(Error::InvalidArgument(format!(
+                        "block size {} of column '{}' is greater than the maximum of {}",
+                        self.block_size, self.name, MAX_BLOCK_SIZE)));
+        }
+        if !self.encoding.is_supported_for(self.data_type) {
+            return Err(Error::InvalidArgument(format!(
+                        "encoding {:?} of column '{}' is not supported for type {:?}, supported encodings: {:?}",
+                        self.encoding, self.name, self.data_type, self.data_type.supported_encodings())));
+        }
+        Ok(())
+    }
+
+    /// Marks the column as immutable. Immutable columns may be set when a row is inserted, but
+    /// updates to them are rejected. Primary key columns can not be immutable.
+    pub fn set_immutable(mut self) -> Column {
+        self.set_immutable_by_ref();
+        self
+    }
+
+    pub fn set_immutable_by_ref(&mut self) -> &mut Column {
+        self.is_immutable = true;
+        self
+    }
+
     #[doc(hidden)]
     pub fn to_pb(&self, is_key: bool) -> ColumnSchemaPB {
         let mut pb = ColumnSchemaPB::new();
@@ -128,25 +199,47 @@ impl Column {
         pb.set_is_key(is_key);
         pb.set_encoding(self.encoding.to_pb());
         pb.set_compression(self.compression.to_pb());
-        // TODO: checked cast.
-        pb.set_cfile_block_size(self.block_size as i32);
+        // Block sizes are validated by `Column::check` before columns are sent to the server.
+        debug_assert!(self.block_size <= MAX_BLOCK_SIZE);
+        pb.set_cfile_block_size(cmp::min(self.block_size, MAX_BLOCK_SIZE) as i32);
+        if self.is_immutable {
+            pb.mut_unknown_fields().add_varint(IMMUTABLE_FIELD_NUMBER, 1);
+        }
+        if self.is_auto_incrementing {
+            pb.mut_unknown_fields().add_varint(IS_AUTO_INCREMENTING_FIELD_NUMBER, 1);
+        }
         pb
     }
 
     #[doc(hidden)]
     pub fn from_pb(mut pb: ColumnSchemaPB) -> Result<Column> {
+        let is_immutable = get_unknown_bool(&pb, IMMUTABLE_FIELD_NUMBER);
+        let is_auto_incrementing = get_unknown_bool(&pb, IS_AUTO_INCREMENTING_FIELD_NUMBER);
+        if pb.get_cfile_block_size() < 0 {
+            return Err(Error::InvalidArgument(format!("column '{}' has negative block size {}",
+                                                      pb.get_name(), pb.get_cfile_block_size())));
+        }
         Ok(Column {
             name: pb.take_name(),
             data_type: try!(DataType::from_pb(pb.get_field_type())),
             is_nullable: pb.get_is_nullable(),
             compression: try!(CompressionType::from_pb(pb.get_compression())),
             encoding: try!(EncodingType::from_pb(pb.get_encoding())),
-            // TODO: checked cast.
             block_size: pb.get_cfile_block_size() as u32,
+            is_immutable: is_immutable,
+            is_auto_incrementing: is_auto_incrementing,
         })
     }
 }
 
+/// Returns the value of a boolean field which is not known to the generated message type.
+fn get_unknown_bool(pb: &ColumnSchemaPB, field_number: u32) -> bool {
+    pb.get_unknown_fields()
+      .get(field_number)
+      .and_then(|values| values.varint.last())
+      .map_or(false, |&value| value != 0)
+}
+
 impl fmt::Debug for Column {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         try!(write!(f, "{} {:?}", self.name, self.data_type));
@@ -162,6 +255,12 @@ impl fmt::Debug for Column {
         if let Some(block_size) = self.block_size() {
             try!(write!(f, " BLOCK SIZE {}", block_size));
         }
+        if self.is_immutable {
+            try!(write!(f, " IMMUTABLE"));
+        }
+        if self.is_auto_incrementing {
+            try!(write!(f, " AUTO_INCREMENTING"));
+        }
         Ok(())
     }
 }
@@ -170,10 +269,15 @@ impl fmt::Debug for Column {
 struct Inner {
     columns: Vec<Column>,
     columns_by_name: HashMap<String, usize>,
+    /// Server-assigned column IDs. Empty if the schema was not retrieved from the server.
+    column_ids: Vec<u32>,
     column_offsets: Vec<usize>,
     num_primary_key_columns: usize,
     row_size: usize,
     has_nullable_columns: bool,
+    fingerprint: u64,
+    /// Indices of the range partition columns. Empty unless set with `SchemaBuilder`.
+    range_partition_columns: Vec<usize>,
 }
 
 #[derive(Clone)]
@@ -184,6 +288,15 @@ pub struct Schema {
 impl Schema {
 
     fn new(columns: Vec<Column>, num_primary_key_columns: usize) -> Schema {
+        Schema::with_column_ids(columns, Vec::new(), num_primary_key_columns, Vec::new())
+    }
+
+    fn with_column_ids(columns: Vec<Column>,
+                       column_ids: Vec<u32>,
+                       num_primary_key_columns: usize,
+                       range_partition_columns: Vec<usize>)
+                       -> Schema {
+        debug_assert!(column_ids.is_empty() || column_ids.len() == columns.len());
         let mut columns_by_name = HashMap::with_capacity(columns.len());
         let mut column_offsets = Vec::with_capacity(columns.len());
         let mut row_size = 0;
@@ -194,15 +307,19 @@ impl Schema {
             row_size += column.data_type.size();
             has_nullable_columns |= column.is_nullable();
         }
+        let fingerprint = fingerprint(&columns, num_primary_key_columns);
 
         Schema {
             inner: Arc::new(Inner {
                 columns: columns,
                 columns_by_name: columns_by_name,
+                column_ids: column_ids,
                 column_offsets: column_offsets,
                 num_primary_key_columns: num_primary_key_columns,
                 row_size: row_size,
                 has_nullable_columns: has_nullable_columns,
+                fingerprint: fingerprint,
+                range_partition_columns: range_partition_columns,
             })
         }
     }
@@ -223,10 +340,43 @@ impl Schema {
         self.inner.columns_by_name.get(name).cloned()
     }
 
+    /// Returns the server-assigned ID of the column at the provided index.
+    ///
+    /// Column IDs are stable across column renames. Only schemas retrieved from the server, such
+    /// as `Table::schema`, have column IDs.
+    pub fn column_id(&self, index: usize) -> Option<u32> {
+        self.inner.column_ids.get(index).cloned()
+    }
+
+    /// Returns the index of the auto-incrementing column, or `None` if the schema has a unique
+    /// primary key. See `SchemaBuilder::set_non_unique_primary_key`.
+    pub fn auto_incrementing_column_index(&self) -> Option<usize> {
+        self.primary_key().iter().position(Column::is_auto_incrementing)
+    }
+
+    /// Returns the index of the column with the provided server-assigned ID.
+    pub fn column_index_by_id(&self, id: u32) -> Option<usize> {
+        self.inner.column_ids.iter().position(|&column_id| column_id == id)
+    }
+
+    /// Returns the indices of the columns the table is range partitioned by, as set with
+    /// `SchemaBuilder::set_range_partition_columns`.
+    ///
+    /// Schemas retrieved from the server do not carry range partition columns; use
+    /// `Table::partition_schema` instead.
+    pub fn range_partition_columns(&self) -> &[usize] {
+        &self.inner.range_partition_columns
+    }
+
     pub fn primary_key(&self) -> &[Column] {
         &self.inner.columns[0..self.inner.num_primary_key_columns]
     }
 
+    /// Returns a builder seeded with the schema. See `SchemaBuilder::from_schema`.
+    pub fn into_builder(self) -> SchemaBuilder {
+        SchemaBuilder::from_schema(&self)
+    }
+
     #[doc(hidden)]
     pub fn num_primary_key_columns(&self) -> usize {
         self.inner.num_primary_key_columns
@@ -236,10 +386,59 @@ impl Schema {
         Schema::new(self.primary_key().to_owned(), self.num_primary_key_columns())
     }
 
+    /// Returns a schema containing the named columns, in order, for reading a subset of a table's
+    /// columns.
+    ///
+    /// Column offsets are recomputed for the projected columns, and primary key columns are only
+    /// retained as primary key columns if they form a prefix of the projection. Fails if a column
+    /// is unknown or is projected more than once.
+    pub fn project<S>(&self, columns: &[S]) -> Result<Schema> where S: AsRef<str> {
+        let mut indices = Vec::with_capacity(columns.len());
+        for column in columns {
+            let column = column.as_ref();
+            let idx = match self.column_index(column) {
+                Some(idx) => idx,
+                None => return Err(Error::InvalidArgument(format!("unknown column '{}'", column))),
+            };
+            if indices.contains(&idx) {
+                return Err(Error::InvalidArgument(format!("column '{}' is projected more than once",
+                                                          column)));
+            }
+            indices.push(idx);
+        }
+        Ok(self.projection(&indices))
+    }
+
+    /// Returns a schema containing the columns at the provided indices, in order. Primary key
+    /// columns are only retained as primary key columns if they form a prefix of the projection.
+    #[doc(hidden)]
+    pub fn projection(&self, indices: &[usize]) -> Schema {
+        let columns = indices.iter().map(|&idx| self.inner.columns[idx].clone()).collect::<Vec<_>>();
+        let column_ids = if self.inner.column_ids.is_empty() { Vec::new() }
+                         else { indices.iter().map(|&idx| self.inner.column_ids[idx]).collect() };
+        let num_primary_key_columns = indices.iter()
+                                             .enumerate()
+                                             .take_while(|&(i, &idx)| i == idx &&
+                                                         idx < self.inner.num_primary_key_columns)
+                                             .count();
+        Schema::with_column_ids(columns, column_ids, num_primary_key_columns, Vec::new())
+    }
+
     pub fn row_size(&self) -> usize {
         self.inner.row_size
     }
 
+    /// Returns a fingerprint of the schema's column names, types, nullability, order, and primary
+    /// key columns.
+    ///
+    /// Schemas which differ in any of these have different fingerprints with high probability, so
+    /// the fingerprint can stand in for the schema in cache keys. The fingerprint is stable across
+    /// processes and does not depend on server-assigned column IDs, nor on column storage
+    /// attributes such as the encoding.
+    pub fn fingerprint(&self) -> u64 {
+        self.inner.fingerprint
+    }
+
     pub fn has_nullable_columns(&self) -> bool {
         self.inner.has_nullable_columns
     }
@@ -252,6 +451,29 @@ impl Schema {
         Row::new(self.clone())
     }
 
+    /// Returns a typed handle to the named column.
+    ///
+    /// Fails if the column does not exist, or if values of type `V` can not be both read from and
+    /// written to the column. As with `Row::set` and `Row::get`, a nullable type (an `Option`) is
+    /// accepted for a column which is not nullable, in which case setting `None` fails.
+    pub fn typed_column<'a, V>(&self, name: &str) -> Result<TypedColumn<V>> where V: Value<'a> {
+        let idx = match self.column_index(name) {
+            Some(idx) => idx,
+            None => return Err(Error::InvalidArgument(format!("unknown column '{}'", name))),
+        };
+        let column = &self.inner.columns[idx];
+        if !V::can_read_from(column.data_type()) || !V::can_write_to(column.data_type()) {
+            return Err(Error::InvalidArgument(format!("type {:?} is invalid for column {:?}",
+                                                      V::data_type(), column)));
+        }
+        Ok(TypedColumn {
+            idx: idx,
+            name: column.name().to_owned(),
+            data_type: column.data_type(),
+            value_type: PhantomData,
+        })
+    }
+
     pub fn ref_eq(&self, other: &Schema) -> bool {
         let this: *const Inner = &*self.inner;
         let that: *const Inner = &*other.inner;
@@ -271,11 +493,17 @@ impl Schema {
     pub fn from_pb(mut pb: SchemaPB) -> Result<Schema> {
         let mut num_primary_key_columns = 0;
         let mut columns = Vec::with_capacity(pb.get_columns().len());
+        let mut column_ids = Vec::with_capacity(pb.get_columns().len());
         for column in pb.take_columns().into_iter() {
             if column.get_is_key() { num_primary_key_columns += 1 }
+            if column.has_id() { column_ids.push(column.get_id()) }
             columns.push(try!(Column::from_pb(column)))
         }
-        Ok(Schema::new(columns, num_primary_key_columns))
+        // Only retain the column IDs if every column has one.
+        if column_ids.len() != columns.len() {
+            column_ids.clear();
+        }
+        Ok(Schema::with_column_ids(columns, column_ids, num_primary_key_columns, Vec::new()))
     }
 }
 
@@ -289,6 +517,70 @@ impl cmp::PartialEq for Schema {
 
 impl cmp::Eq for Schema { }
 
+impl Hash for Schema {
+    fn hash<H>(&self, state: &mut H) where H: Hasher {
+        self.inner.fingerprint.hash(state);
+    }
+}
+
+/// Computes the fingerprint of a schema. See `Schema::fingerprint`.
+fn fingerprint(columns: &[Column], num_primary_key_columns: usize) -> u64 {
+    let mut buf = Vec::new();
+    buf.write_u32::<LittleEndian>(num_primary_key_columns as u32).unwrap();
+    for column in columns {
+        buf.write_u32::<LittleEndian>(column.name().len() as u32).unwrap();
+        buf.extend_from_slice(column.name().as_bytes());
+        buf.write_i32::<LittleEndian>(column.data_type().to_pb() as i32).unwrap();
+        buf.push(column.is_nullable() as u8);
+    }
+    murmur2_64(&buf, 0)
+}
+
+/// A handle to a column whose type has been checked against a value type.
+///
+/// Typed columns are created with `Schema::typed_column`, and used with `Row::set_typed` and
+/// `Row::get_typed`. Because the type check happens when the handle is created, a mismatched
+/// value type is caught where the column is looked up rather than when a row is written.
+pub struct TypedColumn<V> {
+    idx: usize,
+    name: String,
+    data_type: DataType,
+    value_type: PhantomData<V>,
+}
+
+impl <V> TypedColumn<V> {
+
+    /// Returns the index of the column in the schema.
+    pub fn index(&self) -> usize {
+        self.idx
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+}
+
+impl <V> Clone for TypedColumn<V> {
+    fn clone(&self) -> TypedColumn<V> {
+        TypedColumn {
+            idx: self.idx,
+            name: self.name.clone(),
+            data_type: self.data_type,
+            value_type: PhantomData,
+        }
+    }
+}
+
+impl <V> fmt::Debug for TypedColumn<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TypedColumn({:?} {:?})", self.name, self.data_type)
+    }
+}
+
 impl fmt::Debug for Schema {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         try!(write!(f, "("));
@@ -349,8 +641,7 @@ impl quickcheck::Arbitrary for Schema {
             column.set_encoding_by_ref(EncodingType::arbitrary(g, data_type));
             column.set_compression_by_ref(CompressionType::arbitrary(g));
             if bool::arbitrary(g) {
-                // TODO: can Kudu support arbitrary block sizes?
-                column.set_block_size_by_ref(u32::arbitrary(g));
+                column.set_block_size_by_ref(u32::arbitrary(g) % MAX_BLOCK_SIZE);
             }
             builder.add_column_by_ref(column);
         }
@@ -393,6 +684,7 @@ impl quickcheck::Arbitrary for Schema {
 pub struct SchemaBuilder {
     columns: Vec<Column>,
     primary_key: Vec<String>,
+    non_unique_primary_key: bool,
     range_partition_columns: Vec<String>,
 }
 
@@ -402,10 +694,41 @@ impl SchemaBuilder {
         SchemaBuilder {
             columns: Vec::new(),
             primary_key: Vec::new(),
+            non_unique_primary_key: false,
             range_partition_columns: Vec::new(),
         }
     }
 
+    /// Returns a builder seeded with the columns, primary key, and range partition columns of the
+    /// schema, so that it can be modified and built into a new schema.
+    ///
+    /// A non-unique primary key is carried over as such, without its auto-incrementing column,
+    /// which is added back by `build`. Server-assigned column IDs are not carried over.
+    pub fn from_schema(schema: &Schema) -> SchemaBuilder {
+        let auto_incrementing_column = schema.auto_incrementing_column_index();
+        let columns = schema.columns()
+                            .iter()
+                            .enumerate()
+                            .filter(|&(idx, _)| Some(idx) != auto_incrementing_column)
+                            .map(|(_, column)| column.clone())
+                            .collect();
+        let primary_key = schema.primary_key()
+                                .iter()
+                                .filter(|column| !column.is_auto_incrementing())
+                                .map(|column| column.name().to_owned())
+                                .collect();
+        let range_partition_columns = schema.range_partition_columns()
+                                            .iter()
+                                            .map(|&idx| schema.columns()[idx].name().to_owned())
+                                            .collect();
+        SchemaBuilder {
+            columns: columns,
+            primary_key: primary_key,
+            non_unique_primary_key: auto_incrementing_column.is_some(),
+            range_partition_columns: range_partition_columns,
+        }
+    }
+
     pub fn add_column(mut self, column: Column) -> SchemaBuilder {
         self.add_column_by_ref(column);
         self
@@ -416,6 +739,44 @@ impl SchemaBuilder {
         self
     }
 
+    /// Removes the column with the provided name, if any. Building fails if the column is part of
+    /// the primary key or range partition columns, unless they are also changed.
+    pub fn remove_column(mut self, name: &str) -> SchemaBuilder {
+        self.remove_column_by_ref(name);
+        self
+    }
+
+    pub fn remove_column_by_ref(&mut self, name: &str) -> &mut SchemaBuilder {
+        self.columns.retain(|column| column.name() != name);
+        self
+    }
+
+    /// Renames the column with the provided name, if any, along with its references in the
+    /// primary key and range partition columns.
+    pub fn rename_column<S>(mut self, name: &str, new_name: S) -> SchemaBuilder where S: Into<String> {
+        self.rename_column_by_ref(name, new_name);
+        self
+    }
+
+    pub fn rename_column_by_ref<S>(&mut self, name: &str, new_name: S) -> &mut SchemaBuilder
+    where S: Into<String> {
+        let new_name = new_name.into();
+        for column in self.columns.iter_mut().filter(|column| column.name() == name) {
+            column.name = new_name.clone();
+        }
+        for column_name in self.primary_key.iter_mut().chain(self.range_partition_columns.iter_mut()) {
+            if column_name == name {
+                *column_name = new_name.clone();
+            }
+        }
+        self
+    }
+
+    /// Returns the column with the provided name, for modifying its attributes in place.
+    pub fn column_mut(&mut self, name: &str) -> Option<&mut Column> {
+        self.columns.iter_mut().find(|column| column.name() == name)
+    }
+
     pub fn set_primary_key<S>(mut self, columns: Vec<S>) -> SchemaBuilder where S: Into<String> {
         self.set_primary_key_by_ref(columns);
         self
@@ -423,31 +784,143 @@ impl SchemaBuilder {
 
     pub fn set_primary_key_by_ref<S>(&mut self, columns: Vec<S>) -> &mut SchemaBuilder where S: Into<String> {
         self.primary_key = columns.into_iter().map(Into::into).collect();
+        self.non_unique_primary_key = false;
+        self
+    }
+
+    /// Sets the primary key columns, allowing multiple rows with the same primary key values.
+    ///
+    /// An auto-incrementing `INT64` column named `auto_incrementing_id` is appended to the primary
+    /// key. The server assigns it a unique value when a row is inserted, so inserts must leave it
+    /// unset, while updates and deletes must set it to identify the row.
+    pub fn set_non_unique_primary_key<S>(mut self, columns: Vec<S>) -> SchemaBuilder where S: Into<String> {
+        self.set_non_unique_primary_key_by_ref(columns);
         self
     }
 
+    pub fn set_non_unique_primary_key_by_ref<S>(&mut self, columns: Vec<S>) -> &mut SchemaBuilder
+    where S: Into<String> {
+        self.primary_key = columns.into_iter().map(Into::into).collect();
+        self.non_unique_primary_key = true;
+        self
+    }
+
+    /// Sets the columns which tables created with the schema are range partitioned by. The columns
+    /// must be part of the primary key.
+    ///
+    /// The range partition columns are used by `TableBuilder` unless it sets its own.
+    pub fn set_range_partition_columns<S>(mut self, columns: Vec<S>) -> SchemaBuilder where S: Into<String> {
+        self.set_range_partition_columns_by_ref(columns);
+        self
+    }
+
+    pub fn set_range_partition_columns_by_ref<S>(&mut self, columns: Vec<S>) -> &mut SchemaBuilder
+    where S: Into<String> {
+        self.range_partition_columns = columns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Builds the schema.
+    ///
+    /// Fails with `Error::InvalidSchema` if the schema is invalid, for instance because of
+    /// duplicate column names, or primary key columns which are nullable or have a type which can
+    /// not be part of a key. Every problem with the schema is reported, not only the first.
     pub fn build(mut self) -> Result<Schema> {
-        if self.primary_key.is_empty() {
-            return Err(Error::InvalidArgument(
-                    "primary key must have at least one column".to_owned()));
+        let errors = self.validate();
+        if !errors.is_empty() {
+            return Err(Error::InvalidSchema(errors));
         }
 
-        let mut columns = Vec::with_capacity(self.columns.len());
+        let mut columns = Vec::with_capacity(self.columns.len() + 1);
+        for column_name in &self.primary_key {
+            let idx = self.columns.iter().position(|col| col.name() == column_name).unwrap();
+            columns.push(self.columns.remove(idx));
+        }
 
+        if self.non_unique_primary_key {
+            let mut column = Column::builder(AUTO_INCREMENTING_COLUMN_NAME, DataType::Int64).set_not_null();
+            column.is_auto_incrementing = true;
+            columns.push(column);
+        }
+        let num_primary_key_columns = columns.len();
+
+        let range_partition_columns =
+            self.range_partition_columns
+                .iter()
+                .map(|column_name| columns.iter().position(|col| col.name() == column_name).unwrap())
+                .collect();
+
+        columns.extend(self.columns.drain(..));
+
+        Ok(Schema::with_column_ids(columns, Vec::new(), num_primary_key_columns, range_partition_columns))
+    }
+
+    /// Returns every problem with the schema definition.
+    fn validate(&self) -> Vec<SchemaError> {
+        let mut errors = Vec::new();
+
+        let mut column_names = HashSet::new();
+        for column in &self.columns {
+            if !column_names.insert(column.name()) {
+                errors.push(SchemaError::DuplicateColumn(column.name().to_owned()));
+            }
+            if self.non_unique_primary_key && column.name() == AUTO_INCREMENTING_COLUMN_NAME {
+                errors.push(SchemaError::ReservedColumnName(column.name().to_owned()));
+            }
+            if column.block_size > MAX_BLOCK_SIZE {
+                errors.push(SchemaError::InvalidBlockSize(column.name().to_owned(), column.block_size));
+            }
+            if !column.encoding.is_supported_for(column.data_type) {
+                errors.push(SchemaError::UnsupportedEncoding(column.name().to_owned(),
+                                                             column.data_type,
+                                                             column.encoding));
+            }
+        }
+
+        if self.primary_key.is_empty() {
+            errors.push(SchemaError::EmptyPrimaryKey);
+        }
+
+        let mut key_column_names = HashSet::new();
         for column_name in &self.primary_key {
-            let idx = self.columns.iter().position(|col| col.name() == column_name);
-            if let Some(idx) = idx {
-                columns.push(self.columns.remove(idx));
-            } else {
-                return Err(Error::InvalidArgument(
-                    format!("primary key column '{}' has no corresponding column",
-                            column_name)))
+            if !key_column_names.insert(column_name) {
+                errors.push(SchemaError::DuplicatePrimaryKeyColumn(column_name.clone()));
+                continue;
+            }
+            let column = match self.columns.iter().find(|col| col.name() == column_name) {
+                Some(column) => column,
+                None => {
+                    errors.push(SchemaError::MissingPrimaryKeyColumn(column_name.clone()));
+                    continue;
+                },
+            };
+            if column.is_nullable() {
+                errors.push(SchemaError::NullablePrimaryKeyColumn(column_name.clone()));
+            }
+            match column.data_type() {
+                DataType::Bool | DataType::Float | DataType::Double => {
+                    errors.push(SchemaError::UnsupportedPrimaryKeyType(column_name.clone(),
+                                                                       column.data_type()));
+                },
+                _ => (),
+            }
+            if column.is_immutable() {
+                errors.push(SchemaError::ImmutablePrimaryKeyColumn(column_name.clone()));
             }
         }
 
-        columns.extend(self.columns.drain(..));
+        let mut range_partition_column_names = HashSet::new();
+        for column_name in &self.range_partition_columns {
+            if !range_partition_column_names.insert(column_name) {
+                errors.push(SchemaError::DuplicateRangePartitionColumn(column_name.clone()));
+            } else if !self.columns.iter().any(|col| col.name() == column_name) {
+                errors.push(SchemaError::MissingRangePartitionColumn(column_name.clone()));
+            } else if !key_column_names.contains(column_name) {
+                errors.push(SchemaError::RangePartitionColumnNotInPrimaryKey(column_name.clone()));
+            }
+        }
 
-        Ok(Schema::new(columns, self.primary_key.len()))
+        errors
     }
 }
 
@@ -498,4 +971,245 @@ pub mod tests {
     fn test_create_schema() {
         all_types_schema();
     }
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(simple_schema().fingerprint(), simple_schema().fingerprint());
+        assert!(simple_schema().fingerprint() != all_types_schema().fingerprint());
+
+        // Column order.
+        let schema = simple_schema();
+        assert!(schema.fingerprint() != schema.projection(&[1, 0]).fingerprint());
+
+        // Primary key columns.
+        let composite_key = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::String).set_not_null())
+            .add_column(Column::builder("val", DataType::String).set_not_null())
+            .set_primary_key(vec!["key", "val"])
+            .build()
+            .unwrap();
+        assert!(schema.fingerprint() != composite_key.fingerprint());
+
+        // Column names are delimited.
+        let renamed = SchemaBuilder::new()
+            .add_column(Column::builder("keyv", DataType::String).set_not_null())
+            .add_column(Column::builder("al", DataType::String).set_not_null())
+            .set_primary_key(vec!["keyv"])
+            .build()
+            .unwrap();
+        assert!(schema.fingerprint() != renamed.fingerprint());
+
+        // Storage attributes do not change the fingerprint.
+        let encoded = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::String).set_not_null())
+            .add_column(Column::builder("val", DataType::String).set_not_null()
+                                                                .set_block_size(4096))
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+        assert_eq!(schema.fingerprint(), encoded.fingerprint());
+    }
+
+    #[test]
+    fn test_immutable_column() {
+        let column = Column::builder("val", DataType::Int32).set_immutable();
+        assert!(column.is_immutable());
+
+        let pb = column.to_pb(false);
+        let decoded = Column::from_pb(pb).unwrap();
+        assert!(decoded.is_immutable());
+        assert!(!decoded.is_auto_incrementing());
+        assert_eq!(column, decoded);
+
+        let mut pb = Column::builder("auto_incrementing_id", DataType::Int64).set_not_null().to_pb(true);
+        pb.mut_unknown_fields().add_varint(IS_AUTO_INCREMENTING_FIELD_NUMBER, 1);
+        assert!(Column::from_pb(pb).unwrap().is_auto_incrementing());
+
+        assert!(SchemaBuilder::new()
+                    .add_column(Column::builder("key", DataType::Int32).set_not_null().set_immutable())
+                    .set_primary_key(vec!["key"])
+                    .build()
+                    .is_err());
+    }
+
+    #[test]
+    fn test_block_size() {
+        let column = Column::builder("val", DataType::Int32).set_block_size(MAX_BLOCK_SIZE);
+        column.check().unwrap();
+        assert_eq!(Some(MAX_BLOCK_SIZE), Column::from_pb(column.to_pb(false)).unwrap().block_size());
+
+        assert!(Column::builder("val", DataType::Int32).set_block_size(MAX_BLOCK_SIZE + 1).check().is_err());
+        assert!(SchemaBuilder::new()
+                    .add_column(Column::builder("key", DataType::Int32).set_not_null())
+                    .add_column(Column::builder("val", DataType::Int32).set_block_size(u32::max_value()))
+                    .set_primary_key(vec!["key"])
+                    .build()
+                    .is_err());
+
+        let mut pb = Column::builder("val", DataType::Int32).to_pb(false);
+        pb.set_cfile_block_size(-1);
+        assert!(Column::from_pb(pb).is_err());
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let result = SchemaBuilder::new()
+            .add_column(Column::builder("a", DataType::Int32).set_not_null())
+            .add_column(Column::builder("b", DataType::Float).set_not_null())
+            .add_column(Column::builder("c", DataType::String))
+            .add_column(Column::builder("c", DataType::String))
+            .add_column(Column::builder("d", DataType::Bool))
+            .set_primary_key(vec!["a", "b", "c", "d", "e", "a"])
+            .build();
+
+        assert_eq!(Err(Error::InvalidSchema(vec![
+                       SchemaError::DuplicateColumn("c".to_owned()),
+                       SchemaError::UnsupportedPrimaryKeyType("b".to_owned(), DataType::Float),
+                       SchemaError::NullablePrimaryKeyColumn("c".to_owned()),
+                       SchemaError::NullablePrimaryKeyColumn("d".to_owned()),
+                       SchemaError::UnsupportedPrimaryKeyType("d".to_owned(), DataType::Bool),
+                       SchemaError::MissingPrimaryKeyColumn("e".to_owned()),
+                       SchemaError::DuplicatePrimaryKeyColumn("a".to_owned()),
+                   ])),
+                   result);
+
+        assert_eq!(Err(Error::InvalidSchema(vec![SchemaError::EmptyPrimaryKey])),
+                   SchemaBuilder::new().add_column(Column::builder("a", DataType::Int32)).build());
+    }
+
+    #[test]
+    fn test_encoding() {
+        Column::builder("a", DataType::Double).set_encoding(EncodingType::BitShuffle).check().unwrap();
+        Column::builder("a", DataType::String).set_encoding(EncodingType::Dictionary).check().unwrap();
+        assert!(Column::builder("a", DataType::Double).set_encoding(EncodingType::Dictionary).check().is_err());
+        assert!(Column::builder("a", DataType::Bool).set_encoding(EncodingType::BitShuffle).check().is_err());
+
+        let result = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("val", DataType::String).set_encoding(EncodingType::BitShuffle))
+            .set_primary_key(vec!["key"])
+            .build();
+        assert_eq!(Err(Error::InvalidSchema(vec![
+                       SchemaError::UnsupportedEncoding("val".to_owned(),
+                                                        DataType::String,
+                                                        EncodingType::BitShuffle),
+                   ])),
+                   result);
+    }
+
+    #[test]
+    fn test_project() {
+        let schema = all_types_schema();
+
+        let projection = schema.project(&["key", "i32", "string"]).unwrap();
+        assert_eq!(vec!["key", "i32", "string"],
+                   projection.columns().iter().map(Column::name).collect::<Vec<_>>());
+        assert_eq!(1, projection.num_primary_key_columns());
+        assert_eq!(4 + 4 + 16, projection.row_size());
+        assert_eq!(Some(2), projection.column_index("string"));
+
+        let projection = schema.project(&["i32", "key"]).unwrap();
+        assert_eq!(0, projection.num_primary_key_columns());
+
+        assert!(schema.project::<&str>(&[]).unwrap().columns().is_empty());
+        assert!(schema.project(&["missing"]).is_err());
+        assert!(schema.project(&["i32", "i32"]).is_err());
+    }
+
+    #[test]
+    fn test_range_partition_columns() {
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("val", DataType::String))
+            .add_column(Column::builder("b", DataType::Int32).set_not_null())
+            .add_column(Column::builder("a", DataType::Int32).set_not_null())
+            .set_primary_key(vec!["a", "b"])
+            .set_range_partition_columns(vec!["b"])
+            .build()
+            .unwrap();
+        assert_eq!(&[1], schema.range_partition_columns());
+        assert!(simple_schema().range_partition_columns().is_empty());
+
+        let result = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("val", DataType::String))
+            .set_primary_key(vec!["key"])
+            .set_range_partition_columns(vec!["key", "val", "missing", "key"])
+            .build();
+        assert_eq!(Err(Error::InvalidSchema(vec![
+                       SchemaError::RangePartitionColumnNotInPrimaryKey("val".to_owned()),
+                       SchemaError::MissingRangePartitionColumn("missing".to_owned()),
+                       SchemaError::DuplicateRangePartitionColumn("key".to_owned()),
+                   ])),
+                   result);
+    }
+
+    #[test]
+    fn test_non_unique_primary_key() {
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("host", DataType::String).set_not_null())
+            .add_column(Column::builder("val", DataType::Int32))
+            .set_non_unique_primary_key(vec!["host"])
+            .build()
+            .unwrap();
+        assert_eq!(2, schema.num_primary_key_columns());
+        assert_eq!(Some(1), schema.auto_incrementing_column_index());
+        let column = &schema.columns()[1];
+        assert_eq!(AUTO_INCREMENTING_COLUMN_NAME, column.name());
+        assert_eq!(DataType::Int64, column.data_type());
+        assert!(!column.is_nullable());
+
+        // The auto-incrementing attribute survives a round trip through the server schema.
+        let decoded = Schema::from_pb(schema.as_pb()).unwrap();
+        assert_eq!(schema, decoded);
+        assert_eq!(Some(1), decoded.auto_incrementing_column_index());
+
+        assert_eq!(None, simple_schema().auto_incrementing_column_index());
+
+        assert!(SchemaBuilder::new()
+                    .add_column(Column::builder("key", DataType::Int32).set_not_null())
+                    .add_column(Column::builder(AUTO_INCREMENTING_COLUMN_NAME, DataType::Int64))
+                    .set_non_unique_primary_key(vec!["key"])
+                    .build()
+                    .is_err());
+    }
+
+    #[test]
+    fn test_from_schema() {
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("host", DataType::String).set_not_null())
+            .add_column(Column::builder("time", DataType::Timestamp).set_not_null())
+            .add_column(Column::builder("val", DataType::Int32))
+            .set_primary_key(vec!["host", "time"])
+            .set_range_partition_columns(vec!["time"])
+            .build()
+            .unwrap();
+        assert_eq!(schema, schema.clone().into_builder().build().unwrap());
+
+        let evolved = SchemaBuilder::from_schema(&schema)
+            .rename_column("time", "timestamp")
+            .remove_column("val")
+            .add_column(Column::builder("note", DataType::String))
+            .build()
+            .unwrap();
+        let names = evolved.columns().iter().map(Column::name).collect::<Vec<_>>();
+        assert_eq!(vec!["host", "timestamp", "note"], names);
+        assert_eq!(2, evolved.num_primary_key_columns());
+        assert_eq!(&[1], evolved.range_partition_columns());
+
+        let mut builder = schema.clone().into_builder();
+        builder.column_mut("val").unwrap().set_not_null_by_ref();
+        assert!(!builder.build().unwrap().columns()[2].is_nullable());
+
+        // Removing a primary key column without changing the primary key fails to build.
+        assert!(schema.clone().into_builder().remove_column("host").build().is_err());
+
+        // Non-unique primary keys keep a single auto-incrementing column.
+        let non_unique = SchemaBuilder::new()
+            .add_column(Column::builder("host", DataType::String).set_not_null())
+            .add_column(Column::builder("val", DataType::Int32))
+            .set_non_unique_primary_key(vec!["host"])
+            .build()
+            .unwrap();
+        assert_eq!(non_unique, non_unique.clone().into_builder().build().unwrap());
+    }
 }
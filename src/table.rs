@@ -1,31 +1,55 @@
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::mem;
+use std::net::SocketAddr;
 use std::sync::mpsc::sync_channel;
 use std::time::Instant;
 
 use kudu_pb::master::{
     AlterTableRequestPB,
     AlterTableRequestPB_StepType as StepType,
-    CreateTableRequestPB
+    CreateTableRequestPB,
+    GetTableSchemaResponsePB,
+    MasterFeatures,
 };
 use kudu_pb::common::{
     PartitionSchemaPB_ColumnIdentifierPB as ColumnIdentifierPB,
     PartitionSchemaPB_HashBucketSchemaPB as HashBucketSchemaPB
 };
+use kudu_pb::tserver::ListTabletsRequestPB;
+use protobuf::{CodedInputStream, CodedOutputStream, UnknownFields};
+use protobuf::wire_format::WireType;
 
+use BulkLoader;
+use BulkLoaderConfig;
 use Client;
 use Column;
 use Error;
+use key;
 use meta_cache::{Entry, MetaCache};
 use partition::PartitionSchema;
+use RaftRole;
 use Result;
 use row::OperationEncoder;
 use row::Row;
+use rpc::tablet_server;
+use ScanBuilder;
 use Schema;
+use schema::AUTO_INCREMENTING_COLUMN_NAME;
 use TableId;
 use Tablet;
+use TabletServerError;
 use Writer;
 use WriterConfig;
 
+/// Field numbers of table attributes introduced after the Kudu release which the checked-in
+/// Protobuf sources are generated from. The attributes are carried as unknown fields.
+const NEW_EXTRA_CONFIGS_FIELD_NUMBER: u32 = 6; // AlterTableRequestPB.new_extra_configs
+const NEW_TABLE_OWNER_FIELD_NUMBER: u32 = 7; // AlterTableRequestPB.new_table_owner
+const EXTRA_CONFIGS_FIELD_NUMBER: u32 = 8; // GetTableSchemaResponsePB.extra_configs
+const OWNER_FIELD_NUMBER: u32 = 9; // GetTableSchemaResponsePB.owner
+
 #[derive(Clone)]
 pub struct Table {
     name: String,
@@ -33,29 +57,36 @@ pub struct Table {
     schema: Schema,
     partition_schema: PartitionSchema,
     num_replicas: u32,
+    owner: Option<String>,
+    extra_configs: BTreeMap<String, String>,
     meta_cache: MetaCache,
     client: Client,
 }
 
 impl Table {
 
+    /// Creates a table handle. The number of replicas, owner, and extra configuration properties
+    /// are taken from the master's `GetTableSchema` response.
     #[doc(hidden)]
     pub fn new(name: String,
                id: TableId,
                schema: Schema,
                partition_schema: PartitionSchema,
-               num_replicas: u32,
+               resp: &GetTableSchemaResponsePB,
                meta_cache: MetaCache,
-               client: Client) -> Table {
-        Table {
+               client: Client) -> Result<Table> {
+        let (owner, extra_configs) = try!(Table::attributes_from_pb(resp));
+        Ok(Table {
             name: name,
             id: id,
             schema: schema,
             partition_schema: partition_schema,
-            num_replicas: num_replicas,
+            num_replicas: resp.get_num_replicas() as u32,
+            owner: owner,
+            extra_configs: extra_configs,
             meta_cache: meta_cache,
             client: client,
-        }
+        })
     }
 
     pub fn name(&self) -> &str {
@@ -78,10 +109,151 @@ impl Table {
         self.num_replicas
     }
 
+    /// Returns the owner of the table, or `None` if the table has no owner or the master predates
+    /// table ownership.
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_ref().map(|owner| &owner[..])
+    }
+
+    /// Returns the table's extra configuration properties, such as
+    /// `kudu.table.history_max_age_sec`, as of when the table was opened.
+    pub fn extra_configs(&self) -> &BTreeMap<String, String> {
+        &self.extra_configs
+    }
+
+    /// Decodes the owner and extra configuration properties of a table from the master's
+    /// `GetTableSchema` response.
+    fn attributes_from_pb(resp: &GetTableSchemaResponsePB)
+                              -> Result<(Option<String>, BTreeMap<String, String>)> {
+        let fields = resp.get_unknown_fields();
+        let owner = fields.get(OWNER_FIELD_NUMBER).and_then(|values| values.length_delimited.last());
+        let owner = match owner {
+            Some(owner) => Some(try!(String::from_utf8(owner.clone()).map_err(|error| {
+                Error::Serialization(format!("invalid table owner: {}", error))
+            }))),
+            None => None,
+        };
+        Ok((owner, try!(decode_string_map(fields, EXTRA_CONFIGS_FIELD_NUMBER))))
+    }
+
     pub fn new_writer(&self, config: WriterConfig) -> Writer {
         Writer::new(self.clone(), config)
     }
 
+    /// Returns a loader for writing a high volume of rows to the table.
+    pub fn new_bulk_loader(&self, config: BulkLoaderConfig) -> BulkLoader {
+        BulkLoader::new(self.clone(), config)
+    }
+
+    /// Returns a builder for a scan over the table.
+    pub fn scan_builder(&self) -> ScanBuilder {
+        ScanBuilder::new(self.clone())
+    }
+
+    /// Returns the tablets of the table along with the current placement and role of their
+    /// replicas, retrieved directly from the master. Unlike `list_tablets`, the locations are not
+    /// served from the client's cache, so they reflect recent leader changes and re-replication.
+    pub fn tablets(&self, deadline: Instant) -> Result<Vec<Tablet>> {
+        self.meta_cache.fetch_tablets(deadline)
+    }
+
+    /// Returns the tablets of the table which have fewer voting replicas than the table's
+    /// replication factor.
+    pub fn under_replicated_tablets(&self, deadline: Instant) -> Result<Vec<Tablet>> {
+        let tablets = try!(self.tablets(deadline));
+        Ok(tablets.into_iter().filter(|tablet| tablet.num_voters() < self.num_replicas as usize).collect())
+    }
+
+    /// Returns an estimate of the size of the table's data on disk, in bytes.
+    ///
+    /// The estimate is the sum of the on-disk size of one replica of each tablet, as reported by
+    /// the tablet servers. The leader replica is preferred. Each tablet server hosting a replica is
+    /// contacted once, so this is much cheaper than scanning the table.
+    pub fn estimate_size_bytes(&self, deadline: Instant) -> Result<u64> {
+        let tablets = try!(self.tablets(deadline));
+        let sizes = try!(self.tablet_sizes(&tablets, deadline));
+        Ok(sizes.values().sum())
+    }
+
+    /// Returns the on-disk size of one replica of each tablet, by tablet ID. See
+    /// `Table::estimate_size_bytes`.
+    fn tablet_sizes(&self, tablets: &[Tablet], deadline: Instant) -> Result<HashMap<String, u64>> {
+        let mut tablets_by_server: HashMap<SocketAddr, HashSet<String>> = HashMap::new();
+        for tablet in tablets {
+            let replicas = tablet.replicas();
+            let addr = replicas.iter()
+                               .filter(|replica| replica.role() == RaftRole::Leader)
+                               .chain(replicas.iter())
+                               .filter_map(|replica| replica.resolved_rpc_addrs().first())
+                               .next();
+            match addr {
+                Some(&addr) => {
+                    tablets_by_server.entry(addr)
+                                     .or_insert_with(HashSet::new)
+                                     .insert(tablet.id().to_string());
+                },
+                None => return Err(Error::InvalidArgument(
+                        format!("no replica of tablet {} has a resolvable address", tablet.id()))),
+            }
+        }
+
+        let mut sizes = HashMap::with_capacity(tablets.len());
+        for (addr, tablet_ids) in tablets_by_server {
+            let rpc = tablet_server::list_tablets(addr, deadline, ListTabletsRequestPB::new());
+            let (result, rpc) = rpc.send_sync(self.client.messenger());
+            try!(result);
+            let mut response = rpc.take_response();
+            if response.has_error() {
+                return Err(Error::TabletServer(TabletServerError::from(response.take_error())));
+            }
+            for status_and_schema in response.get_status_and_schema() {
+                let status = status_and_schema.get_tablet_status();
+                if tablet_ids.contains(status.get_tablet_id()) {
+                    sizes.insert(status.get_tablet_id().to_owned(),
+                                 cmp::max(0, status.get_estimated_on_disk_size()) as u64);
+                }
+            }
+        }
+        Ok(sizes)
+    }
+
+    /// Returns an estimate of the number of rows in the table, suitable for query planning.
+    ///
+    /// The rows of a single sample tablet are counted by scanning its leading primary key column,
+    /// and the count is scaled by the on-disk size of the table relative to the sample tablet, as
+    /// reported by `Table::estimate_size_bytes`. The smallest tablet with data on disk is sampled,
+    /// to keep the scan cheap; if no tablet has data on disk, the count is scaled by the number of
+    /// tablets instead. The estimate is accurate when rows are evenly sized across tablets, as with
+    /// hash partitioning.
+    pub fn estimate_row_count(&self, deadline: Instant) -> Result<u64> {
+        let tablets = try!(self.tablets(deadline));
+        if tablets.is_empty() {
+            return Ok(0);
+        }
+        let sizes = try!(self.tablet_sizes(&tablets, deadline));
+        let size_of = |tablet: &Tablet| sizes.get(&tablet.id().to_string()).cloned().unwrap_or(0);
+        let sample = tablets.iter()
+                            .filter(|tablet| size_of(tablet) > 0)
+                            .min_by_key(|tablet| size_of(tablet))
+                            .unwrap_or(&tablets[0]);
+
+        let mut scan = self.scan_builder();
+        scan.set_projection(vec![self.schema.columns()[0].name()]);
+        scan.restrict_to_partition(sample.partition());
+        let mut scanner = try!(scan.build());
+        let mut sample_rows = 0;
+        while let Some(batch) = try!(scanner.next_batch(deadline)) {
+            sample_rows += batch.len() as u64;
+        }
+
+        let sample_size = size_of(sample);
+        if sample_size == 0 {
+            return Ok(sample_rows * tablets.len() as u64);
+        }
+        let total_size = sizes.values().sum::<u64>();
+        Ok((sample_rows as f64 * total_size as f64 / sample_size as f64).round() as u64)
+    }
+
     pub fn list_tablets(&self, deadline: Instant) -> Result<Vec<Tablet>> {
         let mut tablets = Vec::new();
         let (send, recv) = sync_channel(1);
@@ -119,6 +291,15 @@ impl Table {
     }
 }
 
+/// Displays the table's name, columns, primary key and partitioning, for instance
+/// `metrics (id Int32 NOT NULL, ts Timestamp NOT NULL) PRIMARY KEY (id, ts) PARTITION BY HASH (id) PARTITIONS 16, RANGE (ts)`.
+/// The range partitions of the table are displayed by the partitions of its tablets.
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:?} PARTITION BY {}", self.name, self.schema, self.partition_schema.display(&self.schema))
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum RangePartitionBound {
     Inclusive(Row),
@@ -159,6 +340,10 @@ impl TableBuilder {
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn schema(&self) -> &Schema {
         &self.schema
     }
@@ -185,7 +370,8 @@ impl TableBuilder {
     /// Range partitions the table by the specified columns.
     ///
     /// Range partitioned tables must have at least one partition added with
-    /// `TableBuilder::add_range_partition`.
+    /// `TableBuilder::add_range_partition`. Overrides the range partition columns of the schema,
+    /// if any were set with `SchemaBuilder::set_range_partition_columns`.
     pub fn set_range_partition_columns<S>(&mut self, columns: Vec<S>) -> &mut TableBuilder
     where S: Into<String> {
         self.range_partition_columns = columns.into_iter().map(Into::into).collect();
@@ -210,11 +396,21 @@ impl TableBuilder {
         self.num_replicas = Some(num_replicas);
     }
 
+    /// Returns the `CreateTable` request, and the master feature flags which it requires.
     #[doc(hidden)]
-    pub fn into_pb(self) -> Result<CreateTableRequestPB> {
+    pub fn into_pb(self) -> Result<(CreateTableRequestPB, Vec<u32>)> {
         let TableBuilder { name, schema, range_partition_columns, range_partitions,
                            range_partition_splits, hash_partitions, num_replicas } = self;
 
+        let range_partition_columns = if range_partition_columns.is_empty() {
+            schema.range_partition_columns()
+                  .iter()
+                  .map(|&idx| schema.columns()[idx].name().to_owned())
+                  .collect()
+        } else {
+            range_partition_columns
+        };
+
         let mut range_encoder = OperationEncoder::new();
 
         if range_partition_columns.is_empty() && !range_partitions.is_empty() {
@@ -225,6 +421,25 @@ impl TableBuilder {
                     "range partition splits specified without range partitioning columns".to_string()));
         }
 
+        if schema.auto_incrementing_column_index().is_some() {
+            let partitions_by_auto_incrementing_column =
+                range_partition_columns.iter()
+                                       .chain(hash_partitions.iter().flat_map(|&(ref columns, _, _)| columns))
+                                       .any(|column| column == AUTO_INCREMENTING_COLUMN_NAME);
+            if partitions_by_auto_incrementing_column {
+                return Err(Error::InvalidArgument(
+                        "tables can not be partitioned by the auto-incrementing column".to_string()));
+            }
+        }
+
+        // Range partitions with explicit bounds require `RANGE_PARTITION_BOUNDS`, otherwise masters
+        // which predate them would create the table with only the split rows.
+        let required_feature_flags = if range_partitions.is_empty() {
+            Vec::new()
+        } else {
+            vec![MasterFeatures::RANGE_PARTITION_BOUNDS as u32]
+        };
+
         for (lower, upper) in range_partitions {
             if &schema != lower.row().schema() || &schema != upper.row().schema() {
                 return Err(Error::InvalidArgument(
@@ -233,11 +448,25 @@ impl TableBuilder {
             range_encoder.encode_range_partition(&lower, &upper);
         }
 
+        // Duplicate splits are rejected here, since the master's error does not name the split.
+        // Splits which do not set every range partitioning column are left for the master.
+        let range_column_idxs = range_partition_columns.iter()
+                                                       .map(|column| schema.column_index(column))
+                                                       .collect::<Option<Vec<_>>>();
+        let mut split_keys = BTreeSet::new();
         for split in range_partition_splits {
             if &schema != split.schema() {
                 return Err(Error::InvalidArgument(
                         "range partition split schema does not match the table schema".to_string()));
             }
+            if let Some(ref idxs) = range_column_idxs {
+                if let Ok(split_key) = key::encode_key_columns(&split, idxs) {
+                    if !split_keys.insert(split_key) {
+                        return Err(Error::InvalidArgument(
+                                format!("duplicate range partition split: {:?}", split)));
+                    }
+                }
+            }
             range_encoder.encode_range_partition_split(&split);
         }
 
@@ -270,7 +499,7 @@ impl TableBuilder {
         }
 
         if let Some(num_replicas) = num_replicas { pb.set_num_replicas(num_replicas as i32); }
-        Ok(pb)
+        Ok((pb, required_feature_flags))
     }
 }
 
@@ -309,6 +538,10 @@ impl AlterTableBuilder {
     }
 
     pub fn add_column_by_ref(&mut self, column: Column) -> &mut AlterTableBuilder {
+        if let Err(error) = column.check() {
+            if self.error.is_ok() { self.error = Err(error); }
+            return self;
+        }
         {
             let mut step = self.pb.mut_alter_schema_steps().push_default();
             step.set_field_type(StepType::ADD_COLUMN);
@@ -331,6 +564,36 @@ impl AlterTableBuilder {
         self
     }
 
+    /// Sets the owner of the table. Masters older than Kudu 1.13 ignore the new owner.
+    pub fn set_owner<S>(mut self, owner: S) -> AlterTableBuilder where S: Into<String> {
+        self.set_owner_by_ref(owner.into());
+        self
+    }
+
+    pub fn set_owner_by_ref<S>(&mut self, owner: S) -> &mut AlterTableBuilder where S: Into<String> {
+        self.pb.mut_unknown_fields().add_length_delimited(NEW_TABLE_OWNER_FIELD_NUMBER,
+                                                          owner.into().into_bytes());
+        self
+    }
+
+    /// Sets an extra configuration property of the table, for example
+    /// `kudu.table.history_max_age_sec`. Setting a property to the empty string resets it to its
+    /// default. Masters older than Kudu 1.12 ignore extra configuration properties.
+    pub fn set_extra_config<S1, S2>(mut self, key: S1, value: S2) -> AlterTableBuilder
+    where S1: Into<String>,
+          S2: Into<String> {
+        self.set_extra_config_by_ref(key.into(), value.into());
+        self
+    }
+
+    pub fn set_extra_config_by_ref<S1, S2>(&mut self, key: S1, value: S2) -> &mut AlterTableBuilder
+    where S1: Into<String>,
+          S2: Into<String> {
+        let entry = encode_string_map_entry(&key.into(), &value.into());
+        self.pb.mut_unknown_fields().add_length_delimited(NEW_EXTRA_CONFIGS_FIELD_NUMBER, entry);
+        self
+    }
+
     pub fn rename_column<S1, S2>(mut self, old_name: S1, new_name: S2) -> AlterTableBuilder
     where S1: Into<String>,
           S2: Into<String> {
@@ -417,6 +680,41 @@ impl AlterTableBuilder {
     }
 }
 
+/// Encodes an entry of a Protobuf `map<string, string>` field.
+fn encode_string_map_entry(key: &str, value: &str) -> Vec<u8> {
+    let mut entry = Vec::new();
+    {
+        let mut output = CodedOutputStream::new(&mut entry);
+        output.write_string(1, key).unwrap();
+        output.write_string(2, value).unwrap();
+        output.flush().unwrap();
+    }
+    entry
+}
+
+/// Decodes a Protobuf `map<string, string>` field which is not known to the generated message type.
+fn decode_string_map(fields: &UnknownFields, field_number: u32) -> Result<BTreeMap<String, String>> {
+    let mut map = BTreeMap::new();
+    let entries = match fields.get(field_number) {
+        Some(values) => &values.length_delimited[..],
+        None => return Ok(map),
+    };
+    for entry in entries {
+        let mut input = CodedInputStream::from_bytes(entry);
+        let mut key = String::new();
+        let mut value = String::new();
+        while !try!(input.eof()) {
+            match try!(input.read_tag_unpack()) {
+                (1, WireType::WireTypeLengthDelimited) => key = try!(input.read_string()),
+                (2, WireType::WireTypeLengthDelimited) => value = try!(input.read_string()),
+                (_, wire_type) => try!(input.skip_field(wire_type)),
+            }
+        }
+        map.insert(key, value);
+    }
+    Ok(map)
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -430,6 +728,7 @@ mod tests {
     use DataType;
     use SchemaBuilder;
     use mini_cluster::{MiniCluster, MiniClusterConfig};
+    use rpc::master;
     use schema::tests::simple_schema;
     use super::*;
 
@@ -449,6 +748,121 @@ mod tests {
         table_builder.add_range_partition_split(split_row);
     }
 
+    #[test]
+    fn duplicate_range_partition_splits() {
+        let mut table_builder = TableBuilder::new("t", simple_schema());
+        table_builder.set_range_partition_columns(vec!["key"]);
+        for &key in &["foo", "bar", "foo"] {
+            let mut split_row = table_builder.schema().new_row();
+            split_row.set_by_name("key", key).unwrap();
+            table_builder.add_range_partition_split(split_row);
+        }
+        match table_builder.into_pb() {
+            Err(Error::InvalidArgument(ref message)) => assert!(message.contains("duplicate")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn table_attributes() {
+        let alter = AlterTableBuilder::new()
+            .set_owner("alice")
+            .set_extra_config("kudu.table.history_max_age_sec", "3600")
+            .set_extra_config("kudu.table.maintenance_priority", "2");
+
+        // Masters echo the altered attributes in `GetTableSchema` responses at different field
+        // numbers, so move the fields over to decode them.
+        let mut resp = GetTableSchemaResponsePB::new();
+        let fields = alter.pb.get_unknown_fields();
+        for owner in &fields.get(NEW_TABLE_OWNER_FIELD_NUMBER).unwrap().length_delimited {
+            resp.mut_unknown_fields().add_length_delimited(OWNER_FIELD_NUMBER, owner.clone());
+        }
+        for entry in &fields.get(NEW_EXTRA_CONFIGS_FIELD_NUMBER).unwrap().length_delimited {
+            resp.mut_unknown_fields().add_length_delimited(EXTRA_CONFIGS_FIELD_NUMBER, entry.clone());
+        }
+
+        let (owner, extra_configs) = Table::attributes_from_pb(&resp).unwrap();
+        assert_eq!(Some("alice".to_owned()), owner);
+        assert_eq!(2, extra_configs.len());
+        assert_eq!("3600", extra_configs["kudu.table.history_max_age_sec"]);
+        assert_eq!("2", extra_configs["kudu.table.maintenance_priority"]);
+
+        let (owner, extra_configs) = Table::attributes_from_pb(&GetTableSchemaResponsePB::new()).unwrap();
+        assert_eq!(None, owner);
+        assert!(extra_configs.is_empty());
+    }
+
+    #[test]
+    fn required_feature_flags() {
+        let addr = "127.0.0.1:7051".parse().unwrap();
+        let schema = simple_schema();
+
+        let mut table_builder = TableBuilder::new("t", schema.clone());
+        let mut split_row = schema.new_row();
+        split_row.set_by_name("key", "foo").unwrap();
+        table_builder.add_range_partition_split(split_row);
+        let (_, required_feature_flags) = table_builder.into_pb().unwrap();
+        assert!(required_feature_flags.is_empty());
+
+        let mut table_builder = TableBuilder::new("t", schema.clone());
+        let mut lower_bound = schema.new_row();
+        let mut upper_bound = schema.new_row();
+        lower_bound.set_by_name("key", "a").unwrap();
+        upper_bound.set_by_name("key", "b").unwrap();
+        table_builder.add_range_partition(RangePartitionBound::Inclusive(lower_bound.clone()),
+                                          RangePartitionBound::Exclusive(upper_bound.clone()));
+        let (_, required_feature_flags) = table_builder.into_pb().unwrap();
+        assert_eq!(vec![MasterFeatures::RANGE_PARTITION_BOUNDS as u32], required_feature_flags);
+
+        let mut alter = AlterTableBuilder::new();
+        alter.add_column_by_ref(Column::builder("c", DataType::Int32));
+        let rpc = master::alter_table(addr, deadline(), alter.pb.clone());
+        assert!(rpc.required_feature_flags.is_empty());
+
+        alter.drop_range_partition_by_ref(&RangePartitionBound::Inclusive(lower_bound),
+                                          &RangePartitionBound::Exclusive(upper_bound));
+        let rpc = master::alter_table(addr, deadline(), alter.pb.clone());
+        assert_eq!(vec![MasterFeatures::ADD_DROP_RANGE_PARTITIONS as u32], rpc.required_feature_flags);
+    }
+
+    #[test]
+    fn non_unique_primary_key_partitioning() {
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .set_non_unique_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut table_builder = TableBuilder::new("t", schema.clone());
+        table_builder.add_hash_partitions(vec!["key"], 4);
+        assert!(table_builder.into_pb().is_ok());
+
+        let mut table_builder = TableBuilder::new("t", schema.clone());
+        table_builder.add_hash_partitions(vec![AUTO_INCREMENTING_COLUMN_NAME], 4);
+        assert!(table_builder.into_pb().is_err());
+    }
+
+    #[test]
+    fn schema_range_partition_columns() {
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("a", DataType::Int32).set_not_null())
+            .add_column(Column::builder("b", DataType::Int32).set_not_null())
+            .set_primary_key(vec!["a", "b"])
+            .set_range_partition_columns(vec!["b"])
+            .build()
+            .unwrap();
+
+        let (pb, _) = TableBuilder::new("t", schema.clone()).into_pb().unwrap();
+        let columns = pb.get_partition_schema().get_range_schema().get_columns();
+        assert_eq!(vec!["b"], columns.iter().map(|column| column.get_name()).collect::<Vec<_>>());
+
+        let mut table_builder = TableBuilder::new("t", schema);
+        table_builder.set_range_partition_columns(vec!["a"]);
+        let (pb, _) = table_builder.into_pb().unwrap();
+        let columns = pb.get_partition_schema().get_range_schema().get_columns();
+        assert_eq!(vec!["a"], columns.iter().map(|column| column.get_name()).collect::<Vec<_>>());
+    }
+
     #[test]
     fn list_tablets() {
         let _ = env_logger::init();
@@ -490,5 +904,52 @@ mod tests {
         let tablets = table.list_tablets(deadline()).unwrap();
 
         assert_eq!(8, tablets.len());
+
+        let tablets = table.tablets(deadline()).unwrap();
+        assert_eq!(8, tablets.len());
+        for tablet in &tablets {
+            assert_eq!(3, tablet.replicas().len());
+            assert!(tablet.num_voters() <= 3);
+        }
+        // The replicas of a newly created table may not have been elected yet, but all replicas
+        // are placed, so no tablets should have fewer than three voters.
+        assert!(table.under_replicated_tablets(deadline()).unwrap().is_empty());
+
+        assert_eq!(0, table.estimate_row_count(deadline()).unwrap());
+    }
+
+    #[test]
+    fn estimate_row_count() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(MiniClusterConfig::default()
+                                                         .num_masters(1)
+                                                         .num_tservers(1));
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("val", DataType::String))
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+        let mut table_builder = TableBuilder::new("estimate_row_count", schema);
+        table_builder.set_num_replicas(1);
+        table_builder.add_hash_partitions(vec!["key"], 4);
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+        assert_eq!(0, table.estimate_row_count(deadline()).unwrap());
+
+        let mut loader = table.new_bulk_loader(BulkLoaderConfig::default());
+        for i in 0..10000 {
+            let mut row = table.schema().new_row();
+            row.set(0, i).unwrap();
+            row.set(1, format!("value-{}", i)).unwrap();
+            loader.insert(row).unwrap();
+        }
+        assert_eq!(10000, loader.finish().unwrap().successful_operations());
+
+        // Hash partitioning spreads the rows evenly, so the sampled tablet is representative.
+        let row_count = table.estimate_row_count(deadline()).unwrap();
+        assert!(row_count >= 7500 && row_count <= 12500, "estimated row count: {}", row_count);
     }
 }
@@ -2,6 +2,7 @@ use std::net::SocketAddr;
 
 use kudu_pb::master::{TabletLocationsPB, TabletLocationsPB_ReplicaPB as ReplicaPB};
 
+use HostPort;
 use Partition;
 use PartitionSchema;
 use RaftRole;
@@ -9,7 +10,7 @@ use Result;
 use Schema;
 use TabletId;
 use TabletServerId;
-use dns;
+use dns::{self, Resolver};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Tablet {
@@ -32,11 +33,36 @@ impl Tablet {
         &self.replicas
     }
 
+    /// Returns the leader replica, if known.
+    pub fn leader(&self) -> Option<&Replica> {
+        self.replicas.iter().find(|replica| replica.role == RaftRole::Leader)
+    }
+
+    /// Returns the number of replicas which participate in consensus votes, that is the leader and
+    /// followers. Learners and non-participants are excluded.
+    pub fn num_voters(&self) -> usize {
+        self.replicas.iter().filter(|replica| replica.is_voter()).count()
+    }
+
+    /// Makes the replica belonging to the tablet server the leader, demoting the current leader to
+    /// a follower. If `leader` is `None` the tablet is left without a known leader.
+    #[cfg(test)]
+    pub fn set_leader(&mut self, leader: Option<&TabletServerId>) {
+        for replica in &mut self.replicas {
+            if Some(&replica.id) == leader {
+                replica.role = RaftRole::Leader;
+            } else if replica.role == RaftRole::Leader {
+                replica.role = RaftRole::Follower;
+            }
+        }
+    }
+
     /// Creates a new `Tablet` from a tablet locations protobuf message.
     #[doc(hidden)]
     pub fn from_pb(primary_key_schema: &Schema,
                    partition_schema: PartitionSchema,
-                   mut pb: TabletLocationsPB)
+                   mut pb: TabletLocationsPB,
+                   resolver: &Resolver)
                    -> Result<Tablet> {
         let id = try!(TabletId::parse_bytes(pb.get_tablet_id()));
         let partition = try!(Partition::from_pb(primary_key_schema,
@@ -44,7 +70,7 @@ impl Tablet {
                                                 pb.take_partition()));
         let mut replicas = Vec::with_capacity(pb.get_replicas().len());
         for replica in pb.take_replicas().into_iter() {
-            replicas.push(try!(Replica::from_pb(replica)));
+            replicas.push(try!(Replica::from_pb(replica, resolver)));
         }
         Ok(Tablet {
             id: id,
@@ -58,7 +84,7 @@ impl Tablet {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Replica {
     id: TabletServerId,
-    rpc_addrs: Vec<(String, u16)>,
+    rpc_addrs: Vec<HostPort>,
     resolved_rpc_addrs: Vec<SocketAddr>,
     role: RaftRole,
     is_local: bool,
@@ -72,7 +98,7 @@ impl Replica {
     }
 
     /// Returns the RPC addresses of the tablet server which owns this replica.
-    pub fn rpc_addrs(&self) -> &[(String, u16)] {
+    pub fn rpc_addrs(&self) -> &[HostPort] {
         &self.rpc_addrs
     }
 
@@ -81,21 +107,30 @@ impl Replica {
         self.role
     }
 
+    /// Returns `true` if the replica is the leader or a follower.
+    pub fn is_voter(&self) -> bool {
+        self.role == RaftRole::Leader || self.role == RaftRole::Follower
+    }
+
     /// The resolved RPC addresses.
     pub fn resolved_rpc_addrs(&self) -> &[SocketAddr] {
         &self.resolved_rpc_addrs
     }
 
+    /// Returns `true` if the tablet server which owns this replica is on the local host.
+    pub fn is_local(&self) -> bool {
+        self.is_local
+    }
+
     /// Creates a new `Replica` from a replica protobuf message.
     #[doc(hidden)]
-    pub fn from_pb(mut pb: ReplicaPB) -> Result<Replica> {
+    pub fn from_pb(pb: ReplicaPB, resolver: &Resolver) -> Result<Replica> {
         let id = try!(TabletServerId::parse_bytes(pb.get_ts_info().get_permanent_uuid()));
         let mut rpc_addrs = Vec::with_capacity(pb.get_ts_info().get_rpc_addresses().len());
-        for mut host_port in pb.mut_ts_info().take_rpc_addresses().into_iter() {
-            let port = host_port.get_port() as u16;
-            rpc_addrs.push((host_port.take_host(), port));
+        for host_port in pb.get_ts_info().get_rpc_addresses() {
+            rpc_addrs.push(try!(HostPort::from_pb(host_port)));
         }
-        let resolved_rpc_addrs = dns::resolve_hostports(&rpc_addrs);
+        let resolved_rpc_addrs = dns::resolve_hostports(resolver, &rpc_addrs);
         let role = RaftRole::from_pb(pb.get_role());
         let is_local = resolved_rpc_addrs.iter().any(|addr| dns::is_local_addr(&addr.ip()));
         Ok(Replica {
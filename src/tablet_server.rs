@@ -2,6 +2,7 @@ use std::time::Duration;
 
 use kudu_pb::master::{ListTabletServersResponsePB_Entry as TabletServerEntry};
 
+use HostPort;
 use Result;
 use TabletServerId;
 
@@ -12,8 +13,8 @@ use TabletServerId;
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TabletServer {
     id: TabletServerId,
-    rpc_addrs: Vec<(String, u16)>,
-    http_addrs: Vec<(String, u16)>,
+    rpc_addrs: Vec<HostPort>,
+    http_addrs: Vec<HostPort>,
     software_version: String,
     seqno: i64,
     duration_since_heartbeat: Duration,
@@ -24,11 +25,11 @@ impl TabletServer {
         &self.id
     }
 
-    pub fn rpc_addrs(&self) -> &[(String, u16)] {
+    pub fn rpc_addrs(&self) -> &[HostPort] {
         &self.rpc_addrs
     }
 
-    pub fn http_addrs(&self) -> &[(String, u16)] {
+    pub fn http_addrs(&self) -> &[HostPort] {
         &self.http_addrs
     }
 
@@ -49,19 +50,16 @@ impl TabletServer {
         let id = try!(TabletServerId::parse_bytes(tablet_server.get_instance_id().get_permanent_uuid()));
         let seqno = tablet_server.get_instance_id().get_instance_seqno();
 
-        // TODO: check bounds on port casts.
-        let rpc_addrs = tablet_server.mut_registration()
-                                     .take_rpc_addresses()
-                                     .into_iter()
-                                     .map(|mut host_port| (host_port.take_host(),
-                                                           host_port.get_port() as u16))
-                                     .collect::<Vec<_>>();
-        let http_addrs = tablet_server.mut_registration()
-                                      .take_http_addresses()
-                                      .into_iter()
-                                      .map(|mut host_port| (host_port.take_host(),
-                                                            host_port.get_port() as u16))
-                                      .collect::<Vec<_>>();
+        let rpc_addrs = try!(tablet_server.get_registration()
+                                          .get_rpc_addresses()
+                                          .iter()
+                                          .map(HostPort::from_pb)
+                                          .collect::<Result<Vec<_>>>());
+        let http_addrs = try!(tablet_server.get_registration()
+                                           .get_http_addresses()
+                                           .iter()
+                                           .map(HostPort::from_pb)
+                                           .collect::<Result<Vec<_>>>());
 
         let software_version = tablet_server.mut_registration().take_software_version();
         let duration_since_heartbeat = Duration::from_millis(tablet_server.get_millis_since_heartbeat() as u64);
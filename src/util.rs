@@ -12,6 +12,10 @@ pub fn duration_to_ms(duration: &Duration) -> u64 {
     duration.as_secs() * 1000 + duration.subsec_nanos() as u64 / 1000_000
 }
 
+pub fn duration_to_us(duration: &Duration) -> u64 {
+    duration.as_secs() * 1000_000 + duration.subsec_nanos() as u64 / 1000
+}
+
 pub fn fmt_hex<T>(f: &mut fmt::Formatter, bytes: &[T]) -> fmt::Result where T: fmt::LowerHex {
     if bytes.is_empty() {
         return write!(f, "0x")
@@ -23,6 +27,87 @@ pub fn fmt_hex<T>(f: &mut fmt::Formatter, bytes: &[T]) -> fmt::Result where T: f
     Ok(())
 }
 
+const BASE64_CHARS: &'static [u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as padded, standard alphabet base64.
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes padded, standard alphabet base64. Returns `None` if the input is not valid base64.
+pub fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.as_bytes();
+    if encoded.len() % 4 != 0 {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(encoded.len() / 4 * 3);
+    for (idx, chunk) in encoded.chunks(4).enumerate() {
+        let last = idx == encoded.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let sextet = match BASE64_CHARS.iter().position(|&b| b == c) {
+                Some(sextet) => sextet as u32,
+                None => return None,
+            };
+            n = n << 6 | sextet;
+        }
+        n <<= 6 * padding as u32;
+        for i in 0..3 - padding {
+            bytes.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// Encodes bytes as lowercase hexadecimal, without a prefix.
+pub fn hex_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        encoded.push_str(&format!("{:02x}", b));
+    }
+    encoded
+}
+
+/// Decodes hexadecimal without a prefix. Returns `None` if the input is not valid hexadecimal.
+pub fn hex_decode(encoded: &str) -> Option<Vec<u8>> {
+    fn nibble(c: u8) -> Option<u8> {
+        match c {
+            b'0'...b'9' => Some(c - b'0'),
+            b'a'...b'f' => Some(c - b'a' + 10),
+            b'A'...b'F' => Some(c - b'A' + 10),
+            _ => None,
+        }
+    }
+    if encoded.len() % 2 != 0 {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(encoded.len() / 2);
+    for pair in encoded.as_bytes().chunks(2) {
+        match (nibble(pair[0]), nibble(pair[1])) {
+            (Some(high), Some(low)) => bytes.push(high << 4 | low),
+            _ => return None,
+        }
+    }
+    Some(bytes)
+}
+
 pub fn time_to_us(time: &SystemTime) -> i64 {
     // TODO: do overflow checking
     match time.duration_since(UNIX_EPOCH) {
@@ -101,6 +186,33 @@ mod tests {
         quickcheck(roundtrip as fn(i64) -> TestResult);
     }
 
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!("", base64_encode(b""));
+        assert_eq!("Zg==", base64_encode(b"f"));
+        assert_eq!("Zm8=", base64_encode(b"fo"));
+        assert_eq!("Zm9v", base64_encode(b"foo"));
+        assert_eq!("Zm9vYmFy", base64_encode(b"foobar"));
+    }
+
+    #[test]
+    fn test_base64_decode() {
+        for bytes in &[&b""[..], b"f", b"fo", b"foo", b"foobar", b"\x00\xff\x10"] {
+            assert_eq!(Some(bytes.to_vec()), base64_decode(&base64_encode(bytes)));
+        }
+        assert_eq!(None, base64_decode("Zg="));
+        assert_eq!(None, base64_decode("Zg==Zg=="));
+        assert_eq!(None, base64_decode("Z!=="));
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!("00ff10", hex_encode(b"\x00\xff\x10"));
+        assert_eq!(Some(b"\x00\xff\x10".to_vec()), hex_decode("00FF10"));
+        assert_eq!(None, hex_decode("0"));
+        assert_eq!(None, hex_decode("0g"));
+    }
+
     #[test]
     fn test_format_timestamp() {
         let schema = schema::tests::all_types_schema();
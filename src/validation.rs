@@ -0,0 +1,175 @@
+//! Client-side checks of table and column names, so that DDL with an invalid name fails without a
+//! round trip to the master.
+
+use kudu_pb::master::{
+    AlterTableRequestPB,
+    AlterTableRequestPB_StepType as StepType,
+    CreateTableRequestPB,
+};
+
+use Error;
+use Result;
+
+/// The maximum length in characters of a Hive Metastore database or table name.
+pub const MAX_HMS_IDENTIFIER_LENGTH: usize = 128;
+
+/// Checks that a table name is accepted by the master: it must be non-empty, and must not contain
+/// NUL characters.
+///
+/// The maximum name length is configured on the master, so it is left to the master to check.
+pub fn validate_table_name(name: &str) -> Result<()> {
+    validate_identifier("table", name)
+}
+
+/// Checks that a column name is accepted by the master. Column names follow the same rules as
+/// table names.
+pub fn validate_column_name(name: &str) -> Result<()> {
+    validate_identifier("column", name)
+}
+
+/// Checks that a table name is accepted by a master which is integrated with the Hive Metastore.
+///
+/// Such table names have the form `<database>.<table>`, and the database and table may only
+/// contain ASCII letters, digits, underscores, and slashes, up to `MAX_HMS_IDENTIFIER_LENGTH`
+/// characters each.
+pub fn validate_hms_table_name(name: &str) -> Result<()> {
+    try!(validate_table_name(name));
+    let mut parts = name.splitn(2, '.');
+    let database = parts.next().unwrap();
+    let table = match parts.next() {
+        Some(table) => table,
+        None => return Err(Error::InvalidArgument(format!(
+                    "table name {:?} is not of the form <database>.<table>, which is required by \
+                     the Hive Metastore", name))),
+    };
+    let parts = [("database", database, 0), ("table", table, database.len() + 1)];
+    for &(kind, part, offset) in &parts {
+        if part.is_empty() {
+            return Err(Error::InvalidArgument(format!(
+                        "table name {:?} has an empty {} name", name, kind)));
+        }
+        if part.len() > MAX_HMS_IDENTIFIER_LENGTH {
+            return Err(Error::InvalidArgument(format!(
+                        "{} name {:?} is longer than the Hive Metastore limit of {} characters",
+                        kind, part, MAX_HMS_IDENTIFIER_LENGTH)));
+        }
+        let invalid = part.char_indices().find(|&(_, c)| {
+            match c {
+                'a'...'z' | 'A'...'Z' | '0'...'9' | '_' | '/' => false,
+                _ => true,
+            }
+        });
+        if let Some((idx, c)) = invalid {
+            return Err(Error::InvalidArgument(format!(
+                        "table name {:?} contains the invalid character {:?} at position {}; Hive \
+                         Metastore {} names may only contain ASCII letters, digits, underscores, \
+                         and slashes", name, c, offset + idx, kind)));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a column name can be synchronized to the Hive Metastore, which rejects column names
+/// containing periods or colons.
+pub fn validate_hms_column_name(name: &str) -> Result<()> {
+    try!(validate_column_name(name));
+    if let Some((idx, c)) = name.char_indices().find(|&(_, c)| c == '.' || c == ':') {
+        return Err(Error::InvalidArgument(format!(
+                    "column name {:?} contains the invalid character {:?} at position {}; Hive \
+                     Metastore column names may not contain periods or colons", name, c, idx)));
+    }
+    Ok(())
+}
+
+fn validate_identifier(kind: &str, name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(Error::InvalidArgument(format!("{} name must not be empty", kind)));
+    }
+    if let Some(idx) = name.find('\0') {
+        return Err(Error::InvalidArgument(format!(
+                    "{} name {:?} contains a NUL character at position {}", kind, name, idx)));
+    }
+    Ok(())
+}
+
+/// Checks the table and column names of a `CreateTable` request.
+pub fn validate_create_table(request: &CreateTableRequestPB, hms_compatible: bool) -> Result<()> {
+    try!(check_table_name(request.get_name(), hms_compatible));
+    for column in request.get_schema().get_columns() {
+        try!(check_column_name(column.get_name(), hms_compatible));
+    }
+    Ok(())
+}
+
+/// Checks the new table and column names of an `AlterTable` request.
+pub fn validate_alter_table(request: &AlterTableRequestPB, hms_compatible: bool) -> Result<()> {
+    if request.has_new_table_name() {
+        try!(check_table_name(request.get_new_table_name(), hms_compatible));
+    }
+    for step in request.get_alter_schema_steps() {
+        let column = match step.get_field_type() {
+            StepType::ADD_COLUMN => step.get_add_column().get_schema().get_name(),
+            StepType::RENAME_COLUMN => step.get_rename_column().get_new_name(),
+            _ => continue,
+        };
+        try!(check_column_name(column, hms_compatible));
+    }
+    Ok(())
+}
+
+fn check_table_name(name: &str, hms_compatible: bool) -> Result<()> {
+    if hms_compatible { validate_hms_table_name(name) } else { validate_table_name(name) }
+}
+
+fn check_column_name(name: &str, hms_compatible: bool) -> Result<()> {
+    if hms_compatible { validate_hms_column_name(name) } else { validate_column_name(name) }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::iter;
+
+    use Error;
+    use super::*;
+
+    fn message(result: Result<()>) -> String {
+        match result {
+            Err(Error::InvalidArgument(message)) => message,
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate_table_name() {
+        validate_table_name("my-table ü").unwrap();
+        assert!(validate_table_name("").is_err());
+        assert!(validate_table_name("a\0b").is_err());
+        validate_table_name(&iter::repeat('a').take(1024).collect::<String>()).unwrap();
+        assert!(validate_column_name("").is_err());
+    }
+
+    #[test]
+    fn test_validate_hms_table_name() {
+        validate_hms_table_name("default.my_table").unwrap();
+        validate_hms_table_name("db/1.Table_2").unwrap();
+        assert!(message(validate_hms_table_name("my_table")).contains("<database>.<table>"));
+        assert!(validate_hms_table_name(".my_table").is_err());
+        assert!(validate_hms_table_name("db.").is_err());
+
+        let error = message(validate_hms_table_name("db.my-table"));
+        assert!(error.contains("'-'"), error);
+        assert!(error.contains("position 5"), error);
+        assert!(validate_hms_table_name("db.a.b").is_err());
+
+        let long = iter::repeat('a').take(MAX_HMS_IDENTIFIER_LENGTH + 1).collect::<String>();
+        assert!(validate_hms_table_name(&format!("db.{}", long)).is_err());
+    }
+
+    #[test]
+    fn test_validate_hms_column_name() {
+        validate_hms_column_name("my column").unwrap();
+        assert!(message(validate_hms_column_name("a:b")).contains("':'"));
+        assert!(validate_hms_column_name("a.b").is_err());
+    }
+}
@@ -1,9 +1,16 @@
+use std::borrow::Cow;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::rc::Rc;
 use std::str;
+use std::sync::Arc;
 use std::time::SystemTime;
 
 use byteorder::{ByteOrder, LittleEndian};
+#[cfg(feature="bytes")] use bytes::Bytes;
+use uuid::Uuid;
 
 use DataType;
+use Error;
 use Result;
 use util::{time_to_us, us_to_time};
 
@@ -151,6 +158,137 @@ impl <'a> Value<'a> for String {
     fn from_data(data: &[u8]) -> Result<String> { str::from_utf8(data).map(str::to_owned).map_err(From::from) }
 }
 
+/// Reads borrow from the row, and writes only copy borrowed strings.
+impl <'a> Value<'a> for Cow<'a, str> {
+    fn can_read_from(data_type: DataType) -> bool { data_type == DataType::String }
+    fn can_write_to(data_type: DataType) -> bool { data_type == DataType::String ||
+                                                   data_type == DataType::Binary }
+    fn data_type() -> DataType { DataType::String }
+    fn size() -> usize { 16 }
+    fn is_var_len() -> bool { true }
+    fn indirect_data(self) -> Vec<u8> { self.into_owned().into_bytes() }
+    fn from_data(data: &'a [u8]) -> Result<Cow<'a, str>> {
+        str::from_utf8(data).map(Cow::Borrowed).map_err(From::from)
+    }
+}
+
+/// Reads borrow from the row, and writes only copy borrowed data.
+impl <'a> Value<'a> for Cow<'a, [u8]> {
+    fn can_read_from(data_type: DataType) -> bool { data_type == DataType::Binary ||
+                                                    data_type == DataType::String }
+    fn can_write_to(data_type: DataType) -> bool { data_type == DataType::Binary }
+    fn data_type() -> DataType { DataType::Binary }
+    fn size() -> usize { 16 }
+    fn is_var_len() -> bool { true }
+    fn indirect_data(self) -> Vec<u8> { self.into_owned() }
+    fn from_data(data: &'a [u8]) -> Result<Cow<'a, [u8]>> { Ok(Cow::Borrowed(data)) }
+}
+
+impl <'a> Value<'a> for Arc<[u8]> {
+    fn can_read_from(data_type: DataType) -> bool { data_type == DataType::Binary ||
+                                                    data_type == DataType::String }
+    fn can_write_to(data_type: DataType) -> bool { data_type == DataType::Binary }
+    fn data_type() -> DataType { DataType::Binary }
+    fn size() -> usize { 16 }
+    fn is_var_len() -> bool { true }
+    fn indirect_data(self) -> Vec<u8> { self.to_vec() }
+    fn from_data(data: &[u8]) -> Result<Arc<[u8]>> { Ok(Arc::from(data)) }
+}
+
+impl <'a> Value<'a> for Rc<[u8]> {
+    fn can_read_from(data_type: DataType) -> bool { data_type == DataType::Binary ||
+                                                    data_type == DataType::String }
+    fn can_write_to(data_type: DataType) -> bool { data_type == DataType::Binary }
+    fn data_type() -> DataType { DataType::Binary }
+    fn size() -> usize { 16 }
+    fn is_var_len() -> bool { true }
+    fn indirect_data(self) -> Vec<u8> { self.to_vec() }
+    fn from_data(data: &[u8]) -> Result<Rc<[u8]>> { Ok(Rc::from(data)) }
+}
+
+#[cfg(feature="bytes")]
+impl <'a> Value<'a> for Bytes {
+    fn can_read_from(data_type: DataType) -> bool { data_type == DataType::Binary ||
+                                                    data_type == DataType::String }
+    fn can_write_to(data_type: DataType) -> bool { data_type == DataType::Binary }
+    fn data_type() -> DataType { DataType::Binary }
+    fn size() -> usize { 16 }
+    fn is_var_len() -> bool { true }
+    fn indirect_data(self) -> Vec<u8> { self.to_vec() }
+    fn from_data(data: &[u8]) -> Result<Bytes> { Ok(Bytes::from(data)) }
+}
+
+/// UUIDs are stored in `BINARY` columns as their 16 bytes.
+impl <'a> Value<'a> for Uuid {
+    fn can_read_from(data_type: DataType) -> bool { data_type == DataType::Binary }
+    fn can_write_to(data_type: DataType) -> bool { data_type == DataType::Binary }
+    fn data_type() -> DataType { DataType::Binary }
+    fn size() -> usize { 16 }
+    fn is_var_len() -> bool { true }
+    fn indirect_data(self) -> Vec<u8> { self.as_bytes().to_vec() }
+    fn from_data(data: &[u8]) -> Result<Uuid> {
+        Uuid::from_bytes(data).map_err(|error| Error::Serialization(format!("invalid UUID: {}", error)))
+    }
+}
+
+/// IPv4 addresses are stored in `BINARY` columns as their 4 octets.
+impl <'a> Value<'a> for Ipv4Addr {
+    fn can_read_from(data_type: DataType) -> bool { data_type == DataType::Binary }
+    fn can_write_to(data_type: DataType) -> bool { data_type == DataType::Binary }
+    fn data_type() -> DataType { DataType::Binary }
+    fn size() -> usize { 16 }
+    fn is_var_len() -> bool { true }
+    fn indirect_data(self) -> Vec<u8> { self.octets().to_vec() }
+    fn from_data(data: &[u8]) -> Result<Ipv4Addr> {
+        if data.len() != 4 {
+            return Err(Error::Serialization(format!("invalid IPv4 address length: {}", data.len())));
+        }
+        Ok(Ipv4Addr::new(data[0], data[1], data[2], data[3]))
+    }
+}
+
+/// IPv6 addresses are stored in `BINARY` columns as their 16 octets.
+impl <'a> Value<'a> for Ipv6Addr {
+    fn can_read_from(data_type: DataType) -> bool { data_type == DataType::Binary }
+    fn can_write_to(data_type: DataType) -> bool { data_type == DataType::Binary }
+    fn data_type() -> DataType { DataType::Binary }
+    fn size() -> usize { 16 }
+    fn is_var_len() -> bool { true }
+    fn indirect_data(self) -> Vec<u8> { self.octets().to_vec() }
+    fn from_data(data: &[u8]) -> Result<Ipv6Addr> {
+        if data.len() != 16 {
+            return Err(Error::Serialization(format!("invalid IPv6 address length: {}", data.len())));
+        }
+        let mut octets = [0; 16];
+        octets.copy_from_slice(data);
+        Ok(Ipv6Addr::from(octets))
+    }
+}
+
+/// IP addresses are stored in `BINARY` columns as 4 octets for IPv4 addresses, or 16 octets for
+/// IPv6 addresses. Addresses can be stored in `STRING` columns in their textual form with
+/// `IpAddr::to_string`, and read back with `str::parse`.
+impl <'a> Value<'a> for IpAddr {
+    fn can_read_from(data_type: DataType) -> bool { data_type == DataType::Binary }
+    fn can_write_to(data_type: DataType) -> bool { data_type == DataType::Binary }
+    fn data_type() -> DataType { DataType::Binary }
+    fn size() -> usize { 16 }
+    fn is_var_len() -> bool { true }
+    fn indirect_data(self) -> Vec<u8> {
+        match self {
+            IpAddr::V4(addr) => addr.indirect_data(),
+            IpAddr::V6(addr) => addr.indirect_data(),
+        }
+    }
+    fn from_data(data: &[u8]) -> Result<IpAddr> {
+        match data.len() {
+            4 => Ipv4Addr::from_data(data).map(IpAddr::V4),
+            16 => Ipv6Addr::from_data(data).map(IpAddr::V6),
+            len => Err(Error::Serialization(format!("invalid IP address length: {}", len))),
+        }
+    }
+}
+
 impl <'a, V> Value<'a> for Option<V> where V: Value<'a> {
     fn data_type() -> DataType { V::data_type() }
     fn can_read_from(data_type: DataType) -> bool { V::can_read_from(data_type) }
@@ -173,4 +311,46 @@ mod tests {
     fn test_foo() {
         assert_eq!(DataType::Bool, bool::data_type());
     }
+
+    #[test]
+    fn test_shared_buffers() {
+        let data = b"foo";
+        match Cow::<str>::from_data(data).unwrap() {
+            Cow::Borrowed(value) => assert_eq!("foo", value),
+            Cow::Owned(_) => panic!("expected a borrowed value"),
+        }
+        assert!(Cow::<str>::from_data(b"\xff").is_err());
+        assert_eq!(b"foo".to_vec(), Cow::Borrowed("foo").indirect_data());
+        assert_eq!(b"foo".to_vec(), Cow::<[u8]>::Owned(b"foo".to_vec()).indirect_data());
+
+        let shared: Arc<[u8]> = Arc::from(&data[..]);
+        assert_eq!(b"foo".to_vec(), shared.clone().indirect_data());
+        assert_eq!(shared, Arc::<[u8]>::from_data(data).unwrap());
+        assert_eq!(&b"foo"[..], &*Rc::<[u8]>::from_data(data).unwrap());
+    }
+
+    #[test]
+    fn test_uuid() {
+        let uuid = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        let data = uuid.indirect_data();
+        assert_eq!(16, data.len());
+        assert_eq!(uuid, Uuid::from_data(&data).unwrap());
+        assert!(Uuid::from_data(&data[..15]).is_err());
+    }
+
+    #[test]
+    fn test_ip_addr() {
+        let v4: IpAddr = "10.1.2.3".parse().unwrap();
+        let data = v4.indirect_data();
+        assert_eq!(vec![10, 1, 2, 3], data);
+        assert_eq!(v4, IpAddr::from_data(&data).unwrap());
+
+        let v6: IpAddr = "2001:db8::ff00:42:8329".parse().unwrap();
+        let data = v6.indirect_data();
+        assert_eq!(16, data.len());
+        assert_eq!(v6, IpAddr::from_data(&data).unwrap());
+        assert!(Ipv4Addr::from_data(&data).is_err());
+
+        assert!(IpAddr::from_data(&[1, 2, 3]).is_err());
+    }
 }
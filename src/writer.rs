@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
 use std::mem;
 use std::net::SocketAddr;
@@ -8,15 +9,20 @@ use std::time::{Duration, Instant};
 
 use kudu_pb::tserver;
 use parking_lot::{Mutex, MutexGuard};
+use protobuf::{Clear, Message};
 
 use Client;
+use DeadlineExceededError;
 use Error;
 use PartitionSchema;
+use RateLimiter;
 use Result;
 use Row;
 use Schema;
+use ServerFeature;
 use Table;
 use TabletId;
+use TabletServerErrorCode;
 use backoff::Backoff;
 use error;
 use key;
@@ -27,15 +33,19 @@ use row::OperationEncoder;
 use rpc::{Callback, Messenger, Rpc, tablet_server};
 use util;
 
+/// Space reserved in write requests for fields other than the row operations and schema, such as
+/// the tablet ID, propagated timestamp, and message framing.
+const WRITE_REQUEST_OVERHEAD: usize = 1024;
+
 #[derive(Debug, Clone)]
 pub struct WriterConfig {
 
     /// Maximum amount of time to wait for a batch of write operations to be sent to a tablet
-    /// server. If the timeout expires before the batch completes, the operations will fail with
-    /// `Error::TimedOut`.
+    /// server. If the timeout expires before the batch completes, the operations will fail with a
+    /// timeout error, which is `Error::DeadlineExceeded` if the batch was retried.
     ///
-    /// Defaults to 30 second.
-    flush_timeout: Duration,
+    /// Defaults to the client's operation timeout. See `ClientConfig::set_operation_timeout`.
+    flush_timeout: Option<Duration>,
 
     /// Maximum amount of time to batch write operations before flushing.
     ///
@@ -51,7 +61,11 @@ pub struct WriterConfig {
     max_buffered_data: usize,
 
     /// Maximum amount of row operation data to batch per write RPC to a tablet. When a batch
-    /// becomes full it is automatically dispatched to the tablet server.
+    /// becomes full it is automatically dispatched to the tablet server. All buffered operations
+    /// for a tablet are otherwise sent in a single write RPC per flush.
+    ///
+    /// The limit is lowered if necessary so that write requests, which include the table schema,
+    /// fit within the client's maximum RPC message length.
     ///
     /// Defaults to 7MiB.
     max_data_per_batch: usize,
@@ -73,19 +87,38 @@ pub struct WriterConfig {
     /// Determines which events are sent to the event_channel.
     event_set: EventSet,
 
+    /// Whether the tablet, server, retries, and latency of each operation are recorded.
+    ///
+    /// Defaults to false.
+    trace_routing: bool,
+
+    /// Limits the rate of applied operations.
+    ///
+    /// Defaults to no limit.
+    rate_limiter: Option<RateLimiter>,
+
+    /// Maximum number of idempotency tags of written operations which the writer remembers in
+    /// order to deduplicate operations applied with `Writer::apply_tagged`.
+    ///
+    /// Defaults to 65536.
+    idempotency_window: usize,
+
     event_channel: Option<SyncSender<Event>>,
 }
 
 impl Default for WriterConfig {
     fn default() -> WriterConfig {
         WriterConfig {
-            flush_timeout: Duration::from_secs(30),
+            flush_timeout: None,
             background_flush_interval: Duration::from_secs(1),
             max_buffered_data: 256 * 1024 * 1024,
             max_data_per_batch: 7 * 1024 * 1024,
             max_batches_per_tablet: 2,
             early_flush_watermark: 80,
             event_set: EventSet::Flushes,
+            trace_routing: false,
+            rate_limiter: None,
+            idempotency_window: 64 * 1024,
             event_channel: None,
         }
     }
@@ -107,15 +140,60 @@ impl WriterConfig {
         self.event_channel = Some(sender);
     }
 
-    fn into_config(mut self) -> (Config, Option<SyncSender<Event>>) {
+    pub fn set_flush_timeout(&mut self, flush_timeout: Duration) -> &mut WriterConfig {
+        self.flush_timeout = Some(flush_timeout);
+        self
+    }
+
+    pub fn set_max_batches_per_tablet(&mut self, max_batches_per_tablet: u8) -> &mut WriterConfig {
+        assert!(max_batches_per_tablet > 0, "max_batches_per_tablet must be at least 1");
+        self.max_batches_per_tablet = max_batches_per_tablet;
+        self
+    }
+
+    pub fn set_event_set(&mut self, event_set: EventSet) -> &mut WriterConfig {
+        self.event_set = event_set;
+        self
+    }
+
+    /// Sets whether the writer records the tablet and tablet server each operation is routed
+    /// to, along with the number of retries and the latency of the operation. The records are
+    /// retrieved with `Writer::take_routing_trace`, and are useful for diagnosing hotspotting.
+    /// Tracing retains a record for every written operation until it is taken.
+    pub fn set_trace_routing(&mut self, trace_routing: bool) -> &mut WriterConfig {
+        self.trace_routing = trace_routing;
+        self
+    }
+
+    /// Sets a rate limiter for the operations applied to the writer. `Writer::apply` blocks while
+    /// the rate of applied operations exceeds the limits. The limits may be changed while the
+    /// writer is in use through `Writer::rate_limiter`, or any clone of the rate limiter.
+    pub fn set_rate_limiter(&mut self, rate_limiter: RateLimiter) -> &mut WriterConfig {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Sets the number of idempotency tags of written operations which the writer remembers. An
+    /// operation applied with `Writer::apply_tagged` is only deduplicated if its tag is among the
+    /// most recently written tags, so the window should cover the span of operations which an
+    /// application may retry.
+    pub fn set_idempotency_window(&mut self, idempotency_window: usize) -> &mut WriterConfig {
+        self.idempotency_window = idempotency_window;
+        self
+    }
+
+    fn into_config(mut self, operation_timeout: Duration) -> (Config, Option<SyncSender<Event>>) {
         (Config {
-            flush_timeout: self.flush_timeout,
+            flush_timeout: self.flush_timeout.unwrap_or(operation_timeout),
             background_flush_interval: self.background_flush_interval,
             max_buffered_data: self.max_buffered_data,
             max_data_per_batch: self.max_data_per_batch,
             max_batches_per_tablet: self.max_batches_per_tablet,
             early_flush_watermark: self.early_flush_watermark,
             event_set: self.event_set,
+            trace_routing: self.trace_routing,
+            rate_limiter: self.rate_limiter.take(),
+            idempotency_window: self.idempotency_window,
         },
         self.event_channel.take())
     }
@@ -130,6 +208,9 @@ struct Config {
     max_batches_per_tablet: u8,
     early_flush_watermark: u8,
     event_set: EventSet,
+    trace_routing: bool,
+    rate_limiter: Option<RateLimiter>,
+    idempotency_window: usize,
 }
 
 impl WriterConfig {
@@ -143,6 +224,9 @@ pub enum Event {
     Flush(FlushStats),
     SuccessfulOperation(Row, OperationType),
     FailedOperation(Row, OperationType, Error),
+    /// An operation applied with `Writer::apply_tagged` which was not written, because an
+    /// operation with the same idempotency tag had already been written.
+    DeduplicatedOperation(Row, OperationType, String),
 }
 
 impl Event {
@@ -164,6 +248,12 @@ impl Event {
             _ => false,
         }
     }
+    pub fn is_deduplicated_operation(&self) -> bool {
+        match *self {
+            Event::DeduplicatedOperation(..) => true,
+            _ => false,
+        }
+    }
 }
 
 /// The events that the writer should return in the event channel. Each event set builds on the
@@ -174,7 +264,7 @@ pub enum EventSet {
     Flushes,
     /// Flush events and failed operation events.
     FailedOperations,
-    /// Flush events, failed operation events, and successful operation events.
+    /// Flush events, failed operation events, and successful and deduplicated operation events.
     SuccessfulOperations,
 }
 
@@ -204,6 +294,7 @@ pub struct Writer {
 struct Inner {
     config: Config,
     state: Mutex<State>,
+    routing_trace: Mutex<Vec<OperationTrace>>,
 }
 
 /// `State` wraps all the mutable state of a writer.
@@ -242,6 +333,9 @@ struct State {
 
     /// Current amount of unflushed data (in-lookup + buffered + batches in flight).
     buffered_data: usize,
+
+    /// Idempotency tags of tagged operations.
+    tags: Tags,
 }
 
 impl State {
@@ -283,6 +377,40 @@ impl State {
     }
 }
 
+/// Tracks the idempotency tags of operations applied with `Writer::apply_tagged`.
+#[derive(Default)]
+struct Tags {
+    /// Tags of operations which have been applied to the writer, but have not yet completed.
+    pending: HashSet<String>,
+    /// Tags of pending operations which have been assigned an index, by index.
+    in_flight: HashMap<usize, String>,
+    /// Tags of written operations, up to the idempotency window.
+    written: HashSet<String>,
+    /// Tags of written operations, in the order they were written.
+    written_order: VecDeque<String>,
+}
+
+impl Tags {
+    /// Records the completion of the operation with the index, if it is tagged.
+    fn complete(&mut self, idx: usize, success: bool, window: usize) {
+        let tag = match self.in_flight.remove(&idx) {
+            Some(tag) => tag,
+            None => return,
+        };
+        self.pending.remove(&tag);
+        if !success || window == 0 {
+            return;
+        }
+        if self.written.len() >= window {
+            if let Some(oldest) = self.written_order.pop_front() {
+                self.written.remove(&oldest);
+            }
+        }
+        self.written.insert(tag.clone());
+        self.written_order.push_back(tag);
+    }
+}
+
 impl fmt::Debug for State {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 
@@ -327,11 +455,17 @@ struct OperationInLookup {
     direct_len: usize,
     indirect_len: usize,
     op_type: OperationType,
+    /// The time the operation was applied, if routing is traced.
+    applied: Option<Instant>,
 }
 
+/// A buffered operation: the row, its apply index, operation type, and apply time if routing is
+/// traced.
+type BufferedOperation = (Row, usize, OperationType, Option<Instant>);
+
 #[must_use]
 struct Buffer {
-    operations: Vec<(Row, usize, OperationType)>,
+    operations: Vec<BufferedOperation>,
     /// Current amount of buffered data.
     direct_buffered_data: usize,
     indirect_buffered_data: usize,
@@ -377,7 +511,9 @@ pub struct FlushStats {
     failed_batches: usize,
     successful_operations: usize,
     failed_operations: usize,
+    deduplicated_operations: usize,
     data: usize,
+    timestamp: Option<u64>,
 }
 
 impl FlushStats {
@@ -388,7 +524,9 @@ impl FlushStats {
             failed_batches: 0,
             successful_operations: 0,
             failed_operations: 0,
+            deduplicated_operations: 0,
             data: 0,
+            timestamp: None,
         }
     }
 }
@@ -409,9 +547,62 @@ impl FlushStats {
     pub fn failed_operations(&self) -> usize {
         self.failed_operations
     }
+    /// Returns the number of tagged operations which were not written because an operation with
+    /// the same idempotency tag had already been written. See `Writer::apply_tagged`.
+    pub fn deduplicated_operations(&self) -> usize {
+        self.deduplicated_operations
+    }
     pub fn data(&self) -> usize {
         self.data
     }
+    /// Returns the latest timestamp assigned by the tablet servers to the writes of the flush, if
+    /// any. Passing it to `Client::timestamp_observed` of another client ensures that the other
+    /// client's subsequent reads observe the writes.
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+}
+
+/// A record of how a written operation was routed, retrieved with `Writer::take_routing_trace`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OperationTrace {
+    index: usize,
+    op_type: OperationType,
+    tablet: TabletId,
+    server: SocketAddr,
+    retries: usize,
+    latency: Duration,
+    succeeded: bool,
+}
+
+impl OperationTrace {
+    /// Returns the index of the operation among the operations applied to the writer.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+    pub fn op_type(&self) -> OperationType {
+        self.op_type
+    }
+    /// Returns the tablet the operation was written to.
+    pub fn tablet(&self) -> TabletId {
+        self.tablet
+    }
+    /// Returns the address of the tablet server which the operation's batch was written to.
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+    /// Returns the number of times the operation's batch was resent.
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+    /// Returns the time between applying the operation and its batch completing.
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+    /// Returns `true` if the operation was written, or `false` if the tablet server rejected it.
+    pub fn succeeded(&self) -> bool {
+        self.succeeded
+    }
 }
 
 impl Writer {
@@ -427,7 +618,12 @@ impl Writer {
         let mut flushes = QueueMap::new();
         debug_assert_eq!(0, flushes.push(flush));
 
-        let (config, event_channel) = config.into_config();
+        let (mut config, event_channel) =
+            config.into_config(table.client().config().operation_timeout());
+        let max_message_data = (table.client().config().max_message_length() as usize)
+            .saturating_sub(table.schema().as_pb().compute_size() as usize + WRITE_REQUEST_OVERHEAD);
+        config.max_data_per_batch = cmp::min(config.max_data_per_batch, max_message_data);
+
         Writer {
             table: table,
             inner: Arc::new(Inner {
@@ -437,7 +633,9 @@ impl Writer {
                     tablets: HashMap::new(),
                     flushes: flushes,
                     buffered_data: 0,
+                    tags: Tags::default(),
                 }),
+                routing_trace: Mutex::new(Vec::new()),
             }),
             event_channel: event_channel,
         }
@@ -460,17 +658,93 @@ impl Writer {
     }
 
     pub fn apply(&self, row: Row, op_type: OperationType) {
+        self.apply_op(row, op_type, None);
+    }
+
+    /// Applies an operation tagged with an idempotency tag chosen by the application, such as the
+    /// ID of the upstream record the operation was derived from, so that an operation which is
+    /// retried after it was written is not written again.
+    ///
+    /// If an operation with the same tag has already been written by the writer, the operation is
+    /// deduplicated: it is not sent, it is counted by `FlushStats::deduplicated_operations` of
+    /// the current flush, and an `Event::DeduplicatedOperation` is sent if the writer's event set
+    /// includes successful operations. An operation whose tagged predecessor failed, including
+    /// with a per-row error, is written again. Applying an operation while an operation with the
+    /// same tag is in flight fails with `Error::InvalidArgument`.
+    ///
+    /// Tags are only remembered in memory by the writer which wrote them, up to its idempotency
+    /// window, so deduplication does not survive a restart of the process: an application which
+    /// replays operations after restarting must deduplicate them itself, for instance by writing
+    /// them as upserts. Write RPCs carry request IDs, so tablet servers apply a batch which is
+    /// resent exactly once regardless of tags.
+    pub fn apply_tagged<S>(&self, row: Row, op_type: OperationType, tag: S) where S: Into<String> {
+        let tag = tag.into();
+        {
+            let mut state = self.lock_state();
+            if state.tags.written.contains(&tag) {
+                let flush_epoch = state.flush_epoch();
+                state.flushes[flush_epoch].stats.deduplicated_operations += 1;
+                drop(state);
+                self.deduplicate_operation(row, op_type, tag);
+                return;
+            }
+            if !state.tags.pending.insert(tag.clone()) {
+                drop(state);
+                self.fail_operation(row, op_type, Error::InvalidArgument(
+                        format!("an operation with idempotency tag {:?} is already in flight", tag)));
+                return;
+            }
+        }
+        if !self.apply_op(row, op_type, Some(tag.clone())) {
+            self.lock_state().tags.pending.remove(&tag);
+        }
+    }
+
+    /// Applies the operation, returning `false` if it failed before being assigned an index.
+    fn apply_op(&self, row: Row, op_type: OperationType, tag: Option<String>) -> bool {
+        if self.client().is_shut_down() {
+            self.fail_operation(row, op_type, Error::Shutdown);
+            return false;
+        }
         if row.schema() != self.schema() {
             self.fail_operation(row, op_type, Error::InvalidArgument(
                     "row operation schema must match the writer table schema".to_owned()));
-            return;
+            return false;
+        }
+        if op_type == OperationType::Insert {
+            let sets_auto_incrementing_column = row.schema().auto_incrementing_column_index()
+                                                   .map_or(false, |idx| row.is_set(idx).unwrap());
+            if sets_auto_incrementing_column {
+                self.fail_operation(row, op_type, Error::InvalidArgument(
+                        "insert may not set the auto-incrementing column".to_owned()));
+                return false;
+            }
+        }
+        if op_type == OperationType::Upsert {
+            if let Err(error) = self.client().server_versions().check(ServerFeature::Upsert) {
+                self.fail_operation(row, op_type, error);
+                return false;
+            }
+        }
+        if op_type == OperationType::Update {
+            let immutable_column = {
+                let columns = row.schema().columns();
+                (row.schema().num_primary_key_columns()..columns.len())
+                    .find(|&idx| columns[idx].is_immutable() && row.is_set(idx).unwrap())
+                    .map(|idx| columns[idx].name().to_owned())
+            };
+            if let Some(column) = immutable_column {
+                self.fail_operation(row, op_type, Error::InvalidArgument(
+                        format!("update may not set immutable column '{}'", column)));
+                return false;
+            }
         }
 
         let partition_key = match key::encode_partition_key(self.partition_schema(), &row) {
             Ok(partition_key) => partition_key,
             Err(error) => {
                 self.fail_operation(row, op_type, error);
-                return;
+                return false;
             },
         };
         let (direct_len, indirect_len) = OperationEncoder::encoded_len(&row);
@@ -481,7 +755,11 @@ impl Writer {
         if encoded_len > self.config().max_data_per_batch {
             self.fail_operation(row, op_type, Error::InvalidArgument(
                     "row operation size is greater than the max batch size".to_owned()));
-            return;
+            return false;
+        }
+
+        if let Some(ref rate_limiter) = self.config().rate_limiter {
+            rate_limiter.acquire(1, encoded_len as u64);
         }
 
         let idx = {
@@ -495,26 +773,40 @@ impl Writer {
             if data > self.config().max_buffered_data {
                 drop(state);
                 self.fail_operation(row, op_type, Error::Backoff);
-                return;
+                return false;
             } else if data > (self.config().max_buffered_data / 100) *
                              self.config().early_flush_watermark as usize {
                 // Do early flush.
                 unimplemented!()
             }
 
+            // The operation's data is also accounted against the client's memory budget until its
+            // batch completes, so that writers can not exhaust memory shared with other writers
+            // and scans.
+            if let Err(error) = self.client().memory_budget().try_reserve(encoded_len) {
+                drop(state);
+                self.fail_operation(row, op_type, error);
+                return false;
+            }
+
             // Add the operation to the operations_in_flight queue. This assigns an idx which
             // uniquely identifies the operation and gives it a total ordering among applied
             // operations in the writer.
             state.buffered_data += data;
             let flush_epoch = state.flush_epoch();
             state.flushes[flush_epoch].lookups_outstanding += 1;
-            state.operations_in_lookup.push(OperationInLookup {
+            let idx = state.operations_in_lookup.push(OperationInLookup {
                 row: row,
                 flush_epoch: flush_epoch,
                 direct_len: direct_len,
                 indirect_len: indirect_len,
                 op_type: op_type,
-            })
+                applied: if self.config().trace_routing { Some(Instant::now()) } else { None },
+            });
+            if let Some(tag) = tag {
+                state.tags.in_flight.insert(idx, tag);
+            }
+            idx
         };
 
         let writer: Writer = self.clone();
@@ -522,6 +814,21 @@ impl Writer {
         self.meta_cache().tablet_id(partition_key, deadline, move |result| {
             writer.op_lookup_complete(idx, result);
         });
+        true
+    }
+
+    /// Returns the rate limiter of the writer, if one is configured. The limits of the rate limiter
+    /// may be adjusted while the writer is in use.
+    pub fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.config().rate_limiter.as_ref()
+    }
+
+    /// Returns the routing records of the operations written since the trace was last taken, and
+    /// clears the trace. Operations are only traced if `WriterConfig::set_trace_routing` is
+    /// enabled, and once their batch completes, so the trace is complete after a flush. Operations
+    /// which fail before being sent to a tablet server are not traced.
+    pub fn take_routing_trace(&self) -> Vec<OperationTrace> {
+        mem::replace(&mut *self.inner.routing_trace.lock(), Vec::new())
     }
 
     /// Flush the `Writer`. The provided callback is called with statistics about the flush when
@@ -572,7 +879,7 @@ impl Writer {
 
         // Retrieve the operation in lookup, and decrement the epoch's operations in lookup
         // counter.
-        let OperationInLookup { row, flush_epoch, direct_len, indirect_len, op_type } =
+        let OperationInLookup { row, flush_epoch, direct_len, indirect_len, op_type, applied } =
             state.operations_in_lookup.remove(idx).unwrap();
         let encoded_len = direct_len + indirect_len;
         state.flushes[flush_epoch].lookups_outstanding -= 1;
@@ -614,7 +921,7 @@ impl Writer {
                     let buffer = buffer.as_mut().unwrap();
 
                     // Add the operation to the buffer.
-                    buffer.operations.push((row, idx, op_type));
+                    buffer.operations.push((row, idx, op_type, applied));
                     buffer.direct_buffered_data += direct_len;
                     buffer.indirect_buffered_data += indirect_len;
                     *buffered_data += encoded_len;
@@ -647,6 +954,8 @@ impl Writer {
         }
 
         if let Some((row, op_type, error)) = failed_op {
+            self.complete_tags(Some((idx, false)));
+            self.client().memory_budget().release(encoded_len);
             self.fail_operation(row, op_type, error);
         }
     }
@@ -657,12 +966,14 @@ impl Writer {
                       flush_epoch: usize,
                       successful_operations: usize,
                       failed_operations: usize,
-                      data: usize) {
+                      data: usize,
+                      timestamp: Option<u64>) {
         let mut complete_flushes = Vec::new();
         {
             let mut state = self.lock_state();
             state.tablets.get_mut(&tablet).unwrap().1 -= 1;
             state.buffered_data -= data;
+            self.client().memory_budget().release(data);
             {
                 let flush = &mut state.flushes[flush_epoch];
                 if success {
@@ -674,6 +985,9 @@ impl Writer {
                 flush.stats.successful_operations += successful_operations;
                 flush.stats.failed_operations += failed_operations;
                 flush.stats.data += data;
+                if timestamp > flush.stats.timestamp {
+                    flush.stats.timestamp = timestamp;
+                }
 
                 flush.batches_outstanding -= 1;
             }
@@ -704,6 +1018,29 @@ impl Writer {
         }
     }
 
+    /// Records the routing of a completed batch's operations. `failed` holds the indices within
+    /// the batch of the operations which the tablet server rejected.
+    fn record_routing(&self,
+                      tablet: TabletId,
+                      server: SocketAddr,
+                      retries: usize,
+                      operations: &[BufferedOperation],
+                      failed: &HashSet<usize>) {
+        let now = Instant::now();
+        let mut routing_trace = self.inner.routing_trace.lock();
+        for (i, &(_, index, op_type, applied)) in operations.iter().enumerate() {
+            routing_trace.push(OperationTrace {
+                index: index,
+                op_type: op_type,
+                tablet: tablet,
+                server: server,
+                retries: retries,
+                latency: applied.map_or(Duration::from_secs(0), |applied| now.duration_since(applied)),
+                succeeded: !failed.contains(&i),
+            });
+        }
+    }
+
     fn schema(&self) -> &Schema {
         self.table.schema()
     }
@@ -722,6 +1059,28 @@ impl Writer {
         }
     }
 
+    fn deduplicate_operation(&self, row: Row, op_type: OperationType, tag: String) {
+        if self.config().event_set.has_successful_operation() {
+            if let Some(ref channel) = self.event_channel {
+                if channel.try_send(Event::DeduplicatedOperation(row, op_type, tag)).is_err() {
+                    debug!("failed to send deduplicated operation to event channel");
+                }
+            }
+        }
+    }
+
+    /// Records the completion of tagged operations, given the index of each completed operation
+    /// and whether it succeeded.
+    fn complete_tags<I>(&self, results: I) where I: IntoIterator<Item=(usize, bool)> {
+        let mut state = self.lock_state();
+        if state.tags.in_flight.is_empty() {
+            return;
+        }
+        for (idx, success) in results {
+            state.tags.complete(idx, success, self.config().idempotency_window);
+        }
+    }
+
     fn config(&self) -> &Config {
         &self.inner.config
     }
@@ -747,11 +1106,17 @@ impl Writer {
 struct Batch {
     tablet: TabletId,
     leader_addrs: Vec<SocketAddr>,
-    operations: Vec<(Row, usize, OperationType)>,
+    operations: Vec<BufferedOperation>,
     writer: Writer,
+    /// Number of times the batch has been sent.
+    attempts: usize,
+    /// The errors which the batch has been retried after, oldest first.
+    failed_attempts: Vec<Error>,
     backoff: Backoff,
     buffered_data: usize,
     flush_epoch: usize,
+    /// The sequence number of the batch's request ID.
+    seq_no: i64,
 }
 
 impl Batch {
@@ -770,7 +1135,7 @@ impl Batch {
         let mut encoder = OperationEncoder::with_capacity(direct_buffered_data,
                                                           indirect_buffered_data);
 
-        for &(ref row, _, op_type) in &operations {
+        for &(ref row, _, op_type, _) in &operations {
             encoder.encode_row(op_type.as_pb(), row);
         }
 
@@ -785,9 +1150,18 @@ impl Batch {
         message.set_propagated_timestamp(writer.client().latest_observed_timestamp());
         message.set_tablet_id(tablet_id.to_string().into_bytes());
 
-        let rpc = tablet_server::write(util::dummy_addr(),
-                                       Instant::now() + writer.config().flush_timeout,
-                                       message);
+        let mut rpc = tablet_server::write(util::dummy_addr(),
+                                           Instant::now() + writer.config().flush_timeout,
+                                           message);
+        // Tablet servers apply a write with a request ID once, even if it is resent.
+        let request_id = writer.client().request_tracker().new_request_id();
+        let seq_no = request_id.get_seq_no();
+        rpc.request_id = Some(request_id);
+        // Serialize the request up front, since write batches can be large.
+        if let Err(error) = rpc.serialize_request() {
+            // Fall back to serializing on the I/O thread, which will surface the error.
+            warn!("failed to serialize write request for tablet {}: {}", tablet_id, error);
+        }
 
         let backoff = Backoff::with_duration_range(10, util::duration_to_ms(&writer.config().flush_timeout) as u32 / 2);
         let batch = Batch {
@@ -795,12 +1169,15 @@ impl Batch {
             leader_addrs: Vec::new(),
             operations: operations,
             writer: writer,
+            attempts: 0,
+            failed_attempts: Vec::new(),
             backoff: backoff,
             buffered_data: direct_buffered_data + indirect_buffered_data,
             flush_epoch: flush_epoch,
+            seq_no: seq_no,
         };
 
-        batch.lookup_locations(rpc);
+        batch.lookup_locations(rpc.into_rpc());
     }
 
     fn lookup_locations(mut self, rpc: Rpc) {
@@ -822,9 +1199,10 @@ impl Batch {
                     self.dispatch_next(rpc);
                 },
                 Ok(None) => self.reapply(),
+                Err(Error::Shutdown) => self.fail(Error::Shutdown),
                 Err(error) => {
                     warn!("unable to look up leader address for tablet {}: {}", self.tablet, error);
-                    self.retry(rpc);
+                    self.retry(rpc, error);
                 }
             }
         });
@@ -835,12 +1213,18 @@ impl Batch {
         match self.leader_addrs.pop() {
             Some(addr) => {
                 rpc.addr = addr;
+                self.attempts += 1;
+                if let Some(ref mut request_id) = rpc.request_id {
+                    request_id.set_attempt_no(self.attempts as i64);
+                }
                 let messenger = self.writer.messenger().clone();
                 rpc.callback = Some(Box::new(self));
                 messenger.send(rpc);
             },
             None => {
-                self.retry(rpc);
+                // The tablet has no known leader, for instance during a leader election.
+                self.mark_leader_stale();
+                self.retry(rpc, Error::TimedOut);
             }
         }
     }
@@ -848,18 +1232,49 @@ impl Batch {
     fn handle_response(mut self: Box<Self>, result: Result<()>, mut rpc: Rpc) {
         match result {
             Ok(_) => {
+                let addr = rpc.addr;
+                let tablet_server_error = {
+                    let response = rpc.mut_response::<tserver::WriteResponsePB>();
+                    if response.has_error() {
+                        Some(error::TabletServerError::from(response.take_error()))
+                    } else {
+                        None
+                    }
+                };
+                if let Some(error) = tablet_server_error {
+                    let batch = *self;
+                    return batch.handle_tablet_server_error(addr, error, rpc);
+                }
+
                 let response = rpc.mut_response::<tserver::WriteResponsePB>();
 
                 trace!("batch response: {:?}", response);
 
-                if response.has_error() {
-                    // TODO
-                    unimplemented!();
-                }
+                let timestamp = if response.has_timestamp() {
+                    self.writer.client().timestamp_observed(response.get_timestamp());
+                    Some(response.get_timestamp())
+                } else {
+                    None
+                };
+
+                self.writer.client().request_tracker().complete(self.seq_no);
 
                 let failed_ops = response.get_per_row_errors().len();
                 let successful_ops = self.operations.len() - failed_ops;
 
+                let failed = response.get_per_row_errors()
+                                     .iter()
+                                     .map(|error| error.get_row_index() as usize)
+                                     .collect::<HashSet<_>>();
+                if self.writer.config().trace_routing {
+                    self.writer.record_routing(self.tablet, addr, self.attempts - 1,
+                                               &self.operations, &failed);
+                }
+                self.writer.complete_tags(self.operations
+                                              .iter()
+                                              .enumerate()
+                                              .map(|(i, op)| (op.1, !failed.contains(&i))));
+
                 debug!("batch complete; successful ops: {}, failed ops: {}",
                        successful_ops, failed_ops);
 
@@ -873,7 +1288,7 @@ impl Batch {
                             {
                                 let ops = operations.drain(error.get_row_index() as usize + 1..);
                                 if writer.config().event_set.has_successful_operation() {
-                                    for (row, _, op_type) in ops {
+                                    for (row, _, op_type, _) in ops {
                                         if channel.try_send(Event::SuccessfulOperation(row, op_type)).is_err() {
                                             debug!("failed to send successful operation to event channel");
                                         }
@@ -882,7 +1297,7 @@ impl Batch {
                                     for _ in ops {}
                                 }
                             }
-                            let (row, _, op_type) = operations.pop().unwrap();
+                            let (row, _, op_type, _) = operations.pop().unwrap();
                             let error = error::TabletServerError::from(error.take_error());
                             if let Err(error) = channel.try_send(Event::FailedOperation(row, op_type, Error::TabletServer(error))) {
                                 debug!("failed to send failed operation to event channel: {}", error);
@@ -890,7 +1305,7 @@ impl Batch {
                         }
 
                         if writer.config().event_set.has_successful_operation() {
-                            for (row, _, op_type) in operations.drain(..) {
+                            for (row, _, op_type, _) in operations.drain(..) {
                                 if channel.try_send(Event::SuccessfulOperation(row, op_type)).is_err() {
                                     debug!("failed to send successful operation to event channel");
                                 }
@@ -904,19 +1319,132 @@ impl Batch {
                                            self.flush_epoch,
                                            successful_ops,
                                            failed_ops,
-                                           self.buffered_data);
+                                           self.buffered_data,
+                                           timestamp);
+            },
+            Err(Error::Shutdown) => {
+                let batch = *self;
+                batch.fail(Error::Shutdown);
+            },
+            Err(ref error) if error.is_network_error() || error.is_circuit_open() => {
+                // The leader may have failed; try its next address, or look the leader up again.
+                debug!("batch for tablet {} failed to reach {}: {}", self.tablet, rpc.addr, error);
+                let batch = *self;
+                if batch.leader_addrs.is_empty() {
+                    batch.mark_leader_stale();
+                    batch.retry(rpc, error.clone());
+                } else {
+                    batch.dispatch_next(rpc);
+                }
             },
             Err(error) => {
-                panic!("error handling unimplemented. error: {}", error);
-            }
+                let batch = *self;
+                batch.fail(error);
+            },
+        }
+    }
+
+    /// Handles an error response to the batch as a whole.
+    ///
+    /// A `Throttled` error means that the tablet server is under memory pressure. The connection
+    /// to the server is throttled, so that fewer batches are in flight to it, and the batch is
+    /// resent after a backoff which grows with each throttled attempt. The batch fails with
+    /// `Error::DeadlineExceeded` if the backoff would exceed its deadline, or with the error if the
+    /// client's retry budget is exhausted.
+    ///
+    /// Errors which mean that the server is no longer the tablet's leader, or that the tablet is
+    /// not yet running on it, are expected while leadership moves between replicas. The cached
+    /// leader is marked stale, and the batch is retried against the new leader. Other errors fail
+    /// the batch.
+    fn handle_tablet_server_error(mut self, addr: SocketAddr, error: error::TabletServerError, mut rpc: Rpc) {
+        match error.code() {
+            TabletServerErrorCode::Throttled => (),
+            TabletServerErrorCode::NotTheLeader |
+            TabletServerErrorCode::TabletNotRunning |
+            TabletServerErrorCode::TabletNotFound => {
+                debug!("batch for tablet {} rejected by {}: {}, retrying", self.tablet, addr, error);
+                self.mark_leader_stale();
+                return self.retry(rpc, Error::TabletServer(error));
+            },
+            _ => return self.fail(Error::TabletServer(error)),
+        }
+        self.writer.messenger().throttle(addr);
+
+        if self.writer.client().is_shut_down() {
+            return self.fail(Error::Shutdown);
         }
+        let duration = Duration::from_millis(self.backoff.next_backoff_ms());
+        if Instant::now() + duration >= rpc.deadline {
+            return self.exceed_deadline(Error::TabletServer(error));
+        }
+        if !self.writer.client().retry_budget().try_retry() {
+            return self.fail(Error::TabletServer(error));
+        }
+        self.failed_attempts.push(Error::TabletServer(error));
+        debug!("batch for tablet {} throttled by {}, retrying in {:?}", self.tablet, addr, duration);
+        // Responses are merged into the response message, so it must be cleared before resending.
+        rpc.mut_response::<tserver::WriteResponsePB>().clear();
+        self.writer.messenger().clone().timer(duration, Box::new(move || self.resume(rpc)));
     }
 
-    fn retry(mut self, rpc: Rpc) {
+    /// Looks up the tablet's leader again, and resends the batch to it after a backoff. The batch
+    /// fails with `Error::DeadlineExceeded` if the backoff would exceed its deadline, or with the
+    /// error which caused the retry if the client's retry budget is exhausted.
+    fn retry(mut self, mut rpc: Rpc, error: Error) {
+        if self.writer.client().is_shut_down() {
+            return self.fail(Error::Shutdown);
+        }
         let duration = Duration::from_millis(self.backoff.next_backoff_ms());
-        self.writer.messenger().clone().timer(duration, Box::new(move || {
-            self.lookup_locations(rpc);
-        }));
+        if Instant::now() + duration >= rpc.deadline {
+            return self.exceed_deadline(error);
+        }
+        if !self.writer.client().retry_budget().try_retry() {
+            return self.fail(error);
+        }
+        self.failed_attempts.push(error);
+        // Responses are merged into the response message, so it must be cleared before resending.
+        rpc.mut_response::<tserver::WriteResponsePB>().clear();
+        self.writer.messenger().clone().timer(duration, Box::new(move || self.resume(rpc)));
+    }
+
+    /// Marks the cached location of the batch's tablet as stale, so that its leader is looked up
+    /// from the master before the batch is resent.
+    fn mark_leader_stale(&self) {
+        let partition_key = key::encode_partition_key(self.writer.partition_schema(),
+                                                      &self.operations[0].0).unwrap();
+        self.writer.meta_cache().mark_stale(&partition_key);
+    }
+
+    /// Resends the batch once its backoff timer fires. The timer fires early if the client shuts
+    /// down, in which case the batch fails.
+    fn resume(self, rpc: Rpc) {
+        if self.writer.client().is_shut_down() {
+            return self.fail(Error::Shutdown);
+        }
+        self.lookup_locations(rpc);
+    }
+
+    /// Fails the batch with `Error::DeadlineExceeded`, following the error of each attempt.
+    fn exceed_deadline(mut self, error: Error) {
+        self.failed_attempts.push(error);
+        let attempts = mem::replace(&mut self.failed_attempts, Vec::new());
+        self.fail(Error::DeadlineExceeded(DeadlineExceededError::new(attempts)));
+    }
+
+    /// Fails each operation in the batch with the error, and completes the batch. A timeout of a
+    /// retried batch is wrapped in `Error::DeadlineExceeded`, following the earlier errors.
+    fn fail(self, error: Error) {
+        let Batch { tablet, operations, writer, buffered_data, flush_epoch, seq_no,
+                    failed_attempts, .. } = self;
+        let error = if error.is_timeout() { error.after_attempts(failed_attempts) } else { error };
+        debug!("batch for tablet {} failed: {}", tablet, error);
+        writer.client().request_tracker().complete(seq_no);
+        writer.complete_tags(operations.iter().map(|op| (op.1, false)));
+        let failed_ops = operations.len();
+        for (row, _, op_type, _) in operations {
+            writer.fail_operation(row, op_type, error.clone());
+        }
+        writer.batch_complete(false, tablet, flush_epoch, 0, failed_ops, buffered_data, None);
     }
 
     /// Reapply each operation in the batch to the writer with the same epoch and idx.
@@ -946,6 +1474,7 @@ impl <F> FlushCallback for F where F: FnOnce(FlushStats) + Send {
 mod test {
 
     use std::sync::mpsc::sync_channel;
+    use std::thread;
     use std::time::{Duration, Instant};
 
     use ClientConfig;
@@ -1034,5 +1563,325 @@ mod test {
         assert_eq!(flush.successful_operations(), 11);
         assert_eq!(flush.failed_operations(), 1);
         assert_eq!(flush.data(), 128);
+
+        // The client observes the timestamps assigned to the writes.
+        let timestamp = flush.timestamp().unwrap();
+        assert!(client.latest_observed_timestamp() >= timestamp);
+        let other_client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+        assert_eq!(0, other_client.latest_observed_timestamp());
+        other_client.timestamp_observed(timestamp);
+        assert_eq!(timestamp, other_client.latest_observed_timestamp());
+    }
+
+    #[test]
+    fn routing_trace() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(&MiniClusterConfig::default());
+
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut table_builder = TableBuilder::new("routing_trace", schema.clone());
+        table_builder.add_hash_partitions(vec!["key"], 4);
+        table_builder.set_num_replicas(1);
+
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+
+        let mut config = WriterConfig::default();
+        config.set_trace_routing(true);
+        let writer = table.new_writer(config);
+
+        for i in 0..10 {
+            let mut insert = table.schema().new_row();
+            insert.set_by_name::<i32>("key", i).unwrap();
+            writer.insert(insert);
+        }
+        let mut insert = table.schema().new_row();
+        insert.set_by_name::<i32>("key", 0).unwrap();
+        writer.insert(insert);
+
+        let (send, recv) = sync_channel(1);
+        writer.flush(move |stats| send.send(stats).unwrap());
+        recv.recv().unwrap();
+
+        let mut trace = writer.take_routing_trace();
+        trace.sort_by_key(OperationTrace::index);
+        assert_eq!(11, trace.len());
+        assert_eq!((0..11).collect::<Vec<_>>(), trace.iter().map(OperationTrace::index).collect::<Vec<_>>());
+        assert_eq!(10, trace.iter().filter(|op| op.succeeded()).count());
+        assert!(!trace[10].succeeded());
+        assert_eq!(trace[0].tablet(), trace[10].tablet());
+        assert!(trace.iter().all(|op| cluster.tserver_addrs().contains(&op.server())));
+
+        assert!(writer.take_routing_trace().is_empty());
+    }
+
+    #[test]
+    fn batching() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(&MiniClusterConfig::default());
+
+        let mut client_config = ClientConfig::new(cluster.master_addrs().to_owned());
+        client_config.set_max_message_length(64 * 1024);
+        let client = Client::new(client_config);
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .add_column(Column::builder("val", DataType::String))
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut table_builder = TableBuilder::new("batching", schema.clone());
+        table_builder.add_hash_partitions(vec!["key"], 2);
+        table_builder.set_num_replicas(1);
+
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+
+        let mut config = WriterConfig::default();
+        config.set_max_batches_per_tablet(16);
+        let writer = table.new_writer(config);
+
+        // All operations for a tablet are sent in a single batch.
+        for i in 0..100 {
+            let mut insert = table.schema().new_row();
+            insert.set(0, i).unwrap();
+            insert.set(1, "small").unwrap();
+            writer.insert(insert);
+        }
+        let (send, recv) = sync_channel(1);
+        writer.flush(move |stats| send.send(stats).unwrap());
+        let stats = recv.recv().unwrap();
+        assert_eq!(100, stats.successful_operations());
+        assert_eq!(2, stats.successful_batches());
+
+        // Operations which do not fit in a single RPC message are split into several batches.
+        let val = ::std::iter::repeat('x').take(1024).collect::<String>();
+        for i in 100..300 {
+            let mut insert = table.schema().new_row();
+            insert.set(0, i).unwrap();
+            insert.set(1, &val[..]).unwrap();
+            writer.insert(insert);
+        }
+        let (send, recv) = sync_channel(1);
+        writer.flush(move |stats| send.send(stats).unwrap());
+        let stats = recv.recv().unwrap();
+        assert_eq!(200, stats.successful_operations());
+        assert_eq!(0, stats.failed_operations());
+        assert!(stats.successful_batches() > 2);
+        assert!(stats.data() / stats.successful_batches() < 64 * 1024);
+    }
+
+    #[test]
+    fn memory_budget() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(&MiniClusterConfig::default());
+
+        let mut client_config = ClientConfig::new(cluster.master_addrs().to_owned());
+        client_config.set_memory_budget(Some(1));
+        let client = Client::new(client_config);
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut table_builder = TableBuilder::new("memory_budget", schema.clone());
+        table_builder.set_range_partition_columns(vec!["key"]);
+        table_builder.set_num_replicas(1);
+
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+
+        let mut config = WriterConfig::default();
+        let recv = config.event_channel();
+        config.set_event_set(EventSet::FailedOperations);
+        let writer = table.new_writer(config);
+
+        // The first operation is admitted despite exceeding the budget, and the budget is
+        // exhausted until its batch completes.
+        for i in 0..3 {
+            let mut insert = table.schema().new_row();
+            insert.set(0, i).unwrap();
+            writer.insert(insert);
+        }
+        assert!(client.memory_budget().used() > 0);
+        for _ in 0..2 {
+            match recv.recv().unwrap() {
+                Event::FailedOperation(_, _, error) => assert_eq!(Error::Backoff, error),
+                event => panic!("unexpected event: {:?}", event),
+            }
+        }
+
+        let (send, flush_recv) = sync_channel(1);
+        writer.flush(move |stats| send.send(stats).unwrap());
+        assert_eq!(1, flush_recv.recv().unwrap().successful_operations());
+        assert_eq!(0, client.memory_budget().used());
+    }
+
+    #[test]
+    fn idempotency_tags() {
+        let _ = env_logger::init();
+        let cluster = MiniCluster::new(&MiniClusterConfig::default());
+
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut table_builder = TableBuilder::new("idempotency_tags", schema.clone());
+        table_builder.add_hash_partitions(vec!["key"], 2);
+        table_builder.set_num_replicas(1);
+
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+
+        let mut config = WriterConfig::default();
+        let events = config.event_channel();
+        config.set_event_set(EventSet::SuccessfulOperations);
+        let writer = table.new_writer(config);
+
+        let insert = |key: i32| {
+            let mut insert = table.schema().new_row();
+            insert.set(0, key).unwrap();
+            insert
+        };
+        let flush = || {
+            let (send, recv) = sync_channel(1);
+            writer.flush(move |stats| send.send(stats).unwrap());
+            recv.recv().unwrap()
+        };
+
+        for i in 0..10 {
+            writer.apply_tagged(insert(i), OperationType::Insert, format!("op-{}", i));
+        }
+        // An insert of an existing key fails with a per-row error.
+        writer.apply_tagged(insert(0), OperationType::Insert, "duplicate");
+        let stats = flush();
+        assert_eq!(10, stats.successful_operations());
+        assert_eq!(1, stats.failed_operations());
+        assert_eq!(0, stats.deduplicated_operations());
+        assert_eq!(None, client.request_tracker().first_incomplete_seq_no());
+
+        // Retried operations which were written are deduplicated, and the failed operation is
+        // written again.
+        for i in 0..11 {
+            writer.apply_tagged(insert(i), OperationType::Insert, format!("op-{}", i));
+        }
+        writer.apply_tagged(insert(0), OperationType::Upsert, "duplicate");
+        let stats = flush();
+        assert_eq!(2, stats.successful_operations());
+        assert_eq!(0, stats.failed_operations());
+        assert_eq!(10, stats.deduplicated_operations());
+
+        let events = events.try_iter().collect::<Vec<_>>();
+        assert_eq!(10, events.iter().filter(|event| event.is_deduplicated_operation()).count());
+        assert_eq!(1, events.iter().filter(|event| event.is_failed_operation()).count());
+    }
+
+    #[test]
+    fn leader_failover() {
+        let _ = env_logger::init();
+        let mut cluster = MiniCluster::new(MiniClusterConfig::default().num_tservers(3));
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut table_builder = TableBuilder::new("leader_failover", schema);
+        table_builder.set_num_replicas(3);
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+
+        // The first flush caches the tablet's leader.
+        let writer = table.new_writer(WriterConfig::default());
+        for i in 0..100 {
+            let mut insert = table.schema().new_row();
+            insert.set(0, i).unwrap();
+            writer.insert(insert);
+        }
+        let (send, recv) = sync_channel(1);
+        writer.flush(move |stats| send.send(stats).unwrap());
+        assert_eq!(100, recv.recv().unwrap().successful_operations());
+
+        // Stop the leader's tablet server, so that the next flush is sent to the cached leader,
+        // and is retried once one of the followers is elected.
+        let tablets = table.tablets(deadline()).unwrap();
+        let leader = tablets[0].leader().unwrap().clone();
+        cluster.stop_node(leader.resolved_rpc_addrs()[0]);
+
+        for i in 100..200 {
+            let mut insert = table.schema().new_row();
+            insert.set(0, i).unwrap();
+            writer.insert(insert);
+        }
+        let (send, recv) = sync_channel(1);
+        writer.flush(move |stats| send.send(stats).unwrap());
+        let stats = recv.recv().unwrap();
+        assert_eq!(0, stats.failed_batches());
+        assert_eq!(100, stats.successful_operations());
+    }
+
+    #[test]
+    fn shutdown_during_backoff() {
+        let _ = env_logger::init();
+        let mut cluster = MiniCluster::new(&MiniClusterConfig::default());
+
+        let client = Client::new(ClientConfig::new(cluster.master_addrs().to_owned()));
+
+        let schema = SchemaBuilder::new()
+            .add_column(Column::builder("key", DataType::Int32).set_not_null())
+            .set_primary_key(vec!["key"])
+            .build()
+            .unwrap();
+
+        let mut table_builder = TableBuilder::new("shutdown_during_backoff", schema.clone());
+        table_builder.set_range_partition_columns(vec!["key"]);
+        table_builder.set_num_replicas(1);
+
+        let table_id = client.create_table(table_builder, deadline()).unwrap();
+        client.wait_for_table_creation_by_id(&table_id, deadline()).unwrap();
+        let table = client.open_table_by_id(&table_id, deadline()).unwrap();
+
+        // Tablet location lookups are retried after a backoff while the masters are down.
+        for addr in cluster.master_addrs().to_owned() {
+            cluster.stop_node(addr);
+        }
+
+        let mut config = WriterConfig::default();
+        config.set_flush_timeout(Duration::from_secs(60));
+        let writer = table.new_writer(config);
+        let mut insert = table.schema().new_row();
+        insert.set(0, 1i32).unwrap();
+        writer.insert(insert);
+
+        let (send, flush_recv) = sync_channel(1);
+        writer.flush(move |stats| send.send(stats).unwrap());
+        thread::sleep(Duration::from_secs(2));
+
+        // The flush completes once the client shuts down.
+        client.shutdown(Duration::from_secs(10)).unwrap();
+        let stats = flush_recv.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(1, stats.failed_batches());
+        assert_eq!(1, stats.failed_operations());
     }
 }
@@ -0,0 +1,22 @@
+//! Runs the `crud` example's table lifecycle against the cluster whose masters are listed in
+//! `KUDU_MASTERS`. The test passes without doing anything if the variable is not set.
+
+extern crate kudu;
+
+#[path = "../examples/crud.rs"]
+#[allow(dead_code)]
+mod crud;
+
+use std::process;
+
+#[test]
+fn crud_lifecycle() {
+    let masters = match crud::masters_from_env() {
+        Some(masters) => masters.unwrap(),
+        None => {
+            println!("KUDU_MASTERS is not set; skipping");
+            return;
+        },
+    };
+    crud::run(masters, &format!("kudu_rs_crud_test_{}", process::id())).unwrap();
+}